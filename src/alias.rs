@@ -0,0 +1,151 @@
+//! Aliases: `rq prodapi /health` expands to the flags saved under `prodapi` in the `[aliases]`
+//! section of the config file, managed with `rq alias`:
+//!
+//! ```ini
+//! [aliases]
+//! prodapi = https://api.example.com -H 'Authorization: Bearer token' --timeout 5
+//! ```
+//!
+//! A URL as first word is the base URL: the first argument starting with `/` is appended to it,
+//! or it is requested as is when there is none. The other words are inserted before the
+//! arguments of the command line.
+use std::{ffi::OsString, io};
+
+use structopt::StructOpt;
+
+use crate::{cli::CliArgs, config, curl};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid alias name `{0}`, use letters, digits, `-` and `_`")]
+    InvalidName(String),
+    #[error("`{0}` is an rq subcommand, it can't be an alias")]
+    Reserved(String),
+    #[error("No alias named `{0}`")]
+    NotFound(String),
+    #[error("Invalid definition for alias `{name}`: {source}")]
+    Invalid { name: String, source: curl::Error },
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+const SECTION: &str = "aliases";
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+}
+
+/// Replace the alias in `args`, the arguments after the program name, by its definition
+fn expand_with(definition: &[String], args: &[OsString]) -> Vec<OsString> {
+    let (base, words) = match definition.split_first() {
+        Some((first, rest)) if first.contains("://") => (Some(first.as_str()), rest),
+        _ => (None, definition),
+    };
+    let mut expanded: Vec<OsString> = words.iter().map(OsString::from).collect();
+    let mut args = args.to_vec();
+    if let Some(base) = base {
+        let path = args
+            .iter_mut()
+            .find(|arg| arg.to_str().is_some_and(|arg| arg.starts_with('/')));
+        match path {
+            Some(path) => {
+                let url = format!("{}{}", base.trim_end_matches('/'), path.to_string_lossy());
+                *path = url.into();
+            }
+            None => args.push(base.into()),
+        }
+    }
+    expanded.extend(args);
+    expanded
+}
+
+/// The command line with the alias given as first argument, if any, replaced by its definition
+pub fn expand(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let name = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(name) if is_valid_name(name) => name.to_string(),
+        _ => return Ok(args),
+    };
+    let definition = match config::entries(&config::read()?, SECTION)
+        .into_iter()
+        .find(|(alias, _)| *alias == name)
+    {
+        Some((_, definition)) => definition,
+        None => return Ok(args),
+    };
+    let definition = curl::split_shell_words(&definition).map_err(|source| Error::Invalid {
+        name: name.clone(),
+        source,
+    })?;
+    let mut expanded = vec![args[0].clone()];
+    expanded.extend(expand_with(&definition, &args[2..]));
+    Ok(expanded)
+}
+
+/// Save the words as the definition of the alias `name`, replacing the previous one
+pub fn add(name: &str, words: &[String]) -> Result<()> {
+    if !is_valid_name(name) {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+    // A subcommand, or the start of one, fails to parse or parses as a subcommand
+    let is_subcommand = !matches!(
+        CliArgs::from_iter_safe(["rq", name]),
+        Ok(args) if args.command.is_none()
+    );
+    if is_subcommand {
+        return Err(Error::Reserved(name.to_string()));
+    }
+    let value: Vec<_> = words.iter().map(|word| curl::shell_quote(word)).collect();
+    let config = config::update(&config::read()?, SECTION, name, Some(&value.join(" ")));
+    Ok(config::write(&config)?)
+}
+
+pub fn list() -> Result<()> {
+    for (name, definition) in config::entries(&config::read()?, SECTION) {
+        println!("{:<20} {}", name, definition);
+    }
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    let config = config::read()?;
+    if !config::entries(&config, SECTION)
+        .iter()
+        .any(|(alias, _)| alias == name)
+    {
+        return Err(Error::NotFound(name.to_string()));
+    }
+    Ok(config::write(&config::update(
+        &config, SECTION, name, None,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    #[test]
+    fn base_url_is_joined_with_the_path() {
+        let definition: Vec<String> = ["https://api.example.com/", "-H", "X-A: 1"]
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        let expand = |args: &[&str]| {
+            let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            super::expand_with(&definition, &args)
+        };
+        assert_eq!(
+            expand(&["/health", "-v"]),
+            ["-H", "X-A: 1", "https://api.example.com/health", "-v"]
+        );
+        assert_eq!(
+            expand(&["-v"]),
+            ["-H", "X-A: 1", "-v", "https://api.example.com/"]
+        );
+    }
+}
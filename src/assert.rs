@@ -0,0 +1,267 @@
+//! Checks of the responses for `--assert-status`, `--assert-header`, `--assert-body-contains`
+//! and `--assert-jsonpath`, for smoke tests in CI pipelines.
+use std::{fmt, str::FromStr};
+
+use serde_json::Value;
+
+use crate::{cli::CliArgs, http::Header, jsonpath::JsonPath, response::Response};
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid status `{0}`, expected a code such as 200 or a class such as 2xx")]
+    InvalidStatus(String),
+    #[error("Invalid assertion `{0}`, expected `$.path`, `$.path == JSON` or `$.path != JSON`")]
+    InvalidJsonPath(String),
+}
+
+/// A status code, or a class of them written `2xx`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Code(u16),
+    Class(u16),
+}
+
+impl FromStr for Status {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidStatus(s.to_string());
+        let s = s.trim();
+        match s.to_ascii_lowercase().strip_suffix("xx") {
+            Some(class) => match class.parse() {
+                Ok(class @ 1..=5) => Ok(Status::Class(class)),
+                _ => Err(invalid()),
+            },
+            None => match s.parse() {
+                Ok(code @ 100..=599) => Ok(Status::Code(code)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Code(code) => write!(f, "{}", code),
+            Status::Class(class) => write!(f, "{}xx", class),
+        }
+    }
+}
+
+impl Status {
+    fn matches(self, code: u16) -> bool {
+        match self {
+            Status::Code(expected) => code == expected,
+            Status::Class(class) => code / 100 == class,
+        }
+    }
+}
+
+/// A value in the JSON body: present, equal to or different from a JSON value
+#[derive(Debug, Clone)]
+pub struct JsonAssertion {
+    text: String,
+    path: JsonPath,
+    expected: Option<(bool, Value)>,
+}
+
+impl FromStr for JsonAssertion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidJsonPath(s.to_string());
+        let operator = ["==", "!="]
+            .iter()
+            .filter_map(|op| s.find(op).map(|i| (i, *op)))
+            .min();
+        let (path, expected) = match operator {
+            Some((i, op)) => {
+                let value =
+                    serde_json::from_str(s[i + op.len()..].trim()).map_err(|_| invalid())?;
+                (&s[..i], Some((op == "==", value)))
+            }
+            None => (s, None),
+        };
+        Ok(JsonAssertion {
+            text: s.trim().to_string(),
+            path: path.trim().parse().map_err(|_| invalid())?,
+            expected,
+        })
+    }
+}
+
+/// An assertion that doesn't hold, displayed as what was expected and what was received
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub assertion: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "assertion failed: {}", self.assertion)?;
+        writeln!(f, "- expected: {}", self.expected)?;
+        write!(f, "+ actual:   {}", self.actual)
+    }
+}
+
+/// All the assertions given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct Assertions {
+    pub status: Option<Status>,
+    pub headers: Vec<Header>,
+    pub body_contains: Vec<String>,
+    pub json: Vec<JsonAssertion>,
+}
+
+impl Assertions {
+    pub fn from_args(args: &CliArgs) -> Self {
+        Assertions {
+            status: args.assert_status,
+            headers: args.assert_header.clone(),
+            body_contains: args.assert_body_contains.clone(),
+            json: args.assert_jsonpath.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none()
+            && self.headers.is_empty()
+            && self.body_contains.is_empty()
+            && self.json.is_empty()
+    }
+
+    /// The assertions the response fails
+    pub fn check(&self, response: &Response) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        if let Some(status) = self.status {
+            if !status.matches(response.status.as_u16()) {
+                failures.push(Failure {
+                    assertion: format!("status {}", status),
+                    expected: status.to_string(),
+                    actual: response.status.to_string(),
+                });
+            }
+        }
+        for header in &self.headers {
+            let values: Vec<_> = response
+                .headers
+                .get_all(header.name.as_str())
+                .iter()
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                .collect();
+            if !values.iter().any(|v| v.trim() == header.value) {
+                failures.push(Failure {
+                    assertion: format!("header {}", header.name),
+                    expected: header.value.clone(),
+                    actual: if values.is_empty() {
+                        "(missing)".to_string()
+                    } else {
+                        values.join(", ")
+                    },
+                });
+            }
+        }
+        let text = response.text(None);
+        for needle in &self.body_contains {
+            if !text.contains(needle.as_str()) {
+                failures.push(Failure {
+                    assertion: "body contains".to_string(),
+                    expected: format!("{:?}", needle),
+                    actual: format!("{} bytes without it", response.body.len()),
+                });
+            }
+        }
+        if self.json.is_empty() {
+            return failures;
+        }
+        let json: Option<Value> = serde_json::from_slice(&response.body).ok();
+        for assertion in &self.json {
+            let actual = json.as_ref().and_then(|json| assertion.path.get(json));
+            let holds = match (&assertion.expected, actual) {
+                (None, actual) => actual.is_some(),
+                (Some((equal, expected)), Some(actual)) => (expected == actual) == *equal,
+                (Some((equal, _)), None) => !equal,
+            };
+            if holds {
+                continue;
+            }
+            failures.push(Failure {
+                assertion: assertion.text.clone(),
+                expected: match &assertion.expected {
+                    None => "a value".to_string(),
+                    Some((true, value)) => value.to_string(),
+                    Some((false, value)) => format!("anything but {}", value),
+                },
+                actual: match (&json, actual) {
+                    (None, _) => "a body that is not JSON".to_string(),
+                    (Some(_), None) => "(missing)".to_string(),
+                    (Some(_), Some(value)) => value.to_string(),
+                },
+            });
+        }
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use super::{Assertions, Status};
+    use crate::response::Response;
+
+    fn response() -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/json".parse().unwrap());
+        Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::NOT_FOUND,
+            version: Version::HTTP_11,
+            headers,
+            body: br#"{"ok": false, "items": [1, 2]}"#.to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn statuses_are_codes_or_classes() {
+        assert_eq!("200".parse::<Status>().unwrap(), Status::Code(200));
+        assert_eq!("4XX".parse::<Status>().unwrap(), Status::Class(4));
+        assert!("9xx".parse::<Status>().is_err());
+        assert!("20".parse::<Status>().is_err());
+    }
+    #[test]
+    fn failed_assertions_are_reported() {
+        let assertions = Assertions {
+            status: Some(Status::Class(4)),
+            headers: vec!["Content-Type: application/json".parse().unwrap()],
+            body_contains: vec!["items".to_string()],
+            json: vec![
+                "$.ok == false".parse().unwrap(),
+                "$.items[1] != 3".parse().unwrap(),
+            ],
+        };
+        assert!(assertions.check(&response()).is_empty());
+        let assertions = Assertions {
+            status: Some(Status::Code(200)),
+            json: vec![
+                "$.ok == true".parse().unwrap(),
+                "$.missing".parse().unwrap(),
+            ],
+            ..Assertions::default()
+        };
+        let failures = assertions.check(&response());
+        assert_eq!(failures.len(), 3);
+        assert_eq!(failures[0].actual, "404 Not Found");
+        assert_eq!(failures[1].expected, "true");
+        assert_eq!(failures[1].actual, "false");
+        assert_eq!(failures[2].actual, "(missing)");
+        assert!("$.ok == nope".parse::<super::JsonAssertion>().is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! `rq audit`: check the security-related headers of a response, like HSTS, the Content Security
+//! Policy and the flags of the cookies, and grade them.
+//!
+//! The checks follow the recommendations of the OWASP Secure Headers Project
+//! (https://owasp.org/www-project-secure-headers/).
+use std::fmt;
+
+use reqwest::header::{HeaderMap, SET_COOKIE};
+
+use crate::response::Response;
+
+/// HSTS should be remembered for at least 180 days
+const MIN_HSTS_MAX_AGE: u64 = 180 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Pass => "PASS",
+            Outcome::Warn => "WARN",
+            Outcome::Fail => "FAIL",
+        })
+    }
+}
+
+/// The result of one check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub outcome: Outcome,
+    pub header: String,
+    pub message: String,
+}
+
+fn finding(outcome: Outcome, header: &str, message: impl Into<String>) -> Finding {
+    Finding {
+        outcome,
+        header: header.to_string(),
+        message: message.into(),
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+}
+
+fn hsts(headers: &HeaderMap, https: bool) -> Finding {
+    const NAME: &str = "Strict-Transport-Security";
+    if !https {
+        return finding(
+            Outcome::Fail,
+            NAME,
+            "served over plain HTTP, which can be read and modified on the way",
+        );
+    }
+    let value = match header(headers, NAME) {
+        Some(value) => value,
+        None => {
+            return finding(
+                Outcome::Fail,
+                NAME,
+                "missing, browsers may connect over HTTP",
+            )
+        }
+    };
+    let directives: Vec<_> = value.split(';').map(|d| d.trim().to_lowercase()).collect();
+    let max_age = directives
+        .iter()
+        .find_map(|d| d.strip_prefix("max-age="))
+        .and_then(|age| age.trim_matches('"').parse::<u64>().ok());
+    match max_age {
+        None => finding(
+            Outcome::Fail,
+            NAME,
+            format!("`{}` has no valid max-age", value),
+        ),
+        Some(age) if age < MIN_HSTS_MAX_AGE => finding(
+            Outcome::Warn,
+            NAME,
+            format!("max-age is {} s, less than 180 days", age),
+        ),
+        Some(_) if !directives.iter().any(|d| d == "includesubdomains") => finding(
+            Outcome::Warn,
+            NAME,
+            format!("`{}` doesn't cover the subdomains", value),
+        ),
+        Some(_) => finding(Outcome::Pass, NAME, value),
+    }
+}
+
+/// The directives of a Content Security Policy, by lowercase name
+fn csp_directives(policy: &str) -> Vec<(String, String)> {
+    policy
+        .split(';')
+        .filter_map(|directive| {
+            let directive = directive.trim();
+            let (name, value) = directive.split_once(' ').unwrap_or((directive, ""));
+            (!name.is_empty()).then(|| (name.to_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn csp(headers: &HeaderMap) -> Finding {
+    const NAME: &str = "Content-Security-Policy";
+    let policy = match header(headers, NAME) {
+        Some(policy) => policy,
+        None if header(headers, "content-security-policy-report-only").is_some() => {
+            return finding(
+                Outcome::Warn,
+                NAME,
+                "only reported with Content-Security-Policy-Report-Only, not enforced",
+            )
+        }
+        None => return finding(Outcome::Fail, NAME, "missing, injected scripts can run"),
+    };
+    let directives = csp_directives(policy);
+    let scripts = directives
+        .iter()
+        .find(|(name, _)| name == "script-src")
+        .or_else(|| directives.iter().find(|(name, _)| name == "default-src"));
+    match scripts {
+        None => finding(
+            Outcome::Warn,
+            NAME,
+            "neither script-src nor default-src restricts the scripts",
+        ),
+        Some((name, sources)) => {
+            let weak: Vec<_> = sources
+                .split_whitespace()
+                .filter(|s| {
+                    matches!(
+                        *s,
+                        "'unsafe-inline'" | "'unsafe-eval'" | "*" | "http:" | "https:"
+                    )
+                })
+                .collect();
+            if weak.is_empty() {
+                finding(Outcome::Pass, NAME, policy)
+            } else {
+                finding(
+                    Outcome::Warn,
+                    NAME,
+                    format!("{} allows {}", name, weak.join(" ")),
+                )
+            }
+        }
+    }
+}
+
+fn frame_options(headers: &HeaderMap) -> Finding {
+    const NAME: &str = "X-Frame-Options";
+    let frame_ancestors = header(headers, "content-security-policy")
+        .map(csp_directives)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name == "frame-ancestors");
+    if let Some((_, sources)) = frame_ancestors {
+        return finding(
+            Outcome::Pass,
+            NAME,
+            format!("framing restricted by the CSP frame-ancestors {}", sources),
+        );
+    }
+    match header(headers, NAME).map(str::to_uppercase).as_deref() {
+        Some(value @ ("DENY" | "SAMEORIGIN")) => finding(Outcome::Pass, NAME, value),
+        Some(value) => finding(
+            Outcome::Warn,
+            NAME,
+            format!(
+                "`{}` is not supported by browsers, use DENY or SAMEORIGIN",
+                value
+            ),
+        ),
+        None => finding(
+            Outcome::Fail,
+            NAME,
+            "missing and no CSP frame-ancestors, the page can be framed for clickjacking",
+        ),
+    }
+}
+
+fn content_type_options(headers: &HeaderMap) -> Finding {
+    const NAME: &str = "X-Content-Type-Options";
+    match header(headers, NAME) {
+        Some(value) if value.eq_ignore_ascii_case("nosniff") => finding(Outcome::Pass, NAME, value),
+        Some(value) => finding(
+            Outcome::Fail,
+            NAME,
+            format!("`{}` should be nosniff", value),
+        ),
+        None => finding(
+            Outcome::Fail,
+            NAME,
+            "missing, browsers may sniff the body as another type",
+        ),
+    }
+}
+
+fn referrer_policy(headers: &HeaderMap) -> Finding {
+    const NAME: &str = "Referrer-Policy";
+    let value = match header(headers, NAME) {
+        Some(value) => value,
+        None => {
+            return finding(
+                Outcome::Warn,
+                NAME,
+                "missing, the policy is left to the browser",
+            )
+        }
+    };
+    // The last policy the browser supports applies
+    let policy = value.rsplit(',').next().unwrap_or_default().trim();
+    match policy.to_lowercase().as_str() {
+        "no-referrer" | "same-origin" | "strict-origin" | "strict-origin-when-cross-origin" => {
+            finding(Outcome::Pass, NAME, value)
+        }
+        "unsafe-url" | "no-referrer-when-downgrade" | "origin-when-cross-origin" | "origin" => {
+            finding(
+                Outcome::Warn,
+                NAME,
+                format!("`{}` sends the referrer to other origins", policy),
+            )
+        }
+        _ => finding(Outcome::Warn, NAME, format!("unknown policy `{}`", policy)),
+    }
+}
+
+/// The missing flags of a `Set-Cookie` header, `None` if it is not a cookie
+fn cookie(value: &str, https: bool) -> Option<Finding> {
+    let mut attributes = value.split(';');
+    let (name, _) = attributes.next()?.split_once('=')?;
+    let name = name.trim();
+    let (mut secure, mut http_only, mut same_site) = (false, false, None);
+    for attribute in attributes {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        match key.trim().to_lowercase().as_str() {
+            "secure" => secure = true,
+            "httponly" => http_only = true,
+            "samesite" => same_site = Some(value.trim().to_lowercase()),
+            _ => {}
+        }
+    }
+    let header = format!("Set-Cookie {}", name);
+    let mut problems = Vec::new();
+    let mut outcome = Outcome::Pass;
+    if !secure {
+        problems.push("no Secure flag");
+        if https {
+            outcome = Outcome::Fail;
+        }
+    }
+    if !http_only {
+        problems.push("no HttpOnly flag, scripts can read it");
+    }
+    match same_site.as_deref() {
+        None => problems.push("no SameSite attribute"),
+        Some("none") if !secure => {
+            problems.push("SameSite=None requires Secure");
+            outcome = Outcome::Fail;
+        }
+        _ => {}
+    }
+    if problems.is_empty() {
+        return Some(finding(
+            Outcome::Pass,
+            &header,
+            "Secure, HttpOnly and SameSite",
+        ));
+    }
+    if outcome == Outcome::Pass {
+        outcome = Outcome::Warn;
+    }
+    Some(finding(outcome, &header, problems.join(", ")))
+}
+
+/// Check the headers of a response received from a `https` URL if `https`
+pub fn check(headers: &HeaderMap, https: bool) -> Vec<Finding> {
+    let mut findings = vec![
+        hsts(headers, https),
+        csp(headers),
+        frame_options(headers),
+        content_type_options(headers),
+        referrer_policy(headers),
+    ];
+    findings.extend(
+        headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| cookie(v, https)),
+    );
+    findings
+}
+
+/// A letter from A to F, from the share of the checks that pass, a warning counting as half
+pub fn grade(findings: &[Finding]) -> char {
+    let points: usize = findings
+        .iter()
+        .map(|f| match f.outcome {
+            Outcome::Pass => 2,
+            Outcome::Warn => 1,
+            Outcome::Fail => 0,
+        })
+        .sum();
+    let score = points * 100 / (findings.len() * 2).max(1);
+    match score {
+        90.. => 'A',
+        75..=89 => 'B',
+        60..=74 => 'C',
+        40..=59 => 'D',
+        _ => 'F',
+    }
+}
+
+/// Print the findings for the response and the grade, returning how many checks failed
+pub fn report(response: &Response) -> usize {
+    let https = response.url.starts_with("https://");
+    let findings = check(&response.headers, https);
+    println!("{} {}", response.status, response.url);
+    println!();
+    for finding in &findings {
+        println!(
+            "{} {}: {}",
+            finding.outcome, finding.header, finding.message
+        );
+    }
+    let count = |outcome| findings.iter().filter(|f| f.outcome == outcome).count();
+    println!();
+    println!(
+        "Grade {} ({} passed, {} warnings, {} failed)",
+        grade(&findings),
+        count(Outcome::Pass),
+        count(Outcome::Warn),
+        count(Outcome::Fail)
+    );
+    count(Outcome::Fail)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+
+    use super::Outcome;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    fn outcomes(headers: &HeaderMap, https: bool) -> Vec<(String, Outcome)> {
+        super::check(headers, https)
+            .into_iter()
+            .map(|f| (f.header, f.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn hardened_response_gets_an_a() {
+        let headers = headers(&[
+            (
+                "strict-transport-security",
+                "max-age=31536000; includeSubDomains",
+            ),
+            (
+                "content-security-policy",
+                "default-src 'self'; frame-ancestors 'none'",
+            ),
+            ("x-content-type-options", "nosniff"),
+            ("referrer-policy", "strict-origin-when-cross-origin"),
+            ("set-cookie", "id=1; Secure; HttpOnly; SameSite=Lax"),
+        ]);
+        let findings = super::check(&headers, true);
+        assert!(findings.iter().all(|f| f.outcome == Outcome::Pass));
+        assert_eq!(findings.len(), 6);
+        assert_eq!(super::grade(&findings), 'A');
+    }
+    #[test]
+    fn weak_headers_are_reported() {
+        let headers = headers(&[
+            ("strict-transport-security", "max-age=3600"),
+            (
+                "content-security-policy",
+                "script-src 'self' 'unsafe-inline'",
+            ),
+            ("x-frame-options", "ALLOW-FROM https://a.example"),
+            ("referrer-policy", "unsafe-url"),
+            ("set-cookie", "id=1; HttpOnly; SameSite=None"),
+        ]);
+        assert_eq!(
+            outcomes(&headers, true),
+            [
+                ("Strict-Transport-Security".to_string(), Outcome::Warn),
+                ("Content-Security-Policy".to_string(), Outcome::Warn),
+                ("X-Frame-Options".to_string(), Outcome::Warn),
+                ("X-Content-Type-Options".to_string(), Outcome::Fail),
+                ("Referrer-Policy".to_string(), Outcome::Warn),
+                ("Set-Cookie id".to_string(), Outcome::Fail),
+            ]
+        );
+    }
+    #[test]
+    fn bare_response_fails() {
+        let findings = super::check(&HeaderMap::new(), false);
+        assert_eq!(
+            findings.iter().map(|f| f.outcome).collect::<Vec<_>>(),
+            [
+                Outcome::Fail,
+                Outcome::Fail,
+                Outcome::Fail,
+                Outcome::Fail,
+                Outcome::Warn
+            ]
+        );
+        assert_eq!(super::grade(&findings), 'F');
+    }
+}
@@ -0,0 +1,270 @@
+//! `rq batch`: the requests of a file sent with a single client, one request per line, e.g. to
+//! seed data or to smoke test an API.
+//!
+//! ```text
+//! # Comments and blank lines are ignored
+//! POST example.com/users name=alice admin:=true X-Request-Id:42
+//! GET example.com/users
+//! rq -m DELETE -H 'Authorization: Bearer abc' example.com/users/1
+//! ```
+//!
+//! A line is either `METHOD URL [items]`, where `Name:value` items are headers and `key=value` or
+//! `key:=json` items are the members of a JSON body, or an rq command line whose request options
+//! are used. `METHOD URL` lines are sent with the connection, redirect and auth options given to
+//! `rq batch`. The cookies set by the responses are sent back with the following requests.
+use std::{fs, io, path::Path, sync::Mutex, time::Instant};
+
+use futures_util::stream::{self, StreamExt};
+use reqwest::{header::COOKIE, Client};
+use structopt::StructOpt;
+
+use crate::{
+    cli::{self, normalize_url, CliArgs},
+    connect,
+    cookie::Jar,
+    curl,
+    http::{merge_data, ContentType, HttpMethod},
+    redirect::Redirects,
+    report,
+    request::Request,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't read {path}: {source}")]
+    Read { path: String, source: io::Error },
+    #[error("{path}:{line}: {message}")]
+    Line {
+        path: String,
+        line: usize,
+        message: String,
+    },
+}
+
+/// The value of the header `name` (case-insensitive) set on the request
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// A `METHOD URL [items]` line, sent with the options of `args`
+fn parse_items(args: &CliArgs, words: &[String]) -> Result<Request, String> {
+    let (method, url, items) = match words {
+        [method, url, items @ ..] => (method, url, items),
+        _ => return Err("expected `METHOD URL [items]`".to_string()),
+    };
+    let method: HttpMethod = method
+        .parse()
+        .map_err(|e: crate::http::Error| e.to_string())?;
+    let mut url = url.clone();
+    normalize_url(&mut url);
+    let mut request = Request::new(method, &url);
+    request.connect = Some(connect::Options::from_args(args));
+    request.redirects = Redirects::from_args(args);
+    request.pipeline = args.pipeline.clone();
+    let (headers, fields): (Vec<_>, Vec<_>) =
+        items.iter().cloned().partition(|i| cli::is_header_item(i));
+    for header in headers {
+        let (name, value) = header.split_once(':').unwrap_or_default();
+        request.set_header(name.trim(), value.trim().to_string());
+    }
+    request.body = merge_data(&fields, Some(&ContentType::Json)).map_err(|e| e.to_string())?;
+    if request.body.is_some() {
+        request.set_header(
+            reqwest::header::CONTENT_TYPE.as_str(),
+            ContentType::Json.to_string(),
+        );
+    }
+    Ok(request)
+}
+
+/// An rq command line, one request per URL
+fn parse_command(words: &[String]) -> Result<Vec<Request>, String> {
+    let mut args = CliArgs::from_iter_safe(words).map_err(|e| e.message)?;
+    cli::resolve_method(&mut args).map_err(|e| e.to_string())?;
+    if args.command.is_some() {
+        return Err("only requests can be batched, not subcommands".to_string());
+    }
+    if args.urls.is_empty() {
+        return Err("no URL to send a request to".to_string());
+    }
+    cli::prepare(&mut args).map_err(|e| e.message)?;
+    Ok(args
+        .urls
+        .iter()
+        .map(|url| Request::from_args(&args, url))
+        .collect())
+}
+
+/// The requests of a line, none for blank lines and comments
+fn parse_line(args: &CliArgs, line: &str) -> Result<Vec<Request>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Vec::new());
+    }
+    let words = curl::split_shell_words(line).map_err(|e| e.to_string())?;
+    match words.first().map(String::as_str) {
+        Some("rq") => parse_command(&words),
+        _ => parse_items(args, &words).map(|request| vec![request]),
+    }
+}
+
+/// The requests of the file, with the number of the line each comes from
+pub fn read(args: &CliArgs, path: &Path) -> Result<Vec<(usize, Request)>, Error> {
+    let display = path.display().to_string();
+    let content = fs::read_to_string(path).map_err(|source| Error::Read {
+        path: display.clone(),
+        source,
+    })?;
+    let mut requests = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let parsed = parse_line(args, line).map_err(|message| Error::Line {
+            path: display.clone(),
+            line: i + 1,
+            message,
+        })?;
+        requests.extend(parsed.into_iter().map(|request| (i + 1, request)));
+    }
+    Ok(requests)
+}
+
+/// Send the requests of the file, at most `concurrency` at a time, printing a status line for
+/// each and a summary. Requests failing or answered with an error status make it fail.
+pub async fn run(
+    client: &Client,
+    args: &CliArgs,
+    path: &Path,
+    concurrency: usize,
+    template: Option<&report::Template>,
+) -> crate::Result<()> {
+    let requests = read(args, path)?;
+    let total = requests.len();
+    let jar = Mutex::new(Jar::default());
+    let jar = &jar;
+    let mut results = stream::iter(requests)
+        .map(|(line, mut request)| async move {
+            let cookies = jar
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .header_value(&request.url);
+            if let Some(cookies) = cookies {
+                let cookies = match header(&request, COOKIE.as_str()) {
+                    Some(given) => format!("{}; {}", given, cookies),
+                    None => cookies,
+                };
+                request.set_header(COOKIE.as_str(), cookies);
+            }
+            let start = Instant::now();
+            let outcome = request.send(client).await;
+            if let Ok(response) = &outcome {
+                let mut jar = jar.lock().unwrap_or_else(|e| e.into_inner());
+                jar.store(&response.url, &response.headers);
+            }
+            (line, request, outcome, start.elapsed())
+        })
+        .buffered(concurrency.max(1));
+    let (mut succeeded, mut failed) = (0, 0);
+    while let Some((line, request, outcome, elapsed)) = results.next().await {
+        if let Some(template) = template {
+            println!("{}", template.render(&request, &outcome, elapsed));
+        }
+        let result = match outcome {
+            Ok(response)
+                if response.status.is_client_error() || response.status.is_server_error() =>
+            {
+                failed += 1;
+                format!("!! {}", response.status.as_u16())
+            }
+            Ok(response) => {
+                succeeded += 1;
+                format!("   {}", response.status.as_u16())
+            }
+            Err(e) => {
+                failed += 1;
+                format!("!! error: {}", e)
+            }
+        };
+        if template.is_some() {
+            continue;
+        }
+        println!(
+            "{:>4}: {}  {} {} ({} ms)",
+            line,
+            result,
+            request.method,
+            request.url,
+            elapsed.as_millis()
+        );
+    }
+    println!("{} sent, {} succeeded, {} failed", total, succeeded, failed);
+    if failed > 0 {
+        return Err(crate::Error::Failed(failed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use crate::{cli::CliArgs, http::HttpMethod};
+
+    #[test]
+    fn items_are_headers_or_json_fields() {
+        let args = CliArgs::from_iter([
+            "rq",
+            "--location-trusted",
+            "--no-decompress",
+            "batch",
+            "requests.txt",
+        ]);
+        let requests = super::parse_line(
+            &args,
+            "POST example.com/users name=alice admin:=true 'X-Request-Id: 42' tags[]=a",
+        )
+        .unwrap();
+        let request = &requests[0];
+        assert!(matches!(request.method, HttpMethod::Post));
+        assert_eq!(request.url, "http://example.com/users");
+        assert_eq!(super::header(request, "x-request-id"), Some("42"));
+        assert_eq!(
+            request.body.as_deref(),
+            Some(r#"{"name":"alice","admin":true,"tags":["a"]}"#)
+        );
+        assert_eq!(
+            super::header(request, "content-type"),
+            Some("application/json")
+        );
+        assert!(request.redirects.trusted);
+        assert!(!request.connect.as_ref().unwrap().decompress);
+        assert!(super::parse_line(&args, "GET").is_err());
+        assert!(super::parse_line(&args, "FETCH example.com").is_err());
+    }
+    #[test]
+    fn rq_command_lines_are_parsed() {
+        let args = CliArgs::from_iter(["rq", "batch", "requests.txt"]);
+        let requests = super::parse_line(
+            &args,
+            "rq -m DELETE -H 'X-A: 1' example.com/a example.com/b",
+        )
+        .unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(matches!(requests[1].method, HttpMethod::Delete));
+        assert_eq!(requests[1].url, "http://example.com/b");
+        assert_eq!(super::header(&requests[0], "x-a"), Some("1"));
+        let requests =
+            super::parse_line(&args, r#"rq -d '{"a":1}' 'example.com/a b/{x,y}'"#).unwrap();
+        assert_eq!(requests[1].url, "http://example.com/a%20b/y");
+        assert_eq!(
+            super::header(&requests[0], "content-type"),
+            Some("application/json")
+        );
+        assert!(super::parse_line(&args, "rq man").is_err());
+        assert!(super::parse_line(&args, "  # a comment")
+            .unwrap()
+            .is_empty());
+    }
+}
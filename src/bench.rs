@@ -0,0 +1,503 @@
+//! Benchmark mode: send the same request many times and report latency statistics.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures_util::future;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::{cli::CliArgs, datetime, report, request::Request};
+
+/// The upper bounds of the latency histogram buckets, in seconds, those of the Prometheus clients
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Unknown metrics format `{0}`, expected prometheus")]
+pub struct UnknownMetricsFormat(String);
+
+/// How `--metrics-format` writes the results
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricsFormat {
+    /// OpenMetrics text, as scraped by Prometheus
+    Prometheus,
+}
+
+impl FromStr for MetricsFormat {
+    type Err = UnknownMetricsFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prometheus" | "openmetrics" => Ok(MetricsFormat::Prometheus),
+            _ => Err(UnknownMetricsFormat(s.to_string())),
+        }
+    }
+}
+
+/// A single request made during the benchmark
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// When the request was sent
+    pub sent_at: SystemTime,
+    /// Time until the whole response body was received
+    pub latency: Duration,
+    /// The status code, or the error message if no response was received
+    pub outcome: std::result::Result<u16, String>,
+}
+
+/// The result of a benchmark run
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub samples: Vec<Sample>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn error_count(&self) -> usize {
+        self.samples.iter().filter(|s| s.outcome.is_err()).count()
+    }
+
+    /// Number of responses per status code
+    pub fn status_counts(&self) -> BTreeMap<u16, usize> {
+        let mut counts = BTreeMap::new();
+        for status in self.samples.iter().filter_map(|s| s.outcome.as_ref().ok()) {
+            *counts.entry(*status).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Number of occurrences per error message
+    pub fn error_counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for error in self.samples.iter().filter_map(|s| s.outcome.as_ref().err()) {
+            *counts.entry(error.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Latencies of the requests which got a response, sorted in ascending order
+    pub fn sorted_latencies(&self) -> Vec<Duration> {
+        let mut latencies: Vec<_> = self
+            .samples
+            .iter()
+            .filter(|s| s.outcome.is_ok())
+            .map(|s| s.latency)
+            .collect();
+        latencies.sort();
+        latencies
+    }
+
+    pub fn throughput(&self) -> f64 {
+        self.samples.len() as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Get the `p`-th percentile (0 to 100) using the nearest-rank method.
+///
+/// `sorted` must be sorted in ascending order. Return `None` if there is no value.
+pub fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// When the benchmark stops
+#[derive(Debug, Clone, Copy)]
+enum Limit {
+    Requests(usize),
+    Duration(Duration),
+}
+
+/// How the requests are sent
+#[derive(Debug, Clone, Copy)]
+struct Load {
+    limit: Limit,
+    concurrency: usize,
+    /// The requests in flight go from 1 to `concurrency` over this time
+    ramp_up: Option<Duration>,
+    /// The requests are started at this constant rate, per second
+    rps: Option<f64>,
+}
+
+fn sleep_until(instant: Instant) -> tokio::time::Sleep {
+    tokio::time::sleep_until(tokio::time::Instant::from_std(instant))
+}
+
+/// Send the requests in a round-robin fashion as `load` says, printing a line per response from
+/// the template if given
+async fn send(
+    client: &Client,
+    requests: &[Request],
+    load: Load,
+    template: Option<&report::Template>,
+) -> Report {
+    let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let samples = Mutex::new(Vec::new());
+    let concurrency = load.concurrency.max(1);
+    let workers = (0..concurrency).map(|worker| {
+        let (next, samples) = (&next, &samples);
+        async move {
+            // The workers start one after the other over the ramp-up
+            if let Some(ramp_up) = load.ramp_up {
+                sleep_until(start + ramp_up.mul_f64(worker as f64 / concurrency as f64)).await;
+            }
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let at = load
+                    .rps
+                    .map(|rps| start + Duration::from_secs_f64(i as f64 / rps));
+                let done = match load.limit {
+                    Limit::Requests(count) => i >= count,
+                    Limit::Duration(duration) => {
+                        at.unwrap_or_else(Instant::now) >= start + duration
+                    }
+                };
+                if done {
+                    break;
+                }
+                if let Some(at) = at {
+                    sleep_until(at).await;
+                }
+                let (sent_at, begin) = (SystemTime::now(), Instant::now());
+                // The transfer of the body is included in the latency
+                let request = &requests[i % requests.len()];
+                let result = request.send(client).await;
+                let latency = begin.elapsed();
+                if let Some(template) = template {
+                    println!("{}", template.render(request, &result, latency));
+                }
+                let outcome = result
+                    .map(|res| res.status.as_u16())
+                    .map_err(|e| e.to_string());
+                let sample = Sample {
+                    sent_at,
+                    latency,
+                    outcome,
+                };
+                samples.lock().unwrap().push(sample);
+            }
+        }
+    });
+    future::join_all(workers).await;
+    Report {
+        samples: samples.into_inner().unwrap(),
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Send `args.requests` requests, or as many as possible for `args.duration`, with at most
+/// `args.concurrency` in flight, after the `args.warmup` requests left out of the report. If
+/// several requests are given, they are sent in a round-robin fashion.
+pub async fn run(client: &Client, args: &CliArgs, requests: &[Request]) -> Report {
+    if let Some(warmup) = args.warmup.filter(|n| *n > 0) {
+        let load = Load {
+            limit: Limit::Requests(warmup),
+            concurrency: args.concurrency,
+            ramp_up: None,
+            rps: None,
+        };
+        send(client, requests, load, None).await;
+    }
+    let limit = match args.duration {
+        Some(duration) => Limit::Duration(duration.0),
+        None => Limit::Requests(args.requests),
+    };
+    let load = Load {
+        limit,
+        concurrency: args.concurrency,
+        ramp_up: args.ramp_up.map(|ramp_up| ramp_up.0),
+        rps: args.rps,
+    };
+    send(client, requests, load, args.output_template.as_ref()).await
+}
+
+fn format_duration(d: Duration) -> String {
+    format!("{:.2} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Print the report in human readable form
+pub fn print_report(report: &Report) {
+    println!("Summary:");
+    println!("  Requests:   {}", report.samples.len());
+    println!("  Total time: {:.4} s", report.elapsed.as_secs_f64());
+    println!("  Throughput: {:.2} req/s", report.throughput());
+    println!("  Errors:     {}", report.error_count());
+
+    let latencies = report.sorted_latencies();
+    if !latencies.is_empty() {
+        let total: Duration = latencies.iter().sum();
+        println!("Latency:");
+        println!("  min:  {}", format_duration(latencies[0]));
+        println!(
+            "  mean: {}",
+            format_duration(total / latencies.len() as u32)
+        );
+        for p in &[50.0, 90.0, 99.0] {
+            if let Some(value) = percentile(&latencies, *p) {
+                println!("  p{}:  {}", p, format_duration(value));
+            }
+        }
+        println!(
+            "  max:  {}",
+            format_duration(latencies[latencies.len() - 1])
+        );
+    }
+
+    let statuses = report.status_counts();
+    if !statuses.is_empty() {
+        println!("Status codes:");
+        for (status, count) in statuses {
+            println!("  {}: {}", status, count);
+        }
+    }
+    let errors = report.error_counts();
+    if !errors.is_empty() {
+        println!("Errors:");
+        for (error, count) in errors {
+            println!("  {}: {}", count, error);
+        }
+    }
+}
+
+/// The file format of `--bench-output`, from its extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+    Csv,
+    Json,
+}
+
+impl SampleFormat {
+    /// JSON for a `.json` file, CSV otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => SampleFormat::Json,
+            _ => SampleFormat::Csv,
+        }
+    }
+}
+
+/// A CSV field, quoted if needed (https://www.rfc-editor.org/rfc/rfc4180)
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Each request of the report in the order they were sent: when, how long it took and the status
+/// or error
+pub fn samples(report: &Report, format: SampleFormat) -> String {
+    let mut samples: Vec<_> = report.samples.iter().collect();
+    samples.sort_by_key(|s| s.sent_at);
+    let timestamp = |s: &Sample| datetime::format_rfc3339_millis(datetime::unix_millis(s.sent_at));
+    let latency_ms = |s: &Sample| s.latency.as_secs_f64() * 1000.0;
+    match format {
+        SampleFormat::Csv => {
+            let mut out = String::from("timestamp,latency_ms,status,error\n");
+            for sample in samples {
+                let (status, error) = match &sample.outcome {
+                    Ok(status) => (status.to_string(), String::new()),
+                    Err(e) => (String::new(), csv_field(e)),
+                };
+                let _ = writeln!(
+                    out,
+                    "{},{:.3},{},{}",
+                    timestamp(sample),
+                    latency_ms(sample),
+                    status,
+                    error
+                );
+            }
+            out
+        }
+        SampleFormat::Json => {
+            let samples: Vec<_> = samples
+                .into_iter()
+                .map(|sample| {
+                    let (status, error) = match &sample.outcome {
+                        Ok(status) => (Value::from(*status), Value::Null),
+                        Err(e) => (Value::Null, Value::from(e.as_str())),
+                    };
+                    json!({
+                        "timestamp": timestamp(sample),
+                        "latency_ms": latency_ms(sample),
+                        "status": status,
+                        "error": error,
+                    })
+                })
+                .collect();
+            format!("{:#}\n", Value::Array(samples))
+        }
+    }
+}
+
+/// The report in the OpenMetrics text format
+/// (https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md): the
+/// responses by status, the errors and the latency histogram
+pub fn openmetrics(report: &Report) -> String {
+    let mut out = String::new();
+    out.push_str("# TYPE rq_bench_responses counter\n");
+    out.push_str("# HELP rq_bench_responses Responses received, by status code\n");
+    for (status, count) in report.status_counts() {
+        let _ = writeln!(
+            out,
+            "rq_bench_responses_total{{status=\"{}\"}} {}",
+            status, count
+        );
+    }
+    out.push_str("# TYPE rq_bench_errors counter\n");
+    out.push_str("# HELP rq_bench_errors Requests that got no response\n");
+    let _ = writeln!(out, "rq_bench_errors_total {}", report.error_count());
+
+    let latencies = report.sorted_latencies();
+    out.push_str("# TYPE rq_bench_latency_seconds histogram\n");
+    out.push_str("# HELP rq_bench_latency_seconds Time until the whole response was received\n");
+    for bound in BUCKETS {
+        let count = latencies
+            .iter()
+            .take_while(|l| l.as_secs_f64() <= *bound)
+            .count();
+        let _ = writeln!(
+            out,
+            "rq_bench_latency_seconds_bucket{{le=\"{:?}\"}} {}",
+            bound, count
+        );
+    }
+    let _ = writeln!(
+        out,
+        "rq_bench_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+        latencies.len()
+    );
+    let total: Duration = latencies.iter().sum();
+    let _ = writeln!(out, "rq_bench_latency_seconds_sum {}", total.as_secs_f64());
+    let _ = writeln!(out, "rq_bench_latency_seconds_count {}", latencies.len());
+
+    out.push_str("# TYPE rq_bench_duration_seconds gauge\n");
+    out.push_str("# HELP rq_bench_duration_seconds Time taken by the whole benchmark\n");
+    let _ = writeln!(
+        out,
+        "rq_bench_duration_seconds {}",
+        report.elapsed.as_secs_f64()
+    );
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use super::SampleFormat;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|v| Duration::from_millis(*v)).collect()
+    }
+
+    #[test]
+    fn metrics_have_cumulative_buckets() {
+        let sample = |ms, outcome| super::Sample {
+            sent_at: SystemTime::UNIX_EPOCH,
+            latency: Duration::from_millis(ms),
+            outcome,
+        };
+        let report = super::Report {
+            samples: vec![
+                sample(3, Ok(200)),
+                sample(40, Ok(200)),
+                sample(700, Ok(503)),
+                sample(5, Err("timed out".to_string())),
+            ],
+            elapsed: Duration::from_secs(2),
+        };
+        let metrics = super::openmetrics(&report);
+        for line in [
+            "rq_bench_responses_total{status=\"200\"} 2",
+            "rq_bench_responses_total{status=\"503\"} 1",
+            "rq_bench_errors_total 1",
+            "rq_bench_latency_seconds_bucket{le=\"0.005\"} 1",
+            "rq_bench_latency_seconds_bucket{le=\"0.05\"} 2",
+            "rq_bench_latency_seconds_bucket{le=\"1.0\"} 3",
+            "rq_bench_latency_seconds_bucket{le=\"+Inf\"} 3",
+            "rq_bench_latency_seconds_sum 0.743",
+            "rq_bench_latency_seconds_count 3",
+            "rq_bench_duration_seconds 2",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{}", line);
+        }
+        assert!(metrics.ends_with("# EOF\n"));
+    }
+    #[test]
+    fn samples_are_exported_in_the_order_they_were_sent() {
+        let sample = |secs, outcome| super::Sample {
+            sent_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            latency: Duration::from_micros(1500),
+            outcome,
+        };
+        let report = super::Report {
+            samples: vec![
+                sample(2, Err("error, \"closed\"".to_string())),
+                sample(1, Ok(200)),
+            ],
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(
+            super::samples(&report, SampleFormat::Csv),
+            "timestamp,latency_ms,status,error\n\
+            1970-01-01T00:00:01.000Z,1.500,200,\n\
+            1970-01-01T00:00:02.000Z,1.500,,\"error, \"\"closed\"\"\"\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&super::samples(&report, SampleFormat::Json)).unwrap();
+        assert_eq!(json[0]["status"], 200);
+        assert_eq!(json[1]["error"], "error, \"closed\"");
+        assert_eq!(
+            SampleFormat::from_path(Path::new("out.JSON")),
+            SampleFormat::Json
+        );
+    }
+    #[test]
+    fn percentile_of_empty_list_is_none() {
+        assert_eq!(super::percentile(&[], 50.0), None);
+    }
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let values = ms(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(
+            super::percentile(&values, 50.0),
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(
+            super::percentile(&values, 90.0),
+            Some(Duration::from_millis(9))
+        );
+        assert_eq!(
+            super::percentile(&values, 99.0),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            super::percentile(&values, 0.0),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(
+            super::percentile(&values, 100.0),
+            Some(Duration::from_millis(10))
+        );
+    }
+}
@@ -0,0 +1,511 @@
+//! On-disk cache of GET responses, enabled with `--cache`, following the rules of a private cache
+//! (https://www.rfc-editor.org/rfc/rfc9111).
+//!
+//! Each URL has one entry in the cache directory, named after the SHA-256 of the URL. Fresh
+//! entries are served without contacting the server, stale ones are revalidated with their
+//! `ETag` or `Last-Modified`.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use futures_util::future::{self, BoxFuture, FutureExt};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, AGE, CACHE_CONTROL, CONTENT_LENGTH, DATE, ETAG,
+        EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY,
+    },
+    StatusCode, Version,
+};
+use ring::digest;
+use serde_json::{json, Value};
+
+use crate::{
+    datetime, http::HttpMethod, middleware::Middleware, output, paths, request::Request,
+    response::Response, Result,
+};
+
+/// Statuses that can be cached without explicit freshness information
+/// (https://www.rfc-editor.org/rfc/rfc9110#section-15.1)
+const HEURISTICALLY_CACHEABLE: &[u16] =
+    &[200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// The directives of the `Cache-Control` headers, names lowercased and quotes removed from values
+fn cache_control(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter(|d| !d.trim().is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_ascii_lowercase(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (directive.trim().to_ascii_lowercase(), None),
+        })
+        .collect()
+}
+
+fn has_directive(headers: &HeaderMap, name: &str) -> bool {
+    cache_control(headers).iter().any(|(n, _)| n == name)
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn request_headers(request: &Request) -> HeaderMap {
+    request
+        .headers
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((
+                HeaderName::from_bytes(name.as_bytes()).ok()?,
+                HeaderValue::from_str(value).ok()?,
+            ))
+        })
+        .collect()
+}
+
+/// The values of the request headers the response varies on
+fn vary_values(request: &Request, response_headers: &HeaderMap) -> Vec<(String, String)> {
+    let request_headers = request_headers(request);
+    response_headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let value = request_headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            (name, value)
+        })
+        .collect()
+}
+
+/// Whether the request can be answered from the cache. Requests with their own validators are
+/// left alone, as the caller wants to handle 304 responses itself.
+fn is_cacheable_request(request: &Request) -> bool {
+    let headers = request_headers(request);
+    matches!(request.method, HttpMethod::Get)
+        && request.body.is_none()
+        && request.form.is_empty()
+        && request.upload.is_none()
+        && !headers.contains_key(IF_NONE_MATCH)
+        && !headers.contains_key(IF_MODIFIED_SINCE)
+        && !has_directive(&headers, "no-store")
+}
+
+/// Whether the response to the request can be stored
+fn is_storable(request: &Request, response: &Response) -> bool {
+    let headers = &response.headers;
+    let explicit_freshness = has_directive(headers, "max-age") || headers.contains_key(EXPIRES);
+    let validators = headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED);
+    !has_directive(headers, "no-store")
+        && !vary_values(request, headers)
+            .iter()
+            .any(|(name, _)| name == "*")
+        && (explicit_freshness || HEURISTICALLY_CACHEABLE.contains(&response.status.as_u16()))
+        && (explicit_freshness || validators)
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    url: String,
+    /// Unix timestamp in seconds of when the response was received or last revalidated
+    stored: i64,
+    vary: Vec<(String, String)>,
+    response: Response,
+}
+
+impl Entry {
+    fn new(request: &Request, response: Response, now: i64) -> Self {
+        Entry {
+            url: request.url.clone(),
+            stored: now,
+            vary: vary_values(request, &response.headers),
+            response,
+        }
+    }
+
+    /// How long the response is fresh for, in seconds
+    fn freshness_lifetime(&self) -> i64 {
+        let headers = &self.response.headers;
+        let max_age = cache_control(headers)
+            .into_iter()
+            .find(|(name, _)| name == "max-age")
+            .and_then(|(_, value)| value?.parse().ok());
+        if let Some(max_age) = max_age {
+            return max_age;
+        }
+        let date = header_str(headers, &DATE)
+            .and_then(datetime::parse_http_date)
+            .unwrap_or(self.stored);
+        if let Some(expires) = headers.get(EXPIRES) {
+            // Invalid dates, like `0`, mean already expired
+            return expires
+                .to_str()
+                .ok()
+                .and_then(datetime::parse_http_date)
+                .map_or(0, |expires| expires - date);
+        }
+        // Heuristic freshness: 10% of the time since the last modification
+        match header_str(headers, &LAST_MODIFIED).and_then(datetime::parse_http_date) {
+            Some(modified) if HEURISTICALLY_CACHEABLE.contains(&self.response.status.as_u16()) => {
+                (date - modified).max(0) / 10
+            }
+            _ => 0,
+        }
+    }
+
+    fn age(&self, now: i64) -> i64 {
+        let age = header_str(&self.response.headers, &AGE)
+            .and_then(|age| age.parse().ok())
+            .unwrap_or(0);
+        age + (now - self.stored).max(0)
+    }
+
+    /// Whether the request has the same values as the stored one for the headers in `Vary`
+    fn matches(&self, request: &Request) -> bool {
+        self.vary == vary_values(request, &self.response.headers)
+    }
+
+    /// Whether the entry can be used without revalidation
+    fn is_fresh(&self, request: &Request, now: i64) -> bool {
+        !has_directive(&self.response.headers, "no-cache")
+            && !has_directive(&request_headers(request), "no-cache")
+            && self.age(now) < self.freshness_lifetime()
+    }
+
+    /// The conditional headers to revalidate the entry with
+    fn validators(&self) -> Vec<(HeaderName, String)> {
+        let headers = &self.response.headers;
+        let mut validators = Vec::new();
+        if let Some(etag) = header_str(headers, &ETAG) {
+            validators.push((IF_NONE_MATCH, etag.to_string()));
+        }
+        if let Some(modified) = header_str(headers, &LAST_MODIFIED) {
+            validators.push((IF_MODIFIED_SINCE, modified.to_string()));
+        }
+        validators
+    }
+
+    /// Update the entry with the headers of a 304 response. Its `Content-Length`, if any, is the
+    /// one of an empty body and is ignored.
+    fn freshen(&mut self, not_modified: &Response, now: i64) {
+        for name in not_modified.headers.keys().filter(|n| *n != CONTENT_LENGTH) {
+            let values = not_modified.headers.get_all(name).iter().cloned();
+            self.response.headers.remove(name);
+            for value in values {
+                self.response.headers.append(name, value);
+            }
+        }
+        self.stored = now;
+    }
+
+    fn to_json(&self) -> Value {
+        let headers: Vec<(&str, String)> = self
+            .response
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        json!({
+            "url": self.url,
+            "final_url": self.response.url,
+            "stored": self.stored,
+            "vary": self.vary,
+            "status": self.response.status.as_u16(),
+            "version": format!("{:?}", self.response.version),
+            "headers": headers,
+            "body": base64::encode(&self.response.body),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let pairs = |value: &Value| {
+            value
+                .as_array()?
+                .iter()
+                .map(|h| Some((h[0].as_str()?.to_string(), h[1].as_str()?.to_string())))
+                .collect::<Option<Vec<_>>>()
+        };
+        let headers = pairs(&value["headers"])?
+            .into_iter()
+            .map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(&value).ok()?,
+                ))
+            })
+            .collect::<Option<HeaderMap>>()?;
+        let version = match value["version"].as_str()? {
+            "HTTP/1.0" => Version::HTTP_10,
+            "HTTP/2.0" => Version::HTTP_2,
+            _ => Version::HTTP_11,
+        };
+        Some(Entry {
+            url: value["url"].as_str()?.to_string(),
+            stored: value["stored"].as_i64()?,
+            vary: pairs(&value["vary"])?,
+            response: Response {
+                url: value["final_url"].as_str()?.to_string(),
+                status: StatusCode::from_u16(value["status"].as_u64()? as u16).ok()?,
+                version,
+                headers,
+                body: base64::decode(value["body"].as_str()?).ok()?,
+                wait: Duration::ZERO,
+                receive: Duration::ZERO,
+            },
+        })
+    }
+}
+
+/// The cache of `--cache`, answering the requests with a fresh entry and revalidating the stale
+/// ones. Problems with the cache files are reported but don't fail the request.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            dir: paths::cache_dir(),
+        }
+    }
+}
+
+impl Cache {
+    fn path(&self, url: &str) -> PathBuf {
+        let hash = digest::digest(&digest::SHA256, url.as_bytes());
+        let name: String = hash.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn lookup(&self, url: &str) -> io::Result<Option<Entry>> {
+        let content = match fs::read_to_string(self.path(url)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // A corrupted entry is as good as a missing one, it is replaced on the next store
+        Ok(serde_json::from_str(&content)
+            .ok()
+            .and_then(|value| Entry::from_json(&value))
+            .filter(|entry| entry.url == url))
+    }
+
+    fn store(&self, entry: &Entry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(&entry.url), entry.to_json().to_string())
+    }
+
+    fn remove(&self, url: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(url)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn warn(dir: &Path, e: io::Error) {
+    output::note(format!(
+        "Failed to use the cache in {}: {}",
+        dir.display(),
+        e
+    ));
+}
+
+fn now() -> i64 {
+    datetime::unix_millis(SystemTime::now()).div_euclid(1000)
+}
+
+impl Cache {
+    /// The entry for the request, if any
+    fn entry(&self, request: &Request) -> Option<Entry> {
+        self.lookup(&request.url)
+            .unwrap_or_else(|e| {
+                warn(&self.dir, e);
+                None
+            })
+            .filter(|entry| entry.matches(request))
+    }
+}
+
+impl Middleware for Cache {
+    fn on_request<'a>(
+        &'a self,
+        request: &'a mut Request,
+    ) -> BoxFuture<'a, Result<Option<Response>>> {
+        if !is_cacheable_request(request) {
+            tracing::debug!(url = %request.url, "not cacheable, bypassing the cache");
+            return future::ready(Ok(None)).boxed();
+        }
+        let entry = self.entry(request);
+        if let Some(entry) = entry.as_ref().filter(|e| e.is_fresh(request, now())) {
+            tracing::debug!(url = %request.url, age = entry.age(now()), "fresh in the cache");
+            return future::ready(Ok(Some(entry.response.clone()))).boxed();
+        }
+        match &entry {
+            Some(_) => tracing::debug!(url = %request.url, "stale in the cache, revalidating"),
+            None => tracing::debug!(url = %request.url, "not in the cache"),
+        }
+        for (name, value) in entry.iter().flat_map(Entry::validators) {
+            request.set_header(name.as_str(), value);
+        }
+        future::ready(Ok(None)).boxed()
+    }
+
+    fn on_response<'a>(
+        &'a self,
+        request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, Result<()>> {
+        if !is_cacheable_request(request) {
+            return future::ready(Ok(())).boxed();
+        }
+        let result = match self.entry(request) {
+            Some(mut entry) if response.status == StatusCode::NOT_MODIFIED => {
+                tracing::debug!(url = %request.url, "not modified, serving the cached response");
+                entry.freshen(response, now());
+                let result = self.store(&entry);
+                *response = Response {
+                    wait: response.wait,
+                    receive: response.receive,
+                    ..entry.response
+                };
+                result
+            }
+            _ if is_storable(request, response) => {
+                tracing::debug!(url = %request.url, "storing the response");
+                self.store(&Entry::new(request, response.clone(), now()))
+            }
+            _ => {
+                tracing::debug!(url = %request.url, "response not storable");
+                self.remove(&request.url)
+            }
+        };
+        if let Err(e) = result {
+            warn(&self.dir, e);
+        }
+        future::ready(Ok(())).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use super::Entry;
+    use crate::{http::HttpMethod, multipart, request::Request, response::Response};
+
+    fn request() -> Request {
+        Request {
+            method: HttpMethod::Get,
+            url: "http://example.com/a".to_string(),
+            headers: vec![("Accept-Language".to_string(), "fr".to_string())],
+            body: None,
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Default::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Default::default(),
+        }
+    }
+
+    fn entry(headers: &[(&'static str, &str)], stored: i64) -> Entry {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, value.parse().unwrap());
+        }
+        let response = Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: map,
+            body: b"hello".to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        Entry::new(&request(), response, stored)
+    }
+
+    #[test]
+    fn freshness_comes_from_max_age_expires_or_last_modified() {
+        let e = entry(&[("cache-control", "public, max-age=60")], 1000);
+        assert!(e.is_fresh(&request(), 1059));
+        assert!(!e.is_fresh(&request(), 1060));
+        let e = entry(
+            &[
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                ("expires", "Sun, 06 Nov 1994 08:50:37 GMT"),
+            ],
+            1000,
+        );
+        assert_eq!(e.freshness_lifetime(), 60);
+        let e = entry(&[("expires", "0")], 1000);
+        assert!(!e.is_fresh(&request(), 1000));
+        let e = entry(
+            &[
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                ("last-modified", "Sun, 06 Nov 1994 08:32:57 GMT"),
+            ],
+            1000,
+        );
+        assert_eq!(e.freshness_lifetime(), 100);
+        let e = entry(&[("cache-control", "max-age=60, no-cache")], 1000);
+        assert!(!e.is_fresh(&request(), 1000));
+    }
+    #[test]
+    fn vary_headers_must_match() {
+        let e = entry(
+            &[("cache-control", "max-age=60"), ("vary", "Accept-Language")],
+            0,
+        );
+        assert!(e.matches(&request()));
+        let mut other = request();
+        other.set_header("Accept-Language", "en".to_string());
+        assert!(!e.matches(&other));
+    }
+    #[test]
+    fn entries_round_trip_through_json() {
+        let e = entry(
+            &[
+                ("etag", "\"v1\""),
+                ("set-cookie", "a=1"),
+                ("set-cookie", "b=2"),
+            ],
+            42,
+        );
+        let parsed = Entry::from_json(&e.to_json()).unwrap();
+        assert_eq!(parsed.stored, 42);
+        assert_eq!(parsed.response.body, b"hello");
+        assert_eq!(parsed.response.headers, e.response.headers);
+        assert_eq!(
+            parsed.validators(),
+            vec![(reqwest::header::IF_NONE_MATCH, "\"v1\"".to_string())]
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! The server certificates shown with `--cert-info` and checked with `--cert-expiry-warn`.
+//!
+//! reqwest doesn't expose the TLS session, so the handshake is done again on a separate
+//! connection, as for `--timing`, made with the options of the client. It accepts the
+//! certificates the client accepts, and those that have expired, for `--cert-expiry-warn` to
+//! report them. The certificates are decoded with a small DER reader covering the fields shown,
+//! no X.509 crate is available to the build.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::Url;
+use tokio_rustls::rustls::Session;
+
+use crate::{connect, datetime, Request};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid URL: {0}")]
+    Url(String),
+    #[error("{0} is not an HTTPS URL")]
+    NotHttps(String),
+    #[error("{0}")]
+    Connect(#[from] connect::Error),
+    #[error("The certificate of {0} can't be checked through --unix-socket")]
+    UnixSocket(String),
+    #[error("Invalid certificate: {0}")]
+    Invalid(&'static str),
+    #[error("Invalid expiry window `{0}`, expected days like 30d or hours like 12h")]
+    InvalidWindow(String),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A DER element: its tag and content
+type Element<'a> = (u8, &'a [u8]);
+
+/// Read the DER elements of a constructed value one after the other
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn next(&mut self) -> Result<Element<'a>> {
+        let truncated = || Error::Invalid("truncated DER");
+        let (&tag, rest) = self.0.split_first().ok_or_else(truncated)?;
+        let (&first, rest) = rest.split_first().ok_or_else(truncated)?;
+        let (length, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count > 4 || rest.len() < count {
+                return Err(Error::Invalid("bad DER length"));
+            }
+            let length = rest[..count]
+                .iter()
+                .fold(0, |length, &byte| length << 8 | byte as usize);
+            (length, &rest[count..])
+        };
+        if rest.len() < length {
+            return Err(truncated());
+        }
+        self.0 = &rest[length..];
+        Ok((tag, &rest[..length]))
+    }
+
+    /// The next element with its tag and length, as encoded
+    fn next_encoded(&mut self) -> Result<&'a [u8]> {
+        let start = self.0;
+        self.next()?;
+        Ok(&start[..start.len() - self.0.len()])
+    }
+
+    /// The next element, which must have this tag
+    fn expect(&mut self, tag: u8) -> Result<&'a [u8]> {
+        match self.next()? {
+            (found, content) if found == tag => Ok(content),
+            _ => Err(Error::Invalid("unexpected DER tag")),
+        }
+    }
+
+    /// The next element if it has this tag
+    fn optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>> {
+        match self.0.first() {
+            Some(&found) if found == tag => self.next().map(|(_, content)| Some(content)),
+            _ => Ok(None),
+        }
+    }
+}
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OID: u8 = 0x06;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+
+const ATTRIBUTES: &[(&[u8], &str)] = &[
+    (&[0x55, 0x04, 0x03], "CN"),
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+];
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const EC: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const CURVES: &[(&[u8], &str)] = &[
+    (&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07], "P-256"),
+    (&[0x2b, 0x81, 0x04, 0x00, 0x22], "P-384"),
+    (&[0x2b, 0x81, 0x04, 0x00, 0x23], "P-521"),
+];
+const ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+/// A distinguished name as `CN=example.com, O=Example`
+fn parse_name(name: &[u8]) -> Result<String> {
+    let mut parts = Vec::new();
+    let mut rdns = Der(name);
+    while !rdns.0.is_empty() {
+        let mut attributes = Der(rdns.expect(SET)?);
+        while !attributes.0.is_empty() {
+            let mut attribute = Der(attributes.expect(SEQUENCE)?);
+            let oid = attribute.expect(OID)?;
+            let (_, value) = attribute.next()?;
+            if let Some((_, short)) = ATTRIBUTES.iter().find(|(known, _)| *known == oid) {
+                parts.push(format!("{}={}", short, String::from_utf8_lossy(value)));
+            }
+        }
+    }
+    Ok(parts.join(", "))
+}
+
+/// A UTCTime or GeneralizedTime as a Unix timestamp
+fn parse_time((tag, value): Element) -> Result<i64> {
+    let invalid = || Error::Invalid("bad time");
+    let value = std::str::from_utf8(value).map_err(|_| invalid())?;
+    let value = match tag {
+        // Two digit years are from 1950 to 2049
+        0x17 => match value.get(..2).and_then(|year| year.parse::<u32>().ok()) {
+            Some(year) if year >= 50 => format!("19{}", value),
+            Some(_) => format!("20{}", value),
+            None => return Err(invalid()),
+        },
+        0x18 => value.to_string(),
+        _ => return Err(invalid()),
+    };
+    let digits = value.get(..14).ok_or_else(invalid)?;
+    let rfc3339 = format!(
+        "{}-{}-{}T{}:{}:{}Z",
+        &digits[..4],
+        &digits[4..6],
+        &digits[6..8],
+        &digits[8..10],
+        &digits[10..12],
+        &digits[12..14]
+    );
+    datetime::parse_rfc3339(&rfc3339).ok_or_else(invalid)
+}
+
+/// The algorithm and size of the public key, e.g. `RSA 2048 bits`
+fn parse_key_type(spki: &[u8]) -> Result<String> {
+    let mut spki = Der(spki);
+    let mut algorithm = Der(spki.expect(SEQUENCE)?);
+    let oid = algorithm.expect(OID)?;
+    let key = spki.expect(BIT_STRING)?;
+    Ok(if oid == RSA {
+        // The bit string starts with the number of unused bits
+        let mut key = Der(Der(key.get(1..).unwrap_or_default()).expect(SEQUENCE)?);
+        let modulus = key.expect(INTEGER)?;
+        let modulus = match modulus.iter().position(|&byte| byte != 0) {
+            Some(start) => &modulus[start..],
+            None => &[],
+        };
+        let bits = modulus.len() * 8 - modulus.first().map_or(0, |b| b.leading_zeros() as usize);
+        format!("RSA {} bits", bits)
+    } else if oid == EC {
+        let curve = algorithm.optional(OID)?.unwrap_or_default();
+        match CURVES.iter().find(|(known, _)| *known == curve) {
+            Some((_, name)) => format!("EC {}", name),
+            None => "EC".to_string(),
+        }
+    } else if oid == ED25519 {
+        "Ed25519".to_string()
+    } else {
+        "unknown".to_string()
+    })
+}
+
+/// The DNS names and IP addresses of the subject alternative name extension
+fn parse_alt_names(names: &[u8]) -> Result<Vec<String>> {
+    let mut alt_names = Vec::new();
+    let mut names = Der(Der(names).expect(SEQUENCE)?);
+    while !names.0.is_empty() {
+        match names.next()? {
+            (0x82, name) => alt_names.push(format!("DNS:{}", String::from_utf8_lossy(name))),
+            (0x87, &[a, b, c, d]) => alt_names.push(format!("IP:{}.{}.{}.{}", a, b, c, d)),
+            (0x87, ip) if ip.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(ip);
+                alt_names.push(format!("IP:{}", std::net::Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+    }
+    Ok(alt_names)
+}
+
+/// The fields of a certificate shown with `--cert-info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub alt_names: Vec<String>,
+    /// The validity window, as Unix timestamps
+    pub not_before: i64,
+    pub not_after: i64,
+    pub key_type: String,
+}
+
+impl Certificate {
+    /// Decode a DER encoded X.509 certificate
+    pub fn parse(der: &[u8]) -> Result<Self> {
+        let mut certificate = Der(Der(der).expect(SEQUENCE)?);
+        let mut tbs = Der(certificate.expect(SEQUENCE)?);
+        tbs.optional(0xa0)?;
+        tbs.expect(INTEGER)?;
+        tbs.expect(SEQUENCE)?;
+        let issuer = parse_name(tbs.expect(SEQUENCE)?)?;
+        let mut validity = Der(tbs.expect(SEQUENCE)?);
+        let not_before = parse_time(validity.next()?)?;
+        let not_after = parse_time(validity.next()?)?;
+        let subject = parse_name(tbs.expect(SEQUENCE)?)?;
+        let key_type = parse_key_type(tbs.expect(SEQUENCE)?)?;
+        tbs.optional(0x81)?;
+        tbs.optional(0x82)?;
+        let mut alt_names = Vec::new();
+        if let Some(extensions) = tbs.optional(0xa3)? {
+            let mut extensions = Der(Der(extensions).expect(SEQUENCE)?);
+            while !extensions.0.is_empty() {
+                let mut extension = Der(extensions.expect(SEQUENCE)?);
+                let oid = extension.expect(OID)?;
+                // The critical flag is optional
+                extension.optional(0x01)?;
+                let value = extension.expect(OCTET_STRING)?;
+                if oid == SUBJECT_ALT_NAME {
+                    alt_names = parse_alt_names(value)?;
+                }
+            }
+        }
+        Ok(Certificate {
+            subject,
+            issuer,
+            alt_names,
+            not_before,
+            not_after,
+            key_type,
+        })
+    }
+
+    /// The DER encoded SubjectPublicKeyInfo of the certificate, which public key pins are hashes
+    /// of
+    pub fn public_key_info(der: &[u8]) -> Result<&[u8]> {
+        let mut certificate = Der(Der(der).expect(SEQUENCE)?);
+        let mut tbs = Der(certificate.expect(SEQUENCE)?);
+        tbs.optional(0xa0)?;
+        // Serial number, signature algorithm, issuer, validity and subject
+        for _ in 0..5 {
+            tbs.next()?;
+        }
+        tbs.next_encoded()
+    }
+
+    /// The seconds left before the certificate expires, negative once it has
+    pub fn expires_in(&self, now: SystemTime) -> i64 {
+        self.not_after - datetime::unix_millis(now).div_euclid(1000)
+    }
+}
+
+/// What was negotiated with the server and the certificates it sent, leaf first
+#[derive(Debug)]
+pub struct TlsInfo {
+    pub version: String,
+    pub cipher: String,
+    pub chain: Vec<Certificate>,
+}
+
+/// Connect to the host of the HTTPS request as the client would and read what the handshake
+/// negotiated
+pub async fn probe(request: &Request) -> Result<TlsInfo> {
+    let url = Url::parse(&request.url).map_err(|_| Error::Url(request.url.clone()))?;
+    if url.scheme() != "https" {
+        return Err(Error::NotHttps(request.url.clone()));
+    }
+    if request.unix_socket.is_some() {
+        return Err(Error::UnixSocket(request.url.clone()));
+    }
+    let options = request.connect.clone().unwrap_or_default();
+    let stream = options.probe(&url).await?;
+    let session = stream.get_ref().1;
+    let chain = session
+        .get_peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .map(|certificate| Certificate::parse(&certificate.0))
+        .collect::<Result<_>>()?;
+    Ok(TlsInfo {
+        version: session
+            .get_protocol_version()
+            .map_or_else(|| "unknown".to_string(), |v| format!("{:?}", v)),
+        cipher: session
+            .get_negotiated_ciphersuite()
+            .map_or_else(|| "unknown".to_string(), |c| format!("{:?}", c.suite)),
+        chain,
+    })
+}
+
+fn format_time(secs: i64) -> String {
+    datetime::format_rfc3339_millis(secs * 1000)
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "protocol  {}", self.version)?;
+        write!(f, "cipher    {}", self.cipher)?;
+        for (i, certificate) in self.chain.iter().enumerate() {
+            write!(f, "\ncertificate {}", i)?;
+            write!(f, "\n  subject   {}", certificate.subject)?;
+            write!(f, "\n  issuer    {}", certificate.issuer)?;
+            if !certificate.alt_names.is_empty() {
+                write!(f, "\n  names     {}", certificate.alt_names.join(", "))?;
+            }
+            write!(
+                f,
+                "\n  valid     {} to {}",
+                format_time(certificate.not_before),
+                format_time(certificate.not_after)
+            )?;
+            write!(f, "\n  key       {}", certificate.key_type)?;
+        }
+        Ok(())
+    }
+}
+
+/// How long before the expiry of the certificate `--cert-expiry-warn` fails, as `30d` or `12h`.
+/// A plain number is a number of days.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExpiryWindow(pub Duration);
+
+impl FromStr for ExpiryWindow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (number, unit) = match s.strip_suffix('h') {
+            Some(hours) => (hours, 3600),
+            None => (s.strip_suffix('d').unwrap_or(s), 86_400),
+        };
+        let number: u64 = number
+            .parse()
+            .map_err(|_| Error::InvalidWindow(s.to_string()))?;
+        Ok(ExpiryWindow(Duration::from_secs(number * unit)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Certificate, ExpiryWindow};
+
+    /// A self-signed P-256 certificate for example.com, valid from 2026-10-15 for a year
+    const CERTIFICATE: &str =
+        "MIIB1jCCAXygAwIBAgIUJw2pvwU66c/bpze3AY43pthPc/cwCgYIKoZIzj0EAwIwKDEUMBIGA1UEAwwL\
+        ZXhhbXBsZS5jb20xEDAOBgNVBAoMB0V4YW1wbGUwHhcNMjYxMDE1MTAzMDUzWhcNMjcxMDE1MTAzMDUzWjAoMRQw\
+        EgYDVQQDDAtleGFtcGxlLmNvbTEQMA4GA1UECgwHRXhhbXBsZTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABLLs\
+        yzlbk+/P2G5RBrYV7PbY/iBuDXUvY5ndenzekWHU9yjTcE6IFgQ/YeyK/CD+ZE8bwTgyBiIPMIo4Q2s8XOijgYMw\
+        gYAwHQYDVR0OBBYEFPxAN5p1sieFE01s7k8J2hEeQs9JMB8GA1UdIwQYMBaAFPxAN5p1sieFE01s7k8J2hEeQs9J\
+        MA8GA1UdEwEB/wQFMAMBAf8wLQYDVR0RBCYwJIILZXhhbXBsZS5jb22CD3d3dy5leGFtcGxlLmNvbYcEfwAAATAK\
+        BggqhkjOPQQDAgNIADBFAiARYZidvhIUvP3gGtaMSg63RxjzSkeXdkiDBUw+ND6QDAIhAJc6SvkTLugbFHdw5TUt\
+        6+YE94KJh4Ve/Wn+AaeCpRR1";
+
+    #[test]
+    fn certificate_fields_are_decoded() {
+        let der = base64::decode(CERTIFICATE).unwrap();
+        let certificate = Certificate::parse(&der).unwrap();
+        assert_eq!(certificate.subject, "CN=example.com, O=Example");
+        assert_eq!(certificate.issuer, certificate.subject);
+        assert_eq!(
+            certificate.alt_names,
+            vec!["DNS:example.com", "DNS:www.example.com", "IP:127.0.0.1"]
+        );
+        assert_eq!(certificate.not_before, 1_792_060_253);
+        assert_eq!(certificate.not_after - certificate.not_before, 365 * 86_400);
+        assert_eq!(certificate.key_type, "EC P-256");
+        let now = UNIX_EPOCH + Duration::from_secs(1_792_060_253);
+        assert_eq!(certificate.expires_in(now), 365 * 86_400);
+        assert!(Certificate::parse(&der[..100]).is_err());
+        let spki = Certificate::public_key_info(&der).unwrap();
+        assert_eq!((spki[0], spki.len()), (0x30, 91));
+    }
+    #[test]
+    fn expiry_windows_are_in_days_or_hours() {
+        let days = |n: u64| ExpiryWindow(Duration::from_secs(n * 86_400));
+        assert_eq!("30d".parse::<ExpiryWindow>().unwrap(), days(30));
+        assert_eq!("7".parse::<ExpiryWindow>().unwrap(), days(7));
+        assert_eq!(
+            "12h".parse::<ExpiryWindow>().unwrap(),
+            ExpiryWindow(Duration::from_secs(12 * 3600))
+        );
+        assert!("soon".parse::<ExpiryWindow>().is_err());
+    }
+}
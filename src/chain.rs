@@ -0,0 +1,247 @@
+//! `rq chain`: requests sent one after the other, where values captured from a response can be
+//! used by the following requests.
+//!
+//! ```yaml
+//! vars:
+//!   base: https://api.example.com
+//! steps:
+//!   - method: POST
+//!     url: "{{base}}/login"
+//!     body: {user: admin, password: secret}
+//!     capture:
+//!       token: $.access_token
+//!   - url: "{{base}}/me"
+//!     headers:
+//!       Authorization: Bearer {{token}}
+//! ```
+//!
+//! The captures can also be listed as `name=$.path`, e.g. `capture: [token=$.access_token]`.
+//! The steps are written as the requests of collections. The chain stops at the first request
+//! that fails or whose capture finds nothing.
+use std::{collections::HashMap, fs, io, path::Path, str::FromStr};
+
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::{collection::Template, fetch, jsonpath::JsonPath, middleware::Pipeline, output, yaml};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Yaml(#[from] yaml::Error),
+    #[error("Invalid chain: {0}")]
+    Invalid(String),
+    #[error("Invalid capture `{0}`, expected `name=$.path`")]
+    InvalidCapture(String),
+    #[error("Step {step}: nothing to capture at {path} for `{name}`")]
+    NothingCaptured {
+        step: usize,
+        name: String,
+        path: String,
+    },
+    #[error("Step {step}: {message}")]
+    Failed { step: usize, message: String },
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A value of the JSON response saved in a variable, given as `name=$.path`
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub name: String,
+    text: String,
+    path: JsonPath,
+}
+
+impl FromStr for Capture {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidCapture(s.to_string());
+        let (name, path) = s.split_once('=').ok_or_else(invalid)?;
+        if name.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok(Capture {
+            name: name.trim().to_string(),
+            text: path.trim().to_string(),
+            path: path.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Capture {
+    /// The captured value: strings as they are, other values as JSON
+    pub fn get(&self, body: &[u8]) -> Option<String> {
+        let json: Value = serde_json::from_slice(body).ok()?;
+        match self.path.get(&json)? {
+            Value::String(s) => Some(s.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    template: Template,
+    captures: Vec<Capture>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chain {
+    vars: HashMap<String, String>,
+    steps: Vec<Step>,
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+impl Chain {
+    pub fn from_value(value: &Value) -> Result<Self> {
+        let invalid = |message: &str| Error::Invalid(message.to_string());
+        let vars = match &value["vars"] {
+            Value::Null => HashMap::new(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(k, v)| {
+                    let v = scalar_to_string(v)
+                        .ok_or_else(|| invalid("`vars` must be a mapping of strings"))?;
+                    Ok((k.clone(), v))
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid("`vars` must be a mapping")),
+        };
+        let steps = value["steps"]
+            .as_array()
+            .ok_or_else(|| invalid("`steps` is required and must be a sequence"))?
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let name = format!("step {}", i + 1);
+                let template =
+                    Template::from_value(&name, step).map_err(|e| Error::Invalid(e.to_string()))?;
+                let captures = match &step["capture"] {
+                    Value::Null => Vec::new(),
+                    Value::Object(map) => map
+                        .iter()
+                        .map(|(name, path)| {
+                            let path = path.as_str().ok_or_else(|| {
+                                invalid("`capture` must be a mapping of JSONPaths")
+                            })?;
+                            format!("{}={}", name, path).parse()
+                        })
+                        .collect::<Result<_>>()?,
+                    // The same as on the command line: `- token=$.access_token`
+                    Value::Array(items) => items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .ok_or_else(|| invalid("`capture` items must be `name=$.path`"))?
+                                .parse()
+                        })
+                        .collect::<Result<_>>()?,
+                    _ => return Err(invalid("`capture` must be a mapping or a sequence")),
+                };
+                Ok(Step { template, captures })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Chain { vars, steps })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Chain::from_value(&yaml::parse(&fs::read_to_string(path)?)?)
+    }
+
+    /// Send the steps in order, printing the body of each response. `vars` override the
+    /// variables of the chain.
+    pub async fn run(
+        &self,
+        client: &Client,
+        pipeline: &Pipeline,
+        vars: &HashMap<String, String>,
+        raw: bool,
+    ) -> Result<()> {
+        let mut vars: HashMap<_, _> = self
+            .vars
+            .iter()
+            .chain(vars)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        for (i, step) in self.steps.iter().enumerate() {
+            let failed = |message: String| Error::Failed {
+                step: i + 1,
+                message,
+            };
+            let mut request = step
+                .template
+                .render(&vars)
+                .map_err(|e| failed(e.to_string()))?;
+            request.pipeline = pipeline.clone();
+            let response = request
+                .send(client)
+                .await
+                .map_err(|e| failed(e.to_string()))?;
+            eprintln!("{} {} -> {}", request.method, request.url, response.status);
+            print!(
+                "{}",
+                output::format_body(&response, None, !raw, fetch::use_color())
+            );
+            for capture in &step.captures {
+                let value = capture
+                    .get(&response.body)
+                    .ok_or_else(|| Error::NothingCaptured {
+                        step: i + 1,
+                        name: capture.name.clone(),
+                        path: capture.text.clone(),
+                    })?;
+                vars.insert(capture.name.clone(), value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capture, Chain};
+
+    #[test]
+    fn captures_are_strings_or_json() {
+        let capture: Capture = "token = $.auth.token".parse().unwrap();
+        assert_eq!(capture.name, "token");
+        let body = br#"{"auth": {"token": "abc", "ttl": 60}}"#;
+        assert_eq!(capture.get(body), Some("abc".to_string()));
+        let capture: Capture = "ttl=$.auth.ttl".parse().unwrap();
+        assert_eq!(capture.get(body), Some("60".to_string()));
+        assert_eq!(capture.get(b"not json"), None);
+        assert!("$.token".parse::<Capture>().is_err());
+    }
+    #[test]
+    fn steps_are_read_from_yaml() {
+        let value = crate::yaml::parse(
+            r#"
+steps:
+  - url: example.com/login
+    capture:
+      token: $.token
+  - url: example.com/me
+    capture:
+      - id=$.id
+"#,
+        )
+        .unwrap();
+        let chain = Chain::from_value(&value).unwrap();
+        assert_eq!(chain.steps.len(), 2);
+        assert_eq!(chain.steps[0].captures[0].name, "token");
+        assert_eq!(chain.steps[1].captures[0].name, "id");
+        assert!(Chain::from_value(&serde_json::json!({"vars": {}})).is_err());
+    }
+}
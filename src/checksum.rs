@@ -0,0 +1,131 @@
+//! Digests of the downloaded bodies, printed with `--checksum` and compared with
+//! `--verify-checksum`.
+use std::{fmt, str::FromStr};
+
+use ring::digest;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown checksum algorithm `{0}`, expected sha1, sha256, sha384 or sha512")]
+    UnknownAlgorithm(String),
+    #[error("Invalid checksum `{0}`, expected `algorithm:hex`, e.g. sha256:2cf24d...")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha1" => Ok(Algorithm::Sha1),
+            "sha256" => Ok(Algorithm::Sha256),
+            "sha384" => Ok(Algorithm::Sha384),
+            "sha512" => Ok(Algorithm::Sha512),
+            _ => Err(Error::UnknownAlgorithm(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+        })
+    }
+}
+
+impl Algorithm {
+    /// The digest of `bytes` in lowercase hexadecimal
+    pub fn hex_digest(self, bytes: &[u8]) -> String {
+        let algorithm = match self {
+            Algorithm::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            Algorithm::Sha256 => &digest::SHA256,
+            Algorithm::Sha384 => &digest::SHA384,
+            Algorithm::Sha512 => &digest::SHA512,
+        };
+        digest::digest(algorithm, bytes)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// The digest a body must have, given as `algorithm:hex`
+#[derive(Debug, Clone)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub hex: String,
+}
+
+impl FromStr for Checksum {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, hex) = s
+            .split_once(':')
+            .ok_or_else(|| Error::Invalid(s.to_string()))?;
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::Invalid(s.to_string()));
+        }
+        Ok(Checksum {
+            algorithm: algorithm.parse()?,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl Checksum {
+    /// The actual digest of the body when it doesn't match
+    pub fn verify(&self, body: &[u8]) -> Result<(), String> {
+        let actual = self.algorithm.hex_digest(body);
+        if actual == self.hex {
+            Ok(())
+        } else {
+            Err(actual)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, Checksum};
+
+    #[test]
+    fn digests_are_hexadecimal() {
+        assert_eq!(
+            Algorithm::Sha256.hex_digest(b"hello"),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            Algorithm::Sha1.hex_digest(b"hello"),
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+        );
+        assert!("md5".parse::<Algorithm>().is_err());
+    }
+    #[test]
+    fn checksums_are_verified() {
+        let checksum: Checksum =
+            "SHA-256:2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824"
+                .parse()
+                .unwrap();
+        assert!(checksum.verify(b"hello").is_ok());
+        assert_eq!(
+            checksum.verify(b"").unwrap_err(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!("sha256".parse::<Checksum>().is_err());
+        assert!("sha256:xyz".parse::<Checksum>().is_err());
+    }
+}
@@ -1,18 +1,65 @@
-use structopt::StructOpt;
+use std::{collections::HashMap, mem, net::IpAddr, path::PathBuf};
 
-use crate::http::{guess_content_type, ContentType, HttpMethod};
+use reqwest::Url;
+use structopt::{
+    clap::{self, AppSettings},
+    StructOpt,
+};
+
+use crate::{
+    alias,
+    assert::{JsonAssertion, Status},
+    bench::MetricsFormat,
+    cert::ExpiryWindow,
+    checksum::{Algorithm, Checksum},
+    compression::{AcceptEncoding, Coding},
+    conditional::{self, TimeCond},
+    cookie::Cookie,
+    dns::DnsServer,
+    filter::Filter,
+    glob,
+    http::{
+        guess_content_type, merge_data, ApiKeyIn, AuthType, BasicAuth, ContentType, Header,
+        HttpMethod, Resolve, UserAgentPreset,
+    },
+    interface,
+    jsonpath::JsonPath,
+    jwt, logging,
+    middleware::Pipeline,
+    multipart::Part,
+    output::{self, Format, Print, Verbosity},
+    pipe::Pipe,
+    range::ByteRange,
+    report,
+    response::Charset,
+    scraper::Selector,
+    size::ByteSize,
+    template::{self, Var},
+    tls,
+    upload::Upload,
+    uri_template,
+    watch::Interval,
+};
 
 #[derive(Debug, Clone, StructOpt)]
-#[structopt(rename_all = "kebab-case")]
+#[structopt(rename_all = "kebab-case", setting = AppSettings::SubcommandsNegateReqs)]
 pub struct CliArgs {
     #[structopt(
         short = "m",
-        long,
-        default_value = "GET",
-        help = "The HTTP method to use (case-insensitive). \
-        Supported methods: GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS"
+        long = "method",
+        help = "The HTTP method to use (case-insensitive), POST if a body is given and GET \
+        otherwise. Supported methods: GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS"
     )]
+    pub(crate) method_arg: Option<HttpMethod>,
+    /// The method given with `--method` or the verb subcommand, or the default one
+    #[structopt(skip = HttpMethod::Get)]
     pub method: HttpMethod,
+    #[structopt(
+        long,
+        help = "Send PUT, PATCH, DELETE and OPTIONS requests as POST with the method in the \
+        X-HTTP-Method-Override header, for servers behind proxies blocking these methods"
+    )]
+    pub method_override: bool,
     #[structopt(
         short = "t",
         long = "type",
@@ -21,29 +68,1551 @@ pub struct CliArgs {
                 - json: for application/json\n\
                 - form: for application/x-www-form-urlencoded\n\
                 - multipart: for multipart/form-data\n\
+                - msgpack: for application/msgpack, the JSON body is sent as MessagePack\n\
                 By default the content type will be guessed based on the request body,
                 but this guess may not be correct, so specifying the content type explicitly \
                 is recommended."
     )]
     pub content_type: Option<ContentType>,
-    #[structopt(short, long, help = "The request body")]
+    #[structopt(
+        short = "d",
+        long = "data",
+        number_of_values = 1,
+        help = "The request body. Can be repeated: like curl, the values are joined with `&`, \
+        and `key=value` items become a JSON object with `--type json`, nested with \
+        `user[name]=bob` or `tags[]=a`. `key:=json` items have a JSON value, or one read from \
+        `key:=@file.json`, and make the body JSON"
+    )]
+    pub(crate) data_items: Vec<String>,
+    /// The body merged from the `--data` items
+    #[structopt(skip)]
     pub data: Option<String>,
+    #[structopt(
+        short = "F",
+        long = "form",
+        number_of_values = 1,
+        conflicts_with = "data-items",
+        help = "Add a field to a multipart/form-data body: `name=value`, or `name@path` to send a \
+        file. Append `;type=mime/type` or `;filename=name` to override the part's content type \
+        or file name. Can be repeated"
+    )]
+    pub form: Vec<Part>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["data-items", "form"],
+        help = "Stream the request body from a file with `@path`, or from stdin with `@-`, \
+        using chunked transfer encoding instead of reading it into memory. The content type of \
+        a file is guessed from its extension or its first bytes unless given"
+    )]
+    pub data_binary: Option<Upload>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["data-binary", "form"],
+        help = "Write the request body in $VISUAL or $EDITOR, starting from the body given with \
+        -d, replayed from the history or generated from the OpenAPI schema"
+    )]
+    pub edit: bool,
+    #[structopt(
+        long,
+        requires = "data-binary",
+        help = "Send the `--data-binary` body with a Content-Length instead of chunked. \
+        Data from stdin is then read into memory first"
+    )]
+    pub content_length: bool,
+    #[structopt(
+        long,
+        require_equals = true,
+        help = "Compress the request body and set Content-Encoding: `--compress` or \
+        `--compress=gzip` for gzip, `--compress=br` for brotli"
+    )]
+    pub compress: Option<Option<Coding>>,
+    #[structopt(
+        long,
+        help = "Keep compressed responses as received instead of decompressing them, e.g. to \
+        save a .gz file intact"
+    )]
+    pub no_decompress: bool,
+    #[structopt(
+        long,
+        help = "The content codings to accept for the response, e.g. `gzip,br` or `identity`. \
+        deflate responses can only be received with --no-decompress"
+    )]
+    pub accept_encoding: Option<AcceptEncoding>,
+    #[structopt(
+        short = "A",
+        long,
+        help = "Value for the User-Agent header, instead of `rq/<version>`"
+    )]
+    pub user_agent: Option<String>,
+    #[structopt(
+        long,
+        conflicts_with = "user-agent",
+        help = "Send the User-Agent of a common client: chrome, firefox, safari, curl or rq"
+    )]
+    pub ua: Option<UserAgentPreset>,
+    #[structopt(long, help = "Save the ETag of the response to this file")]
+    pub etag_save: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Send If-None-Match with the ETag saved in this file. A 304 Not Modified \
+        response is a success and leaves the output untouched"
+    )]
+    pub etag_compare: Option<PathBuf>,
+    /// The ETag read from the `--etag-compare` file
+    #[structopt(skip)]
+    pub if_none_match: Option<String>,
+    #[structopt(
+        long,
+        help = "Send If-Modified-Since with this date (e.g. 2021-10-01T12:30:00Z) or the \
+        modification time of this file"
+    )]
+    pub time_cond: Option<TimeCond>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Ask for this range of bytes only: FIRST-LAST, FIRST- to the end or -LENGTH for \
+        the end, e.g. 0-1023. Can be repeated for several ranges. A warning is printed unless \
+        the server answers with 206 Partial Content"
+    )]
+    pub range: Vec<ByteRange>,
+    #[structopt(
+        long,
+        help = "Cache GET responses on disk: fresh responses are served without contacting the \
+        server and stale ones are revalidated, following Cache-Control, ETag and Last-Modified"
+    )]
+    pub cache: bool,
+    /// The middlewares the requests are sent through, enabled by the other options
+    #[structopt(skip)]
+    pub pipeline: Pipeline,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Connect to this address instead of resolving the host, in the form \
+        `host:port:address`, keeping the Host header and TLS server name. The override applies \
+        to every port of the host. Can be repeated"
+    )]
+    pub resolve: Vec<Resolve>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["bench", "sse"],
+        help = "Send the requests over this Unix domain socket instead of TCP, e.g. \
+        /var/run/docker.sock. The URL still gives the path and the Host header"
+    )]
+    pub unix_socket: Option<PathBuf>,
+    #[structopt(
+        long = "expect100",
+        conflicts_with = "unix-socket",
+        help = "Send `Expect: 100-continue` and wait for the server to accept the request before \
+        sending the body, so that an upload it rejects is not sent for nothing"
+    )]
+    pub expect_continue: bool,
+    #[structopt(
+        long,
+        help = "Keep POST when following a 301 redirect instead of switching to GET"
+    )]
+    pub post301: bool,
+    #[structopt(
+        long,
+        help = "Keep POST when following a 302 redirect instead of switching to GET"
+    )]
+    pub post302: bool,
+    #[structopt(
+        long,
+        help = "Keep POST when following a 303 redirect instead of switching to GET"
+    )]
+    pub post303: bool,
+    #[structopt(
+        long,
+        help = "Send the Authorization and Cookie headers to other hosts when following \
+        redirects. They are only sent to the original origin by default"
+    )]
+    pub location_trusted: bool,
+    #[structopt(
+        long,
+        help = "Abort the transfer once the response body is larger than this, e.g. 10MB, and \
+        exit with status 4"
+    )]
+    pub max_response_size: Option<ByteSize>,
+    #[structopt(
+        long,
+        help = "Send and receive no more than this many bytes per second, e.g. 500K or 1M, so \
+        that large transfers leave some bandwidth"
+    )]
+    pub limit_rate: Option<ByteSize>,
+    #[structopt(
+        long,
+        conflicts_with = "unix-socket",
+        help = "Log the bytes sent and received with their time to this file, `-` for stderr, as \
+        `curl --trace`. Bodies are logged up to 4 KiB, as a hex dump if they are binary. Redirects \
+        are not followed and responses not decompressed"
+    )]
+    pub trace: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Close the connections kept alive after this many idle seconds (default: 90)"
+    )]
+    pub pool_idle_timeout: Option<f64>,
+    #[structopt(
+        long,
+        help = "Maximum number of idle connections kept alive per host (default: unlimited)"
+    )]
+    pub pool_max_idle_per_host: Option<usize>,
+    #[structopt(
+        long,
+        help = "Send TCP keep-alive probes after the connection is idle for this many seconds"
+    )]
+    pub tcp_keepalive: Option<f64>,
+    #[structopt(
+        long,
+        help = "Whether to disable Nagle's algorithm and send small packets right away: true \
+        (the default) or false"
+    )]
+    pub tcp_nodelay: Option<bool>,
+    #[structopt(
+        long,
+        help = "Print the negotiated TLS version and cipher and the certificate chain of the \
+        server to stderr"
+    )]
+    pub cert_info: bool,
+    #[structopt(
+        long,
+        help = "Exit with status 5 if the certificate of the server expires within this \
+        window, e.g. 30d or 12h"
+    )]
+    pub cert_expiry_warn: Option<ExpiryWindow>,
+    #[structopt(
+        long,
+        help = "Minimum TLS version to accept: 1.2 or 1.3. Older versions are not implemented"
+    )]
+    pub tls_min: Option<tls::Version>,
+    #[structopt(long, help = "Maximum TLS version to accept: 1.2 or 1.3")]
+    pub tls_max: Option<tls::Version>,
+    #[structopt(
+        long = "pinnedpubkey",
+        help = "Only accept servers whose public key has one of these SHA-256 hashes, given as \
+        `sha256//BASE64` separated by `;`"
+    )]
+    pub pinned_pubkey: Option<tls::PinnedKeys>,
+    #[structopt(long, help = "Send the requests from this local IP address")]
+    pub local_address: Option<IpAddr>,
+    #[structopt(
+        long,
+        conflicts_with = "local-address",
+        help = "Send the requests from the address of this network interface, e.g. eth1"
+    )]
+    pub interface: Option<String>,
+    #[structopt(
+        short = "4",
+        long,
+        conflicts_with_all = &["ipv6", "local-address"],
+        help = "Only connect to IPv4 addresses"
+    )]
+    pub ipv4: bool,
+    #[structopt(
+        short = "6",
+        long,
+        conflicts_with = "local-address",
+        help = "Only connect to IPv6 addresses"
+    )]
+    pub ipv6: bool,
+    #[structopt(
+        long,
+        conflicts_with_all = &["doh-url", "unix-socket"],
+        help = "Resolve the hosts with this DNS server instead of the system resolver, in the \
+        form `ip` or `ip:port`, e.g. 1.1.1.1"
+    )]
+    pub dns_server: Option<DnsServer>,
+    #[structopt(
+        long,
+        conflicts_with = "unix-socket",
+        help = "Resolve the hosts with this DNS over HTTPS server instead of the system \
+        resolver, e.g. https://cloudflare-dns.com/dns-query"
+    )]
+    pub doh_url: Option<Url>,
+    #[structopt(
+        short = "H",
+        long = "header",
+        number_of_values = 1,
+        help = "Add a header to the request, in the form `Name: value`. Can be repeated"
+    )]
+    pub headers: Vec<Header>,
+    #[structopt(
+        long,
+        help = "Send the headers, credentials, API key and cookies kept in this session, and keep \
+        the ones of this request in it. See `rq session`"
+    )]
+    pub session: Option<String>,
+    #[structopt(
+        long,
+        help = "Fill the `{{name}}` placeholders of `rq run`, `rq chain` and `rq test` with the \
+        variables of this environment. See `rq env`"
+    )]
+    pub env: Option<String>,
+    #[structopt(
+        long,
+        help = "Read variables from this .env file instead of ./.env, for the `{{NAME}}` \
+        placeholders and the `{{NAME}}`, `$NAME` and `${NAME}` references in headers and bodies"
+    )]
+    pub env_file: Option<PathBuf>,
+    #[structopt(
+        short,
+        long,
+        help = "Credentials for basic authentication, in the form `user:password`"
+    )]
+    pub auth: Option<BasicAuth>,
+    #[structopt(
+        long,
+        help = "Authenticate to the proxy set by http_proxy, https_proxy or all_proxy with these \
+        credentials, `user:password`, using basic authentication"
+    )]
+    pub proxy_user: Option<BasicAuth>,
+    #[structopt(
+        long,
+        default_value = "basic",
+        help = "How to authenticate: `basic` with --auth, or `negotiate` for Kerberos (SPNEGO) \
+        with the tickets obtained with `kinit`"
+    )]
+    pub auth_type: AuthType,
+    #[structopt(
+        long,
+        env = "RQ_API_KEY",
+        hide_env_values = true,
+        help = "An API key to send with the requests, where --api-key-in says. Read from \
+        $RQ_API_KEY when not given, to keep it off the command line"
+    )]
+    pub api_key: Option<String>,
+    #[structopt(
+        long,
+        default_value = "header:X-Api-Key",
+        help = "Where to send the API key: `header:NAME` or `query:NAME`, e.g. `query:api_key`"
+    )]
+    pub api_key_in: ApiKeyIn,
+    #[structopt(
+        long,
+        conflicts_with = "auth",
+        help = "Sign a JSON Web Token valid for 5 minutes with this key file, and send it as the \
+        bearer token. A PEM private key for RS256 and ES256, the secret for HS256"
+    )]
+    pub jwt_sign: Option<PathBuf>,
+    #[structopt(
+        long,
+        requires = "jwt-sign",
+        help = "The claims of the JWT, a JSON object, e.g. `{\"iss\":\"me\",\"aud\":\"api\"}`. \
+        `iat` and `exp` are added unless given"
+    )]
+    pub jwt_claims: Option<String>,
+    #[structopt(
+        long,
+        requires = "jwt-sign",
+        help = "The algorithm signing the JWT: RS256 (default), ES256 or HS256"
+    )]
+    pub jwt_alg: Option<jwt::Algorithm>,
+    #[structopt(
+        short = "b",
+        long = "cookie",
+        number_of_values = 1,
+        help = "Send a cookie, in the form `name=value`, or the cookies of a Netscape cookie file \
+        matching the URL with `@file`. Can be repeated"
+    )]
+    pub cookies: Vec<Cookie>,
+    #[structopt(
+        long,
+        help = "Read the URLs to fetch from this file, one per line. Use `-` to read from stdin"
+    )]
+    pub url_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["url-file", "output", "bench", "sse", "watch", "tui", "ndjson"],
+        help = "Download each URL read from stdin, one per line, into --output-dir (or the current \
+        directory) as they are read, then print a table of the successes and failures. For lists \
+        too long to hold in memory"
+    )]
+    pub stdin_urls: bool,
+    #[structopt(
+        long,
+        help = "Send the URLs as given instead of percent-encoding the spaces, non-ASCII and \
+        other characters not allowed in their path, query and fragment"
+    )]
+    pub no_url_escape: bool,
+    #[structopt(
+        long = "var",
+        number_of_values = 1,
+        help = "Value for a variable of the URLs, in the form `name=value`. The URLs are then RFC \
+        6570 templates, e.g. `example.com/users/{id}/posts{?page,per_page}`, where the \
+        variables of query expressions are optional"
+    )]
+    pub url_vars: Vec<Var>,
+    #[structopt(
+        short = "g",
+        long,
+        help = "Send the URLs as given instead of expanding the ranges like `[1-100]` and the \
+        sets like `{a,b,c}` into several URLs"
+    )]
+    pub globoff: bool,
+    /// The values the globs took in each expanded URL, for the `#N` of `--output`
+    #[structopt(skip)]
+    pub glob_values: HashMap<String, Vec<String>>,
+    #[structopt(
+        short = "c",
+        long,
+        default_value = "4",
+        help = "Maximum number of requests in flight when fetching multiple URLs"
+    )]
+    pub concurrency: usize,
+    #[structopt(
+        long,
+        conflicts_with = "ndjson",
+        help = "Write each response body to its own file inside this directory"
+    )]
+    pub output_dir: Option<PathBuf>,
+    #[structopt(
+        short = "o",
+        long,
+        conflicts_with_all = &["output-dir", "ndjson"],
+        help = "Write the response body to this file instead of stdout. `#1`, `#2`... are \
+        replaced with the values of the first, second... range or set of the URL, e.g. \
+        `-o 'img#1.png'` for `example.com/img[1-100].png`"
+    )]
+    pub output: Option<String>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["output", "output-dir", "ndjson", "stdin-urls"],
+        help = "Stream the response body into the stdin of this command instead of printing it, \
+        e.g. `--pipe 'jq .items'`, and exit with its exit status. The command is run without a \
+        shell"
+    )]
+    pub pipe: Option<Pipe>,
+    #[structopt(
+        long,
+        help = "Print one JSON object per response instead of raw bodies, same as `--format ndjson`"
+    )]
+    pub ndjson: bool,
+    #[structopt(
+        long,
+        conflicts_with_all = &["ndjson", "watch-diff"],
+        help = "Print a line per response from this template instead of the response, e.g. \
+        `'{{status}} id={{body.$.id}} took {{timing.total_ms}}ms'`, also in batch and bench \
+        modes. Placeholders: url, method, status, reason, error, size, header.NAME, body, \
+        body.JSONPATH, timing.total_ms, timing.wait_ms and timing.receive_ms"
+    )]
+    pub output_template: Option<report::Template>,
+    #[structopt(
+        long,
+        default_value = "text",
+        help = "Output format: text, or ndjson for one JSON object per request with its url, \
+        method, status, time and body. The body is left out unless printed with --print"
+    )]
+    pub format: Format,
+    #[structopt(
+        long,
+        require_equals = true,
+        value_name = "file",
+        help = "Print the status, headers, timings, final URL and body size of each response as \
+        a JSON object on one line, to stderr or appended to the file given with `=`"
+    )]
+    pub meta_json: Option<Option<PathBuf>>,
+    #[structopt(
+        long,
+        help = "Also write the requests and responses in HTTP/1.1 wire format to this file, e.g. \
+        to attach to a bug report"
+    )]
+    pub dump: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Print the digest of each response body to stderr with this algorithm: sha1, \
+        sha256, sha384 or sha512"
+    )]
+    pub checksum: Option<Algorithm>,
+    #[structopt(
+        long,
+        help = "Fail with exit status 3 unless the response body has this digest, given as \
+        `algorithm:hex`, e.g. sha256:2cf24d... The body is not output when it doesn't match"
+    )]
+    pub verify_checksum: Option<Checksum>,
+    #[structopt(
+        short = "f",
+        long,
+        help = "Fail with exit status 22 when the response status is 4xx or 5xx, as curl does. \
+        The body of these responses is not output"
+    )]
+    pub fail: bool,
+    #[structopt(
+        long,
+        conflicts_with = "fail",
+        help = "Like --fail, but still output the body of the responses with a 4xx or 5xx status"
+    )]
+    pub fail_with_body: bool,
+    #[structopt(
+        long,
+        help = "Fail with exit status 6 unless the response has this status, e.g. 200 or 2xx"
+    )]
+    pub assert_status: Option<Status>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Fail with exit status 6 unless the response has this header, given as \
+        `name: value`. Can be repeated"
+    )]
+    pub assert_header: Vec<Header>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Fail with exit status 6 unless the response body contains this text. Can be \
+        repeated"
+    )]
+    pub assert_body_contains: Vec<String>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Fail with exit status 6 unless the JSON response has a value at this path, \
+        e.g. `$.id`, or compares to a JSON value with == or !=, e.g. `$.ok == true`. Can be \
+        repeated"
+    )]
+    pub assert_jsonpath: Vec<JsonAssertion>,
+    #[structopt(
+        long,
+        help = "Send the request again until the response has this status, e.g. 200 or 2xx"
+    )]
+    pub until_status: Option<Status>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Send the request again until the JSON response has a value at this path, or one \
+        comparing to a JSON value with == or !=, e.g. `$.status == \"done\"`. Can be repeated"
+    )]
+    pub until_jsonpath: Vec<JsonAssertion>,
+    #[structopt(
+        long,
+        default_value = "1s",
+        help = "How long to wait between the requests of --until-status and --until-jsonpath"
+    )]
+    pub poll_interval: Interval,
+    #[structopt(
+        long,
+        help = "Give up --until-status and --until-jsonpath after this long, e.g. 5m, and exit \
+        with status 7"
+    )]
+    pub poll_timeout: Option<Interval>,
+    #[structopt(
+        long,
+        help = "Send the requests again at this interval until interrupted, e.g. 5s, 500ms or 1m"
+    )]
+    pub watch: Option<Interval>,
+    #[structopt(
+        long,
+        requires = "watch",
+        help = "Clear the screen before each round of --watch instead of printing a separator"
+    )]
+    pub watch_clear: bool,
+    #[structopt(
+        long,
+        requires = "watch",
+        help = "With --watch, print only what changed in the body since the previous round"
+    )]
+    pub watch_diff: bool,
+    #[structopt(
+        long,
+        help = "Benchmark mode: send the request repeatedly and report latency statistics \
+        instead of printing the responses"
+    )]
+    pub bench: bool,
+    #[structopt(
+        short = "n",
+        long,
+        default_value = "200",
+        help = "Number of requests to send in benchmark mode"
+    )]
+    pub requests: usize,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "In benchmark mode, send requests for this long instead of -n requests, e.g. 60s"
+    )]
+    pub duration: Option<Interval>,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "In benchmark mode, go from 1 to --concurrency requests in flight over this time, \
+        e.g. 10s"
+    )]
+    pub ramp_up: Option<Interval>,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "In benchmark mode, send this many requests first and leave them out of the report"
+    )]
+    pub warmup: Option<usize>,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "In benchmark mode, start the requests at this constant rate per second instead of \
+        as fast as possible"
+    )]
+    pub rps: Option<f64>,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "Write the benchmark results as metrics instead of the report: `prometheus` for \
+        the OpenMetrics text format"
+    )]
+    pub metrics_format: Option<MetricsFormat>,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "Write the benchmark metrics to this file, in the --metrics-format (default: \
+        prometheus), and still print the report"
+    )]
+    pub metrics_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        requires = "bench",
+        help = "Write the time sent, latency and status of each benchmark request to this file, \
+        in JSON if it ends with .json, or else CSV"
+    )]
+    pub bench_output: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Print the request in HTTP/1.1 wire format instead of sending it"
+    )]
+    pub offline: bool,
+    #[structopt(
+        long,
+        help = "Print an equivalent curl command instead of sending the request"
+    )]
+    pub print_curl: bool,
+    #[structopt(
+        long,
+        help = "Append the requests and their responses to this file in HAR 1.2 format"
+    )]
+    pub har: Option<PathBuf>,
+    #[structopt(long, help = "Do not record the requests in the history")]
+    pub no_history: bool,
+    #[structopt(
+        long,
+        help = "Read the response as a stream of Server-Sent Events and print each event as it \
+        arrives (as JSON with --ndjson). The connection is reopened when it is lost"
+    )]
+    pub sse: bool,
+    #[structopt(
+        long,
+        conflicts_with_all = &["bench", "sse", "ndjson", "output-dir", "unix-socket"],
+        help = "Browse the response in a full-screen view with the history, searchable with /. \
+        The URL is optional"
+    )]
+    pub tui: bool,
+    #[structopt(
+        long,
+        help = "GraphQL mode: send the query given with `-d` (or `@file.graphql`, or read from stdin) \
+        in a JSON envelope, and print the `data` and `errors` of the response separately"
+    )]
+    pub graphql: bool,
+    #[structopt(
+        long = "gql-var",
+        number_of_values = 1,
+        requires = "graphql",
+        help = "Value for a GraphQL variable, in the form `name=value`. \
+        Values that are valid JSON are sent as JSON, others as strings"
+    )]
+    pub gql_vars: Vec<Var>,
+    #[structopt(
+        long,
+        help = "Print only the parts of the JSON response selected by this jq-like filter, \
+        e.g. `.items[].name`"
+    )]
+    pub filter: Option<Filter>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["filter", "graphql"],
+        help = "Print only the single value at this JSONPath, e.g. `$.access_token`. \
+        Fails if the response has no value there"
+    )]
+    pub extract: Option<JsonPath>,
+    #[structopt(
+        long,
+        help = "Convert XML response bodies to JSON before filtering and printing them: attributes \
+        become `@name` keys, repeated elements arrays and mixed text `#text`"
+    )]
+    pub xml_to_json: bool,
+    #[structopt(
+        long,
+        help = "Print the differences between this file and the response body instead of the body. \
+        JSON bodies are compared regardless of the order of the keys"
+    )]
+    pub diff_against: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Print the response as received, without reformatting XML and HTML, and strings \
+        selected with --extract without quotes"
+    )]
+    pub raw: bool,
+    #[structopt(
+        long,
+        help = "Decode the response body with this charset, e.g. iso-8859-1 or shift_jis, \
+        instead of the one in Content-Type or the detected one"
+    )]
+    pub response_charset: Option<Charset>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["raw", "extract", "filter", "select", "graphql"],
+        help = "Print the response body as an offset, hexadecimal and ASCII dump like xxd, to \
+        inspect binary payloads"
+    )]
+    pub hexdump: bool,
+    #[structopt(
+        long,
+        default_value = "b",
+        help = "What to print: any of H (request headers), B (request body), h (response status \
+        and headers) and b (response body). The response body goes to stdout, the rest to stderr"
+    )]
+    pub print: Print,
+    #[structopt(
+        short = "v",
+        long,
+        conflicts_with_all = &["silent", "quiet"],
+        help = "Also print the request headers and body and the response status and headers, \
+        same as `--print HBhb`"
+    )]
+    pub verbose: bool,
+    #[structopt(
+        short = "s",
+        long,
+        help = "Don't print progress messages and warnings, only the response body and errors"
+    )]
+    pub silent: bool,
+    #[structopt(
+        short = "q",
+        long,
+        help = "Don't print the response body either, e.g. when only the exit status matters with \
+        --fail or --assert-status, or with --output-dir"
+    )]
+    pub quiet: bool,
+    #[structopt(
+        long,
+        help = "Log what happens under the hood, like connections, TLS handshakes, redirects and \
+        cache decisions, up to this level: error, warn, info, debug or trace. Debug by default \
+        with --log-file"
+    )]
+    pub log_level: Option<logging::Level>,
+    #[structopt(
+        long,
+        help = "Append the log to this file instead of writing it to stderr"
+    )]
+    pub log_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Report the time until the first byte and in total, and the response size, on \
+        stderr, along with the time spent in DNS resolution, TCP connect and TLS handshake on a \
+        separate connection opened the same way just before the request, which is not part of \
+        the total"
+    )]
+    pub timing: bool,
+    #[structopt(
+        long,
+        conflicts_with_all = &["filter", "extract", "graphql"],
+        help = "Print the text of the elements of the HTML response matching this CSS selector, \
+        e.g. `a.external`"
+    )]
+    pub select: Option<Selector>,
+    #[structopt(
+        long,
+        requires = "select",
+        help = "Print the value of this attribute of the selected elements instead of their text"
+    )]
+    pub attr: Option<String>,
+    #[structopt(
+        required_unless_one = &["url-file", "stdin-urls", "tui"],
+        help = "The URLs to send the request to"
+    )]
+    pub urls: Vec<String>,
+    // `rq !!` is handled in `args()` as a shortcut for `rq replay last`
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Command {
+    #[structopt(about = "Send a request saved in a collection")]
+    Run {
+        #[structopt(help = "Name of the request in the collection")]
+        name: String,
+        #[structopt(
+            long = "var",
+            number_of_values = 1,
+            help = "Value for a `{{name}}` placeholder, in the form `name=value`"
+        )]
+        vars: Vec<Var>,
+        #[structopt(
+            long,
+            help = "Look for the request in this file instead of the collections directory"
+        )]
+        collection: Option<PathBuf>,
+    },
+    #[structopt(about = "Send the request described by a curl command line")]
+    FromCurl {
+        #[structopt(help = "The curl command, e.g. as copied from the browser's developer tools")]
+        command: String,
+        #[structopt(
+            long,
+            help = "Print the equivalent rq command instead of sending the request"
+        )]
+        print: bool,
+    },
+    #[structopt(
+        about = "Send the requests recorded in a HAR file again and compare the response statuses"
+    )]
+    ReplayHar {
+        #[structopt(help = "The HAR file")]
+        file: PathBuf,
+        #[structopt(
+            long,
+            help = "Only replay the requests to this host and its subdomains"
+        )]
+        filter: Option<String>,
+        #[structopt(
+            short = "c",
+            long,
+            default_value = "1",
+            help = "Maximum number of requests in flight"
+        )]
+        concurrency: usize,
+    },
+    #[structopt(
+        about = "Send the requests of a file, one per line as `METHOD URL [items]` or an rq \
+        command line, and print the status of each"
+    )]
+    Batch {
+        #[structopt(help = "The file listing the requests")]
+        file: PathBuf,
+        #[structopt(
+            short = "c",
+            long,
+            default_value = "1",
+            help = "Maximum number of requests in flight"
+        )]
+        concurrency: usize,
+    },
+    #[structopt(
+        about = "Send the requests of a YAML file in order, the values captured from a response \
+        filling the placeholders of the following requests"
+    )]
+    Chain {
+        #[structopt(help = "The YAML file listing the steps")]
+        file: PathBuf,
+        #[structopt(
+            long = "var",
+            number_of_values = 1,
+            help = "Value for a `{{name}}` placeholder, in the form `name=value`, overriding the \
+            `vars` of the file"
+        )]
+        vars: Vec<Var>,
+    },
+    #[structopt(about = "Run the requests of a test suite and check their responses")]
+    Test {
+        #[structopt(help = "The YAML file describing the test suite")]
+        file: PathBuf,
+        #[structopt(
+            long = "var",
+            number_of_values = 1,
+            help = "Value for a `{{name}}` placeholder, in the form `name=value`, overriding the \
+            `vars` of the suite"
+        )]
+        vars: Vec<Var>,
+    },
+    #[structopt(
+        about = "Run a server answering every request with its method, path, headers and body \
+        as JSON"
+    )]
+    Serve {
+        #[structopt(long, default_value = "8080", help = "The port to listen on")]
+        port: u16,
+        #[structopt(long, default_value = "127.0.0.1", help = "The address to listen on")]
+        bind: IpAddr,
+        #[structopt(
+            long,
+            number_of_values = 2,
+            value_names = &["STATUS", "PATH"],
+            help = "Answer the requests for PATH with STATUS, e.g. `--respond 404 /missing`. A \
+            path ending with `*` is a prefix. Can be repeated"
+        )]
+        respond: Vec<String>,
+    },
+    #[structopt(
+        about = "Run a forward HTTP proxy recording the exchanges to a HAR file, or answering \
+        from one"
+    )]
+    Proxy {
+        #[structopt(long, default_value = "8080", help = "The port to listen on")]
+        port: u16,
+        #[structopt(long, default_value = "127.0.0.1", help = "The address to listen on")]
+        bind: IpAddr,
+        #[structopt(
+            long,
+            help = "Append the requests and their responses to this HAR file"
+        )]
+        record: Option<PathBuf>,
+        #[structopt(
+            long,
+            conflicts_with = "record",
+            help = "Answer with the responses recorded in this HAR file instead of forwarding"
+        )]
+        replay: Option<PathBuf>,
+    },
+    #[structopt(about = "Print the man page in roff format, e.g. `rq man > rq.1`")]
+    Man,
+    #[structopt(about = "List the most recent requests in the history")]
+    History {
+        #[structopt(
+            short = "n",
+            long,
+            default_value = "20",
+            help = "Number of entries to show"
+        )]
+        limit: usize,
+    },
+    #[structopt(about = "Send a GET request, like `rq -m GET`")]
+    Get(Verb),
+    #[structopt(about = "Send a POST request, like `rq -m POST`")]
+    Post(Verb),
+    #[structopt(about = "Send a PUT request, like `rq -m PUT`")]
+    Put(Verb),
+    #[structopt(about = "Send a PATCH request, like `rq -m PATCH`")]
+    Patch(Verb),
+    #[structopt(about = "Send a DELETE request, like `rq -m DELETE`")]
+    Delete(Verb),
+    #[structopt(about = "Send an OPTIONS request, like `rq -m OPTIONS`")]
+    Options(Verb),
+    #[structopt(about = "Send a HEAD request and print the response status and headers")]
+    Head {
+        #[structopt(required = true, help = "The URLs to send the request to")]
+        urls: Vec<String>,
+    },
+    #[structopt(about = "Send a request from the history again. `rq !!` replays the last one")]
+    Replay {
+        #[structopt(help = "ID of the entry as shown by `rq history`, or `last`")]
+        id: String,
+        #[structopt(short = "m", long, help = "Replace the HTTP method")]
+        method: Option<HttpMethod>,
+        #[structopt(short = "t", long = "type", help = "Replace the Content-Type header")]
+        content_type: Option<ContentType>,
+        #[structopt(short, long, help = "Replace the request body")]
+        data: Option<String>,
+        #[structopt(short, long, help = "Replace the URL")]
+        url: Option<String>,
+    },
+    #[structopt(about = "Send a request to an operation described in an OpenAPI 3 specification")]
+    Api {
+        #[structopt(help = "The specification, in YAML or JSON")]
+        spec: PathBuf,
+        #[structopt(help = "The `operationId` of the operation")]
+        operation_id: String,
+        #[structopt(
+            long = "param",
+            number_of_values = 1,
+            help = "Value for a parameter of the operation, in the form `name=value`. \
+            Missing required parameters are prompted for when stdin is a terminal"
+        )]
+        params: Vec<Var>,
+        #[structopt(
+            long,
+            help = "Use this server URL instead of the first one of the specification"
+        )]
+        server: Option<String>,
+        #[structopt(
+            short,
+            long,
+            help = "The request body. Defaults to a skeleton generated from the body schema"
+        )]
+        data: Option<String>,
+    },
+    #[structopt(
+        about = "Open a WebSocket and bridge it to stdin and stdout, one message per line. \
+        The headers and credentials given before the subcommand are sent in the handshake"
+    )]
+    Ws {
+        #[structopt(help = "The WebSocket URL, e.g. wss://example.com/socket")]
+        url: String,
+        #[structopt(
+            long = "ws-send",
+            number_of_values = 1,
+            help = "Send this message instead of reading stdin, print as many replies, then close. \
+            Can be repeated"
+        )]
+        messages: Vec<String>,
+    },
+    #[structopt(
+        about = "Send the request to two URLs and print the differences between the responses. \
+        JSON bodies are compared regardless of the order of the keys"
+    )]
+    Diff {
+        #[structopt(help = "The URL of the response shown as removed")]
+        old: String,
+        #[structopt(help = "The URL of the response shown as added")]
+        new: String,
+    },
+    #[structopt(
+        about = "Check the links of an HTML page, following the links to the pages of the same \
+        host up to --depth, and report the broken ones"
+    )]
+    Links {
+        #[structopt(help = "The URL of the page")]
+        url: String,
+        #[structopt(
+            long,
+            default_value = "1",
+            help = "1 checks the links of the page, 2 also the links of the pages of the same host \
+            it links to, and so on"
+        )]
+        depth: usize,
+        #[structopt(long, help = "Only check the links to the host of the page")]
+        same_host: bool,
+        #[structopt(
+            short = "c",
+            long,
+            default_value = "8",
+            help = "Maximum number of requests in flight"
+        )]
+        concurrency: usize,
+    },
+    #[structopt(
+        about = "Fetch the URL and grade the security headers of the response: HSTS, CSP, \
+        X-Frame-Options, X-Content-Type-Options, Referrer-Policy and the cookie flags"
+    )]
+    Audit {
+        #[structopt(help = "The URL to audit")]
+        url: String,
+    },
+    #[structopt(
+        about = "Send the CORS preflight a browser would send before a cross-origin request, and \
+        tell which Access-Control-* response headers allow or block it"
+    )]
+    Cors {
+        #[structopt(help = "The URL the cross-origin request is sent to")]
+        url: String,
+        #[structopt(long, help = "The origin of the page, e.g. https://app.example.com")]
+        origin: String,
+        #[structopt(
+            long,
+            default_value = "GET",
+            help = "The method of the cross-origin request"
+        )]
+        request_method: HttpMethod,
+        #[structopt(
+            long,
+            use_delimiter = true,
+            help = "The non-safelisted headers of the cross-origin request, comma-separated, \
+            e.g. `x-custom,authorization`"
+        )]
+        request_headers: Vec<String>,
+        #[structopt(
+            long,
+            help = "The request includes credentials (cookies or HTTP authentication), which \
+            rules out the `*` wildcards"
+        )]
+        credentials: bool,
+    },
+    #[structopt(
+        about = "Send the bytes of a hand-written HTTP/1.1 request, e.g. exported by the browser \
+        devtools or Burp, as they are and print the raw response"
+    )]
+    Raw {
+        #[structopt(help = "The file of the request")]
+        file: PathBuf,
+        #[structopt(
+            long,
+            help = "The server to connect to, `host:port`, instead of the Host header of the \
+            request"
+        )]
+        target: Option<String>,
+        #[structopt(
+            long,
+            help = "Connect with TLS, to port 443 unless the target has a port"
+        )]
+        tls: bool,
+    },
+    #[structopt(about = "Add, list or remove the aliases of the config file")]
+    Alias {
+        #[structopt(subcommand)]
+        action: AliasAction,
+    },
+    #[structopt(about = "List or show the environments selected with --env")]
+    Env {
+        #[structopt(subcommand)]
+        action: EnvAction,
+    },
+    #[structopt(
+        about = "Store, print or remove the secrets of the keyring referenced as `keyring:NAME`"
+    )]
+    Secret {
+        #[structopt(subcommand)]
+        action: SecretAction,
+    },
+    #[structopt(about = "List, show, edit or remove the sessions saved with --session")]
+    Session {
+        #[structopt(subcommand)]
+        action: SessionAction,
+    },
+}
+
+/// The arguments of `rq get`, `rq post` and the other verb subcommands
+#[derive(Debug, Clone, StructOpt)]
+pub struct Verb {
     #[structopt(help = "The URL to send the request to")]
     pub url: String,
+    #[structopt(
+        help = "Headers `Name:value`, and body fields `key=value` or `key:=json` sent as JSON \
+        unless --type says otherwise"
+    )]
+    pub items: Vec<String>,
 }
 
-/// Parse the command line arguments
-pub fn args() -> CliArgs {
-    let mut args = CliArgs::from_args();
+/// Whether the item is a `Name:value` header rather than a `key=value` or `key:=json` field
+pub(crate) fn is_header_item(item: &str) -> bool {
+    match (item.find(':'), item.find('=')) {
+        (Some(colon), Some(equal)) => colon < equal && !item[colon..].starts_with(":="),
+        (colon, _) => colon.is_some(),
+    }
+}
+
+/// Turn the request options into the requests to send: pick the method and content type, merge
+/// the body items and expand, escape and check the URLs. Shared by the command line and the
+/// command lines of `rq batch`.
+pub(crate) fn prepare(args: &mut CliArgs) -> Result<(), clap::Error> {
+    let error = |message: &dyn std::fmt::Display, kind| {
+        Err(clap::Error::with_description(&message.to_string(), kind))
+    };
+    if args.graphql {
+        // GraphQL queries are sent in a JSON body, which needs POST
+        if matches!(args.method, HttpMethod::Get) {
+            args.method = HttpMethod::Post;
+        }
+        args.content_type = Some(ContentType::Json);
+    }
+    // Forms are submitted with POST, as curl does for `-F`
+    if !args.form.is_empty() && matches!(args.method, HttpMethod::Get) {
+        args.method = HttpMethod::Post;
+    }
+    args.data = match merge_data(&args.data_items, args.content_type.as_ref()) {
+        Ok(data) => data,
+        Err(e) => return error(&e, clap::ErrorKind::ArgumentConflict),
+    };
+    if let (Some(ContentType::MsgPack), Some(data)) = (&args.content_type, &args.data) {
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(data) {
+            return error(
+                &format!("MessagePack bodies are given as JSON: {}", e),
+                clap::ErrorKind::InvalidValue,
+            );
+        }
+    }
+    if let (Some(accepted), false) = (&args.accept_encoding, args.no_decompress) {
+        if let Some(coding) = accepted.undecodable() {
+            return error(
+                &format!(
+                    "{} responses can't be decompressed, add --no-decompress to accept them",
+                    coding
+                ),
+                clap::ErrorKind::ArgumentConflict,
+            );
+        }
+    }
+    if let Some(path) = &args.etag_compare {
+        args.if_none_match = match conditional::read_etag(path) {
+            Ok(etag) => etag,
+            Err(e) => {
+                return error(
+                    &format!("Can't read the ETag from {}: {}", path.display(), e),
+                    clap::ErrorKind::Io,
+                )
+            }
+        };
+    }
+    if let Some(name) = &args.interface {
+        let family_matches =
+            |addr: &IpAddr| !(args.ipv4 && addr.is_ipv6() || args.ipv6 && addr.is_ipv4());
+        args.local_address = match interface::addresses(name) {
+            Ok(addresses) if addresses.iter().any(family_matches) => {
+                addresses.into_iter().find(family_matches)
+            }
+            Ok(_) => {
+                return error(
+                    &format!("The network interface {} has no usable address", name),
+                    clap::ErrorKind::InvalidValue,
+                )
+            }
+            Err(e) => {
+                return error(
+                    &format!("Can't list the network interfaces: {}", e),
+                    clap::ErrorKind::Io,
+                )
+            }
+        };
+    }
+    if args.content_length {
+        args.data_binary = match args.data_binary.take().map(Upload::sized).transpose() {
+            Ok(upload) => upload,
+            Err(e) => {
+                return error(
+                    &format!("Can't read the body from stdin: {}", e),
+                    clap::ErrorKind::Io,
+                )
+            }
+        };
+    }
     // Guess content type if not provided
     if let Some(body) = &args.data {
         if args.content_type.is_none() {
             args.content_type = Some(guess_content_type(body));
         }
     }
-    let url = args.url.as_str();
-    if !url.starts_with("http://") && !url.starts_with("https://") {
-        args.url.insert_str(0, "http://");
+    let url_vars = template::to_map(&args.url_vars);
+    for url in mem::take(&mut args.urls) {
+        let url = if url_vars.is_empty() {
+            url
+        } else {
+            match uri_template::expand(&url, &url_vars) {
+                Ok(url) => url,
+                Err(e) => return error(&e, clap::ErrorKind::InvalidValue),
+            }
+        };
+        // The braces of URL templates are not globs
+        let expanded = if args.globoff || !url_vars.is_empty() {
+            vec![(url, Vec::new())]
+        } else {
+            match glob::expand(&url) {
+                Ok(expanded) => expanded,
+                Err(e) => return error(&e, clap::ErrorKind::InvalidValue),
+            }
+        };
+        for (mut url, values) in expanded {
+            normalize_url(&mut url);
+            if !args.no_url_escape {
+                url = escape_url(&url);
+            }
+            if let Err(e) = Url::parse(&url) {
+                return error(
+                    &format!("Invalid URL `{}`: {}", url, e),
+                    clap::ErrorKind::InvalidValue,
+                );
+            }
+            if !values.is_empty() {
+                args.glob_values.insert(url.clone(), values);
+            }
+            args.urls.push(url);
+        }
+    }
+    args.pipeline = match Pipeline::from_args(args) {
+        Ok(pipeline) => pipeline,
+        Err(e) => return error(&e, clap::ErrorKind::InvalidValue),
+    };
+    Ok(())
+}
+
+/// Turn a verb subcommand into the URL, headers and body items it stands for, then pick the
+/// method: the one given, or POST if there is a body and GET otherwise, as curl and httpie do
+pub(crate) fn resolve_method(args: &mut CliArgs) -> Result<(), crate::http::Error> {
+    let verb = match &args.command {
+        Some(Command::Get(verb)) => Some((HttpMethod::Get, verb.clone())),
+        Some(Command::Post(verb)) => Some((HttpMethod::Post, verb.clone())),
+        Some(Command::Put(verb)) => Some((HttpMethod::Put, verb.clone())),
+        Some(Command::Patch(verb)) => Some((HttpMethod::Patch, verb.clone())),
+        Some(Command::Delete(verb)) => Some((HttpMethod::Delete, verb.clone())),
+        Some(Command::Options(verb)) => Some((HttpMethod::Options, verb.clone())),
+        _ => None,
+    };
+    if let Some((method, verb)) = verb {
+        args.command = None;
+        args.urls = vec![verb.url];
+        args.method_arg = Some(method);
+        for item in verb.items {
+            if is_header_item(&item) {
+                args.headers.push(item.parse()?);
+            } else {
+                args.data_items.push(item);
+            }
+        }
+        // Like httpie, the fields make a JSON object
+        if !args.data_items.is_empty() && args.content_type.is_none() {
+            args.content_type = Some(ContentType::Json);
+        }
+    }
+    let has_body =
+        !args.data_items.is_empty() || !args.form.is_empty() || args.data_binary.is_some();
+    args.method = match &args.method_arg {
+        Some(method) => method.clone(),
+        None if has_body => HttpMethod::Post,
+        None => HttpMethod::Get,
+    };
+    Ok(())
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum AliasAction {
+    #[structopt(
+        about = "Save flags, after an optional base URL, to run them with `rq NAME`",
+        setting = AppSettings::TrailingVarArg
+    )]
+    Add {
+        name: String,
+        #[structopt(
+            required = true,
+            allow_hyphen_values = true,
+            help = "The base URL, whose path is given after the alias, and the flags, e.g. \
+            `https://api.example.com -H 'Authorization: Bearer token'`. Put `--` before them \
+            if they start with a flag"
+        )]
+        words: Vec<String>,
+    },
+    #[structopt(about = "List the aliases with their definition")]
+    List,
+    #[structopt(about = "Remove an alias")]
+    Rm { name: String },
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum EnvAction {
+    #[structopt(about = "List the environments of each file with their number of variables")]
+    List {
+        #[structopt(
+            long,
+            help = "Also list the environments of this collection, chain or test suite"
+        )]
+        collection: Option<PathBuf>,
+    },
+    #[structopt(about = "Print the variables of an environment")]
+    Show {
+        name: String,
+        #[structopt(
+            long,
+            help = "Include the environments of this collection, chain or test suite"
+        )]
+        collection: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum SecretAction {
+    #[structopt(
+        about = "Store a secret, read from the terminal without echoing it or from stdin, to use \
+        as `keyring:NAME` in headers, credentials and API keys"
+    )]
+    Set { name: String },
+    #[structopt(about = "Print a secret")]
+    Get { name: String },
+    #[structopt(about = "Remove a secret")]
+    Rm { name: String },
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum SessionAction {
+    #[structopt(about = "List the sessions with the number of headers and cookies they hold")]
+    List,
+    #[structopt(about = "Print a session")]
+    Show { name: String },
+    #[structopt(about = "Open a session in $VISUAL or $EDITOR")]
+    Edit { name: String },
+    #[structopt(about = "Remove a session")]
+    Rm { name: String },
+}
+
+/// Prepend `http://` to the URL if it has no scheme
+pub fn normalize_url(url: &mut String) {
+    let has_scheme = url.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    // `data:` URLs have no authority
+    if !has_scheme
+        && !url
+            .get(..5)
+            .is_some_and(|s| s.eq_ignore_ascii_case("data:"))
+    {
+        url.insert_str(0, "http://");
+    }
+}
+
+/// Percent-encode the characters not allowed in the path, query and fragment of the URL
+/// (https://www.rfc-editor.org/rfc/rfc3986#section-3.3), like spaces, non-ASCII characters or
+/// brackets. Sequences already percent-encoded are kept as they are.
+pub fn escape_url(url: &str) -> String {
+    let start = url.find("://").map_or(0, |i| i + 3);
+    let end = url[start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |i| start + i);
+    let (authority, rest) = url.split_at(end);
+    let bytes = rest.as_bytes();
+    let mut escaped = authority.to_string();
+    let mut in_fragment = false;
+    for (i, &b) in bytes.iter().enumerate() {
+        let encoded = b == b'%'
+            && bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        let allowed = b.is_ascii_alphanumeric()
+            || b"-._~!$&'()*+,;=:@/?".contains(&b)
+            || (b == b'#' && !in_fragment)
+            || encoded;
+        in_fragment |= b == b'#';
+        if allowed {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", b));
+        }
+    }
+    escaped
+}
+
+/// Parse the command line arguments
+pub fn args() -> CliArgs {
+    let argv = match alias::expand(std::env::args_os().collect()) {
+        Ok(argv) => argv,
+        Err(e) => {
+            clap::Error::with_description(&e.to_string(), clap::ErrorKind::InvalidValue).exit()
+        }
+    };
+    let mut args = CliArgs::from_iter(argv);
+    if args.command.is_none() && args.urls.len() == 1 && args.urls[0] == "!!" {
+        args.urls.clear();
+        args.command = Some(Command::Replay {
+            id: "last".to_string(),
+            method: None,
+            content_type: None,
+            data: None,
+            url: None,
+        });
+    }
+    if let Err(e) = resolve_method(&mut args) {
+        clap::Error::with_description(&e.to_string(), clap::ErrorKind::InvalidValue).exit()
+    }
+    if let Some(Command::Head { urls }) = &args.command {
+        args.urls = urls.clone();
+        args.method = HttpMethod::Head;
+        args.command = None;
+    }
+    // A HEAD response has no body, its headers are what is interesting
+    if matches!(args.method, HttpMethod::Head) {
+        args.print.response_headers = true;
+        args.print.response_body = false;
+    }
+    let verbosity = match (args.quiet, args.silent, args.verbose) {
+        (true, _, _) => Verbosity::Quiet,
+        (_, true, _) => Verbosity::Silent,
+        (_, _, true) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    };
+    output::set_verbosity(verbosity);
+    match verbosity {
+        Verbosity::Quiet => args.print = Print::default(),
+        Verbosity::Verbose => {
+            args.print = Print {
+                response_body: args.print.response_body,
+                ..Print::all()
+            }
+        }
+        _ => {}
+    }
+    if args.auth_type == AuthType::Negotiate && args.auth.is_some() {
+        clap::Error::with_description(
+            "--auth-type negotiate uses the Kerberos tickets of the user, --auth can't be given",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    if args.format == Format::Ndjson {
+        if args.output_dir.is_some() {
+            clap::Error::with_description(
+                "--format ndjson can't be used with --output-dir",
+                clap::ErrorKind::ArgumentConflict,
+            )
+            .exit()
+        }
+        args.ndjson = true;
+    }
+    if args.rps.is_some_and(|rps| !(rps.is_finite() && rps > 0.0)) {
+        clap::Error::with_description(
+            "--rps must be a positive number of requests per second",
+            clap::ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    for (name, secs) in [
+        ("--pool-idle-timeout", args.pool_idle_timeout),
+        ("--tcp-keepalive", args.tcp_keepalive),
+    ] {
+        if secs.is_some_and(|secs| !(secs.is_finite() && secs >= 0.0)) {
+            clap::Error::with_description(
+                &format!("{} must be a positive number of seconds", name),
+                clap::ErrorKind::InvalidValue,
+            )
+            .exit()
+        }
+    }
+    if args.tls_max.is_some_and(|max| max < tls::Version::Tls1_2) {
+        clap::Error::with_description(
+            "TLS versions below 1.2 are not implemented, --tls-max must be 1.2 or 1.3",
+            clap::ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    if let (Some(min), Some(max)) = (args.tls_min, args.tls_max) {
+        if min > max {
+            clap::Error::with_description(
+                "--tls-min can't be above --tls-max",
+                clap::ErrorKind::ArgumentConflict,
+            )
+            .exit()
+        }
+    }
+    if let Err(e) = prepare(&mut args) {
+        e.exit()
+    }
+    if args.concurrency == 0 {
+        args.concurrency = 1;
     }
     args
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn unsafe_url_characters_are_escaped() {
+        assert_eq!(
+            super::escape_url("http://example.com/a b/caf\u{e9}?q=x y&r=[1]#f#g"),
+            "http://example.com/a%20b/caf%C3%A9?q=x%20y&r=%5B1%5D#f%23g"
+        );
+        assert_eq!(
+            super::escape_url("http://user:pw@[::1]:80/100%25/50%?a|b"),
+            "http://user:pw@[::1]:80/100%25/50%25?a%7Cb"
+        );
+        assert_eq!(
+            super::escape_url("http://example.com"),
+            "http://example.com"
+        );
+    }
+    #[test]
+    fn verbs_become_requests_with_a_method() {
+        use structopt::StructOpt;
+
+        use crate::http::{ContentType, HttpMethod};
+
+        let mut args = super::CliArgs::from_iter(["rq", "post", "example.com", "X-A:1", "n=bob"]);
+        super::resolve_method(&mut args).unwrap();
+        assert!(args.command.is_none());
+        assert!(matches!(args.method, HttpMethod::Post));
+        assert_eq!(args.urls, ["example.com"]);
+        assert_eq!(args.headers[0].name, "X-A");
+        assert_eq!(args.data_items, ["n=bob"]);
+        assert!(matches!(args.content_type, Some(ContentType::Json)));
+        let mut args = super::CliArgs::from_iter(["rq", "example.com", "-d", "a=1"]);
+        super::resolve_method(&mut args).unwrap();
+        assert!(matches!(args.method, HttpMethod::Post));
+        let mut args = super::CliArgs::from_iter(["rq", "-m", "put", "example.com"]);
+        super::resolve_method(&mut args).unwrap();
+        assert!(matches!(args.method, HttpMethod::Put));
+    }
+}
@@ -0,0 +1,298 @@
+//! Request collections: named request templates stored in YAML files.
+//!
+//! Every `.yaml` or `.yml` file in the collections directory (or the file given with
+//! `--collection`) maps request names to their definitions:
+//!
+//! ```yaml
+//! create-user:
+//!   method: POST
+//!   url: https://api.example.com/users
+//!   headers:
+//!     Authorization: Bearer {{token}}
+//!   body:
+//!     name: "{{name}}"
+//!   vars:
+//!     name: anonymous
+//! ```
+//!
+//! `{{name}}` placeholders are replaced by the values given with `--var name=value`, falling back
+//! to the `vars` section of the request. A body written as a mapping or a sequence is sent as
+//! JSON, a string body is sent as is.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use reqwest::header::CONTENT_TYPE;
+use serde_json::Value;
+
+use crate::{
+    cli::normalize_url,
+    environment,
+    http::{guess_content_type, ContentType, HttpMethod},
+    multipart, paths,
+    request::Request,
+    template, yaml,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Request `{0}` not found in the collections")]
+    NotFound(String),
+    #[error("{}: {source}", .path.display())]
+    Yaml { path: PathBuf, source: yaml::Error },
+    #[error("Invalid definition for request `{name}`: {message}")]
+    Invalid { name: String, message: String },
+    #[error("Missing values for variables: {}", .0.join(", "))]
+    MissingVars(Vec<String>),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A request definition whose placeholders have not been filled yet
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub method: HttpMethod,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Value>,
+    /// Default values for the variables
+    pub vars: HashMap<String, String>,
+}
+
+/// Convert a YAML scalar to the string it was written as
+pub(crate) fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Read a mapping whose values are all scalars
+fn string_map(name: &str, field: &str, value: Option<&Value>) -> Result<Vec<(String, String)>> {
+    let invalid = || Error::Invalid {
+        name: name.to_string(),
+        message: format!("`{}` must be a mapping of strings", field),
+    };
+    match value {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Object(map)) => map
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), scalar_to_string(v).ok_or_else(invalid)?)))
+            .collect(),
+        Some(_) => Err(invalid()),
+    }
+}
+
+impl Template {
+    pub fn from_value(name: &str, value: &Value) -> Result<Self> {
+        let invalid = |message: &str| Error::Invalid {
+            name: name.to_string(),
+            message: message.to_string(),
+        };
+        let def = value
+            .as_object()
+            .ok_or_else(|| invalid("expected a mapping"))?;
+        let method = match def.get("method") {
+            None => HttpMethod::Get,
+            Some(Value::String(m)) => {
+                m.parse().map_err(|e: crate::http::Error| Error::Invalid {
+                    name: name.to_string(),
+                    message: e.to_string(),
+                })?
+            }
+            Some(_) => return Err(invalid("`method` must be a string")),
+        };
+        let url = def
+            .get("url")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("`url` is required and must be a string"))?
+            .to_string();
+        Ok(Template {
+            method,
+            url,
+            headers: string_map(name, "headers", def.get("headers"))?,
+            body: def.get("body").filter(|b| !b.is_null()).cloned(),
+            vars: string_map(name, "vars", def.get("vars"))?
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    /// Fill the placeholders with `vars`, falling back to the defaults of the template
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<Request> {
+        let mut missing = Vec::new();
+        let mut fill = |s: &str| {
+            template::render(s, |name| {
+                vars.get(name).or_else(|| self.vars.get(name)).cloned()
+            })
+            .unwrap_or_else(|names| {
+                for name in names {
+                    if !missing.contains(&name) {
+                        missing.push(name);
+                    }
+                }
+                String::new()
+            })
+        };
+        let mut url = fill(&self.url);
+        let mut headers: Vec<_> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), fill(value)))
+            .collect();
+        let body = self.body.as_ref().map(|body| match body {
+            Value::String(s) => fill(s),
+            structured => render_json(structured, &mut fill).to_string(),
+        });
+        if !missing.is_empty() {
+            return Err(Error::MissingVars(missing));
+        }
+        let has_content_type = headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()));
+        if let (Some(body), false) = (&body, has_content_type) {
+            let content_type = match self.body {
+                Some(Value::String(_)) => guess_content_type(body),
+                _ => ContentType::Json,
+            };
+            headers.push((CONTENT_TYPE.to_string(), content_type.to_string()));
+        }
+        normalize_url(&mut url);
+        Ok(Request {
+            method: self.method.clone(),
+            url,
+            headers,
+            body,
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Default::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Default::default(),
+        })
+    }
+}
+
+/// Fill the placeholders in every string of a JSON value
+fn render_json<F: FnMut(&str) -> String>(value: &Value, fill: &mut F) -> Value {
+    match value {
+        Value::String(s) => Value::String(fill(s)),
+        Value::Array(items) => Value::Array(items.iter().map(|v| render_json(v, fill)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), render_json(v, fill)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// The collection files in the collections directory, sorted by name
+fn collection_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        if is_yaml && !environment::is_env_file(&path) && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn load(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path)?;
+    yaml::parse(&content).map_err(|source| Error::Yaml {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Find the request named `name` in `file`, or in the collections directory if no file is given,
+/// and the file defining it. If several files define the same name, the first file in
+/// alphabetical order wins.
+pub fn find(name: &str, file: Option<&Path>) -> Result<(Template, PathBuf)> {
+    let files = match file {
+        Some(file) => vec![file.to_path_buf()],
+        None => collection_files(&paths::collections_dir())?,
+    };
+    for path in files {
+        if let Some(def) = load(&path)?.get(name) {
+            return Ok((Template::from_value(name, def)?, path));
+        }
+    }
+    Err(Error::NotFound(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    fn template() -> super::Template {
+        let def = crate::yaml::parse(
+            r#"
+method: post
+url: example.com/users/{{id}}
+headers:
+  Authorization: Bearer {{token}}
+body:
+  name: "{{name}}"
+  admin: false
+vars:
+  name: anonymous
+"#,
+        )
+        .unwrap();
+        super::Template::from_value("create-user", &def).unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_filled_with_vars_then_defaults() {
+        let vars: HashMap<_, _> = vec![("id", "1"), ("token", "t")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let req = template().render(&vars).unwrap();
+        assert_eq!(req.url, "http://example.com/users/1");
+        assert_eq!(req.headers[0], ("Authorization".into(), "Bearer t".into()));
+        assert_eq!(
+            req.headers[1],
+            ("content-type".into(), "application/json".into())
+        );
+        let body: serde_json::Value = serde_json::from_str(&req.body.unwrap()).unwrap();
+        assert_eq!(body, json!({"name": "anonymous", "admin": false}));
+    }
+    #[test]
+    fn missing_vars_are_reported() {
+        match template().render(&HashMap::new()) {
+            Err(super::Error::MissingVars(names)) => assert_eq!(names, vec!["id", "token"]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    #[test]
+    fn url_is_required() {
+        assert!(super::Template::from_value("a", &json!({"method": "GET"})).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Compression of request bodies with `--compress`, and the codings accepted for responses.
+use std::{
+    fmt::{Display, Formatter},
+    io::Write,
+    str::FromStr,
+};
+
+use async_compression::{
+    tokio::bufread::{BrotliEncoder, GzipEncoder},
+    Level,
+};
+use tokio::io::{AsyncBufRead, AsyncRead};
+
+/// The default brotli quality (11) is too slow for large uploads
+const BROTLI_QUALITY: u32 = 5;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown content coding `{0}`, expected gzip or br")]
+    Unknown(String),
+    #[error("The {0} content coding is not supported by this build of rq")]
+    Unsupported(String),
+    #[error("Unknown content coding `{0}`, expected gzip, br, deflate, zstd, identity or *")]
+    UnknownAccepted(String),
+}
+
+/// The codings rq can decompress
+const DECODED: &[&str] = &["gzip", "br", "zstd", "identity"];
+
+/// A content coding (https://www.rfc-editor.org/rfc/rfc9110#section-8.4.1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coding {
+    Gzip,
+    Brotli,
+}
+
+impl FromStr for Coding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" => Ok(Coding::Gzip),
+            "br" => Ok(Coding::Brotli),
+            "zstd" => Err(Error::Unsupported(s.to_string())),
+            _ => Err(Error::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl Display for Coding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Coding::Gzip => "gzip",
+            Coding::Brotli => "br",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+impl Coding {
+    /// Compress a body held in memory
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        // Writing to a Vec never fails
+        match self {
+            Coding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).expect("writing to memory failed");
+                encoder.finish().expect("writing to memory failed")
+            }
+            Coding::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, 22);
+                encoder.write_all(data).expect("writing to memory failed");
+                encoder.into_inner()
+            }
+        }
+    }
+
+    /// Compress a body while it is read
+    pub fn compress_reader<R>(self, reader: R) -> Box<dyn AsyncRead + Unpin + Send + Sync>
+    where
+        R: AsyncBufRead + Unpin + Send + Sync + 'static,
+    {
+        match self {
+            Coding::Gzip => Box::new(GzipEncoder::new(reader)),
+            Coding::Brotli => Box::new(BrotliEncoder::with_quality(
+                reader,
+                Level::Precise(BROTLI_QUALITY),
+            )),
+        }
+    }
+}
+
+/// The value of `Accept-Encoding`: codings with an optional weight, e.g. `gzip, br;q=0.5`
+#[derive(Debug, Clone)]
+pub struct AcceptEncoding(Vec<String>);
+
+impl FromStr for AcceptEncoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let codings = s
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<_>>();
+        for item in &codings {
+            let name = item.split(';').next().unwrap_or_default().trim();
+            if !["gzip", "br", "deflate", "zstd", "identity", "*"]
+                .contains(&name.to_ascii_lowercase().as_str())
+            {
+                return Err(Error::UnknownAccepted(name.to_string()));
+            }
+        }
+        Ok(AcceptEncoding(codings))
+    }
+}
+
+impl Display for AcceptEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join(", "))
+    }
+}
+
+impl AcceptEncoding {
+    /// The first accepted coding that rq can't decompress, if any. `*` is left to the server.
+    pub fn undecodable(&self) -> Option<&str> {
+        self.0
+            .iter()
+            .map(|item| item.split(';').next().unwrap_or_default().trim())
+            .find(|name| *name != "*" && !DECODED.contains(&name.to_ascii_lowercase().as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::{AcceptEncoding, Coding};
+
+    #[test]
+    fn codings_are_parsed() {
+        assert_eq!("GZIP".parse::<Coding>().unwrap(), Coding::Gzip);
+        assert_eq!("br".parse::<Coding>().unwrap(), Coding::Brotli);
+        assert!("zstd".parse::<Coding>().is_err());
+        assert!("lzma".parse::<Coding>().is_err());
+    }
+    #[test]
+    fn accepted_codings_are_checked() {
+        let accepted: AcceptEncoding = "gzip,br;q=0.5, identity".parse().unwrap();
+        assert_eq!(accepted.to_string(), "gzip, br;q=0.5, identity");
+        assert_eq!(accepted.undecodable(), None);
+        let accepted: AcceptEncoding = "gzip, zstd".parse().unwrap();
+        assert_eq!(accepted.undecodable(), None);
+        let accepted: AcceptEncoding = "gzip, deflate".parse().unwrap();
+        assert_eq!(accepted.undecodable(), Some("deflate"));
+        assert!("gzip, lzma".parse::<AcceptEncoding>().is_err());
+    }
+    #[tokio::test]
+    async fn compressed_bodies_round_trip() {
+        let data = b"hello hello hello hello".repeat(100);
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&Coding::Gzip.compress(&data)[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut compressed = Vec::new();
+        Coding::Brotli
+            .compress_reader(std::io::Cursor::new(data.clone()))
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(&compressed[..], 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+}
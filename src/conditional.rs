@@ -0,0 +1,91 @@
+//! Conditional requests (https://www.rfc-editor.org/rfc/rfc9110#section-13): `--etag-save`,
+//! `--etag-compare` and `--time-cond`.
+use std::{fs, io, path::Path, str::FromStr};
+
+use reqwest::header::ETAG;
+
+use crate::{datetime, response::Response};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Invalid time condition `{0}`, expected a date like 2021-10-01T12:30:00Z, an HTTP date \
+or an existing file"
+)]
+pub struct Error(String);
+
+/// The value of `If-Modified-Since`, given as a date or as a file whose modification time is used
+#[derive(Debug, Clone)]
+pub struct TimeCond(String);
+
+impl FromStr for TimeCond {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.ends_with("GMT") {
+            return Ok(TimeCond(s.to_string()));
+        }
+        if let Some(secs) = datetime::parse_rfc3339(s) {
+            return Ok(TimeCond(datetime::format_http_date(secs)));
+        }
+        let modified = fs::metadata(s)
+            .and_then(|m| m.modified())
+            .map_err(|_| Error(s.to_string()))?;
+        Ok(TimeCond(datetime::format_http_date(
+            datetime::unix_millis(modified).div_euclid(1000),
+        )))
+    }
+}
+
+impl TimeCond {
+    pub fn header_value(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The ETag saved by a previous `--etag-save`. A missing file means there is nothing to compare
+/// with yet, so the request is sent unconditionally.
+pub fn read_etag(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(etag) => Ok(Some(etag.trim().to_string()).filter(|e| !e.is_empty())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Save the ETag of the response, if it has one
+pub fn save_etag(path: &Path, response: &Response) -> io::Result<()> {
+    match response.header(ETAG.as_str()) {
+        Some(etag) => fs::write(path, format!("{}\n", etag)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::SystemTime};
+
+    use super::TimeCond;
+    use crate::datetime;
+
+    #[test]
+    fn time_conditions_are_parsed() {
+        let cond: TimeCond = "1994-11-06T08:49:37Z".parse().unwrap();
+        assert_eq!(cond.header_value(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        let cond: TimeCond = "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap();
+        assert_eq!(cond.header_value(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!("/does/not/exist".parse::<TimeCond>().is_err());
+    }
+    #[test]
+    fn file_time_condition_uses_modification_time() {
+        let path = std::env::temp_dir().join("rq-time-cond-test");
+        let now_secs = || datetime::unix_millis(SystemTime::now()).div_euclid(1000);
+        let before = now_secs();
+        fs::write(&path, "").unwrap();
+        let cond: TimeCond = path.to_str().unwrap().parse().unwrap();
+        fs::remove_file(&path).unwrap();
+        let after = now_secs();
+        assert!((before..=after)
+            .map(datetime::format_http_date)
+            .any(|date| date == cond.header_value()));
+    }
+}
@@ -0,0 +1,135 @@
+//! The config file, `config` in the config directory, written as INI sections of
+//! `name = value` lines:
+//!
+//! ```ini
+//! [aliases]
+//! prodapi = https://api.example.com -H 'Authorization: Bearer keyring:prod'
+//!
+//! [settings]
+//! load-dotenv = false
+//! ```
+//!
+//! Lines starting with `#` or `;` are comments.
+use std::{fs, io};
+
+use crate::paths;
+
+/// The name of the section opened by the line, if it opens one
+fn section(line: &str) -> Option<&str> {
+    let line = line.trim();
+    Some(line.strip_prefix('[')?.strip_suffix(']')?.trim())
+}
+
+/// The `name = value` lines of the section `wanted`, in order
+pub fn entries(config: &str, wanted: &str) -> Vec<(String, String)> {
+    let mut in_section = false;
+    let mut entries = Vec::new();
+    for line in config.lines() {
+        if let Some(name) = section(line) {
+            in_section = name == wanted;
+            continue;
+        }
+        let line = line.trim();
+        if !in_section || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some((name, value)) = line.split_once('=') {
+            entries.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    entries
+}
+
+/// The value of `name` in the section `wanted`, the last one if it is repeated
+pub fn get(config: &str, wanted: &str, name: &str) -> Option<String> {
+    entries(config, wanted)
+        .into_iter()
+        .rev()
+        .find(|(entry, _)| entry == name)
+        .map(|(_, value)| value)
+}
+
+/// The config with the entry `name` of the section `wanted` replaced by `value`, or removed if
+/// `value` is `None`. The other lines, comments included, are kept.
+pub fn update(config: &str, wanted: &str, name: &str, value: Option<&str>) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_section = false;
+    // Where the new entry goes: after the last line of the section
+    let mut insert_at = None;
+    for line in config.lines() {
+        if let Some(section) = section(line) {
+            in_section = section == wanted;
+        } else if in_section {
+            let defines_name = line
+                .split_once('=')
+                .is_some_and(|(key, _)| key.trim() == name);
+            if defines_name {
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+        if in_section && !line.trim().is_empty() {
+            insert_at = Some(lines.len());
+        }
+    }
+    if let Some(value) = value {
+        let entry = format!("{} = {}", name, value);
+        match insert_at {
+            Some(index) => lines.insert(index, entry),
+            None => {
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.push(format!("[{}]", wanted));
+                lines.push(entry);
+            }
+        }
+    }
+    let mut config = lines.join("\n");
+    config.push('\n');
+    config
+}
+
+/// The content of the config file, empty if there is none
+pub fn read() -> io::Result<String> {
+    match fs::read_to_string(paths::config_file()) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        result => result,
+    }
+}
+
+pub fn write(config: &str) -> io::Result<()> {
+    let path = paths::config_file();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, config)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn entries_are_updated_in_place() {
+        let config = "[user]\nname = me\n\n[aliases]\n# prod\nprod = -v\nlocal = -k\n";
+        assert_eq!(
+            super::entries(config, "aliases"),
+            [
+                ("prod".to_string(), "-v".to_string()),
+                ("local".to_string(), "-k".to_string())
+            ]
+        );
+        assert_eq!(super::get(config, "user", "name").as_deref(), Some("me"));
+        assert_eq!(
+            super::update(config, "aliases", "prod", Some("-q")),
+            "[user]\nname = me\n\n[aliases]\n# prod\nlocal = -k\nprod = -q\n"
+        );
+        assert_eq!(
+            super::update(config, "aliases", "local", None),
+            "[user]\nname = me\n\n[aliases]\n# prod\nprod = -v\n"
+        );
+        assert_eq!(
+            super::update("[user]\nname = me", "aliases", "a", Some("-v")),
+            "[user]\nname = me\n\n[aliases]\na = -v\n"
+        );
+    }
+}
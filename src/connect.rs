@@ -0,0 +1,412 @@
+//! Connections made outside of reqwest, for `--expect100`, `--trace`, WebSockets and the timing
+//! and certificate probes, with the options the client is created with: the `--resolve`
+//! overrides, the proxies of the environment, the local address and the TLS versions and pinned
+//! keys.
+//!
+//! HTTPS and WebSocket connections go through proxies in a `CONNECT` tunnel, plain HTTP requests
+//! are forwarded to the proxy with their whole URL as target.
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use percent_encoding::percent_decode_str;
+use reqwest::{header::HeaderValue, Url};
+use rustls::ClientConfig;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{lookup_host, TcpSocket, TcpStream},
+};
+use tokio_rustls::{client::TlsStream, webpki::DNSNameRef, TlsConnector};
+
+use crate::{
+    cli::CliArgs,
+    http::{BasicAuth, Resolve},
+    proxy_env::{Decision, Proxies},
+    tls, TIMEOUT,
+};
+
+/// The longest response to `CONNECT` read from a proxy
+const MAX_TUNNEL_RESPONSE: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid URL: {0}")]
+    Url(String),
+    #[error("Can't connect to {host}: {source}")]
+    Connect { host: String, source: io::Error },
+    #[error("The proxy refused to open a tunnel to {host}: {status}")]
+    Tunnel { host: String, status: String },
+    #[error("Can't connect to {0} within {} ms", TIMEOUT.as_millis())]
+    Timeout(String),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub(crate) trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// The address to connect from: the one given, or else the unspecified address of the family
+/// chosen with `-4` or `-6`, as binding to it only connects to addresses of that family
+pub fn local_address(args: &CliArgs) -> Option<IpAddr> {
+    match (args.local_address, args.ipv4, args.ipv6) {
+        (Some(addr), _, _) => Some(addr),
+        (None, true, _) => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        (None, _, true) => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        (None, false, false) => None,
+    }
+}
+
+/// The options of the client that apply to the connections made outside of it
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    resolve: Vec<Resolve>,
+    local_address: Option<IpAddr>,
+    tls_min: Option<tls::Version>,
+    tls_max: Option<tls::Version>,
+    pinned_pubkey: Option<tls::PinnedKeys>,
+    proxies: Proxies,
+    proxy_user: Option<BasicAuth>,
+    /// Whether compressed responses are asked for and decompressed
+    pub decompress: bool,
+}
+
+/// How long the phases of a connection took
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    /// Resolving the host, or the proxy
+    pub dns: Duration,
+    /// Connecting to the host, or to the proxy and through its tunnel
+    pub connect: Duration,
+    /// The TLS handshake with the host, `None` for plain HTTP
+    pub tls: Option<Duration>,
+}
+
+/// A connection to the host of a URL
+pub(crate) struct Connection {
+    pub stream: Box<dyn Stream>,
+    /// Set when the requests are forwarded to an HTTP proxy, with its credentials if any
+    forwarded: Option<Option<HeaderValue>>,
+    pub timings: Timings,
+}
+
+impl Connection {
+    /// The target of the request line: the path and query, or the whole URL for a proxy
+    pub fn target(&self, url: &Url) -> String {
+        match (&self.forwarded, url.query()) {
+            (Some(_), _) => url.to_string(),
+            (None, Some(query)) => format!("{}?{}", url.path(), query),
+            (None, None) => url.path().to_string(),
+        }
+    }
+
+    /// The `Proxy-Authorization` header of the requests forwarded to a proxy
+    pub fn proxy_authorization(&self) -> Option<&HeaderValue> {
+        self.forwarded.as_ref().and_then(Option::as_ref)
+    }
+}
+
+/// The host of the URL, without the brackets of IPv6 addresses, and its port
+fn host_and_port(url: &Url) -> Result<(String, u16)> {
+    let invalid = || Error::Url(url.to_string());
+    let host = url.host_str().ok_or_else(invalid)?;
+    let port = url.port_or_known_default().ok_or_else(invalid)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host.to_string(), port))
+}
+
+fn is_tls(url: &Url) -> bool {
+    matches!(url.scheme(), "https" | "wss")
+}
+
+impl Options {
+    pub fn from_args(args: &CliArgs) -> Self {
+        Options {
+            resolve: args.resolve.clone(),
+            local_address: local_address(args),
+            tls_min: args.tls_min,
+            tls_max: args.tls_max,
+            pinned_pubkey: args.pinned_pubkey.clone(),
+            proxies: Proxies::from_env(),
+            proxy_user: args.proxy_user.clone(),
+            decompress: !args.no_decompress,
+        }
+    }
+
+    /// The TLS configuration of the client
+    pub(crate) fn tls_config(&self) -> io::Result<ClientConfig> {
+        match &self.pinned_pubkey {
+            Some(pins) => tls::pinned_config(pins, self.tls_min, self.tls_max),
+            None => tls::config(self.tls_min, self.tls_max),
+        }
+    }
+
+    /// The proxy for the URL, WebSockets going through the proxy of their HTTP counterpart
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let mut as_http = url.clone();
+        let scheme = match url.scheme() {
+            "ws" => "http",
+            "wss" => "https",
+            scheme => scheme,
+        };
+        as_http.set_scheme(scheme).ok()?;
+        match self.proxies.decide(&as_http) {
+            Decision::Proxy(proxy) => Some(proxy),
+            Decision::Direct(_) => None,
+        }
+    }
+
+    /// The credentials for the proxy: those of `--proxy-user`, or else those of its URL
+    fn proxy_authorization(&self, proxy: &Url) -> Option<HeaderValue> {
+        let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+        let auth = match &self.proxy_user {
+            Some(user) => user.clone(),
+            None if !proxy.username().is_empty() => BasicAuth {
+                user: decode(proxy.username()),
+                password: proxy.password().map(decode),
+            },
+            None => return None,
+        };
+        HeaderValue::from_str(&auth.header_value()).ok()
+    }
+
+    /// The addresses of the host: the `--resolve` override, whose port is ignored as by the
+    /// client, or else those of the system resolver of the family of the local address
+    async fn addresses(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        if let Some(resolve) = self
+            .resolve
+            .iter()
+            .find(|r| r.host.eq_ignore_ascii_case(host))
+        {
+            return Ok(vec![SocketAddr::new(resolve.addr.ip(), port)]);
+        }
+        let ipv6 = self.local_address.map(|addr| addr.is_ipv6());
+        let addresses: Vec<_> = lookup_host((host, port))
+            .await?
+            .filter(|addr| ipv6.is_none_or(|ipv6| addr.is_ipv6() == ipv6))
+            .collect();
+        if addresses.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no address for the host",
+            ));
+        }
+        Ok(addresses)
+    }
+
+    /// A TCP connection to the first of the addresses that accepts one, from the local address
+    async fn tcp(&self, addresses: &[SocketAddr]) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for &addr in addresses {
+            let socket = match addr {
+                SocketAddr::V4(_) => TcpSocket::new_v4()?,
+                SocketAddr::V6(_) => TcpSocket::new_v6()?,
+            };
+            if let Some(local) = self.local_address {
+                socket.bind(SocketAddr::new(local, 0))?;
+            }
+            match socket.connect(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::ErrorKind::NotFound.into()))
+    }
+
+    /// A connection to the host of the URL, or to the proxy for it, tunneled to the host unless
+    /// the requests are forwarded. The TLS handshake with the host is left to the caller.
+    pub(crate) async fn open(&self, url: &Url) -> Result<Connection> {
+        let (host, port) = host_and_port(url)?;
+        let proxy = self.proxy_for(url);
+        let (peer, peer_port) = match &proxy {
+            Some(proxy) => host_and_port(proxy)?,
+            None => (host.clone(), port),
+        };
+        let connect_error = |source| Error::Connect {
+            host: peer.clone(),
+            source,
+        };
+        let opening = async {
+            let start = Instant::now();
+            let addresses = self
+                .addresses(&peer, peer_port)
+                .await
+                .map_err(connect_error)?;
+            let dns = start.elapsed();
+            let start = Instant::now();
+            let mut stream: Box<dyn Stream> =
+                Box::new(self.tcp(&addresses).await.map_err(connect_error)?);
+            let mut forwarded = None;
+            if let Some(proxy) = &proxy {
+                if is_tls(proxy) {
+                    let config = tls::config(None, None).map_err(connect_error)?;
+                    stream = Box::new(handshake(stream, proxy, config).await?);
+                }
+                let authorization = self.proxy_authorization(proxy);
+                if url.scheme() == "http" {
+                    forwarded = Some(authorization);
+                } else {
+                    let authority = format!("{}:{}", url.host_str().unwrap_or_default(), port);
+                    tunnel(&mut stream, &authority, authorization.as_ref())
+                        .await
+                        .map_err(|e| match e {
+                            TunnelError::Io(source) => Error::Connect {
+                                host: peer.clone(),
+                                source,
+                            },
+                            TunnelError::Refused(status) => Error::Tunnel {
+                                host: authority.clone(),
+                                status,
+                            },
+                        })?;
+                }
+            }
+            let timings = Timings {
+                dns,
+                connect: start.elapsed(),
+                tls: None,
+            };
+            Ok(Connection {
+                stream,
+                forwarded,
+                timings,
+            })
+        };
+        tokio::time::timeout(TIMEOUT, opening)
+            .await
+            .map_err(|_| Error::Timeout(peer.clone()))?
+    }
+
+    /// A connection to the host of the URL, over TLS for HTTPS and secure WebSockets
+    pub(crate) async fn connect(&self, url: &Url) -> Result<Connection> {
+        let mut connection = self.open(url).await?;
+        if is_tls(url) {
+            let (host, _) = host_and_port(url)?;
+            let config = self.tls_config().map_err(|source| Error::Connect {
+                host: host.clone(),
+                source,
+            })?;
+            let start = Instant::now();
+            let stream = tokio::time::timeout(TIMEOUT, handshake(connection.stream, url, config))
+                .await
+                .map_err(|_| Error::Timeout(host))??;
+            connection.timings.tls = Some(start.elapsed());
+            connection.stream = Box::new(stream);
+        }
+        Ok(connection)
+    }
+
+    /// The TLS session with the host of the HTTPS URL for the certificate probe, which also
+    /// completes the handshake with servers whose certificate has expired
+    pub(crate) async fn probe(&self, url: &Url) -> Result<TlsStream<Box<dyn Stream>>> {
+        let connection = self.open(url).await?;
+        let (host, _) = host_and_port(url)?;
+        let config = tls::probe_config(self.pinned_pubkey.as_ref(), self.tls_min, self.tls_max)
+            .map_err(|source| Error::Connect {
+                host: host.clone(),
+                source,
+            })?;
+        tokio::time::timeout(TIMEOUT, handshake(connection.stream, url, config))
+            .await
+            .map_err(|_| Error::Timeout(host))?
+    }
+}
+
+/// The TLS handshake with the host of the URL over the stream
+pub(crate) async fn handshake(
+    stream: Box<dyn Stream>,
+    url: &Url,
+    config: ClientConfig,
+) -> Result<TlsStream<Box<dyn Stream>>> {
+    let (host, _) = host_and_port(url)?;
+    let name = DNSNameRef::try_from_ascii_str(&host).map_err(|_| Error::Url(url.to_string()))?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .map_err(|source| Error::Connect { host, source })
+}
+
+enum TunnelError {
+    Io(io::Error),
+    Refused(String),
+}
+
+impl From<io::Error> for TunnelError {
+    fn from(e: io::Error) -> Self {
+        TunnelError::Io(e)
+    }
+}
+
+/// Ask the proxy for a tunnel to `authority`, reading its response head and nothing more
+async fn tunnel(
+    stream: &mut Box<dyn Stream>,
+    authority: &str,
+    authorization: Option<&HeaderValue>,
+) -> std::result::Result<(), TunnelError> {
+    let mut head = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(authorization) = authorization.and_then(|value| value.to_str().ok()) {
+        head.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.flush().await?;
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > MAX_TUNNEL_RESPONSE {
+            return Err(TunnelError::Refused("response too long".to_string()));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(TunnelError::Refused(status_line.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{Options, Resolve};
+
+    #[test]
+    fn ipv6_hosts_lose_their_brackets() {
+        let url = Url::parse("ws://[::1]:9000/chat").unwrap();
+        assert_eq!(
+            super::host_and_port(&url).unwrap(),
+            ("::1".to_string(), 9000)
+        );
+    }
+    #[tokio::test]
+    async fn overridden_hosts_are_reached_at_their_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"hi").await.unwrap();
+        });
+        let options = Options {
+            resolve: vec!["api.invalid:1:127.0.0.1".parse::<Resolve>().unwrap()],
+            ..Options::default()
+        };
+        let url = Url::parse(&format!("http://api.invalid:{}/", port)).unwrap();
+        let mut connection = options.open(&url).await.unwrap();
+        assert_eq!(connection.target(&url), "/");
+        let mut read = String::new();
+        connection.stream.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "hi");
+        server.await.unwrap();
+    }
+}
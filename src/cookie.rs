@@ -0,0 +1,318 @@
+//! Cookies given with `--cookie`, as `name=value` pairs or read from a cookie file in the
+//! Netscape format written by curl and browser extensions, and the cookies set by the responses
+//! kept in a [`Jar`].
+use std::{
+    fs,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{header::HeaderMap, Url};
+use serde_json::{json, Value};
+
+use crate::datetime;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid cookie `{0}`, expected `name=value` or `@file`")]
+    Invalid(String),
+    #[error("Can't read the cookie file {0}: {1}")]
+    Read(String, String),
+    #[error("{0}:{1}: invalid cookie line, expected 7 fields separated by tabs")]
+    InvalidLine(String, usize),
+}
+
+/// A cookie of a cookie file, sent only to the URLs it matches
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    domain: String,
+    include_subdomains: bool,
+    path: String,
+    secure: bool,
+    /// Seconds since the epoch, 0 for a session cookie
+    expires: u64,
+    name: String,
+    value: String,
+}
+
+impl Entry {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "domain": self.domain,
+            "include_subdomains": self.include_subdomains,
+            "path": self.path,
+            "secure": self.secure,
+            "expires": self.expires,
+            "name": self.name,
+            "value": self.value,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Entry {
+            domain: value["domain"].as_str()?.to_string(),
+            include_subdomains: value["include_subdomains"].as_bool().unwrap_or_default(),
+            path: value["path"].as_str().unwrap_or("/").to_string(),
+            secure: value["secure"].as_bool().unwrap_or_default(),
+            expires: value["expires"].as_u64().unwrap_or_default(),
+            name: value["name"].as_str()?.to_string(),
+            value: value["value"].as_str()?.to_string(),
+        })
+    }
+
+    /// Whether the cookie expired, so that it is no longer sent
+    pub fn is_expired(&self) -> bool {
+        self.expires != 0 && self.expires <= now()
+    }
+
+    fn matches(&self, url: &Url, now: u64) -> bool {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let domain = self.domain.trim_start_matches('.').to_ascii_lowercase();
+        let domain_matches =
+            host == domain || (self.include_subdomains && host.ends_with(&format!(".{}", domain)));
+        domain_matches
+            && url.path().starts_with(&self.path)
+            && (!self.secure || url.scheme() == "https")
+            && (self.expires == 0 || self.expires > now)
+    }
+}
+
+/// Parse a Netscape cookie file. `#HttpOnly_` marks HTTP only cookies, other lines starting with
+/// `#` are comments.
+fn parse_file(content: &str, source: &str) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || Error::InvalidLine(source.to_string(), number + 1);
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(invalid());
+        }
+        entries.push(Entry {
+            domain: fields[0].to_string(),
+            include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+            path: fields[2].to_string(),
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            expires: fields[4].parse().map_err(|_| invalid())?,
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// A `--cookie` argument: a cookie sent with every request, or the cookies of a file
+#[derive(Debug, Clone)]
+pub enum Cookie {
+    Pair(String, String),
+    File(Vec<Entry>),
+}
+
+impl FromStr for Cookie {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix('@') {
+            let content = fs::read_to_string(path)
+                .map_err(|e| Error::Read(path.to_string(), e.to_string()))?;
+            return parse_file(&content, path).map(Cookie::File);
+        }
+        match s.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => Ok(Cookie::Pair(
+                name.trim().to_string(),
+                value.trim().to_string(),
+            )),
+            _ => Err(Error::Invalid(s.to_string())),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The cookie of a `Set-Cookie` header received from `url`. `Max-Age` wins over `Expires`, a
+/// cookie already expired is kept so that it replaces the previous one.
+fn parse_set_cookie(value: &str, url: &Url, now: u64) -> Option<Entry> {
+    let mut attributes = value.split(';');
+    let (name, value) = attributes.next()?.split_once('=')?;
+    if name.trim().is_empty() {
+        return None;
+    }
+    let default_path = match url.path().rfind('/') {
+        Some(0) | None => "/",
+        Some(end) => &url.path()[..end],
+    };
+    let mut entry = Entry {
+        domain: url.host_str()?.to_ascii_lowercase(),
+        include_subdomains: false,
+        path: default_path.to_string(),
+        secure: false,
+        expires: 0,
+        name: name.trim().to_string(),
+        value: value.trim().to_string(),
+    };
+    let mut max_age = None;
+    for attribute in attributes {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                entry.domain = value.trim_start_matches('.').to_ascii_lowercase();
+                entry.include_subdomains = true;
+            }
+            "path" if value.starts_with('/') => entry.path = value.to_string(),
+            "secure" => entry.secure = true,
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" if entry.expires == 0 => {
+                entry.expires = datetime::parse_http_date(value).map_or(0, |t| t.max(1) as u64);
+            }
+            _ => {}
+        }
+    }
+    if let Some(max_age) = max_age {
+        entry.expires = if max_age > 0 { now + max_age as u64 } else { 1 };
+    }
+    Some(entry)
+}
+
+/// The cookies set by the responses, sent back with the following requests
+#[derive(Debug, Default)]
+pub struct Jar {
+    entries: Vec<Entry>,
+}
+
+impl Jar {
+    /// Keep the cookies set by the response received from `url`, replacing the ones with the
+    /// same name, domain and path
+    pub fn store(&mut self, url: &str, headers: &HeaderMap) {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let now = now();
+        for value in headers.get_all(reqwest::header::SET_COOKIE) {
+            let entry = value
+                .to_str()
+                .ok()
+                .and_then(|value| parse_set_cookie(value, &url, now));
+            if let Some(entry) = entry {
+                self.entries.retain(|e| {
+                    (&e.name, &e.domain, &e.path) != (&entry.name, &entry.domain, &entry.path)
+                });
+                self.entries.push(entry);
+            }
+        }
+    }
+
+    pub fn from_entries(entries: Vec<Entry>) -> Self {
+        Jar { entries }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The value of the `Cookie` header to send to `url`, if any cookie applies to it
+    pub fn header_value(&self, url: &str) -> Option<String> {
+        header_value(&[Cookie::File(self.entries.clone())], url)
+    }
+}
+
+/// The value of the `Cookie` header to send to `url`, if any cookie applies to it
+pub fn header_value(cookies: &[Cookie], url: &str) -> Option<String> {
+    let url = Url::parse(url).ok();
+    let now = now();
+    let mut pairs = Vec::new();
+    for cookie in cookies {
+        match cookie {
+            Cookie::Pair(name, value) => pairs.push(format!("{}={}", name, value)),
+            Cookie::File(entries) => pairs.extend(
+                entries
+                    .iter()
+                    .filter(|entry| matches!(&url, Some(url) if entry.matches(url, now)))
+                    .map(|entry| format!("{}={}", entry.name, entry.value)),
+            ),
+        }
+    }
+    Some(pairs.join("; ")).filter(|header| !header.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cookie;
+
+    const FILE: &str = "# Netscape HTTP Cookie File\n\
+        .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n\
+        #HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t0\ttoken\txyz\n\
+        example.com\tFALSE\t/\tFALSE\t1\texpired\told\n";
+
+    #[test]
+    fn cookies_are_parsed() {
+        assert!(
+            matches!("a=1".parse(), Ok(Cookie::Pair(name, value)) if name == "a" && value == "1")
+        );
+        assert!("a".parse::<Cookie>().is_err());
+        assert!(super::parse_file("example.com\tTRUE\t/", "jar").is_err());
+        assert_eq!(super::parse_file(FILE, "jar").unwrap().len(), 3);
+    }
+    #[test]
+    fn file_cookies_are_sent_to_matching_urls() {
+        let cookies = vec![
+            Cookie::Pair("a".to_string(), "1".to_string()),
+            Cookie::File(super::parse_file(FILE, "jar").unwrap()),
+        ];
+        let header = |url| super::header_value(&cookies, url);
+        assert_eq!(
+            header("https://api.example.com/v1/users").as_deref(),
+            Some("a=1; session=abc; token=xyz")
+        );
+        assert_eq!(
+            header("http://api.example.com/v1/users").as_deref(),
+            Some("a=1; session=abc")
+        );
+        assert_eq!(header("http://other.org/").as_deref(), Some("a=1"));
+        assert_eq!(
+            super::header_value(&cookies[1..], "http://other.org/"),
+            None
+        );
+    }
+    #[test]
+    fn jar_keeps_the_cookies_set_by_responses() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for value in [
+            "session=abc; Path=/; HttpOnly",
+            "theme=dark; Domain=example.com; Max-Age=3600",
+            "secret=1; Path=/admin; Secure",
+        ] {
+            headers.append("set-cookie", value.parse().unwrap());
+        }
+        let mut jar = super::Jar::default();
+        jar.store("http://example.com/login", &headers);
+        let header = |jar: &super::Jar, url| jar.header_value(url);
+        assert_eq!(
+            header(&jar, "http://example.com/me").as_deref(),
+            Some("session=abc; theme=dark")
+        );
+        assert_eq!(
+            header(&jar, "http://api.example.com/").as_deref(),
+            Some("theme=dark")
+        );
+        assert_eq!(
+            header(&jar, "https://example.com/admin/users").as_deref(),
+            Some("session=abc; theme=dark; secret=1")
+        );
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.append("set-cookie", "session=; Path=/; Max-Age=0".parse().unwrap());
+        jar.store("http://example.com/logout", &headers);
+        assert_eq!(
+            header(&jar, "http://example.com/me").as_deref(),
+            Some("theme=dark")
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! `rq cors`: send the preflight request a browser would send before a cross-origin request, and
+//! tell which `Access-Control-*` headers of the response allow or block it
+//! (https://fetch.spec.whatwg.org/#http-cors-protocol).
+use reqwest::{
+    header::{HeaderMap, ORIGIN},
+    Client, Method, StatusCode,
+};
+
+use crate::http::HttpMethod;
+
+const ALLOW_ORIGIN: &str = "access-control-allow-origin";
+const ALLOW_CREDENTIALS: &str = "access-control-allow-credentials";
+const ALLOW_METHODS: &str = "access-control-allow-methods";
+const ALLOW_HEADERS: &str = "access-control-allow-headers";
+const MAX_AGE: &str = "access-control-max-age";
+
+/// The cross-origin request the browser is asked to make
+#[derive(Debug, Clone)]
+pub struct Preflight {
+    pub origin: String,
+    pub method: HttpMethod,
+    /// The non-safelisted headers of the request, lowercase
+    pub headers: Vec<String>,
+    /// Whether the request includes cookies or HTTP authentication
+    pub credentials: bool,
+}
+
+/// What a response header means for the request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow(String),
+    Block(String),
+    Note(String),
+}
+
+/// The comma-separated values of a header, all its lines included
+fn list(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Evaluate the response to the preflight as the browser would, one verdict per check
+pub fn evaluate(preflight: &Preflight, status: StatusCode, headers: &HeaderMap) -> Vec<Verdict> {
+    let mut verdicts = Vec::new();
+    if status.is_success() {
+        verdicts.push(Verdict::Allow(format!("status {}", status)));
+    } else {
+        verdicts.push(Verdict::Block(format!(
+            "status {}, the preflight must succeed with a 2xx status without redirects",
+            status
+        )));
+    }
+
+    let origins: Vec<_> = headers
+        .get_all(ALLOW_ORIGIN)
+        .iter()
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .collect();
+    verdicts.push(match origins.as_slice() {
+        [] => Verdict::Block(format!("origin: no {} header", ALLOW_ORIGIN)),
+        [origin] if origin == "*" && preflight.credentials => Verdict::Block(format!(
+            "origin: {} is `*`, which is not allowed with credentials",
+            ALLOW_ORIGIN
+        )),
+        [origin] if origin == "*" || *origin == preflight.origin => {
+            Verdict::Allow(format!("origin: {} is `{}`", ALLOW_ORIGIN, origin))
+        }
+        [origin] => Verdict::Block(format!(
+            "origin: {} is `{}`, not `{}`",
+            ALLOW_ORIGIN, origin, preflight.origin
+        )),
+        _ => Verdict::Block(format!(
+            "origin: {} is sent {} times, it must be sent once",
+            ALLOW_ORIGIN,
+            origins.len()
+        )),
+    });
+    let varies = list(headers, "vary")
+        .iter()
+        .any(|v| v.eq_ignore_ascii_case("origin"));
+    if origins.len() == 1 && origins[0] != "*" && !varies {
+        verdicts.push(Verdict::Note(
+            "the allowed origin depends on the request but `Vary: Origin` is missing, caches may \
+            serve it to other origins"
+                .to_string(),
+        ));
+    }
+
+    if preflight.credentials {
+        let allowed = headers.get(ALLOW_CREDENTIALS).map(|v| v.as_bytes());
+        verdicts.push(if allowed == Some(b"true") {
+            Verdict::Allow(format!("credentials: {} is `true`", ALLOW_CREDENTIALS))
+        } else {
+            Verdict::Block(format!("credentials: {} is not `true`", ALLOW_CREDENTIALS))
+        });
+    }
+
+    let methods = list(headers, ALLOW_METHODS);
+    let method = preflight.method.to_string();
+    let wildcard = !preflight.credentials && methods.iter().any(|m| m == "*");
+    verdicts.push(
+        // Methods are compared case-sensitively, `put` doesn't allow PUT
+        if methods.contains(&method) || wildcard {
+            Verdict::Allow(format!("method {}: listed in {}", method, ALLOW_METHODS))
+        } else if matches!(
+            preflight.method,
+            HttpMethod::Get | HttpMethod::Head | HttpMethod::Post
+        ) {
+            Verdict::Allow(format!("method {}: safelisted", method))
+        } else {
+            Verdict::Block(format!(
+                "method {}: not in {} ({})",
+                method,
+                ALLOW_METHODS,
+                methods.join(", ")
+            ))
+        },
+    );
+
+    let allowed: Vec<_> = list(headers, ALLOW_HEADERS)
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    let wildcard = !preflight.credentials && allowed.iter().any(|h| h == "*");
+    for header in &preflight.headers {
+        // The wildcard doesn't cover Authorization, it must be listed
+        verdicts.push(
+            if allowed.contains(header) || (wildcard && header != "authorization") {
+                Verdict::Allow(format!("header {}: listed in {}", header, ALLOW_HEADERS))
+            } else {
+                Verdict::Block(format!(
+                    "header {}: not in {} ({})",
+                    header,
+                    ALLOW_HEADERS,
+                    allowed.join(", ")
+                ))
+            },
+        );
+    }
+
+    if let Some(max_age) = headers.get(MAX_AGE).and_then(|v| v.to_str().ok()) {
+        verdicts.push(Verdict::Note(format!(
+            "the preflight is cached for {} s at most, browsers cap it",
+            max_age
+        )));
+    }
+    verdicts
+}
+
+/// Send the preflight to `url`, print the verdicts and return how many checks block the request
+pub async fn run(client: &Client, url: &str, preflight: &Preflight) -> crate::Result<usize> {
+    let mut request = client
+        .request(Method::OPTIONS, url)
+        .header(ORIGIN, &preflight.origin)
+        .header(
+            "access-control-request-method",
+            preflight.method.to_string(),
+        );
+    if !preflight.headers.is_empty() {
+        request = request.header(
+            "access-control-request-headers",
+            preflight.headers.join(","),
+        );
+    }
+    let response = request.send().await?;
+    println!("OPTIONS {}", url);
+    for (name, value) in response.headers() {
+        if name.as_str().starts_with("access-control-") || name == "vary" {
+            println!(
+                "    {}: {}",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            );
+        }
+    }
+    println!();
+    let verdicts = evaluate(preflight, response.status(), response.headers());
+    let mut blocked = 0;
+    for verdict in verdicts {
+        match verdict {
+            Verdict::Allow(reason) => println!("ALLOW {}", reason),
+            Verdict::Block(reason) => {
+                blocked += 1;
+                println!("BLOCK {}", reason)
+            }
+            Verdict::Note(reason) => println!("NOTE  {}", reason),
+        }
+    }
+    println!();
+    if blocked == 0 {
+        println!(
+            "The browser would send the {} request from {}",
+            preflight.method, preflight.origin
+        );
+    } else {
+        println!(
+            "The browser would block the {} request from {}",
+            preflight.method, preflight.origin
+        );
+    }
+    Ok(blocked)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{header::HeaderMap, StatusCode};
+
+    use super::{Preflight, Verdict};
+    use crate::http::HttpMethod;
+
+    fn preflight(credentials: bool) -> Preflight {
+        Preflight {
+            origin: "https://app.example.com".to_string(),
+            method: HttpMethod::Put,
+            headers: vec!["x-custom".to_string()],
+            credentials,
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    fn blocked(verdicts: &[Verdict]) -> Vec<&str> {
+        verdicts
+            .iter()
+            .filter_map(|v| match v {
+                Verdict::Block(reason) => reason.split(':').next(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matching_headers_allow_the_request() {
+        let headers = headers(&[
+            ("access-control-allow-origin", "https://app.example.com"),
+            ("access-control-allow-methods", "GET, PUT"),
+            ("access-control-allow-headers", "X-Custom"),
+            ("vary", "Origin"),
+        ]);
+        let verdicts = super::evaluate(&preflight(false), StatusCode::NO_CONTENT, &headers);
+        assert!(blocked(&verdicts).is_empty());
+        assert!(!verdicts.iter().any(|v| matches!(v, Verdict::Note(_))));
+    }
+    #[test]
+    fn each_missing_permission_is_reported() {
+        let headers = headers(&[
+            ("access-control-allow-origin", "https://other.example.com"),
+            ("access-control-allow-methods", "GET, put"),
+        ]);
+        let verdicts = super::evaluate(&preflight(false), StatusCode::OK, &headers);
+        assert_eq!(
+            blocked(&verdicts),
+            ["origin", "method PUT", "header x-custom"]
+        );
+        let verdicts = super::evaluate(&preflight(false), StatusCode::FORBIDDEN, &HeaderMap::new());
+        assert!(blocked(&verdicts)[0].starts_with("status 403"));
+    }
+    #[test]
+    fn wildcards_are_ignored_with_credentials() {
+        let headers = headers(&[
+            ("access-control-allow-origin", "*"),
+            ("access-control-allow-methods", "*"),
+            ("access-control-allow-headers", "*"),
+        ]);
+        let verdicts = super::evaluate(&preflight(false), StatusCode::OK, &headers);
+        assert!(blocked(&verdicts).is_empty());
+        let verdicts = super::evaluate(&preflight(true), StatusCode::OK, &headers);
+        assert_eq!(
+            blocked(&verdicts),
+            ["origin", "credentials", "method PUT", "header x-custom"]
+        );
+    }
+}
@@ -0,0 +1,528 @@
+//! Conversion between requests and curl command lines.
+use std::fs;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, REFERER, USER_AGENT};
+
+use crate::{
+    cli::normalize_url,
+    http::{BasicAuth, ContentType, Header, HttpMethod},
+    multipart,
+    request::Request,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid curl command: {0}")]
+    Syntax(String),
+    #[error("Unsupported curl option: {0}")]
+    UnsupportedOption(String),
+    #[error("Missing value for curl option {0}")]
+    MissingValue(String),
+    #[error("{0}")]
+    Http(#[from] crate::http::Error),
+    #[error("Failed to read {0}: {1}")]
+    ReadFile(String, std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Quote the argument for POSIX shells if it contains characters having special meaning
+pub(crate) fn shell_quote(s: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !s.is_empty() && s.chars().all(is_safe) {
+        s.to_string()
+    } else {
+        // Inside single quotes nothing is special except the single quote itself, which has to
+        // be written as `'\''`: close the quote, escaped quote, reopen the quote
+        format!("'{}'", s.replace('\'', r#"'\''"#))
+    }
+}
+
+/// Build a curl command sending the same request as rq would
+pub fn to_curl(req: &Request) -> String {
+    let mut args = vec!["curl".to_string()];
+    let has_body = req.body.is_some() || !req.form.is_empty() || req.upload.is_some();
+    match (&req.method, has_body) {
+        // These are the methods curl uses by default
+        (HttpMethod::Get, false) | (HttpMethod::Post, true) => {}
+        (HttpMethod::Head, false) => args.push("--head".to_string()),
+        (method, _) => {
+            args.push("-X".to_string());
+            args.push(method.to_string());
+        }
+    }
+    for (name, value) in &req.headers {
+        let basic_auth = if name.eq_ignore_ascii_case(AUTHORIZATION.as_str()) {
+            BasicAuth::from_header_value(value)
+        } else {
+            None
+        };
+        match basic_auth {
+            Some(auth) => {
+                args.push("-u".to_string());
+                let mut credentials = auth.user;
+                if let Some(password) = auth.password {
+                    credentials.push(':');
+                    credentials.push_str(&password);
+                }
+                args.push(shell_quote(&credentials));
+            }
+            None => {
+                args.push("-H".to_string());
+                args.push(shell_quote(&format!("{}: {}", name, value)));
+            }
+        }
+    }
+    if let Some(upload) = &req.upload {
+        args.push("--data-binary".to_string());
+        args.push(shell_quote(&upload.to_arg()));
+    } else if req.form.is_empty() {
+        if let Some(body) = &req.body {
+            args.push("--data-raw".to_string());
+            args.push(shell_quote(body));
+        }
+    }
+    for part in &req.form {
+        args.push("-F".to_string());
+        args.push(shell_quote(&part.to_arg()));
+    }
+    // rq accepts compressed responses and decompresses them, so does curl with this flag
+    args.push("--compressed".to_string());
+    args.push(shell_quote(&req.url));
+    args.join(" ")
+}
+
+/// Build the rq command sending the same request
+pub fn to_rq_command(req: &Request) -> String {
+    let mut args = vec!["rq".to_string()];
+    if !matches!(req.method, HttpMethod::Get) {
+        args.push("-m".to_string());
+        args.push(req.method.to_string());
+    }
+    for (name, value) in &req.headers {
+        let basic_auth = if name.eq_ignore_ascii_case(AUTHORIZATION.as_str()) {
+            BasicAuth::from_header_value(value)
+        } else {
+            None
+        };
+        match basic_auth {
+            Some(auth) => {
+                args.push("-a".to_string());
+                let mut credentials = auth.user;
+                if let Some(password) = auth.password {
+                    credentials.push(':');
+                    credentials.push_str(&password);
+                }
+                args.push(shell_quote(&credentials));
+            }
+            None => {
+                args.push("-H".to_string());
+                args.push(shell_quote(&format!("{}: {}", name, value)));
+            }
+        }
+    }
+    if let Some(upload) = &req.upload {
+        args.push("--data-binary".to_string());
+        args.push(shell_quote(&upload.to_arg()));
+    } else if req.form.is_empty() {
+        if let Some(body) = &req.body {
+            args.push("-d".to_string());
+            args.push(shell_quote(body));
+        }
+    }
+    for part in &req.form {
+        args.push("-F".to_string());
+        args.push(shell_quote(&part.to_arg()));
+    }
+    if let Some(coding) = req.compress {
+        args.push(format!("--compress={}", coding));
+    }
+    args.push(shell_quote(&req.url));
+    args.join(" ")
+}
+
+/// Split a command line into words the way a POSIX shell does, handling single quotes, double
+/// quotes, backslash escapes, line continuations and the `$'...'` quoting produced by browsers'
+/// "Copy as cURL".
+pub fn split_shell_words(s: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(w) = word.take() {
+                    words.push(w);
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuation
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(Error::Syntax("trailing backslash".to_string())),
+            },
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err(Error::Syntax("unterminated single quote".to_string())),
+                    }
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // Inside double quotes, backslash only escapes these characters
+                        Some('\\') if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                            w.push(chars.next().unwrap_or_default());
+                        }
+                        Some('\\') if chars.peek() == Some(&'\n') => {
+                            chars.next();
+                        }
+                        Some(c) => w.push(c),
+                        None => return Err(Error::Syntax("unterminated double quote".to_string())),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => w.push(ansi_c_escape(&mut chars)?),
+                        Some(c) => w.push(c),
+                        None => return Err(Error::Syntax("unterminated $' quote".to_string())),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Decode the escape sequence following a backslash inside `$'...'`
+fn ansi_c_escape(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<char> {
+    let invalid = || Error::Syntax("invalid escape sequence in $' quote".to_string());
+    let hex_len = match chars.next().ok_or_else(invalid)? {
+        'n' => return Ok('\n'),
+        't' => return Ok('\t'),
+        'r' => return Ok('\r'),
+        'a' => return Ok('\x07'),
+        'b' => return Ok('\x08'),
+        'e' | 'E' => return Ok('\x1b'),
+        'f' => return Ok('\x0c'),
+        'v' => return Ok('\x0b'),
+        '0' => return Ok('\0'),
+        'x' => 2,
+        'u' => 4,
+        'U' => 8,
+        c => return Ok(c),
+    };
+    let digits: String = (0..hex_len).filter_map(|_| chars.next()).collect();
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(invalid)
+}
+
+/// Read the value of a `-d` style option, which may reference a file with `@path`
+fn read_data(value: &str, strip_newlines: bool) -> Result<String> {
+    match value.strip_prefix('@') {
+        Some(path) => {
+            let content = if path == "-" {
+                std::io::read_to_string(std::io::stdin())
+            } else {
+                fs::read_to_string(path)
+            }
+            .map_err(|e| Error::ReadFile(path.to_string(), e))?;
+            Ok(if strip_newlines {
+                content.replace(['\r', '\n'], "")
+            } else {
+                content
+            })
+        }
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Encode the value of `--data-urlencode`, which is either `content`, `=content`,
+/// `name=content`, `@file` or `name@file`
+fn data_urlencode(value: &str) -> Result<String> {
+    let encode = |s: &str| utf8_percent_encode(s, NON_ALPHANUMERIC).to_string();
+    if let Some((name, content)) = value.split_once('=') {
+        return Ok(if name.is_empty() {
+            encode(content)
+        } else {
+            format!("{}={}", name, encode(content))
+        });
+    }
+    match value.split_once('@') {
+        Some((name, path)) => {
+            let content = read_data(&format!("@{}", path), false)?;
+            Ok(if name.is_empty() {
+                encode(&content)
+            } else {
+                format!("{}={}", name, encode(&content))
+            })
+        }
+        None => Ok(encode(value)),
+    }
+}
+
+/// Parse a curl command line, with or without the leading `curl`, into a request. Options not
+/// affecting the request itself (`-s`, `-v`, `-L`, `--compressed`...) are ignored.
+pub fn from_curl(command: &str) -> Result<Request> {
+    let mut words = split_shell_words(command)?.into_iter().peekable();
+    if words.peek().map(String::as_str) == Some("curl") {
+        words.next();
+    }
+    let mut method: Option<HttpMethod> = None;
+    let mut url = None;
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut data: Vec<String> = Vec::new();
+    let mut head = false;
+    let mut get = false;
+    while let Some(word) = words.next() {
+        // Support both `--opt value` and `--opt=value` for long options
+        let (option, inline_value) = match word.split_once('=') {
+            Some((opt, value)) if opt.starts_with("--") => {
+                (opt.to_string(), Some(value.to_string()))
+            }
+            _ => (word.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| words.next())
+                .ok_or_else(|| Error::MissingValue(option.clone()))
+        };
+        match option.as_str() {
+            "-X" | "--request" => method = Some(value()?.parse()?),
+            "-H" | "--header" => {
+                let header: Header = value()?.parse()?;
+                headers.push((header.name, header.value));
+            }
+            "-d" | "--data" | "--data-ascii" => data.push(read_data(&value()?, true)?),
+            "--data-binary" => data.push(read_data(&value()?, false)?),
+            "--data-raw" => data.push(value()?),
+            "--data-urlencode" => data.push(data_urlencode(&value()?)?),
+            "--json" => {
+                data.push(read_data(&value()?, false)?);
+                headers.push((CONTENT_TYPE.to_string(), ContentType::Json.to_string()));
+                headers.push(("Accept".to_string(), ContentType::Json.to_string()));
+            }
+            "-u" | "--user" => {
+                let auth: BasicAuth = value()?.parse()?;
+                headers.push((AUTHORIZATION.to_string(), auth.header_value()));
+            }
+            "-A" | "--user-agent" => headers.push((USER_AGENT.to_string(), value()?)),
+            "-e" | "--referer" => headers.push((REFERER.to_string(), value()?)),
+            "-b" | "--cookie" => headers.push((COOKIE.to_string(), value()?)),
+            "--url" => url = Some(value()?),
+            "-I" | "--head" => head = true,
+            "-G" | "--get" => get = true,
+            "--compressed" | "-s" | "--silent" | "-S" | "--show-error" | "-v" | "--verbose"
+            | "-L" | "--location" | "-i" | "--include" | "-k" | "--insecure" | "-f" | "--fail"
+            | "-#" | "--progress-bar" | "--http1.1" | "--http2" => {}
+            "-o" | "--output" | "-m" | "--max-time" | "--connect-timeout" | "-w"
+            | "--write-out" => {
+                value()?;
+            }
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(Error::UnsupportedOption(option.to_string()))
+            }
+            _ => url = Some(word),
+        }
+    }
+    let mut url = url.ok_or_else(|| Error::Syntax("no URL found".to_string()))?;
+    normalize_url(&mut url);
+    let mut body = None;
+    if !data.is_empty() {
+        let joined = data.join("&");
+        if get {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&joined);
+        } else {
+            body = Some(joined);
+        }
+    }
+    // curl sends data as a form unless told otherwise
+    let has_content_type = headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()));
+    if body.is_some() && !has_content_type {
+        headers.push((CONTENT_TYPE.to_string(), ContentType::Form.to_string()));
+    }
+    let method = method.unwrap_or(match (head, &body) {
+        (true, _) => HttpMethod::Head,
+        (false, Some(_)) => HttpMethod::Post,
+        (false, None) => HttpMethod::Get,
+    });
+    Ok(Request {
+        method,
+        url,
+        headers,
+        body,
+        form: Vec::new(),
+        boundary: multipart::boundary(),
+        upload: None,
+        compress: None,
+        unix_socket: None,
+        expect_continue: false,
+        connect: None,
+        redirects: Default::default(),
+        max_response_size: None,
+        limit_rate: None,
+        trace: None,
+        pipeline: Default::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{http::HttpMethod, multipart, request::Request};
+
+    fn request(method: HttpMethod, body: Option<&str>) -> Request {
+        Request {
+            method,
+            url: "http://example.com/a?b=c&d".to_string(),
+            headers: Vec::new(),
+            body: body.map(str::to_string),
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Default::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Default::default(),
+        }
+    }
+
+    #[test]
+    fn special_chars_are_quoted() {
+        assert_eq!(super::shell_quote("abc"), "abc");
+        assert_eq!(super::shell_quote(""), "''");
+        assert_eq!(super::shell_quote("a b"), "'a b'");
+        assert_eq!(super::shell_quote("it's"), r#"'it'\''s'"#);
+    }
+    #[test]
+    fn default_methods_are_omitted() {
+        assert_eq!(
+            super::to_curl(&request(HttpMethod::Get, None)),
+            "curl --compressed 'http://example.com/a?b=c&d'"
+        );
+        assert_eq!(
+            super::to_curl(&request(HttpMethod::Post, Some("{\"a\": 1}"))),
+            "curl --data-raw '{\"a\": 1}' --compressed 'http://example.com/a?b=c&d'"
+        );
+        assert_eq!(
+            super::to_curl(&request(HttpMethod::Delete, None)),
+            "curl -X DELETE --compressed 'http://example.com/a?b=c&d'"
+        );
+    }
+    #[test]
+    fn shell_words_are_split() {
+        let words = super::split_shell_words(
+            "curl 'a b' \"c \\\"d\\\" $e\" f\\ g \\\n  $'h\\ni\\'' --x=\"\"",
+        )
+        .unwrap();
+        assert_eq!(
+            words,
+            vec!["curl", "a b", "c \"d\" $e", "f g", "h\ni'", "--x="]
+        );
+        assert!(super::split_shell_words("'open").is_err());
+    }
+    #[test]
+    fn curl_command_is_parsed() {
+        let req = super::from_curl(
+            "curl 'https://example.com/api' -H 'Accept: application/json' \
+            -u bob:secret --data-raw '{\"a\":1}' -H 'Content-Type: application/json' \
+            --compressed",
+        )
+        .unwrap();
+        assert!(matches!(req.method, HttpMethod::Post));
+        assert_eq!(req.url, "https://example.com/api");
+        assert_eq!(req.body.as_deref(), Some("{\"a\":1}"));
+        assert_eq!(
+            req.headers,
+            vec![
+                ("Accept".to_string(), "application/json".to_string()),
+                (
+                    "authorization".to_string(),
+                    "Basic Ym9iOnNlY3JldA==".to_string()
+                ),
+                ("Content-Type".to_string(), "application/json".to_string()),
+            ]
+        );
+    }
+    #[test]
+    fn curl_data_defaults_to_form() {
+        let req =
+            super::from_curl("curl -d a=1 -d b=2 --data-urlencode 'c=x y' example.com").unwrap();
+        assert_eq!(req.body.as_deref(), Some("a=1&b=2&c=x%20y"));
+        assert_eq!(req.url, "http://example.com");
+        assert_eq!(
+            req.headers,
+            vec![(
+                "content-type".to_string(),
+                "application/x-www-form-urlencoded".to_string()
+            )]
+        );
+    }
+    #[test]
+    fn curl_get_flag_moves_data_to_query() {
+        let req = super::from_curl("curl -G -d a=1 'http://example.com/?b=2' -X DELETE").unwrap();
+        assert!(matches!(req.method, HttpMethod::Delete));
+        assert_eq!(req.url, "http://example.com/?b=2&a=1");
+        assert_eq!(req.body, None);
+    }
+    #[test]
+    fn unknown_curl_options_are_rejected() {
+        assert!(super::from_curl("curl --proxy x example.com").is_err());
+        assert!(super::from_curl("curl -H").is_err());
+        assert!(super::from_curl("curl -v").is_err());
+    }
+    #[test]
+    fn rq_command_is_built() {
+        let mut req = request(HttpMethod::Put, Some("a=1"));
+        req.headers = vec![(
+            "Authorization".to_string(),
+            "Basic dXNlcjpwYXNz".to_string(),
+        )];
+        assert_eq!(
+            super::to_rq_command(&req),
+            "rq -m PUT -a user:pass -d a=1 'http://example.com/a?b=c&d'"
+        );
+    }
+    #[test]
+    fn basic_auth_is_converted_to_user_flag() {
+        let mut req = request(HttpMethod::Get, None);
+        req.headers = vec![
+            (
+                "Authorization".to_string(),
+                "Basic dXNlcjpwYXNz".to_string(),
+            ),
+            ("Accept".to_string(), "text/html".to_string()),
+        ];
+        assert_eq!(
+            super::to_curl(&req),
+            "curl -u user:pass -H 'Accept: text/html' --compressed 'http://example.com/a?b=c&d'"
+        );
+    }
+}
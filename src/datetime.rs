@@ -0,0 +1,157 @@
+//! Formatting of timestamps without pulling a date library.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Convert a number of days since 1970-01-01 to a (year, month, day) civil date.
+///
+/// This is the algorithm from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert a (year, month, day) civil date to a number of days since 1970-01-01, the inverse of
+/// `civil_from_days`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Parse an RFC 3339 date or date-time in UTC, e.g. `2021-10-01` or `2021-10-01T12:30:00Z`, into
+/// a Unix timestamp in seconds
+pub fn parse_rfc3339(s: &str) -> Option<i64> {
+    let (date, time) = match s.split_once(&['T', ' '][..]) {
+        Some((date, time)) => (date, Some(time.trim_end_matches('Z'))),
+        None => (s, None),
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let secs_of_day = match time {
+        Some(time) => {
+            let mut parts = time.splitn(3, ':').map(str::parse::<u32>);
+            let (hour, minute) = (parts.next()?.ok()?, parts.next()?.ok()?);
+            let second = parts.next().transpose().ok()?.unwrap_or(0);
+            if hour > 23 || minute > 59 || second > 60 {
+                return None;
+            }
+            (hour * 3600 + minute * 60 + second) as i64
+        }
+        None => 0,
+    };
+    Some(days_from_civil(year as i64, month, day) * 86_400 + secs_of_day)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parse an HTTP date in the preferred format, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, into a Unix
+/// timestamp in seconds. The obsolete formats are not supported.
+pub fn parse_http_date(s: &str) -> Option<i64> {
+    let mut parts = s.split_once(", ")?.1.split(' ');
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let time = parts.next()?;
+    if parts.next() != Some("GMT") {
+        return None;
+    }
+    parse_rfc3339(&format!("{:04}-{:02}-{:02}T{}Z", year, month, day, time))
+}
+
+/// Format a Unix timestamp in seconds as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn format_http_date(secs: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let days = secs.div_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let secs_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        // 1970-01-01 was a Thursday
+        WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Format a Unix timestamp in milliseconds as RFC 3339 in UTC, e.g. `2021-10-01T12:30:00.000Z`
+pub fn format_rfc3339_millis(millis: i64) -> String {
+    let secs = millis.div_euclid(1000);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let secs_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        millis.rem_euclid(1000)
+    )
+}
+
+/// Milliseconds elapsed since the Unix epoch
+pub fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn epoch_is_formatted() {
+        assert_eq!(super::format_rfc3339_millis(0), "1970-01-01T00:00:00.000Z");
+    }
+    #[test]
+    fn leap_day_is_formatted() {
+        assert_eq!(
+            super::format_rfc3339_millis(951_782_400_123),
+            "2000-02-29T00:00:00.123Z"
+        );
+    }
+    #[test]
+    fn http_date_is_formatted_from_rfc3339() {
+        let secs = super::parse_rfc3339("1994-11-06T08:49:37Z").unwrap();
+        assert_eq!(
+            super::format_http_date(secs),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            super::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(secs)
+        );
+        assert_eq!(super::parse_rfc3339("2000-02-29"), Some(951_782_400));
+        assert_eq!(super::parse_rfc3339("2000-13-01"), None);
+        assert_eq!(super::parse_rfc3339("yesterday"), None);
+    }
+    #[test]
+    fn time_before_epoch_is_formatted() {
+        assert_eq!(
+            super::format_rfc3339_millis(-1000),
+            "1969-12-31T23:59:59.000Z"
+        );
+    }
+}
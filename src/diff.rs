@@ -0,0 +1,266 @@
+//! Differences between two responses, for `rq diff` and `--diff-against`.
+//!
+//! JSON bodies are compared structurally, so the order of the keys doesn't matter and each change
+//! is reported with the path of the value, e.g. `$.items[0].name`. Other bodies are compared line
+//! by line.
+use std::collections::BTreeSet;
+
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+
+use crate::response::Response;
+
+const REMOVED_COLOR: &str = "\x1b[31m";
+const ADDED_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// A difference between two JSON documents, at the path of the value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Removed(String, Value),
+    Added(String, Value),
+    Changed(String, Value, Value),
+}
+
+fn member_path(path: &str, name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if plain {
+        format!("{}.{}", path, name)
+    } else {
+        format!("{}['{}']", path, name.replace('\'', "\\'"))
+    }
+}
+
+fn diff_values(path: String, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let names: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+            for name in names {
+                let path = member_path(&path, name);
+                match (old.get(name), new.get(name)) {
+                    (Some(old), Some(new)) => diff_values(path, old, new, changes),
+                    (Some(old), None) => changes.push(Change::Removed(path, old.clone())),
+                    (None, Some(new)) => changes.push(Change::Added(path, new.clone())),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for i in 0..old.len().max(new.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (old.get(i), new.get(i)) {
+                    (Some(old), Some(new)) => diff_values(path, old, new, changes),
+                    (Some(old), None) => changes.push(Change::Removed(path, old.clone())),
+                    (None, Some(new)) => changes.push(Change::Added(path, new.clone())),
+                    (None, None) => {}
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(Change::Changed(path, old.clone(), new.clone())),
+        _ => {}
+    }
+}
+
+/// The changes from `old` to `new`, ignoring the order of the keys
+pub fn json(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_values("$".to_string(), old, new, &mut changes);
+    changes
+}
+
+/// A line of a line by line diff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The lines of `old` and `new` aligned on their longest common subsequence
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            diff.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn removed(text: &str, color: bool) -> String {
+    if color {
+        format!("{}- {}{}\n", REMOVED_COLOR, text, RESET)
+    } else {
+        format!("- {}\n", text)
+    }
+}
+
+fn added(text: &str, color: bool) -> String {
+    if color {
+        format!("{}+ {}{}\n", ADDED_COLOR, text, RESET)
+    } else {
+        format!("+ {}\n", text)
+    }
+}
+
+/// The differences between two bodies, structural if both are JSON. Empty if they are the same.
+pub fn bodies(old: &[u8], new: &[u8], color: bool) -> String {
+    let mut out = String::new();
+    if let (Ok(old), Ok(new)) = (
+        serde_json::from_slice::<Value>(old),
+        serde_json::from_slice::<Value>(new),
+    ) {
+        for change in json(&old, &new) {
+            match change {
+                Change::Removed(path, value) => {
+                    out.push_str(&removed(&format!("{}: {}", path, value), color))
+                }
+                Change::Added(path, value) => {
+                    out.push_str(&added(&format!("{}: {}", path, value), color))
+                }
+                Change::Changed(path, old, new) => {
+                    out.push_str(&removed(&format!("{}: {}", path, old), color));
+                    out.push_str(&added(&format!("{}: {}", path, new), color));
+                }
+            }
+        }
+        return out;
+    }
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let diff = lines(&old, &new);
+    if diff.iter().all(|line| matches!(line, Line::Same(_))) {
+        return out;
+    }
+    for line in diff {
+        match line {
+            Line::Same(text) => out.push_str(&format!("  {}\n", text)),
+            Line::Removed(text) => out.push_str(&removed(text, color)),
+            Line::Added(text) => out.push_str(&added(text, color)),
+        }
+    }
+    out
+}
+
+/// The headers whose values differ, repeated headers being compared as a whole
+pub fn headers(old: &HeaderMap, new: &HeaderMap, color: bool) -> String {
+    let value = |headers: &HeaderMap, name: &str| {
+        let values: Vec<_> = headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    };
+    let names: BTreeSet<_> = old.keys().chain(new.keys()).map(|n| n.as_str()).collect();
+    let mut out = String::new();
+    for name in names {
+        let (old, new) = (value(old, name), value(new, name));
+        if old == new {
+            continue;
+        }
+        if let Some(old) = old {
+            out.push_str(&removed(&format!("{}: {}", name, old), color));
+        }
+        if let Some(new) = new {
+            out.push_str(&added(&format!("{}: {}", name, new), color));
+        }
+    }
+    out
+}
+
+/// The differences between two responses: status, headers and body
+pub fn responses(old: &Response, new: &Response, color: bool) -> String {
+    let mut out = format!("--- {}\n+++ {}\n", old.url, new.url);
+    if old.status != new.status {
+        out.push_str(&removed(&old.status.to_string(), color));
+        out.push_str(&added(&new.status.to_string(), color));
+    }
+    out.push_str(&headers(&old.headers, &new.headers, color));
+    let body = bodies(&old.body, &new.body, color);
+    if !body.is_empty() {
+        out.push('\n');
+        out.push_str(&body);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+    use serde_json::json;
+
+    use super::{Change, Line};
+
+    #[test]
+    fn json_is_compared_regardless_of_key_order() {
+        let old = json!({"a": 1, "b": [1, 2], "c": {"d": true}, "e f": 0});
+        let new = json!({"c": {"d": true}, "b": [1, 3, 4], "a": 1, "g": null});
+        assert_eq!(
+            super::json(&old, &new),
+            vec![
+                Change::Changed("$.b[1]".into(), json!(2), json!(3)),
+                Change::Added("$.b[2]".into(), json!(4)),
+                Change::Removed("$['e f']".into(), json!(0)),
+                Change::Added("$.g".into(), json!(null)),
+            ]
+        );
+        assert_eq!(
+            super::bodies(br#"{"a":1,"b":2}"#, br#"{"b":2,"a":1}"#, false),
+            ""
+        );
+    }
+    #[test]
+    fn text_is_compared_line_by_line() {
+        assert_eq!(
+            super::lines("a\nb\nc", "a\nc\nd"),
+            vec![
+                Line::Same("a"),
+                Line::Removed("b"),
+                Line::Same("c"),
+                Line::Added("d"),
+            ]
+        );
+        assert_eq!(super::bodies(b"x\ny", b"x\nz", false), "  x\n- y\n+ z\n");
+    }
+    #[test]
+    fn only_different_headers_are_shown() {
+        let mut old = HeaderMap::new();
+        old.insert("server", "a".parse().unwrap());
+        old.insert("etag", "1".parse().unwrap());
+        let mut new = old.clone();
+        new.insert("etag", "2".parse().unwrap());
+        new.insert("age", "3".parse().unwrap());
+        assert_eq!(
+            super::headers(&old, &new, false),
+            "+ age: 3\n- etag: 1\n+ etag: 2\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Resolution of the hosts with a chosen DNS server, over UDP (`--dns-server`) or HTTPS
+//! (`--doh-url`, https://www.rfc-editor.org/rfc/rfc8484), instead of the system resolver.
+//!
+//! reqwest can't be given a resolver, so the hosts of the requests are resolved before sending
+//! and the addresses passed to the client as overrides, like `--resolve`.
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+use tokio::net::UdpSocket;
+
+use crate::{cli::CliArgs, http::Resolve, request::Request};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid DNS server address, expected `ip` or `ip:port`: {0}")]
+    InvalidServer(String),
+    #[error("Invalid DNS name: {0}")]
+    InvalidName(String),
+    #[error("Invalid DNS response: {0}")]
+    InvalidResponse(&'static str),
+    #[error("The DNS server answered with error code {0}")]
+    Server(u8),
+    #[error("No address found for {0}")]
+    NotFound(String),
+    #[error("No answer from the DNS server within {} s", TIMEOUT.as_secs())]
+    Timeout,
+    #[error("DNS over HTTPS failed: {0}")]
+    Doh(#[from] reqwest::Error),
+    #[error("IoError: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A DNS server given as `ip` or `ip:port`, port 53 by default
+#[derive(Debug, Clone)]
+pub struct DnsServer(SocketAddr);
+
+impl FromStr for DnsServer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let addr = match s.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, 53),
+            Err(_) => s
+                .parse::<SocketAddr>()
+                .map_err(|_| Error::InvalidServer(s.to_string()))?,
+        };
+        Ok(DnsServer(addr))
+    }
+}
+
+/// Where the queries are sent
+pub enum Resolver {
+    Udp(SocketAddr),
+    Https(Url),
+}
+
+/// Encode a recursive query for the records of type `qtype` of `name`
+fn encode_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut query = Vec::new();
+    query.extend_from_slice(&id.to_be_bytes());
+    // Flags: recursion desired. One question, no answer, authority nor additional records.
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// The position after the name starting at `pos`, which may end with a compression pointer
+fn skip_name(message: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        match message.get(pos) {
+            Some(0) => return Ok(pos + 1),
+            Some(len) if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            Some(len) => pos += 1 + *len as usize,
+            None => return Err(Error::InvalidResponse("truncated name")),
+        }
+    }
+}
+
+fn read_u16(message: &[u8], pos: usize) -> Result<u16> {
+    message
+        .get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or(Error::InvalidResponse("truncated record"))
+}
+
+/// The A and AAAA records in the answer to the query `id`. Other records, such as the CNAME
+/// records leading to the addresses, are skipped.
+fn parse_response(message: &[u8], id: u16) -> Result<Vec<IpAddr>> {
+    if message.len() < 12 || read_u16(message, 0)? != id {
+        return Err(Error::InvalidResponse("not the answer to the query"));
+    }
+    let rcode = message[3] & 0x0f;
+    if rcode != 0 {
+        return Err(Error::Server(rcode));
+    }
+    let questions = read_u16(message, 4)?;
+    let answers = read_u16(message, 6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(message, pos)? + 4;
+    }
+    let mut addresses = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(message, pos)?;
+        let rtype = read_u16(message, pos)?;
+        let len = read_u16(message, pos + 8)? as usize;
+        pos += 10;
+        let data = message
+            .get(pos..pos + len)
+            .ok_or(Error::InvalidResponse("truncated record"))?;
+        match (rtype, len) {
+            (TYPE_A, 4) => addresses.push(IpAddr::V4(Ipv4Addr::new(
+                data[0], data[1], data[2], data[3],
+            ))),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addresses.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+    Ok(addresses)
+}
+
+impl Resolver {
+    /// The resolver chosen with `--dns-server` or `--doh-url`, if any
+    pub fn from_args(args: &CliArgs) -> Option<Resolver> {
+        match (&args.dns_server, &args.doh_url) {
+            (Some(DnsServer(addr)), _) => Some(Resolver::Udp(*addr)),
+            (None, Some(url)) => Some(Resolver::Https(url.clone())),
+            (None, None) => None,
+        }
+    }
+
+    async fn exchange(&self, client: &Client, query: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Resolver::Udp(server) => {
+                let bind: SocketAddr = if server.is_ipv4() {
+                    (Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(server).await?;
+                socket.send(query).await?;
+                let mut buf = vec![0; 4096];
+                let len = tokio::time::timeout(TIMEOUT, socket.recv(&mut buf))
+                    .await
+                    .map_err(|_| Error::Timeout)??;
+                buf.truncate(len);
+                Ok(buf)
+            }
+            Resolver::Https(url) => {
+                let response = client
+                    .post(url.clone())
+                    .header(CONTENT_TYPE, "application/dns-message")
+                    .header("accept", "application/dns-message")
+                    .body(query.to_vec())
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                Ok(response.bytes().await?.to_vec())
+            }
+        }
+    }
+
+    /// The addresses of `host`, IPv4 first
+    async fn lookup(&self, client: &Client, host: &str) -> Result<Vec<IpAddr>> {
+        let mut addresses = Vec::new();
+        for (id, qtype) in [(1, TYPE_A), (2, TYPE_AAAA)] {
+            let response = self
+                .exchange(client, &encode_query(id, host, qtype)?)
+                .await?;
+            addresses.extend(parse_response(&response, id)?);
+        }
+        Ok(addresses)
+    }
+
+    /// Resolve the hosts of the requests that are not IP addresses nor already overridden with
+    /// `--resolve`. `ipv6` keeps only IPv4 (`Some(false)`) or IPv6 (`Some(true)`) addresses.
+    pub async fn resolve_hosts(
+        &self,
+        client: &Client,
+        requests: &[Request],
+        overridden: &[Resolve],
+        ipv6: Option<bool>,
+    ) -> Result<Vec<Resolve>> {
+        let hosts: BTreeSet<String> = requests
+            .iter()
+            .filter_map(|r| Url::parse(&r.url).ok())
+            .filter_map(|url| url.domain().map(str::to_string))
+            .filter(|host| !overridden.iter().any(|r| r.host == *host))
+            .collect();
+        let mut resolved = Vec::new();
+        for host in hosts {
+            let addr = self
+                .lookup(client, &host)
+                .await?
+                .into_iter()
+                .find(|addr| ipv6.is_none_or(|ipv6| addr.is_ipv6() == ipv6))
+                .ok_or_else(|| Error::NotFound(host.clone()))?;
+            // The port is ignored by the client, the one of the URL is used
+            resolved.push(Resolve {
+                host,
+                addr: SocketAddr::new(addr, 0),
+            });
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    #[test]
+    fn query_is_encoded() {
+        assert_eq!(
+            super::encode_query(0x1234, "a.bc", super::TYPE_A).unwrap(),
+            [0x12, 0x34, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, b'a', 2, b'b', b'c', 0, 0, 1, 0, 1]
+        );
+        assert!(super::encode_query(1, "a..b", super::TYPE_A).is_err());
+    }
+    #[test]
+    fn addresses_are_read_from_response() {
+        let mut response = super::encode_query(7, "a.bc", super::TYPE_A).unwrap();
+        // Response flags with 2 answers: a CNAME then an A record, both with compressed names
+        response[2..4].copy_from_slice(&[0x81, 0x80]);
+        response[6..8].copy_from_slice(&[0, 2]);
+        response.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+        response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 1]);
+        assert_eq!(
+            super::parse_response(&response, 7).unwrap(),
+            vec!["10.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert!(super::parse_response(&response, 8).is_err());
+        response[3] = 0x83;
+        assert!(matches!(
+            super::parse_response(&response, 7),
+            Err(super::Error::Server(3))
+        ));
+    }
+}
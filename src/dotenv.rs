@@ -0,0 +1,180 @@
+//! `--env-file`: the variables of a `.env` file, as developers keep the API keys of a project,
+//! filling the `{{NAME}}` placeholders of collections, chains and test suites and the `{{NAME}}`,
+//! `$NAME` and `${NAME}` references in the headers and bodies of requests.
+//!
+//! `./.env` is read when no file is given and it exists, unless `load-dotenv = false` is set in
+//! the `[settings]` section of the config file, and skipped with a warning when it can't be read.
+//! Only the names defined in the file are replaced, other `{{`, `${` and `$` are sent as they are.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{cli::CliArgs, config, output, Request};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't read {}: {source}", .path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("{}:{line}: expected `NAME=value`", .path.display())]
+    Syntax { path: PathBuf, line: usize },
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The value written after `=`: quoted, or up to a ` #` comment
+fn value(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if let Some(rest) = raw.strip_prefix('\'') {
+        return Some(rest[..rest.find('\'')?].to_string());
+    }
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.chars();
+        loop {
+            match chars.next()? {
+                '"' => return Some(value),
+                '\\' => match chars.next()? {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    c => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+    let end = raw.find(" #").unwrap_or(raw.len());
+    Some(raw[..end].trim_end().to_string())
+}
+
+/// The `NAME=value` lines, `export NAME=value` included
+pub fn parse(path: &Path, content: &str) -> Result<HashMap<String, String>> {
+    let mut vars = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let syntax = || Error::Syntax {
+            path: path.to_path_buf(),
+            line: i + 1,
+        };
+        let (name, raw) = line.split_once('=').ok_or_else(syntax)?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(syntax());
+        }
+        vars.insert(name.to_string(), value(raw).ok_or_else(syntax)?);
+    }
+    Ok(vars)
+}
+
+fn read(path: &Path) -> Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path).map_err(|source| Error::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(path, &content)
+}
+
+/// The variables of the file given with `--env-file`, or of `./.env`, which is skipped rather
+/// than failing every command when it is invalid
+pub fn load(args: &CliArgs) -> Result<HashMap<String, String>> {
+    if let Some(path) = &args.env_file {
+        return read(path);
+    }
+    let disabled = config::read()
+        .ok()
+        .and_then(|config| config::get(&config, "settings", "load-dotenv"))
+        .is_some_and(|load| load == "false");
+    let path = Path::new(".env");
+    if disabled || !path.is_file() {
+        return Ok(HashMap::new());
+    }
+    read(path).or_else(|e| {
+        output::note(format!("Warning: {}, the file is skipped", e));
+        Ok(HashMap::new())
+    })
+}
+
+/// Replace the `{{NAME}}`, `${NAME}` and `$NAME` references to the variables
+pub fn expand(s: &str, vars: &HashMap<String, String>) -> String {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(['$', '{']) {
+        output.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let reference = if let Some(inner) = tail.strip_prefix("{{") {
+            inner.find("}}").map(|end| (inner[..end].trim(), end + 4))
+        } else if let Some(inner) = tail.strip_prefix("${") {
+            inner.find('}').map(|end| (&inner[..end], end + 3))
+        } else if let Some(inner) = tail.strip_prefix('$') {
+            let end = inner.find(|c: char| !is_name(c)).unwrap_or(inner.len());
+            Some((&inner[..end], end + 1))
+        } else {
+            None
+        };
+        match reference.and_then(|(name, len)| Some((vars.get(name)?, len))) {
+            Some((value, len)) => {
+                output.push_str(value);
+                rest = &tail[len..];
+            }
+            None => {
+                output.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Replace the references in the header values and the body of the request
+pub fn apply(request: &mut Request, vars: &HashMap<String, String>) {
+    if vars.is_empty() {
+        return;
+    }
+    for (_, value) in &mut request.headers {
+        *value = expand(value, vars);
+    }
+    if let Some(body) = &request.body {
+        request.body = Some(expand(body, vars));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    #[test]
+    fn env_files_are_parsed() {
+        let content = "# keys\nexport API_KEY=abc123\nQUOTED=\"a b\\n\"\nRAW='$x # y'\n\
+            PLAIN=v # comment\nEMPTY=\n";
+        let vars = super::parse(Path::new(".env"), content).unwrap();
+        assert_eq!(vars["API_KEY"], "abc123");
+        assert_eq!(vars["QUOTED"], "a b\n");
+        assert_eq!(vars["RAW"], "$x # y");
+        assert_eq!(vars["PLAIN"], "v");
+        assert_eq!(vars["EMPTY"], "");
+        assert!(super::parse(Path::new(".env"), "A=1\nno value\n").is_err());
+    }
+    #[test]
+    fn only_defined_references_are_replaced() {
+        let vars: HashMap<_, _> = [("KEY".to_string(), "k".to_string())].into();
+        assert_eq!(
+            super::expand("{{KEY}} {{ KEY }} ${KEY} ${OTHER} {{x}} $ {", &vars),
+            "k k k ${OTHER} {{x}} $ {"
+        );
+    }
+    #[test]
+    fn bare_names_are_replaced_when_defined() {
+        let vars: HashMap<_, _> = [("KEY".to_string(), "k".to_string())].into();
+        assert_eq!(
+            super::expand("$KEY/$KEYS $KEY-x $OTHER $KEY", &vars),
+            "k/$KEYS k-x $OTHER k"
+        );
+    }
+}
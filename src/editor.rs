@@ -0,0 +1,102 @@
+//! Composing the request body in the user's editor with `--edit`.
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use reqwest::header::CONTENT_TYPE;
+
+use crate::{http::guess_content_type, request::Request};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The editor `{0}` exited with {1}")]
+    Failed(String, process::ExitStatus),
+    #[error("The body is empty, the request is not sent")]
+    Empty,
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The editor to use: `$VISUAL`, `$EDITOR` or vi, as git does
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|editor| !editor.is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Open the file at `path` in `editor`, which may contain arguments, e.g. `code --wait`
+fn run(editor: &str, path: &Path) -> Result<()> {
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Failed(editor.to_string(), status))
+    }
+}
+
+/// Open the file at `path` in the user's editor
+pub fn edit_file(path: &Path) -> Result<()> {
+    run(&editor(), path)
+}
+
+/// Open `template` in `editor` and return the saved text. `extension` is given to the temporary
+/// file for syntax highlighting.
+fn edit_with(editor: &str, template: &str, extension: &str) -> Result<String> {
+    let path: PathBuf = env::temp_dir().join(format!("rq-body-{}.{}", process::id(), extension));
+    fs::write(&path, template)?;
+    let status = run(editor, &path);
+    let text = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    status?;
+    Ok(text?)
+}
+
+/// Replace the body of the request with the one written in the editor, starting from the current
+/// body. The content type is guessed from the new body unless the request has one.
+pub fn edit_body(request: &mut Request) -> Result<()> {
+    let content_type = request
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()))
+        .map(|(_, value)| value.clone());
+    let extension = match &content_type {
+        Some(content_type) if content_type.contains("json") => "json",
+        Some(content_type) if content_type.contains("xml") => "xml",
+        _ => "txt",
+    };
+    let template = request.body.as_deref().unwrap_or_default();
+    let body = edit_with(&editor(), template, extension)?;
+    if body.trim().is_empty() {
+        return Err(Error::Empty);
+    }
+    if content_type.is_none() {
+        request.set_header(CONTENT_TYPE.as_str(), guess_content_type(&body).to_string());
+    }
+    request.body = Some(body);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(unix)]
+    fn saved_text_is_returned() {
+        let text = super::edit_with("sed -i s/1/2/", "{\"a\":1}", "json").unwrap();
+        assert_eq!(text, "{\"a\":2}");
+        assert!(matches!(
+            super::edit_with("false", "", "txt"),
+            Err(super::Error::Failed(_, _))
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! `--env NAME`: the variable set filling the `{{name}}` placeholders of collections, chains and
+//! test suites, so that the same requests are sent to other hosts with other tokens, as Postman
+//! environments do.
+//!
+//! The environments are read from `environments.yaml` in the config directory, then from the
+//! `NAME.env.yaml` file next to the collection, chain or suite `NAME.yaml`, whose variables win:
+//!
+//! ```yaml
+//! staging:
+//!   base: https://staging.example.com
+//!   token: abc
+//! production:
+//!   base: https://api.example.com
+//! ```
+//!
+//! The `--var` values override the environment.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{collection, paths, yaml};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No environment named `{0}`")]
+    NotFound(String),
+    #[error("{}: {source}", .path.display())]
+    Yaml { path: PathBuf, source: yaml::Error },
+    #[error("{}: {message}", .path.display())]
+    Invalid { path: PathBuf, message: String },
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// An environment and its variables, in the order of the file
+type Environment = (String, Vec<(String, String)>);
+
+/// The environment file of a collection, chain or suite: `api.env.yaml` for `api.yaml`
+pub fn env_file_of(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.env.yaml", stem))
+}
+
+/// Whether the file holds environments rather than requests
+pub fn is_env_file(path: &Path) -> bool {
+    path.file_stem()
+        .is_some_and(|stem| stem.to_string_lossy().ends_with(".env"))
+}
+
+/// The files the environments are read from, the later ones overriding the earlier ones
+fn files(file: Option<&Path>) -> Vec<PathBuf> {
+    let mut files = vec![paths::config_dir().join("environments.yaml")];
+    files.extend(file.map(env_file_of));
+    files
+}
+
+fn parse(path: &Path, content: &str) -> Result<Vec<Environment>> {
+    let invalid = |message: String| Error::Invalid {
+        path: path.to_path_buf(),
+        message,
+    };
+    let value = yaml::parse(content).map_err(|source| Error::Yaml {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let environments = match value {
+        Value::Null => return Ok(Vec::new()),
+        Value::Object(environments) => environments,
+        _ => return Err(invalid("expected a mapping of environments".to_string())),
+    };
+    environments
+        .into_iter()
+        .map(|(name, vars)| {
+            let not_strings = || invalid(format!("`{}` must be a mapping of strings", name));
+            let vars = match vars {
+                Value::Null => Vec::new(),
+                Value::Object(vars) => vars
+                    .iter()
+                    .map(|(var, value)| {
+                        let value = collection::scalar_to_string(value).ok_or_else(not_strings)?;
+                        Ok((var.clone(), value))
+                    })
+                    .collect::<Result<_>>()?,
+                _ => return Err(not_strings()),
+            };
+            Ok((name, vars))
+        })
+        .collect()
+}
+
+/// The environments of the files that exist
+fn read(file: Option<&Path>) -> Result<Vec<(PathBuf, Vec<Environment>)>> {
+    let mut read = Vec::new();
+    for path in files(file) {
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let environments = parse(&path, &content)?;
+                read.push((path, environments));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read)
+}
+
+/// The variables of the environment `name` for the requests of `file`, in the order they were
+/// defined
+fn variables(name: &str, file: Option<&Path>) -> Result<Vec<(String, String)>> {
+    let mut found = false;
+    let mut variables: Vec<(String, String)> = Vec::new();
+    for (_, environments) in read(file)? {
+        for (_, vars) in environments.into_iter().filter(|(env, _)| env == name) {
+            found = true;
+            for (var, value) in vars {
+                variables.retain(|(v, _)| *v != var);
+                variables.push((var, value));
+            }
+        }
+    }
+    if !found {
+        return Err(Error::NotFound(name.to_string()));
+    }
+    Ok(variables)
+}
+
+/// The variables of the environment selected with `--env`, if any, overridden by `vars`
+pub fn with_vars(
+    env: Option<&str>,
+    file: Option<&Path>,
+    vars: HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let mut variables: HashMap<_, _> = match env {
+        Some(name) => variables(name, file)?.into_iter().collect(),
+        None => HashMap::new(),
+    };
+    variables.extend(vars);
+    Ok(variables)
+}
+
+pub fn list(file: Option<&Path>) -> Result<()> {
+    for (path, environments) in read(file)? {
+        println!("{}", path.display());
+        for (name, vars) in environments {
+            println!("    {:<20} {} variable(s)", name, vars.len());
+        }
+    }
+    Ok(())
+}
+
+pub fn show(name: &str, file: Option<&Path>) -> Result<()> {
+    for (var, value) in variables(name, file)? {
+        println!("{}={}", var, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn environments_are_mappings_of_strings() {
+        let path = Path::new("environments.yaml");
+        let environments = super::parse(
+            path,
+            "staging:\n  base: https://s.example\n  port: 8080\nlocal:\n",
+        )
+        .unwrap();
+        assert_eq!(
+            environments,
+            [
+                (
+                    "staging".to_string(),
+                    vec![
+                        ("base".to_string(), "https://s.example".to_string()),
+                        ("port".to_string(), "8080".to_string())
+                    ]
+                ),
+                ("local".to_string(), vec![])
+            ]
+        );
+        assert!(super::parse(path, "staging: [a, b]\n").is_err());
+        assert!(super::parse(path, "staging:\n  nested: {a: 1}\n").is_err());
+    }
+    #[test]
+    fn env_files_sit_next_to_their_collection() {
+        let env_file = super::env_file_of(Path::new("/c/api.yaml"));
+        assert_eq!(env_file, Path::new("/c/api.env.yaml"));
+        assert!(super::is_env_file(&env_file));
+        assert!(!super::is_env_file(Path::new("/c/api.yaml")));
+    }
+}
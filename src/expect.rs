@@ -0,0 +1,213 @@
+//! `Expect: 100-continue` (https://www.rfc-editor.org/rfc/rfc9110#section-10.1.1): the headers
+//! are sent first and the body only once the server answers `100 Continue`, so that an upload the
+//! server rejects, e.g. for authentication or size, is not sent for nothing.
+//!
+//! hyper writes the body right after the headers and hides interim responses, so the connection
+//! is made here, with the options of the client, and watched for the first bytes of the response.
+//! The body is held back until they arrive, or until `WAIT` passes for servers ignoring the
+//! header; only this wait is bounded, not the upload.
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder};
+use futures_util::{
+    future,
+    stream::{self, Stream, StreamExt},
+};
+use hyper::{
+    body::Bytes,
+    client::conn,
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, EXPECT, PROXY_AUTHORIZATION},
+    Body, HeaderMap,
+};
+use reqwest::header::HeaderValue;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, BufReader, ReadBuf},
+    sync::oneshot,
+    task::JoinHandle,
+};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::{connect, unix, upload::Chunks, ACCEPT_ENCODING_VALUE};
+
+/// How long to wait for `100 Continue` before sending the body anyway, as curl does
+const WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Connect(#[from] connect::Error),
+    #[error("{0}")]
+    Http(#[from] hyper::Error),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] hyper::http::Error),
+}
+
+/// Whether the first bytes of the response are those of `100 Continue`. A read too short to tell
+/// counts as a yes, sending the body is the safe default.
+fn is_continue(read: &[u8]) -> bool {
+    read.get(9..12).is_none_or(|status| status == b"100")
+}
+
+/// A connection telling whether the server answered `100 Continue` once the response starts
+struct Watched {
+    inner: Box<dyn connect::Stream>,
+    answer: Option<oneshot::Sender<bool>>,
+}
+
+impl AsyncRead for Watched {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        let read = &buf.filled()[filled..];
+        if let (Poll::Ready(Ok(())), false) = (&poll, read.is_empty()) {
+            if let Some(answer) = this.answer.take() {
+                let _ = answer.send(is_continue(read));
+            }
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for Watched {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+type BodyStream = Box<dyn Stream<Item = io::Result<Bytes>> + Send + Unpin>;
+
+/// The body of the response, which stops the connection once it is read or dropped, as the body
+/// of the request may still be held back
+struct ResponseBody {
+    body: BodyStream,
+    connection: JoinHandle<hyper::Result<()>>,
+}
+
+impl Stream for ResponseBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().body).poll_next(cx)
+    }
+}
+
+impl Drop for ResponseBody {
+    fn drop(&mut self) {
+        self.connection.abort();
+    }
+}
+
+/// The body decompressed as told by `Content-Encoding`, whose header is then removed along with
+/// the length, as reqwest does
+fn decode<S>(headers: &mut HeaderMap, body: S) -> BodyStream
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static,
+{
+    let coding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let reader = BufReader::new(StreamReader::new(body));
+    let decoded: Box<dyn AsyncRead + Send + Unpin> = match coding.as_deref() {
+        Some("gzip") => Box::new(GzipDecoder::new(reader)),
+        Some("br") => Box::new(BrotliDecoder::new(reader)),
+        _ => return Box::new(ReaderStream::new(reader)),
+    };
+    headers.remove(CONTENT_ENCODING);
+    headers.remove(CONTENT_LENGTH);
+    Box::new(ReaderStream::new(decoded))
+}
+
+/// Send the request with `Expect: 100-continue` over a connection made with `options`, holding
+/// `body` back until the server answers. The body is not sent at all when the server answers with
+/// a final response right away.
+pub async fn send(
+    request: &reqwest::Request,
+    body: Chunks,
+    length: Option<u64>,
+    options: &connect::Options,
+) -> Result<reqwest::Response, Error> {
+    let url = request.url();
+    let connection = options.connect(url).await?;
+    let mut headers = unix::default_headers(request);
+    headers.insert(EXPECT, HeaderValue::from_static("100-continue"));
+    if let Some(length) = length {
+        headers.insert(CONTENT_LENGTH, length.into());
+    }
+    if let Some(authorization) = connection.proxy_authorization() {
+        headers.insert(PROXY_AUTHORIZATION, authorization.clone());
+    }
+    // The codings are asked for, and decoded, when the client would do it
+    let decompress = options.decompress;
+    if decompress && !headers.contains_key(ACCEPT_ENCODING) {
+        headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_static(ACCEPT_ENCODING_VALUE),
+        );
+    }
+    let mut builder = hyper::Request::builder()
+        .method(request.method().clone())
+        .uri(connection.target(url));
+    for (name, value) in &headers {
+        builder = builder.header(name, value);
+    }
+    let (answer, answered) = oneshot::channel();
+    let gate = async move {
+        // A final response means the body is not wanted, it is never sent
+        if let Ok(Ok(false)) = tokio::time::timeout(WAIT, answered).await {
+            future::pending::<()>().await;
+        }
+    };
+    let body = stream::once(gate)
+        .filter_map(|()| future::ready(None::<io::Result<Vec<u8>>>))
+        .chain(body);
+    let hyper_request = builder.body(Body::wrap_stream(body))?;
+    let stream = Watched {
+        inner: connection.stream,
+        answer: Some(answer),
+    };
+    let (mut sender, connection) = conn::handshake(stream).await?;
+    let connection = tokio::spawn(connection);
+    let response = sender.send_request(hyper_request).await?;
+    let (mut parts, body) = response.into_parts();
+    let body = body.map(|chunk| chunk.map_err(io::Error::other));
+    let body: BodyStream = if decompress {
+        decode(&mut parts.headers, body)
+    } else {
+        Box::new(body)
+    };
+    let body = Body::wrap_stream(ResponseBody { body, connection });
+    Ok(hyper::Response::from_parts(parts, body).into())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn interim_response_is_recognized() {
+        assert!(super::is_continue(b"HTTP/1.1 100 Continue\r\n\r\n"));
+        assert!(!super::is_continue(b"HTTP/1.1 413 Payload Too Large\r\n"));
+        assert!(super::is_continue(b"HTTP/1.1"));
+    }
+}
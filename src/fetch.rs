@@ -0,0 +1,680 @@
+//! Fetching one or many URLs concurrently with a shared client.
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures_util::stream::{self, StreamExt};
+use reqwest::{Client, StatusCode};
+use tokio::io::AsyncBufReadExt;
+
+use crate::{
+    accepts_encoding,
+    assert::Assertions,
+    cert,
+    cli::{escape_url, normalize_url, CliArgs},
+    diff, glob, graphql, har, history, msgpack, output,
+    poll::Poll,
+    range,
+    request::Request,
+    response::Response,
+    scraper,
+    session::Session,
+    stats, wire, xml, Error, Result,
+};
+
+/// The outcome of sending a single request
+struct Fetched {
+    request: Request,
+    /// The request that went out, with the headers added by the middlewares and the client
+    sent: Option<reqwest::Request>,
+    started: SystemTime,
+    elapsed: Duration,
+    /// Connection phases of a separate connection, measured with `--timing`
+    phases: Option<stats::Phases>,
+    /// The TLS session, for `--cert-info` and `--cert-expiry-warn`
+    tls: Option<std::result::Result<cert::TlsInfo, cert::Error>>,
+    result: Result<Response>,
+}
+
+/// Collect the URLs given on the command line and those listed in `--url-file`
+pub fn collect_urls(args: &CliArgs) -> Result<Vec<String>> {
+    let mut urls = args.urls.clone();
+    if let Some(path) = &args.url_file {
+        let reader: Box<dyn Read> = if path.as_os_str() == "-" {
+            Box::new(io::stdin())
+        } else {
+            Box::new(fs::File::open(path)?)
+        };
+        for line in BufReader::new(reader).lines() {
+            urls.extend(url_of_line(&line?, args.no_url_escape));
+        }
+    }
+    Ok(urls)
+}
+
+/// The URL of a line of a URL list, or `None` for blank lines and comments so that the list can
+/// be maintained by hand
+fn url_of_line(line: &str, no_escape: bool) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let mut url = line.to_string();
+    normalize_url(&mut url);
+    if !no_escape {
+        url = escape_url(&url);
+    }
+    Some(url)
+}
+
+/// `--stdin-urls`: download the URLs read from stdin into `--output-dir` as they are read, so that
+/// lists of any length can be streamed in. A row is printed per URL as it completes, then the
+/// totals. Returns the number of URLs that failed.
+pub async fn fetch_stdin(client: &Client, args: &CliArgs) -> Result<usize> {
+    let dir = args.output_dir.as_deref().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir)?;
+    let lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    let urls = stream::unfold(lines, |mut lines| async {
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => match url_of_line(&line, args.no_url_escape) {
+                    Some(url) => return Some((url, lines)),
+                    None => continue,
+                },
+                Ok(None) => return None,
+                Err(e) => {
+                    eprintln!("Can't read the URLs from stdin: {}", e);
+                    return None;
+                }
+            }
+        }
+    });
+    let mut results = Box::pin(urls)
+        .map(|url| async move {
+            let request = Request::from_args(args, &url);
+            let start = Instant::now();
+            let result = request.send(client).await;
+            (url, start.elapsed(), result)
+        })
+        .buffer_unordered(args.concurrency.max(1));
+    let mut used_names = HashSet::new();
+    let (mut succeeded, mut failed) = (0, 0);
+    println!("{:<6} {:>10} {:>8}  URL", "STATUS", "BYTES", "TIME");
+    while let Some((url, elapsed, result)) = results.next().await {
+        let outcome = match result {
+            Ok(response)
+                if response.status.is_client_error() || response.status.is_server_error() =>
+            {
+                Err(response.status.as_u16().to_string())
+            }
+            Ok(response) => {
+                let path = unique_path(dir, &file_name_for(&url), &mut used_names);
+                match fs::write(&path, &response.body) {
+                    Ok(()) => Ok((response, path)),
+                    Err(e) => Err(format!("{}: {}", path.display(), e)),
+                }
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        match outcome {
+            Ok((response, path)) => {
+                succeeded += 1;
+                println!(
+                    "{:<6} {:>10} {:>7}ms  {} -> {}",
+                    response.status.as_u16(),
+                    response.body.len(),
+                    elapsed.as_millis(),
+                    url,
+                    path.display()
+                );
+            }
+            Err(reason) => {
+                failed += 1;
+                println!(
+                    "{:<6} {:>10} {:>7}ms  {}  ({})",
+                    "FAIL",
+                    "-",
+                    elapsed.as_millis(),
+                    url,
+                    reason
+                );
+            }
+        }
+    }
+    println!("{} succeeded, {} failed", succeeded, failed);
+    Ok(failed)
+}
+
+async fn fetch_one(client: &Client, request: Request, args: &CliArgs) -> Fetched {
+    let phases = if args.timing {
+        stats::probe(&request).await
+    } else {
+        None
+    };
+    let tls = if args.cert_info || args.cert_expiry_warn.is_some() {
+        Some(cert::probe(&request).await)
+    } else {
+        None
+    };
+    let started = SystemTime::now();
+    let start = Instant::now();
+    let mut sent = None;
+    let result = match Poll::from_args(args) {
+        Some(poll) => poll.run(client, &request, &mut sent).await,
+        None => request.send_recording(client, &mut sent).await,
+    };
+    Fetched {
+        request,
+        sent,
+        started,
+        elapsed: start.elapsed(),
+        phases,
+        tls,
+        result,
+    }
+}
+
+/// Print the TLS session for `--cert-info` and check the expiry of the leaf certificate for
+/// `--cert-expiry-warn`. Returns whether the certificate expires within the window.
+fn check_tls(
+    args: &CliArgs,
+    url: &str,
+    tls: &std::result::Result<cert::TlsInfo, cert::Error>,
+) -> bool {
+    let info = match tls {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!("{}: {}", url, e);
+            return false;
+        }
+    };
+    if args.cert_info {
+        eprintln!("{}", info);
+    }
+    let (window, leaf) = match (args.cert_expiry_warn, info.chain.first()) {
+        (Some(window), Some(leaf)) => (window, leaf),
+        _ => return false,
+    };
+    let left = leaf.expires_in(SystemTime::now());
+    if left >= window.0.as_secs() as i64 {
+        return false;
+    }
+    if left < 0 {
+        eprintln!("{}: the certificate has expired", url);
+    } else {
+        eprintln!(
+            "{}: the certificate expires in {} day(s)",
+            url,
+            left / 86_400
+        );
+    }
+    true
+}
+
+/// The number of requests that went wrong, by kind
+#[derive(Debug, Default)]
+pub struct Failures {
+    /// No response, or a response that could not be output
+    pub requests: usize,
+    /// A response body not matching `--verify-checksum`
+    pub checksums: usize,
+    /// A response body larger than `--max-response-size`
+    pub too_large: usize,
+    /// A certificate expiring within `--cert-expiry-warn`
+    pub expiring: usize,
+    /// A 4xx or 5xx response with `--fail` or `--fail-with-body`
+    pub http_errors: usize,
+    /// A response failing one of the `--assert-*` assertions
+    pub assertions: usize,
+    /// A request whose `--until-*` condition was not met within `--poll-timeout`
+    pub timed_out: usize,
+    /// The last non-zero exit status of the `--pipe` command
+    pub pipe_status: i32,
+}
+
+/// Send all requests with at most `args.concurrency` in flight, writing the results as they come
+/// in. Results are written in the same order as the requests were given.
+pub async fn fetch_all(
+    client: &Client,
+    args: &CliArgs,
+    requests: Vec<Request>,
+) -> Result<Failures> {
+    if let Some(dir) = &args.output_dir {
+        fs::create_dir_all(dir)?;
+    }
+    let assertions = Assertions::from_args(args);
+    let mut meta_file = match &args.meta_json {
+        Some(Some(path)) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        _ => None,
+    };
+    let mut dump_file = match &args.dump {
+        Some(path) => Some(fs::File::create(path)?),
+        None => None,
+    };
+    let mut session = match &args.session {
+        Some(name) => {
+            let mut session = Session::load(name)?;
+            session.remember(args);
+            Some(session)
+        }
+        None => None,
+    };
+    let mut used_names = HashSet::new();
+    let mut har_entries = Vec::new();
+    let mut failures = Failures::default();
+    let mut results = stream::iter(requests)
+        .map(|req| fetch_one(client, req, args))
+        .buffered(args.concurrency);
+    while let Some(mut fetched) = results.next().await {
+        match &fetched.result {
+            Err(Error::SizeLimitExceeded(_)) => failures.too_large += 1,
+            Err(Error::PollTimeout(_)) => failures.timed_out += 1,
+            // A response hook or script rejecting the response is a failed assertion
+            Err(Error::MiddlewareError(e)) if e.is_rejection() => failures.assertions += 1,
+            Err(_) => failures.requests += 1,
+            Ok(_) => {}
+        }
+        if !args.no_history {
+            let status = fetched.result.as_ref().ok().map(|r| r.status.as_u16());
+            if let Err(e) = history::record(&fetched.request, status, fetched.elapsed) {
+                output::note(format!(
+                    "Failed to record the request in the history: {}",
+                    e
+                ));
+            }
+        }
+        if let (Some(session), Ok(response)) = (&mut session, &fetched.result) {
+            session.store_cookies(&response.url, &response.headers);
+        }
+        if let (Some(_), Ok(response)) = (&args.har, &fetched.result) {
+            let request = har_request(&fetched);
+            har_entries.push(har::Entry::new(&request, response, fetched.started));
+        }
+        let not_modified = fetched
+            .result
+            .as_ref()
+            .is_ok_and(|r| r.status == StatusCode::NOT_MODIFIED);
+        if args.meta_json.is_some() {
+            let meta = to_meta_json(&fetched);
+            match &mut meta_file {
+                Some(file) => writeln!(file, "{}", meta)?,
+                None => eprintln!("{}", meta),
+            }
+        }
+        if let (Some(file), Some(request)) = (&mut dump_file, &fetched.sent) {
+            wire::dump(file, request, &fetched.result, accepts_encoding(args))?;
+        }
+        // The history, the HAR and the dumps keep the XML as it was received
+        if let (true, Ok(response)) = (args.xml_to_json, &mut fetched.result) {
+            if let Err(e) = xml::convert(response) {
+                failures.requests += 1;
+                fetched.result = Err(e.into());
+            }
+        }
+        let url = &fetched.request.url;
+        if let Some(tls) = &fetched.tls {
+            if check_tls(args, url, tls) {
+                failures.expiring += 1;
+            }
+        }
+        let http_error = (args.fail || args.fail_with_body)
+            && fetched
+                .result
+                .as_ref()
+                .is_ok_and(|r| r.status.is_client_error() || r.status.is_server_error());
+        if let (true, Ok(response)) = (http_error, &fetched.result) {
+            failures.http_errors += 1;
+            eprintln!(
+                "{}: The requested URL returned error: {}",
+                url, response.status
+            );
+        }
+        if let (false, Ok(response)) = (args.range.is_empty(), &fetched.result) {
+            match range::check(response.status) {
+                Some(problem) if response.status == StatusCode::OK => {
+                    output::note(format!("{}: {}", url, problem))
+                }
+                Some(problem) => eprintln!("{}: {} ({})", url, problem, response.status),
+                None => {}
+            }
+        }
+        if let (false, Ok(response)) = (assertions.is_empty(), &fetched.result) {
+            let failed = assertions.check(response);
+            for failure in &failed {
+                eprintln!("{}: {}", url, failure);
+            }
+            if !failed.is_empty() {
+                failures.assertions += 1;
+            }
+        }
+        if args.ndjson {
+            println!("{}", to_ndjson(&fetched, args.print.response_body));
+            continue;
+        }
+        if let Some(template) = &args.output_template {
+            let line = template.render(&fetched.request, &fetched.result, fetched.elapsed);
+            println!("{}", line);
+            continue;
+        }
+        if let Some(request) = &fetched.sent {
+            output::print_request(request, args.print, accepts_encoding(args));
+        }
+        if let Ok(response) = &fetched.result {
+            output::print_response_head(response, args.print);
+            if args.timing {
+                eprintln!("{}", stats::format(fetched.phases.as_ref(), response));
+            }
+            if let Some(algorithm) = args.checksum {
+                let digest = algorithm.hex_digest(&response.body);
+                eprintln!("{}:{}  {}", algorithm, digest, url);
+            }
+            if let Some(Err(actual)) = args
+                .verify_checksum
+                .as_ref()
+                .map(|c| c.verify(&response.body))
+            {
+                failures.checksums += 1;
+                eprintln!("{}: checksum mismatch, got {}", url, actual);
+                continue;
+            }
+        }
+        if http_error && !args.fail_with_body {
+            continue;
+        }
+        match (fetched.result, &args.output_dir, &args.output) {
+            // The copy from a previous download is still up to date
+            (Ok(_), _, _) if not_modified => output::note(format!("{}: not modified", url)),
+            (Ok(response), Some(dir), _) => {
+                let path = unique_path(dir, &file_name_for(url), &mut used_names);
+                fs::write(&path, response.body)?;
+                output::note(format!("{} -> {}", url, path.display()));
+            }
+            (Ok(response), None, Some(template)) => {
+                let values = args.glob_values.get(url).map_or(&[][..], Vec::as_slice);
+                let path = glob::output_name(template, values);
+                fs::write(&path, response.body)?;
+                output::note(format!("{} -> {}", url, path));
+            }
+            (Ok(response), None, None) if args.pipe.is_some() => {
+                let pipe = args.pipe.as_ref().expect("checked by the guard");
+                match pipe.run(&response.body) {
+                    Ok(0) => {}
+                    Ok(code) => failures.pipe_status = code,
+                    Err(e) => {
+                        failures.requests += 1;
+                        eprintln!("{}: {}", url, e);
+                    }
+                }
+            }
+            (Ok(_), None, None) if !args.print.response_body => {}
+            (Ok(response), None, None) => {
+                if let Err(e) = print_body(args, &response) {
+                    failures.requests += 1;
+                    eprintln!("{}: {}", url, e);
+                }
+            }
+            (Err(e), _, _) => eprintln!("{}: {}", url, e),
+        }
+    }
+    if let (Some(session), Some(name)) = (&session, &args.session) {
+        session.save(name)?;
+    }
+    if let Some(path) = &args.har {
+        har::append(path, &har_entries)?;
+    }
+    Ok(failures)
+}
+
+/// Print the response body to stdout, after applying `--select`, `--extract` or `--filter` if
+/// given
+fn print_body(args: &CliArgs, response: &Response) -> std::result::Result<(), String> {
+    if args.hexdump {
+        print!("{}", output::hexdump(&response.body));
+        return Ok(());
+    }
+    let decoded = msgpack::to_json(response, !args.raw).map_err(|e| e.to_string())?;
+    let response = decoded.as_ref().unwrap_or(response);
+    if args.graphql {
+        graphql::print_response(&response.body);
+        return Ok(());
+    }
+    if let Some(path) = &args.diff_against {
+        let old = fs::read(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+        print!("{}", diff::bodies(&old, &response.body, use_color()));
+        return Ok(());
+    }
+    if let Some(selector) = &args.select {
+        let html = response.text(args.response_charset);
+        for value in scraper::select(&html, selector, args.attr.as_deref()) {
+            println!("{}", value);
+        }
+        return Ok(());
+    }
+    if let Some(path) = &args.extract {
+        let json: serde_json::Value = serde_json::from_slice(&response.body)
+            .map_err(|e| format!("The response is not valid JSON: {}", e))?;
+        match path.get(&json) {
+            Some(serde_json::Value::String(s)) if args.raw => println!("{}", s),
+            Some(value) => println!("{}", value),
+            None => return Err("No value at the path given with --extract".to_string()),
+        }
+        return Ok(());
+    }
+    let filter = match &args.filter {
+        Some(filter) => filter,
+        None => {
+            let text = output::format_body(response, args.response_charset, !args.raw, use_color());
+            print!("{}", text);
+            return Ok(());
+        }
+    };
+    let json: serde_json::Value = serde_json::from_slice(&response.body)
+        .map_err(|e| format!("The response is not valid JSON: {}", e))?;
+    for value in filter.apply(&json).map_err(|e| e.to_string())? {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&value).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Whether to highlight the output: only on a terminal, and unless `NO_COLOR` is set
+/// (https://no-color.org)
+pub fn use_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// The record of one request for `--format ndjson`, with the body if `body` is set
+fn to_ndjson(fetched: &Fetched, body: bool) -> serde_json::Value {
+    let mut record = serde_json::json!({
+        "url": fetched.request.url,
+        "method": fetched.request.method.to_string(),
+        "time_ms": fetched.elapsed.as_secs_f64() * 1000.0,
+    });
+    match &fetched.result {
+        Ok(response) => {
+            record["status"] = response.status.as_u16().into();
+            if body {
+                record["body"] = response.text(None).into();
+            }
+        }
+        Err(e) => record["error"] = e.to_string().into(),
+    }
+    record
+}
+
+/// The request to record in the HAR, with the headers it went out with
+fn har_request(fetched: &Fetched) -> Request {
+    let mut request = fetched.request.clone();
+    if let Some(sent) = &fetched.sent {
+        request.url = sent.url().to_string();
+        request.headers = sent
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect();
+    }
+    request
+}
+
+/// The metadata of the response for `--meta-json`, or the error if there is none. Repeated
+/// headers have an array of values.
+fn to_meta_json(fetched: &Fetched) -> serde_json::Value {
+    let response = match &fetched.result {
+        Ok(response) => response,
+        Err(e) => {
+            return serde_json::json!({
+                "url": fetched.request.url,
+                "error": e.to_string(),
+            })
+        }
+    };
+    let mut headers = serde_json::Map::new();
+    for name in response.headers.keys() {
+        let mut values: Vec<_> = response
+            .headers
+            .get_all(name)
+            .iter()
+            .map(|v| serde_json::Value::from(String::from_utf8_lossy(v.as_bytes())))
+            .collect();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            values.into()
+        };
+        headers.insert(name.to_string(), value);
+    }
+    let phases = fetched.phases.as_ref().map(|phases| {
+        serde_json::json!({
+            "dns_ms": phases.dns.as_secs_f64() * 1000.0,
+            "connect_ms": phases.connect.as_secs_f64() * 1000.0,
+            "tls_ms": phases.tls.map(|tls| tls.as_secs_f64() * 1000.0),
+        })
+    });
+    serde_json::json!({
+        "url": fetched.request.url,
+        "final_url": response.url,
+        "status": response.status.as_u16(),
+        "version": format!("{:?}", response.version),
+        "headers": headers,
+        "size": response.body.len(),
+        "timings": {
+            "separate_connection": phases,
+            "wait_ms": response.wait.as_secs_f64() * 1000.0,
+            "receive_ms": response.receive.as_secs_f64() * 1000.0,
+            "total_ms": fetched.elapsed.as_secs_f64() * 1000.0,
+        },
+    })
+}
+
+/// Derive a file name from the URL, keeping only characters that are safe in file names.
+///
+/// The scheme is dropped and everything else is kept so that URLs differing only in their
+/// query string still get different names, e.g. `http://example.com/a?b=1` becomes
+/// `example.com_a_b_1`.
+fn file_name_for(url: &str) -> String {
+    let without_scheme = url.splitn(2, "://").last().unwrap_or(url);
+    let name: String = without_scheme
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '.' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    if name.is_empty() {
+        "index".to_string()
+    } else {
+        name
+    }
+}
+
+/// Append a counter to the name if it was already used by a previous URL
+fn unique_path(dir: &Path, name: &str, used_names: &mut HashSet<String>) -> PathBuf {
+    let mut candidate = name.to_string();
+    let mut counter = 1;
+    while !used_names.insert(candidate.clone()) {
+        candidate = format!("{}-{}", name, counter);
+        counter += 1;
+    }
+    dir.join(candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+    use serde_json::json;
+
+    use crate::{http::HttpMethod, request::Request, response::Response};
+
+    #[test]
+    fn file_name_drops_scheme_and_replaces_unsafe_chars() {
+        assert_eq!(
+            super::file_name_for("http://example.com/a?b=1"),
+            "example.com_a_b_1"
+        );
+        assert_eq!(super::file_name_for("https://example.com/"), "example.com");
+    }
+    #[test]
+    fn url_lists_skip_blank_lines_and_comments() {
+        assert_eq!(super::url_of_line("  # mirrors", false), None);
+        assert_eq!(super::url_of_line("   ", false), None);
+        assert_eq!(
+            super::url_of_line(" example.com/a b ", false).as_deref(),
+            Some("http://example.com/a%20b")
+        );
+    }
+    #[test]
+    fn duplicated_file_names_get_a_counter() {
+        let mut used = HashSet::new();
+        let dir = Path::new("out");
+        assert_eq!(super::unique_path(dir, "a", &mut used), dir.join("a"));
+        assert_eq!(super::unique_path(dir, "a", &mut used), dir.join("a-1"));
+        assert_eq!(super::unique_path(dir, "a", &mut used), dir.join("a-2"));
+    }
+    #[test]
+    fn repeated_headers_are_arrays_in_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.append("set-cookie", "a=1".parse().unwrap());
+        headers.append("set-cookie", "b=2".parse().unwrap());
+        headers.append("etag", "\"x\"".parse().unwrap());
+        let fetched = super::Fetched {
+            request: Request::new(HttpMethod::Get, "http://localhost/a"),
+            sent: None,
+            started: SystemTime::now(),
+            elapsed: Duration::from_millis(3),
+            phases: None,
+            tls: None,
+            result: Ok(Response {
+                url: "http://localhost/b".to_string(),
+                status: StatusCode::OK,
+                version: Version::HTTP_11,
+                headers,
+                body: vec![0; 5],
+                wait: Duration::from_millis(2),
+                receive: Duration::from_millis(1),
+            }),
+        };
+        let meta = super::to_meta_json(&fetched);
+        assert_eq!(meta["final_url"], "http://localhost/b");
+        assert_eq!(meta["size"], 5);
+        assert_eq!(meta["headers"]["set-cookie"], json!(["a=1", "b=2"]));
+        assert_eq!(meta["headers"]["etag"], "\"x\"");
+        assert_eq!(meta["timings"]["total_ms"], 3.0);
+    }
+}
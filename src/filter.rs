@@ -0,0 +1,551 @@
+//! A subset of the jq language (https://jqlang.github.io/jq/manual/) to select parts of JSON
+//! responses.
+//!
+//! Supported: `.`, `.name`, `."name"`, `.[index]`, `.[start:end]`, `.[]`, `?`, pipes `|`,
+//! commas `,`, parentheses, literals, comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) and the
+//! builtins `length`, `keys` and `select(f)`. For example `.items[] | select(.age > 30) | .name`.
+use std::{cmp::Ordering, str::FromStr};
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid filter at position {position}: {message}")]
+    Parse { position: usize, message: String },
+    #[error("{0}")]
+    Eval(String),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dot,
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Colon,
+    Pipe,
+    Comma,
+    Question,
+    Compare(CompareOp),
+}
+
+#[derive(Debug, Clone)]
+enum Ast {
+    Identity,
+    Field(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    Iterate,
+    Literal(Value),
+    Pipe(Box<Ast>, Box<Ast>),
+    Comma(Box<Ast>, Box<Ast>),
+    Compare(Box<Ast>, CompareOp, Box<Ast>),
+    /// `f?`: errors of `f` produce no output
+    Try(Box<Ast>),
+    Length,
+    Keys,
+    Select(Box<Ast>),
+}
+
+/// A parsed filter, ready to be applied to JSON values
+#[derive(Debug, Clone)]
+pub struct Filter {
+    ast: Ast,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        i += 1;
+        let token = match c {
+            ' ' | '\t' | '\n' | '\r' => continue,
+            '.' => Token::Dot,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ':' => Token::Colon,
+            '|' => Token::Pipe,
+            ',' => Token::Comma,
+            '?' => Token::Question,
+            '=' | '!' | '<' | '>' => {
+                let with_eq = chars.get(i) == Some(&'=');
+                if with_eq {
+                    i += 1;
+                }
+                Token::Compare(match (c, with_eq) {
+                    ('=', true) => CompareOp::Eq,
+                    ('!', true) => CompareOp::Ne,
+                    ('<', false) => CompareOp::Lt,
+                    ('<', true) => CompareOp::Le,
+                    ('>', false) => CompareOp::Gt,
+                    ('>', true) => CompareOp::Ge,
+                    _ => return Err(parse_error(start, format!("unexpected `{}`", c))),
+                })
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(parse_error(start, "unterminated string")),
+                        Some('"') => break,
+                        Some('\\') => {
+                            s.push(match chars.get(i + 1) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(c) => *c,
+                                None => return Err(parse_error(start, "unterminated string")),
+                            });
+                            i += 2;
+                        }
+                        Some(c) => {
+                            s.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                Token::Str(s)
+            }
+            '-' | '0'..='9' => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                Token::Num(
+                    text.parse()
+                        .map_err(|_| parse_error(start, format!("invalid number `{}`", text)))?,
+                )
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                Token::Ident(chars[start..i].iter().collect())
+            }
+            c => return Err(parse_error(start, format!("unexpected `{}`", c))),
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+fn parse_error(position: usize, message: impl Into<String>) -> Error {
+    Error::Parse {
+        position,
+        message: message.into(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(p, _)| *p)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let position = self.position();
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(parse_error(position, format!("expected {:?}", expected))),
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Ast> {
+        let mut ast = self.comma()?;
+        while self.peek() == Some(&Token::Pipe) {
+            self.pos += 1;
+            ast = Ast::Pipe(Box::new(ast), Box::new(self.comma()?));
+        }
+        Ok(ast)
+    }
+
+    fn comma(&mut self) -> Result<Ast> {
+        let mut ast = self.comparison()?;
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            ast = Ast::Comma(Box::new(ast), Box::new(self.comparison()?));
+        }
+        Ok(ast)
+    }
+
+    fn comparison(&mut self) -> Result<Ast> {
+        let lhs = self.postfix()?;
+        match self.peek() {
+            Some(Token::Compare(op)) => {
+                let op = *op;
+                self.pos += 1;
+                Ok(Ast::Compare(Box::new(lhs), op, Box::new(self.postfix()?)))
+            }
+            _ => Ok(lhs),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Ast> {
+        let mut ast = self.primary()?;
+        loop {
+            let suffix = match self.peek() {
+                Some(Token::Dot) => {
+                    self.pos += 1;
+                    self.field()?
+                }
+                Some(Token::LBracket) => self.bracket()?,
+                Some(Token::Question) => {
+                    self.pos += 1;
+                    ast = Ast::Try(Box::new(ast));
+                    continue;
+                }
+                _ => return Ok(ast),
+            };
+            ast = Ast::Pipe(Box::new(ast), Box::new(suffix));
+        }
+    }
+
+    /// The name after a dot
+    fn field(&mut self) -> Result<Ast> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Ident(name)) | Some(Token::Str(name)) => Ok(Ast::Field(name)),
+            _ => Err(parse_error(position, "expected a field name after `.`")),
+        }
+    }
+
+    fn integer(&mut self) -> Result<Option<i64>> {
+        match self.peek() {
+            Some(Token::Num(n)) if n.fract() == 0.0 => {
+                let n = *n as i64;
+                self.pos += 1;
+                Ok(Some(n))
+            }
+            Some(Token::Num(_)) => Err(parse_error(self.position(), "expected an integer")),
+            _ => Ok(None),
+        }
+    }
+
+    /// `[]`, `[index]`, `["name"]` or `[start:end]`
+    fn bracket(&mut self) -> Result<Ast> {
+        self.expect(Token::LBracket)?;
+        if self.peek() == Some(&Token::RBracket) {
+            self.pos += 1;
+            return Ok(Ast::Iterate);
+        }
+        if let Some(Token::Str(name)) = self.peek() {
+            let name = name.clone();
+            self.pos += 1;
+            self.expect(Token::RBracket)?;
+            return Ok(Ast::Field(name));
+        }
+        let start = self.integer()?;
+        let ast = if self.peek() == Some(&Token::Colon) {
+            self.pos += 1;
+            Ast::Slice(start, self.integer()?)
+        } else {
+            Ast::Index(start.ok_or_else(|| parse_error(self.position(), "expected an index"))?)
+        };
+        self.expect(Token::RBracket)?;
+        Ok(ast)
+    }
+
+    fn primary(&mut self) -> Result<Ast> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Dot) => match self.peek() {
+                Some(Token::Ident(_)) | Some(Token::Str(_)) => self.field(),
+                _ => Ok(Ast::Identity),
+            },
+            Some(Token::LParen) => {
+                let ast = self.pipe()?;
+                self.expect(Token::RParen)?;
+                Ok(ast)
+            }
+            Some(Token::Str(s)) => Ok(Ast::Literal(Value::String(s))),
+            Some(Token::Num(n)) => Ok(Ast::Literal(
+                serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number),
+            )),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Ast::Literal(Value::Bool(true))),
+                "false" => Ok(Ast::Literal(Value::Bool(false))),
+                "null" => Ok(Ast::Literal(Value::Null)),
+                "length" => Ok(Ast::Length),
+                "keys" => Ok(Ast::Keys),
+                "select" => {
+                    self.expect(Token::LParen)?;
+                    let condition = self.pipe()?;
+                    self.expect(Token::RParen)?;
+                    Ok(Ast::Select(Box::new(condition)))
+                }
+                _ => Err(parse_error(
+                    position,
+                    format!("unknown function `{}`", name),
+                )),
+            },
+            _ => Err(parse_error(position, "expected a filter")),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            end: s.len(),
+        };
+        let ast = parser.pipe()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parse_error(parser.position(), "unexpected token"));
+        }
+        Ok(Filter { ast })
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// The order jq uses: null < false < true < numbers < strings < arrays < objects
+fn compare(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(false) => 1,
+            Value::Bool(true) => 2,
+            Value::Number(_) => 3,
+            Value::String(_) => 4,
+            Value::Array(_) => 5,
+            Value::Object(_) => 6,
+        }
+    }
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+/// Resolve a possibly negative index against a length, clamping it to `0..=len`
+fn clamp_index(index: i64, len: usize) -> usize {
+    if index < 0 {
+        len.saturating_sub(index.unsigned_abs() as usize)
+    } else {
+        (index as usize).min(len)
+    }
+}
+
+fn eval(ast: &Ast, input: &Value) -> Result<Vec<Value>> {
+    let error = |message: String| Err(Error::Eval(message));
+    Ok(match ast {
+        Ast::Identity => vec![input.clone()],
+        Ast::Literal(value) => vec![value.clone()],
+        Ast::Field(name) => match input {
+            Value::Object(map) => vec![map.get(name).cloned().unwrap_or(Value::Null)],
+            Value::Null => vec![Value::Null],
+            other => {
+                return error(format!(
+                    "cannot index {} with \"{}\"",
+                    type_name(other),
+                    name
+                ))
+            }
+        },
+        Ast::Index(index) => match input {
+            Value::Array(items) => {
+                let resolved = if *index < 0 {
+                    items.len().checked_sub(index.unsigned_abs() as usize)
+                } else {
+                    Some(*index as usize)
+                };
+                vec![resolved
+                    .and_then(|i| items.get(i))
+                    .cloned()
+                    .unwrap_or(Value::Null)]
+            }
+            Value::Null => vec![Value::Null],
+            other => return error(format!("cannot index {} with number", type_name(other))),
+        },
+        Ast::Slice(start, end) => match input {
+            Value::Array(items) => {
+                let start = start.map_or(0, |s| clamp_index(s, items.len()));
+                let end = end.map_or(items.len(), |e| clamp_index(e, items.len()));
+                vec![Value::Array(items[start..end.max(start)].to_vec())]
+            }
+            Value::String(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                let start = start.map_or(0, |s| clamp_index(s, chars.len()));
+                let end = end.map_or(chars.len(), |e| clamp_index(e, chars.len()));
+                vec![Value::String(chars[start..end.max(start)].iter().collect())]
+            }
+            Value::Null => vec![Value::Null],
+            other => return error(format!("cannot slice {}", type_name(other))),
+        },
+        Ast::Iterate => match input {
+            Value::Array(items) => items.clone(),
+            Value::Object(map) => map.values().cloned().collect(),
+            other => return error(format!("cannot iterate over {}", type_name(other))),
+        },
+        Ast::Pipe(lhs, rhs) => {
+            let mut outputs = Vec::new();
+            for value in eval(lhs, input)? {
+                outputs.extend(eval(rhs, &value)?);
+            }
+            outputs
+        }
+        Ast::Comma(lhs, rhs) => {
+            let mut outputs = eval(lhs, input)?;
+            outputs.extend(eval(rhs, input)?);
+            outputs
+        }
+        Ast::Compare(lhs, op, rhs) => {
+            let mut outputs = Vec::new();
+            for r in eval(rhs, input)? {
+                for l in eval(lhs, input)? {
+                    let ordering = compare(&l, &r);
+                    outputs.push(Value::Bool(match op {
+                        CompareOp::Eq => ordering == Ordering::Equal,
+                        CompareOp::Ne => ordering != Ordering::Equal,
+                        CompareOp::Lt => ordering == Ordering::Less,
+                        CompareOp::Le => ordering != Ordering::Greater,
+                        CompareOp::Gt => ordering == Ordering::Greater,
+                        CompareOp::Ge => ordering != Ordering::Less,
+                    }));
+                }
+            }
+            outputs
+        }
+        Ast::Try(inner) => eval(inner, input).unwrap_or_default(),
+        Ast::Length => vec![match input {
+            Value::Null => Value::from(0),
+            Value::Bool(_) => return error("boolean has no length".to_string()),
+            Value::Number(n) => Value::from(n.as_f64().map_or(0.0, f64::abs)),
+            Value::String(s) => Value::from(s.chars().count()),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Object(map) => Value::from(map.len()),
+        }],
+        Ast::Keys => vec![match input {
+            Value::Object(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                Value::from(keys.into_iter().cloned().collect::<Vec<_>>())
+            }
+            Value::Array(items) => Value::from((0..items.len()).collect::<Vec<_>>()),
+            other => return error(format!("{} has no keys", type_name(other))),
+        }],
+        Ast::Select(condition) => {
+            if eval(condition, input)?.iter().any(is_truthy) {
+                vec![input.clone()]
+            } else {
+                Vec::new()
+            }
+        }
+    })
+}
+
+impl Filter {
+    /// Apply the filter, returning all the values it outputs
+    pub fn apply(&self, input: &Value) -> Result<Vec<Value>> {
+        eval(&self.ast, input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    fn apply(filter: &str, input: Value) -> Vec<Value> {
+        filter
+            .parse::<super::Filter>()
+            .unwrap()
+            .apply(&input)
+            .unwrap()
+    }
+
+    #[test]
+    fn paths_and_iteration() {
+        let input = json!({"items": [{"name": "a"}, {"name": "b"}], "a b": 1});
+        assert_eq!(
+            apply(".items[].name", input.clone()),
+            vec![json!("a"), json!("b")]
+        );
+        assert_eq!(apply(".items[-1].name", input.clone()), vec![json!("b")]);
+        assert_eq!(
+            apply(".[\"a b\"], .\"a b\"", input.clone()),
+            vec![json!(1), json!(1)]
+        );
+        assert_eq!(apply(".items[1:] | length", input.clone()), vec![json!(1)]);
+        assert_eq!(apply(".missing.deeper", input), vec![Value::Null]);
+    }
+    #[test]
+    fn select_and_comparisons() {
+        let input = json!([{"age": 20, "n": "x"}, {"age": 40, "n": "y"}]);
+        assert_eq!(
+            apply(".[] | select(.age >= 30) | .n", input.clone()),
+            vec![json!("y")]
+        );
+        assert_eq!(apply(".[0] | keys", input), vec![json!(["age", "n"])]);
+    }
+    #[test]
+    fn errors_are_reported_unless_optional() {
+        let filter: super::Filter = ".a.b".parse().unwrap();
+        assert!(filter.apply(&json!({"a": 1})).is_err());
+        assert_eq!(apply(".a.b?", json!({"a": 1})), Vec::<Value>::new());
+        assert!(".a[".parse::<super::Filter>().is_err());
+        assert!("foo".parse::<super::Filter>().is_err());
+    }
+}
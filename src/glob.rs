@@ -0,0 +1,198 @@
+//! curl-style URL globbing: `example.com/img[1-100].png` and `example.com/{users,groups}` stand
+//! for several URLs, each sent as its own request.
+//!
+//! Ranges are `[1-100]`, `[001-100]` with leading zeros kept, `[a-z]` or `[1-100:10]` with a
+//! step. Sets are `{a,b,c}`. Brackets and braces that are neither, like the brackets of an IPv6
+//! address, are kept as they are. With `-o`, `#1`, `#2`... in the file name are replaced with the
+//! values of the first, second... glob of the URL.
+use std::iter;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid range `[{0}]` in the URL, the step must be positive")]
+    InvalidStep(String),
+    #[error("The URL globs expand to more than {} URLs", MAX_URLS)]
+    TooMany,
+}
+
+/// The most URLs a single URL can expand to
+const MAX_URLS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Values(Vec<String>),
+}
+
+/// The values of a `[first-last:step]` range, `None` if it is not a range
+fn range(spec: &str) -> Option<Result<Vec<String>, Error>> {
+    let (bounds, step) = match spec.split_once(':') {
+        Some((bounds, step)) => (bounds, Some(step.parse::<u64>().ok()?)),
+        None => (spec, None),
+    };
+    if step == Some(0) {
+        return Some(Err(Error::InvalidStep(spec.to_string())));
+    }
+    let step = step.unwrap_or(1) as usize;
+    let (first, last) = bounds.split_once('-')?;
+    if let (Ok(start), Ok(end)) = (first.parse::<u64>(), last.parse::<u64>()) {
+        // `[001-100]` gives 001, 002... like curl
+        let width = if first.starts_with('0') {
+            first.len()
+        } else {
+            0
+        };
+        let values = (start..=end)
+            .step_by(step)
+            .map(|n| format!("{:0width$}", n, width = width))
+            .collect();
+        return Some(Ok(values));
+    }
+    let mut chars = (first.chars(), last.chars());
+    match (
+        chars.0.next(),
+        chars.0.next(),
+        chars.1.next(),
+        chars.1.next(),
+    ) {
+        (Some(start), None, Some(end), None) if start.is_ascii_alphabetic() => {
+            Some(Ok((start..=end).step_by(step).map(String::from).collect()))
+        }
+        _ => None,
+    }
+}
+
+fn parse(url: &str) -> Result<Vec<Segment>, Error> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = url;
+    while let Some(start) = rest.find(['[', '{']) {
+        let close = if rest[start..].starts_with('[') {
+            ']'
+        } else {
+            '}'
+        };
+        let end = match rest[start..].find(close) {
+            Some(end) => start + end,
+            None => break,
+        };
+        let spec = &rest[start + 1..end];
+        let values = match close {
+            ']' => range(spec).transpose()?,
+            _ if spec.contains(',') => Some(spec.split(',').map(String::from).collect()),
+            _ => None,
+        };
+        match values {
+            Some(values) => {
+                literal.push_str(&rest[..start]);
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                segments.push(Segment::Values(values));
+            }
+            None => literal.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    literal.push_str(rest);
+    segments.push(Segment::Literal(literal));
+    Ok(segments)
+}
+
+/// The URLs a URL expands to, each with the values its globs took, in the order curl sends
+/// them: the last glob changes first
+pub fn expand(url: &str) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let segments = parse(url)?;
+    let total = segments
+        .iter()
+        .try_fold(1usize, |total, segment| match segment {
+            Segment::Values(values) => total.checked_mul(values.len()),
+            Segment::Literal(_) => Some(total),
+        });
+    if total.is_none_or(|total| total > MAX_URLS) {
+        return Err(Error::TooMany);
+    }
+    let mut expanded = vec![(String::new(), Vec::new())];
+    for segment in segments {
+        expanded = match segment {
+            Segment::Literal(literal) => {
+                for (url, _) in &mut expanded {
+                    url.push_str(&literal);
+                }
+                expanded
+            }
+            Segment::Values(values) => expanded
+                .into_iter()
+                .flat_map(|(url, taken)| {
+                    values.iter().map(move |value| {
+                        let taken = taken.iter().cloned().chain(iter::once(value.clone()));
+                        (format!("{}{}", url, value), taken.collect())
+                    })
+                })
+                .collect(),
+        };
+    }
+    Ok(expanded)
+}
+
+/// The file name for `-o`, with `#N` replaced by the value of the Nth glob
+pub fn output_name(template: &str, values: &[String]) -> String {
+    let mut name = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('#') {
+        name.push_str(&rest[..start]);
+        let digits = rest[start + 1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(rest.len() - start - 1, |end| end);
+        let value = rest[start + 1..start + 1 + digits]
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| values.get(n.checked_sub(1)?));
+        match value {
+            Some(value) => name.push_str(value),
+            None => name.push_str(&rest[start..start + 1 + digits]),
+        }
+        rest = &rest[start + 1 + digits..];
+    }
+    name.push_str(rest);
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+
+    fn urls(url: &str) -> Vec<String> {
+        super::expand(url)
+            .unwrap()
+            .into_iter()
+            .map(|(url, _)| url)
+            .collect()
+    }
+
+    #[test]
+    fn ranges_and_sets_are_expanded() {
+        assert_eq!(
+            urls("a/img[1-3].png"),
+            ["a/img1.png", "a/img2.png", "a/img3.png"]
+        );
+        assert_eq!(urls("a/[08-10]"), ["a/08", "a/09", "a/10"]);
+        assert_eq!(urls("a/[a-e:2]"), ["a/a", "a/c", "a/e"]);
+        assert_eq!(urls("a/{x,y}/[1-2]"), ["a/x/1", "a/x/2", "a/y/1", "a/y/2"]);
+        assert_eq!(super::expand("a/[1-2]{x,y}").unwrap()[1].1, ["1", "y"]);
+    }
+    #[test]
+    fn other_brackets_are_kept() {
+        assert_eq!(urls("http://[::1]:80/{a}"), ["http://[::1]:80/{a}"]);
+        assert_eq!(urls("a/[1-2"), ["a/[1-2"]);
+        assert_eq!(
+            super::expand("a/[1-2:0]"),
+            Err(Error::InvalidStep("1-2:0".into()))
+        );
+        assert_eq!(super::expand("[1-1000][1-1000]"), Err(Error::TooMany));
+    }
+    #[test]
+    fn output_names_use_the_glob_values() {
+        let values = ["7".to_string(), "b".to_string()];
+        assert_eq!(super::output_name("img#1_#2.png", &values), "img7_b.png");
+        assert_eq!(super::output_name("#3#x#", &values), "#3#x#");
+    }
+}
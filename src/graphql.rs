@@ -0,0 +1,82 @@
+//! GraphQL over HTTP: wrapping the query in the JSON envelope and splitting the response.
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+use serde_json::{json, Map, Value};
+
+use crate::template::Var;
+
+/// Read the query given with `-d`: `@path` reads a file, `-` or no value reads stdin
+fn read_query(data: Option<&str>) -> io::Result<String> {
+    match data {
+        None | Some("-") => {
+            let mut query = String::new();
+            io::stdin().read_to_string(&mut query)?;
+            Ok(query)
+        }
+        Some(data) => match data.strip_prefix('@') {
+            Some(path) => fs::read_to_string(path),
+            None => Ok(data.to_string()),
+        },
+    }
+}
+
+/// The value of a variable: JSON if it parses as JSON (`3`, `true`, `[1]`...), a string otherwise
+fn variable_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+fn envelope(query: &str, vars: &[Var]) -> Value {
+    let variables: Map<String, Value> = vars
+        .iter()
+        .map(|var| (var.name.clone(), variable_value(&var.value)))
+        .collect();
+    json!({ "query": query, "variables": variables })
+}
+
+/// The JSON body of the request for the query given with `-d` and the variables
+pub fn request_body(data: Option<&str>, vars: &[Var]) -> io::Result<String> {
+    Ok(envelope(&read_query(data)?, vars).to_string())
+}
+
+/// Print the `data` of a GraphQL response to stdout and its `errors` to stderr, pretty-printed.
+/// A body that is not a GraphQL response is printed as is.
+pub fn print_response(body: &[u8]) {
+    let value: Value = match serde_json::from_slice(body) {
+        Ok(Value::Object(value)) if value.contains_key("data") || value.contains_key("errors") => {
+            Value::Object(value)
+        }
+        _ => {
+            println!("{}", String::from_utf8_lossy(body));
+            return;
+        }
+    };
+    if let Some(data) = value.get("data").filter(|data| !data.is_null()) {
+        println!("{}", pretty(data));
+    }
+    if let Some(errors) = value.get("errors") {
+        eprintln!("{}", pretty(errors));
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::template::Var;
+
+    #[test]
+    fn variables_are_typed_when_they_look_like_json() {
+        let vars: Vec<Var> = vec!["id=3".parse().unwrap(), "name=Ann".parse().unwrap()];
+        assert_eq!(
+            super::envelope("query { a }", &vars),
+            json!({"query": "query { a }", "variables": {"id": 3, "name": "Ann"}})
+        );
+    }
+}
@@ -0,0 +1,470 @@
+//! HTTP Archive (HAR) 1.2 models, see http://www.softwareishard.com/blog/har-12-spec/
+//!
+//! Only the fields rq can fill are modelled. Cookies are not parsed, so they are always empty,
+//! and timings which are not measured (DNS, connect...) are reported as unknown (`-1`).
+use std::{fs, io, path::Path, time::SystemTime};
+
+use serde_json::{json, Value};
+
+use crate::{datetime, multipart, request::Request, response::Response};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0} is not a valid HAR file: {1}")]
+    Invalid(String, String),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameValue {
+    pub name: String,
+    pub value: String,
+}
+
+impl NameValue {
+    fn to_json(&self) -> Value {
+        json!({"name": self.name, "value": self.value})
+    }
+}
+
+fn to_json_list(items: &[NameValue]) -> Value {
+    Value::Array(items.iter().map(NameValue::to_json).collect())
+}
+
+/// Parse a list of name/value pairs, a missing list is empty
+fn from_json_list(value: &Value) -> Option<Vec<NameValue>> {
+    match value {
+        Value::Null => Some(Vec::new()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| {
+                Some(NameValue {
+                    name: item["name"].as_str()?.to_string(),
+                    value: item["value"].as_str()?.to_string(),
+                })
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn str_or_empty(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostData {
+    pub mime_type: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HarRequest {
+    pub method: String,
+    pub url: String,
+    pub http_version: String,
+    pub headers: Vec<NameValue>,
+    pub query_string: Vec<NameValue>,
+    pub post_data: Option<PostData>,
+    pub body_size: i64,
+}
+
+impl HarRequest {
+    fn to_json(&self) -> Value {
+        let mut value = json!({
+            "method": self.method,
+            "url": self.url,
+            "httpVersion": self.http_version,
+            "cookies": [],
+            "headers": to_json_list(&self.headers),
+            "queryString": to_json_list(&self.query_string),
+            "headersSize": -1,
+            "bodySize": self.body_size,
+        });
+        if let Some(post_data) = &self.post_data {
+            value["postData"] = json!({
+                "mimeType": post_data.mime_type,
+                "text": post_data.text,
+            });
+        }
+        value
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let post_data = match &value["postData"] {
+            Value::Null => None,
+            post_data => Some(PostData {
+                mime_type: str_or_empty(&post_data["mimeType"]),
+                text: str_or_empty(&post_data["text"]),
+            }),
+        };
+        Some(HarRequest {
+            method: value["method"].as_str()?.to_string(),
+            url: value["url"].as_str()?.to_string(),
+            http_version: str_or_empty(&value["httpVersion"]),
+            headers: from_json_list(&value["headers"])?,
+            query_string: from_json_list(&value["queryString"])?,
+            post_data,
+            body_size: value["bodySize"].as_i64().unwrap_or(-1),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Content {
+    pub size: i64,
+    pub mime_type: String,
+    pub text: Option<String>,
+    /// `base64` if the text is the base64 encoding of a binary body
+    pub encoding: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HarResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    pub headers: Vec<NameValue>,
+    pub content: Content,
+    pub redirect_url: String,
+    pub body_size: i64,
+}
+
+impl HarResponse {
+    fn to_json(&self) -> Value {
+        let mut content = json!({
+            "size": self.content.size,
+            "mimeType": self.content.mime_type,
+        });
+        if let Some(text) = &self.content.text {
+            content["text"] = json!(text);
+        }
+        if let Some(encoding) = &self.content.encoding {
+            content["encoding"] = json!(encoding);
+        }
+        json!({
+            "status": self.status,
+            "statusText": self.status_text,
+            "httpVersion": self.http_version,
+            "cookies": [],
+            "headers": to_json_list(&self.headers),
+            "content": content,
+            "redirectURL": self.redirect_url,
+            "headersSize": -1,
+            "bodySize": self.body_size,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let content = &value["content"];
+        Some(HarResponse {
+            status: value["status"].as_u64()? as u16,
+            status_text: str_or_empty(&value["statusText"]),
+            http_version: str_or_empty(&value["httpVersion"]),
+            headers: from_json_list(&value["headers"])?,
+            content: Content {
+                size: content["size"].as_i64().unwrap_or(-1),
+                mime_type: str_or_empty(&content["mimeType"]),
+                text: content["text"].as_str().map(str::to_string),
+                encoding: content["encoding"].as_str().map(str::to_string),
+            },
+            redirect_url: str_or_empty(&value["redirectURL"]),
+            body_size: value["bodySize"].as_i64().unwrap_or(-1),
+        })
+    }
+}
+
+/// Durations in milliseconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timings {
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Unix timestamp in milliseconds
+    pub started: i64,
+    pub request: HarRequest,
+    pub response: HarResponse,
+    pub timings: Timings,
+}
+
+impl Entry {
+    /// Build an entry from a request and the response received for it
+    pub fn new(request: &Request, response: &Response, started: SystemTime) -> Self {
+        let headers = request
+            .headers
+            .iter()
+            .map(|(name, value)| NameValue {
+                name: name.clone(),
+                value: value.clone(),
+            })
+            .collect();
+        let query_string = reqwest::Url::parse(&request.url)
+            .map(|url| {
+                url.query_pairs()
+                    .map(|(name, value)| NameValue {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let post_data = request.body.as_ref().map(|body| PostData {
+            mime_type: request
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default(),
+            text: body.clone(),
+        });
+        let response_headers = response
+            .headers
+            .iter()
+            .map(|(name, value)| NameValue {
+                name: name.to_string(),
+                value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            })
+            .collect();
+        let (text, encoding) = match std::str::from_utf8(&response.body) {
+            Ok(text) => (text.to_string(), None),
+            Err(_) => (base64::encode(&response.body), Some("base64".to_string())),
+        };
+        let http_version = format!("{:?}", response.version);
+        Entry {
+            started: datetime::unix_millis(started),
+            request: HarRequest {
+                method: request.method.to_string(),
+                url: request.url.clone(),
+                http_version: http_version.clone(),
+                headers,
+                query_string,
+                post_data,
+                body_size: request.body.as_ref().map_or(0, |b| b.len() as i64),
+            },
+            response: HarResponse {
+                status: response.status.as_u16(),
+                status_text: response
+                    .status
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_string(),
+                http_version,
+                headers: response_headers,
+                content: Content {
+                    size: response.body.len() as i64,
+                    mime_type: response
+                        .header("content-type")
+                        .unwrap_or_default()
+                        .to_string(),
+                    text: Some(text),
+                    encoding,
+                },
+                redirect_url: response.header("location").unwrap_or_default().to_string(),
+                body_size: response.body.len() as i64,
+            },
+            timings: Timings {
+                send: 0.0,
+                wait: response.wait.as_secs_f64() * 1000.0,
+                receive: response.receive.as_secs_f64() * 1000.0,
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let timings = &self.timings;
+        json!({
+            "startedDateTime": datetime::format_rfc3339_millis(self.started),
+            "time": timings.send + timings.wait + timings.receive,
+            "request": self.request.to_json(),
+            "response": self.response.to_json(),
+            "cache": {},
+            "timings": {
+                "blocked": -1,
+                "dns": -1,
+                "connect": -1,
+                "ssl": -1,
+                "send": timings.send,
+                "wait": timings.wait,
+                "receive": timings.receive,
+            },
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let timings = &value["timings"];
+        // Unknown timings are -1, count them as 0 so they can be summed
+        let timing = |name: &str| timings[name].as_f64().unwrap_or_default().max(0.0);
+        Some(Entry {
+            started: 0,
+            request: HarRequest::from_json(&value["request"])?,
+            response: HarResponse::from_json(&value["response"])?,
+            timings: Timings {
+                send: timing("send"),
+                wait: timing("wait"),
+                receive: timing("receive"),
+            },
+        })
+    }
+
+    /// Convert the recorded request back to a request rq can send. Headers computed by the
+    /// client when sending (`Host`, `Content-Length`...) and HTTP/2 pseudo-headers are dropped.
+    pub fn to_request(&self) -> std::result::Result<Request, crate::http::Error> {
+        const COMPUTED_HEADERS: &[&str] =
+            &["host", "content-length", "connection", "accept-encoding"];
+        let headers = self
+            .request
+            .headers
+            .iter()
+            .filter(|h| !h.name.starts_with(':'))
+            .filter(|h| !COMPUTED_HEADERS.contains(&h.name.to_lowercase().as_str()))
+            .map(|h| (h.name.clone(), h.value.clone()))
+            .collect();
+        Ok(Request {
+            method: self.request.method.parse()?,
+            url: self.request.url.clone(),
+            headers,
+            body: self.request.post_data.as_ref().map(|p| p.text.clone()),
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Default::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Default::default(),
+        })
+    }
+}
+
+/// Read the entries of a HAR file
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let invalid = |message: String| Error::Invalid(path.display().to_string(), message);
+    let har: Value =
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+    har["log"]["entries"]
+        .as_array()
+        .ok_or_else(|| invalid("missing log.entries".to_string()))?
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            Entry::from_json(entry).ok_or_else(|| invalid(format!("entry {} is malformed", i + 1)))
+        })
+        .collect()
+}
+
+/// An empty HAR document
+fn new_log() -> Value {
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": {"name": "rq", "version": env!("CARGO_PKG_VERSION")},
+            "entries": [],
+        }
+    })
+}
+
+/// Append the entries to the HAR file, creating it if it does not exist
+pub fn append(path: &Path, entries: &[Entry]) -> Result<()> {
+    let invalid = |message: &str| Error::Invalid(path.display().to_string(), message.to_string());
+    let mut har = match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| invalid(&e.to_string()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => new_log(),
+        Err(e) => return Err(e.into()),
+    };
+    let log_entries = har["log"]["entries"]
+        .as_array_mut()
+        .ok_or_else(|| invalid("missing log.entries"))?;
+    log_entries.extend(entries.iter().map(Entry::to_json));
+    let content = serde_json::to_string_pretty(&har).map_err(|e| invalid(&e.to_string()))?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use crate::{http::HttpMethod, multipart, request::Request, response::Response};
+
+    #[test]
+    fn entry_is_converted_to_har() {
+        let request = Request {
+            method: HttpMethod::Post,
+            url: "http://example.com/a?b=1&c=x%20y".to_string(),
+            headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+            body: Some("hi".to_string()),
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Default::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Default::default(),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/octet-stream".parse().unwrap());
+        let response = Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers,
+            body: vec![0xff, 0x00],
+            wait: Duration::from_millis(10),
+            receive: Duration::from_millis(5),
+        };
+        let started = UNIX_EPOCH + Duration::from_secs(1);
+        let har = super::Entry::new(&request, &response, started).to_json();
+        assert_eq!(har["startedDateTime"], "1970-01-01T00:00:01.000Z");
+        assert_eq!(har["time"], 15.0);
+        assert_eq!(har["request"]["queryString"][1]["value"], "x y");
+        assert_eq!(har["request"]["postData"]["mimeType"], "text/plain");
+        assert_eq!(har["response"]["statusText"], "OK");
+        assert_eq!(har["response"]["httpVersion"], "HTTP/1.1");
+        assert_eq!(har["response"]["content"]["text"], "/wA=");
+        assert_eq!(har["response"]["content"]["encoding"], "base64");
+    }
+    #[test]
+    fn entry_is_read_back_from_har() {
+        let har = serde_json::json!({
+            "request": {
+                "method": "POST",
+                "url": "https://example.com/",
+                "headers": [
+                    {"name": ":authority", "value": "example.com"},
+                    {"name": "Content-Length", "value": "2"},
+                    {"name": "Accept", "value": "*/*"}
+                ],
+                "postData": {"mimeType": "application/json", "text": "{}"}
+            },
+            "response": {"status": 201, "content": {"mimeType": "text/plain"}},
+            "timings": {"dns": -1, "wait": 3.5}
+        });
+        let entry = super::Entry::from_json(&har).unwrap();
+        assert_eq!(entry.response.status, 201);
+        assert_eq!(entry.timings.wait, 3.5);
+        let request = entry.to_request().unwrap();
+        assert_eq!(
+            request.headers,
+            vec![("Accept".to_string(), "*/*".to_string())]
+        );
+        assert_eq!(request.body.as_deref(), Some("{}"));
+    }
+}
@@ -0,0 +1,95 @@
+//! Replaying the requests recorded in a HAR file and comparing the responses.
+use std::path::Path;
+
+use futures_util::stream::{self, StreamExt};
+use reqwest::Client;
+
+use crate::{har, middleware::Pipeline, Error, Result};
+
+/// Whether the host of the URL is `host` or one of its subdomains
+fn matches_host(url: &str, host: &str) -> bool {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .is_some_and(|h| {
+            let host = host.to_lowercase();
+            h == host || h.ends_with(&format!(".{}", host))
+        })
+}
+
+/// Send the requests recorded in the HAR file again and print the status received next to the
+/// recorded one. Return an error if some statuses differ or some requests failed.
+pub async fn run(
+    client: &Client,
+    pipeline: &Pipeline,
+    path: &Path,
+    filter: Option<&str>,
+    concurrency: usize,
+) -> Result<()> {
+    let entries: Vec<_> = har::read(path)?
+        .into_iter()
+        .filter(|e| filter.is_none_or(|host| matches_host(&e.request.url, host)))
+        .collect();
+    let total = entries.len();
+    let mut results = stream::iter(entries)
+        .map(|entry| async move {
+            let outcome = match entry.to_request() {
+                Ok(mut request) => {
+                    request.pipeline = pipeline.clone();
+                    request
+                        .send(client)
+                        .await
+                        .map(|response| response.status.as_u16())
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            (entry, outcome)
+        })
+        .buffered(concurrency.max(1));
+    let mut differences = 0;
+    let mut failures = 0;
+    while let Some((entry, outcome)) = results.next().await {
+        let recorded = entry.response.status;
+        let (marker, received) = match outcome {
+            Ok(status) if status == recorded => ("  ", status.to_string()),
+            Ok(status) => {
+                differences += 1;
+                ("!=", status.to_string())
+            }
+            Err(e) => {
+                failures += 1;
+                ("!!", format!("error: {}", e))
+            }
+        };
+        println!(
+            "{} {} -> {}  {} {}",
+            marker, recorded, received, entry.request.method, entry.request.url
+        );
+    }
+    println!(
+        "{} replayed, {} with a different status, {} failed",
+        total, differences, failures
+    );
+    if differences + failures > 0 {
+        return Err(Error::Failed(differences + failures));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn host_filter_matches_subdomains() {
+        assert!(super::matches_host(
+            "https://api.example.com/a",
+            "example.com"
+        ));
+        assert!(super::matches_host("https://Example.com/a", "example.com"));
+        assert!(!super::matches_host(
+            "https://badexample.com/a",
+            "example.com"
+        ));
+        assert!(!super::matches_host("not a url", "example.com"));
+    }
+}
@@ -0,0 +1,253 @@
+//! History of the executed requests, stored as one JSON object per line in the data directory.
+//!
+//! The ID of an entry is its line number, so IDs are stable as long as the file is only appended
+//! to. The file is readable by the user only, and the values of the headers holding credentials
+//! are not recorded.
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use serde_json::{json, Value};
+
+use crate::{datetime, multipart, paths, request::Request};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No history entry with ID {0}")]
+    NotFound(String),
+    #[error("History entry {0} is corrupted")]
+    Corrupted(usize),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A request that was sent, with some metadata about its response
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: usize,
+    /// Unix timestamp in milliseconds
+    pub time: i64,
+    pub request: Request,
+    /// The status code, or `None` if no response was received
+    pub status: Option<u16>,
+    pub elapsed: Duration,
+}
+
+impl Entry {
+    fn to_json(&self) -> Value {
+        json!({
+            "time": self.time,
+            "method": self.request.method.to_string(),
+            "url": self.request.url,
+            "headers": self.request.headers,
+            "body": self.request.body,
+            "status": self.status,
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+        })
+    }
+
+    fn from_json(id: usize, value: &Value) -> Option<Self> {
+        let headers = value["headers"]
+            .as_array()?
+            .iter()
+            .map(|h| Some((h[0].as_str()?.to_string(), h[1].as_str()?.to_string())))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Entry {
+            id,
+            time: value["time"].as_i64()?,
+            request: Request {
+                method: value["method"].as_str()?.parse().ok()?,
+                url: value["url"].as_str()?.to_string(),
+                headers,
+                body: value["body"].as_str().map(str::to_string),
+                form: Vec::new(),
+                boundary: multipart::boundary(),
+                upload: None,
+                compress: None,
+                unix_socket: None,
+                expect_continue: false,
+                connect: None,
+                redirects: Default::default(),
+                max_response_size: None,
+                limit_rate: None,
+                trace: None,
+                pipeline: Default::default(),
+            },
+            status: value["status"].as_u64().map(|s| s as u16),
+            elapsed: Duration::from_millis(value["elapsed_ms"].as_u64()?),
+        })
+    }
+}
+
+/// Value recorded in place of credentials
+const REDACTED: &str = "<redacted>";
+
+/// Whether the header carries credentials: authorization, cookies or an API key
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    matches!(
+        name.as_str(),
+        "authorization" | "proxy-authorization" | "cookie"
+    ) || name.replace('_', "-").contains("api-key")
+        || name.contains("apikey")
+}
+
+/// The headers with the values of those carrying credentials replaced
+fn redact(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_sensitive(name) { REDACTED } else { value };
+            (name.clone(), value.to_string())
+        })
+        .collect()
+}
+
+/// Append a request to the history
+pub fn record(request: &Request, status: Option<u16>, elapsed: Duration) -> Result<()> {
+    let path = paths::history_file();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut request = request.clone();
+    request.headers = redact(&request.headers);
+    let entry = Entry {
+        id: 0,
+        time: datetime::unix_millis(SystemTime::now()),
+        request,
+        status,
+        elapsed,
+    };
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", entry.to_json())?;
+    Ok(())
+}
+
+fn read_all(path: &Path) -> Result<Vec<Entry>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let id = i + 1;
+        let value: Value = serde_json::from_str(&line?).map_err(|_| Error::Corrupted(id))?;
+        entries.push(Entry::from_json(id, &value).ok_or(Error::Corrupted(id))?);
+    }
+    Ok(entries)
+}
+
+/// All entries in the history, oldest first
+pub fn entries() -> Result<Vec<Entry>> {
+    read_all(&paths::history_file())
+}
+
+/// Find an entry by its ID. `last` (or `!!`) means the most recent entry.
+pub fn find(id: &str) -> Result<Entry> {
+    let mut entries = entries()?;
+    let index = match id {
+        "last" | "!!" => entries.len().checked_sub(1),
+        id => id
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| id.checked_sub(1))
+            .filter(|index| *index < entries.len()),
+    };
+    match index {
+        Some(index) => Ok(entries.swap_remove(index)),
+        None => Err(Error::NotFound(id.to_string())),
+    }
+}
+
+/// Print the last `limit` entries, one per line
+pub fn print(entries: &[Entry], limit: usize) {
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        let status = entry
+            .status
+            .map_or_else(|| "ERR".to_string(), |s| s.to_string());
+        println!(
+            "{:>5}  {}  {:<7} {}  {}",
+            entry.id,
+            datetime::format_rfc3339_millis(entry.time),
+            entry.request.method.to_string(),
+            status,
+            entry.request.url
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{http::HttpMethod, multipart, request::Request};
+
+    #[test]
+    fn credentials_are_redacted() {
+        let headers = [
+            ("Authorization", "Bearer abc"),
+            ("cookie", "id=1"),
+            ("X-Api-Key", "k"),
+            ("x_apikey", "k"),
+            ("Accept", "*/*"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<Vec<_>>();
+        let values = super::redact(&headers)
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                "<redacted>",
+                "<redacted>",
+                "<redacted>",
+                "<redacted>",
+                "*/*"
+            ]
+        );
+    }
+    #[test]
+    fn entry_survives_a_round_trip() {
+        let entry = super::Entry {
+            id: 3,
+            time: 1_600_000_000_000,
+            request: Request {
+                method: HttpMethod::Post,
+                url: "http://example.com".to_string(),
+                headers: vec![("a".to_string(), "b".to_string())],
+                body: Some("{}".to_string()),
+                form: Vec::new(),
+                boundary: multipart::boundary(),
+                upload: None,
+                compress: None,
+                unix_socket: None,
+                expect_continue: false,
+                connect: None,
+                redirects: Default::default(),
+                max_response_size: None,
+                limit_rate: None,
+                trace: None,
+                pipeline: Default::default(),
+            },
+            status: None,
+            elapsed: Duration::from_millis(42),
+        };
+        let mut parsed = super::Entry::from_json(3, &entry.to_json()).unwrap();
+        // A new boundary is chosen for the request read back
+        parsed.request.boundary = entry.request.boundary.clone();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", entry));
+    }
+}
@@ -1,14 +1,31 @@
 use std::{
     fmt::{Display, Formatter},
+    net::{IpAddr, SocketAddr},
     str::FromStr,
 };
 
+use crate::items;
+
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum Error {
     #[error("Unknown HTTP method: {0}")]
     UnknownMethod(String),
     #[error("Unknown Content-Type: {0}")]
     UnknownContentType(String),
+    #[error("Invalid header, expected `Name: value`: {0}")]
+    InvalidHeader(String),
+    #[error("Can't mix `key=value` data with raw data: {0}")]
+    MixedData(String),
+    #[error("{0}")]
+    InvalidItem(String),
+    #[error("Unknown User-Agent preset `{0}`, expected chrome, firefox, safari, curl or rq")]
+    UnknownUserAgent(String),
+    #[error("Invalid DNS override, expected `host:port:address`: {0}")]
+    InvalidResolve(String),
+    #[error("Unknown authentication type `{0}`, expected basic or negotiate")]
+    UnknownAuthType(String),
+    #[error("Invalid API key location `{0}`, expected `header:NAME` or `query:NAME`")]
+    InvalidApiKeyIn(String),
 }
 
 // Need custom type because reqwest::Method allow arbitrary method.
@@ -88,6 +105,8 @@ pub enum ContentType {
     /// URL encoded (percent encoded)
     Form,
     Multipart,
+    /// MessagePack, encoded from a JSON body
+    MsgPack,
 }
 
 impl FromStr for ContentType {
@@ -99,6 +118,7 @@ impl FromStr for ContentType {
             "json" | "application/json" => ContentType::Json,
             "form" | "application/x-www-form-urlencoded" => ContentType::Form,
             "file" | "multipart/form-data" => ContentType::Multipart,
+            "msgpack" | "application/msgpack" | "application/x-msgpack" => ContentType::MsgPack,
             content_type => return Err(Error::UnknownContentType(content_type.to_string())),
         })
     }
@@ -111,11 +131,222 @@ impl Display for ContentType {
             ContentType::Json => "application/json",
             ContentType::Form => "application/x-www-form-urlencoded",
             ContentType::Multipart => "multipart/form-data",
+            ContentType::MsgPack => "application/msgpack",
         };
         write!(f, "{}", str)
     }
 }
 
+/// A header given on the command line as `Name: value`
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for Header {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => Ok(Header {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            }),
+            _ => Err(Error::InvalidHeader(s.to_string())),
+        }
+    }
+}
+
+/// User-Agent strings of common clients, for `--ua`
+#[derive(Debug, Clone)]
+pub enum UserAgentPreset {
+    Chrome,
+    Firefox,
+    Safari,
+    Curl,
+    Rq,
+}
+
+impl FromStr for UserAgentPreset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "chrome" => UserAgentPreset::Chrome,
+            "firefox" => UserAgentPreset::Firefox,
+            "safari" => UserAgentPreset::Safari,
+            "curl" => UserAgentPreset::Curl,
+            "rq" => UserAgentPreset::Rq,
+            preset => return Err(Error::UnknownUserAgent(preset.to_string())),
+        })
+    }
+}
+
+impl UserAgentPreset {
+    pub fn value(&self) -> &'static str {
+        match self {
+            UserAgentPreset::Chrome => {
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36"
+            }
+            UserAgentPreset::Firefox => {
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0"
+            }
+            UserAgentPreset::Safari => {
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 \
+                (KHTML, like Gecko) Version/17.2 Safari/605.1.15"
+            }
+            UserAgentPreset::Curl => "curl/8.5.0",
+            UserAgentPreset::Rq => crate::USER_AGENT_VALUE,
+        }
+    }
+}
+
+/// A DNS override given as `host:port:address`, like curl's `--resolve`. IPv6 addresses can be
+/// written in brackets.
+#[derive(Debug, Clone)]
+pub struct Resolve {
+    pub host: String,
+    pub addr: SocketAddr,
+}
+
+impl FromStr for Resolve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || Error::InvalidResolve(s.to_string());
+        let mut parts = s.splitn(3, ':');
+        let (host, port, addr) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(host), Some(port), Some(addr)) if !host.is_empty() => (host, port, addr),
+            _ => return Err(error()),
+        };
+        let port = port.parse().map_err(|_| error())?;
+        let addr: IpAddr = addr
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| error())?;
+        Ok(Resolve {
+            host: host.to_string(),
+            addr: SocketAddr::new(addr, port),
+        })
+    }
+}
+
+/// Credentials for HTTP basic authentication, given as `user:password`. The password is optional.
+#[derive(Debug, Clone)]
+pub struct BasicAuth {
+    pub user: String,
+    pub password: Option<String>,
+}
+
+impl FromStr for BasicAuth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            Some((user, password)) => BasicAuth {
+                user: user.to_string(),
+                password: Some(password.to_string()),
+            },
+            None => BasicAuth {
+                user: s.to_string(),
+                password: None,
+            },
+        })
+    }
+}
+
+impl BasicAuth {
+    /// Value of the `Authorization` header for these credentials
+    pub fn header_value(&self) -> String {
+        let credentials = format!(
+            "{}:{}",
+            self.user,
+            self.password.as_deref().unwrap_or_default()
+        );
+        format!("Basic {}", base64::encode(credentials))
+    }
+
+    /// Decode the value of an `Authorization` header, if it uses basic authentication
+    pub fn from_header_value(value: &str) -> Option<Self> {
+        let encoded = value.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+        decoded.parse().ok()
+    }
+}
+
+/// How the requests are authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AuthType {
+    /// With the user and password of `--auth`
+    #[default]
+    Basic,
+    /// With SPNEGO and the Kerberos tickets of the user
+    Negotiate,
+}
+
+impl FromStr for AuthType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "basic" => AuthType::Basic,
+            "negotiate" => AuthType::Negotiate,
+            _ => return Err(Error::UnknownAuthType(s.to_string())),
+        })
+    }
+}
+
+/// Where the API key of `--api-key` is sent
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiKeyIn {
+    /// In the header with this name
+    Header(String),
+    /// In the query parameter with this name
+    Query(String),
+}
+
+impl Default for ApiKeyIn {
+    fn default() -> Self {
+        ApiKeyIn::Header("X-Api-Key".to_string())
+    }
+}
+
+impl FromStr for ApiKeyIn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidApiKeyIn(s.to_string());
+        let (location, name) = s.split_once(':').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        match location.trim().to_lowercase().as_str() {
+            "header" => Ok(ApiKeyIn::Header(name.to_string())),
+            "query" => Ok(ApiKeyIn::Query(name.to_string())),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl ApiKeyIn {
+    /// Add the key to the request
+    pub fn apply(&self, key: &str, request: &mut crate::Request) {
+        match self {
+            ApiKeyIn::Header(name) => request.set_header(name, key.to_string()),
+            ApiKeyIn::Query(name) => {
+                if let Ok(mut url) = reqwest::Url::parse(&request.url) {
+                    url.query_pairs_mut().append_pair(name, key);
+                    request.url = url.to_string();
+                }
+            }
+        }
+    }
+}
+
 /// Guess whether the string is URL encoded (percent encoded) or not.
 ///
 /// The guess is made based on whether a key followed by the equal sign can be found or not.
@@ -254,6 +485,67 @@ pub fn guess_content_type(s: &str) -> ContentType {
     }
 }
 
+/// Split a `key=value` or `key:=json` item of `-d`, whose key is made of the characters seen in
+/// form field names. The flag tells whether the value is raw JSON.
+fn as_field(item: &str) -> Option<(&str, &str, bool)> {
+    let (key, value) = item.split_once('=')?;
+    let (key, raw_json) = match key.strip_suffix(':') {
+        Some(key) => (key, true),
+        None => (key, false),
+    };
+    let valid_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_.[]".contains(c));
+    if valid_key {
+        Some((key, value, raw_json))
+    } else {
+        None
+    }
+}
+
+/// Merge the items given with `-d` into a single body. As with curl, items are joined with `&`,
+/// except that `key=value` items become the members of an object when the content type is JSON,
+/// nested according to the brackets in the keys.
+/// `key:=json` items, whose value is parsed as JSON, make the body JSON when no content type is
+/// given. `key=value` items can't be mixed with raw data.
+pub fn merge_data(
+    items: &[String],
+    content_type: Option<&ContentType>,
+) -> Result<Option<String>, Error> {
+    let fields: Vec<_> = items.iter().filter_map(|item| as_field(item)).collect();
+    let has_raw_json = fields.iter().any(|(_, _, raw_json)| *raw_json);
+    let json = match content_type {
+        Some(content_type) => matches!(content_type, ContentType::Json | ContentType::MsgPack),
+        None => has_raw_json,
+    };
+    if fields.len() == items.len() && json {
+        let mut object = serde_json::Value::Object(serde_json::Map::new());
+        for (key, value, raw_json) in fields {
+            let value = if raw_json {
+                items::parse_raw_json(value).map_err(Error::InvalidItem)?
+            } else {
+                value.into()
+            };
+            items::insert(&mut object, key, value).map_err(Error::InvalidItem)?;
+        }
+        return Ok(Some(object.to_string()));
+    }
+    if !fields.is_empty() && fields.len() != items.len() && items.len() > 1 {
+        let raw = items.iter().find(|item| as_field(item).is_none());
+        return Err(Error::MixedData(raw.cloned().unwrap_or_default()));
+    }
+    if has_raw_json {
+        return Err(Error::InvalidItem(
+            "`key:=json` items can only be sent in a JSON body".to_string(),
+        ));
+    }
+    if items.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(items.join("&")))
+}
+
 // Tests having `looks_like` in name are about the case where the guess may be incorrect. Other
 // tests are about the case where the guess is guaranteed to be correct. There are explanations
 // in the specific tests.
@@ -356,4 +648,77 @@ mod tests {
         ));
         assert!(super::maybe_json(r#"  {"":  "#));
     }
+    #[test]
+    fn header_is_split_at_first_colon() {
+        let header: super::Header = "X-Time:  12:30 ".parse().unwrap();
+        assert_eq!(
+            (header.name.as_str(), header.value.as_str()),
+            ("X-Time", "12:30")
+        );
+        assert!("no colon".parse::<super::Header>().is_err());
+        assert!(": value".parse::<super::Header>().is_err());
+    }
+    #[test]
+    fn api_key_goes_in_the_header_or_the_query() {
+        let mut request = crate::Request::new(super::HttpMethod::Get, "http://a/b?x=1");
+        super::ApiKeyIn::default().apply("k1", &mut request);
+        let location: super::ApiKeyIn = "query:api_key".parse().unwrap();
+        location.apply("k 2", &mut request);
+        assert_eq!(request.headers, [("X-Api-Key".into(), "k1".into())]);
+        assert_eq!(request.url, "http://a/b?x=1&api_key=k+2");
+        assert!("cookie:key".parse::<super::ApiKeyIn>().is_err());
+        assert!("header:".parse::<super::ApiKeyIn>().is_err());
+    }
+    #[test]
+    fn basic_auth_header_round_trip() {
+        let auth: super::BasicAuth = "user:pa:ss".parse().unwrap();
+        assert_eq!(auth.header_value(), "Basic dXNlcjpwYTpzcw==");
+        let decoded = super::BasicAuth::from_header_value(&auth.header_value()).unwrap();
+        assert_eq!(decoded.user, "user");
+        assert_eq!(decoded.password.as_deref(), Some("pa:ss"));
+        assert!(super::BasicAuth::from_header_value("Bearer abc").is_none());
+    }
+    #[test]
+    fn data_items_are_merged_according_to_content_type() {
+        use super::{merge_data, ContentType};
+        let items: Vec<String> = vec!["a=1".into(), "b=x y".into()];
+        assert_eq!(merge_data(&items, None).unwrap().unwrap(), "a=1&b=x y");
+        assert_eq!(
+            merge_data(&items, Some(&ContentType::Json))
+                .unwrap()
+                .unwrap(),
+            r#"{"a":"1","b":"x y"}"#
+        );
+        let nested = vec!["user[name]=bob".to_string(), "tags[]=a".to_string()];
+        assert_eq!(
+            merge_data(&nested, Some(&ContentType::Json))
+                .unwrap()
+                .unwrap(),
+            r#"{"user":{"name":"bob"},"tags":["a"]}"#
+        );
+        let typed = vec!["n:=5".to_string(), "tags:=[1,true]".to_string()];
+        assert_eq!(
+            merge_data(&typed, None).unwrap().unwrap(),
+            r#"{"n":5,"tags":[1,true]}"#
+        );
+        assert!(merge_data(&typed, Some(&ContentType::Form)).is_err());
+        let raw = vec![r#"{"a":1}"#.to_string()];
+        assert_eq!(
+            merge_data(&raw, Some(&ContentType::Json)).unwrap().unwrap(),
+            r#"{"a":1}"#
+        );
+        let mixed = vec!["a=1".to_string(), "raw text".to_string()];
+        assert!(merge_data(&mixed, None).is_err());
+        assert_eq!(merge_data(&[], None).unwrap(), None);
+    }
+    #[test]
+    fn dns_overrides_are_parsed() {
+        let resolve: super::Resolve = "example.com:443:127.0.0.1".parse().unwrap();
+        assert_eq!(resolve.host, "example.com");
+        assert_eq!(resolve.addr, "127.0.0.1:443".parse().unwrap());
+        let resolve: super::Resolve = "example.com:80:[::1]".parse().unwrap();
+        assert_eq!(resolve.addr, "[::1]:80".parse().unwrap());
+        assert!("example.com:443".parse::<super::Resolve>().is_err());
+        assert!("example.com:x:127.0.0.1".parse::<super::Resolve>().is_err());
+    }
 }
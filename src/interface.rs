@@ -0,0 +1,68 @@
+//! Addresses of the network interfaces, for `--interface`.
+#[cfg(unix)]
+use std::{
+    ffi::CStr,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+use std::{io, net::IpAddr};
+
+/// All addresses of the network interface called `name`, IPv4 first
+#[cfg(unix)]
+pub fn addresses(name: &str) -> io::Result<Vec<IpAddr>> {
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: `getifaddrs` fills `list` with a linked list that is valid until `freeifaddrs`
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut addresses = Vec::new();
+    let mut current = list;
+    while !current.is_null() {
+        // SAFETY: the nodes and their names are valid until `freeifaddrs`, and the address is
+        // a `sockaddr_in` or `sockaddr_in6` according to its family
+        unsafe {
+            let ifaddr = &*current;
+            current = ifaddr.ifa_next;
+            if ifaddr.ifa_addr.is_null()
+                || CStr::from_ptr(ifaddr.ifa_name).to_bytes() != name.as_bytes()
+            {
+                continue;
+            }
+            match (*ifaddr.ifa_addr).sa_family as i32 {
+                libc::AF_INET => {
+                    let addr = &*(ifaddr.ifa_addr as *const libc::sockaddr_in);
+                    addresses.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                        addr.sin_addr.s_addr,
+                    ))));
+                }
+                libc::AF_INET6 => {
+                    let addr = &*(ifaddr.ifa_addr as *const libc::sockaddr_in6);
+                    addresses.push(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)));
+                }
+                _ => {}
+            }
+        }
+    }
+    // SAFETY: `list` was allocated by `getifaddrs` and is not used anymore
+    unsafe { libc::freeifaddrs(list) };
+    addresses.sort_by_key(|addr| addr.is_ipv6());
+    Ok(addresses)
+}
+
+#[cfg(not(unix))]
+pub fn addresses(_name: &str) -> io::Result<Vec<IpAddr>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "network interfaces can't be listed on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn loopback_has_an_address() {
+        let addresses = super::addresses("lo").unwrap();
+        assert!(addresses.iter().any(|addr| addr.is_loopback()));
+        assert!(super::addresses("no-such-interface").unwrap().is_empty());
+    }
+}
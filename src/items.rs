@@ -0,0 +1,141 @@
+//! Nested paths in the `key=value` items of `-d`, building JSON bodies the way httpie does:
+//! `user[name]=bob` sets a member of an object, `tags[]=a` appends to an array and `list[1]=b`
+//! sets an element of an array, padding it with nulls.
+//!
+//! The values of `key:=json` items are parsed as JSON, or read from a JSON file with
+//! `key:=@path`.
+use std::fs;
+
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Append,
+}
+
+/// Split `a[b][0][]` into its segments. The first segment is always a key.
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let invalid = || format!("Invalid path `{}`", path);
+    let (first, mut rest) = match path.find('[') {
+        Some(start) => (&path[..start], &path[start..]),
+        None => (path, ""),
+    };
+    if first.is_empty() {
+        return Err(invalid());
+    }
+    let mut segments = vec![Segment::Key(first.to_string())];
+    while !rest.is_empty() {
+        let end = rest.find(']').filter(|_| rest.starts_with('['));
+        let end = end.ok_or_else(invalid)?;
+        let inside = &rest[1..end];
+        segments.push(match inside.parse() {
+            _ if inside.is_empty() => Segment::Append,
+            Ok(index) => Segment::Index(index),
+            Err(_) => Segment::Key(inside.to_string()),
+        });
+        rest = &rest[end + 1..];
+    }
+    Ok(segments)
+}
+
+/// The value of a `key:=json` item: JSON, or `@path` of a file containing JSON
+pub fn parse_raw_json(value: &str) -> Result<Value, String> {
+    let (json, source) = match value.strip_prefix('@') {
+        Some(path) => (
+            fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?,
+            path,
+        ),
+        None => (value.to_string(), value),
+    };
+    serde_json::from_str(&json).map_err(|e| format!("Invalid JSON in `{}`: {}", source, e))
+}
+
+/// Set the value at the path inside `target`, creating the objects and arrays on the way
+pub fn insert(target: &mut Value, path: &str, value: Value) -> Result<(), String> {
+    let segments = parse_path(path)?;
+    let mut current = target;
+    for segment in &segments {
+        let conflict = || format!("`{}` conflicts with another item", path);
+        current = match segment {
+            Segment::Key(key) => {
+                if current.is_null() {
+                    *current = Value::Object(Map::new());
+                }
+                let object = current.as_object_mut().ok_or_else(conflict)?;
+                object.entry(key.clone()).or_insert(Value::Null)
+            }
+            Segment::Index(index) => {
+                if current.is_null() {
+                    *current = Value::Array(Vec::new());
+                }
+                let array = current.as_array_mut().ok_or_else(conflict)?;
+                if array.len() <= *index {
+                    array.resize(index + 1, Value::Null);
+                }
+                &mut array[*index]
+            }
+            Segment::Append => {
+                if current.is_null() {
+                    *current = Value::Array(Vec::new());
+                }
+                let array = current.as_array_mut().ok_or_else(conflict)?;
+                array.push(Value::Null);
+                array.last_mut().expect("an element was just pushed")
+            }
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::Segment;
+
+    #[test]
+    fn paths_are_split_into_segments() {
+        assert_eq!(
+            super::parse_path("a[b][0][]").unwrap(),
+            vec![
+                Segment::Key("a".to_string()),
+                Segment::Key("b".to_string()),
+                Segment::Index(0),
+                Segment::Append
+            ]
+        );
+        assert!(super::parse_path("[a]").is_err());
+        assert!(super::parse_path("a[b").is_err());
+        assert!(super::parse_path("a[b]c").is_err());
+    }
+    #[test]
+    fn nested_values_are_inserted() {
+        let mut body = Value::Null;
+        for (path, value) in [
+            ("user[name]", "bob"),
+            ("tags[]", "a"),
+            ("tags[]", "b"),
+            ("list[2]", "c"),
+        ] {
+            super::insert(&mut body, path, value.into()).unwrap();
+        }
+        assert_eq!(
+            body,
+            json!({"user": {"name": "bob"}, "tags": ["a", "b"], "list": [null, null, "c"]})
+        );
+        assert!(super::insert(&mut body, "user[0]", "x".into()).is_err());
+    }
+    #[test]
+    fn raw_json_is_parsed_or_read_from_a_file() {
+        assert_eq!(super::parse_raw_json("[1, 2]").unwrap(), json!([1, 2]));
+        assert!(super::parse_raw_json("bob").is_err());
+        let path = std::env::temp_dir().join(format!("rq-item-{}.json", std::process::id()));
+        std::fs::write(&path, "{\"a\": null}").unwrap();
+        let value = super::parse_raw_json(&format!("@{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(value.unwrap(), json!({"a": null}));
+    }
+}
@@ -0,0 +1,130 @@
+//! Singular JSONPath expressions (https://www.rfc-editor.org/rfc/rfc9535), which select at most one
+//! value: `$.name`, `$['name']` and `$[index]` segments only.
+use std::str::FromStr;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid JSONPath at position {position}: {message}")]
+pub struct Error {
+    position: usize,
+    message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Name(String),
+    Index(i64),
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for JsonPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |position: usize, message: &str| Error {
+            position,
+            message: message.to_string(),
+        };
+        let chars: Vec<char> = s.chars().collect();
+        if chars.first() != Some(&'$') {
+            return Err(error(0, "must start with `$`"));
+        }
+        let mut segments = Vec::new();
+        let mut i = 1;
+        while i < chars.len() {
+            match chars[i] {
+                '.' => {
+                    let start = i + 1;
+                    i = start;
+                    while i < chars.len()
+                        && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-')
+                    {
+                        i += 1;
+                    }
+                    if i == start {
+                        return Err(error(start, "expected a member name"));
+                    }
+                    segments.push(Segment::Name(chars[start..i].iter().collect()));
+                }
+                '[' => {
+                    let start = i + 1;
+                    let end = chars[start..]
+                        .iter()
+                        .position(|c| *c == ']')
+                        .map(|len| start + len)
+                        .ok_or_else(|| error(i, "missing `]`"))?;
+                    let inner: String = chars[start..end].iter().collect();
+                    let inner = inner.trim();
+                    let quoted = ['\'', '"'].iter().find_map(|q| {
+                        inner
+                            .strip_prefix(*q)
+                            .and_then(|rest| rest.strip_suffix(*q))
+                    });
+                    segments.push(match quoted {
+                        Some(name) => Segment::Name(name.to_string()),
+                        None => Segment::Index(
+                            inner
+                                .parse()
+                                .map_err(|_| error(start, "expected a quoted name or an index"))?,
+                        ),
+                    });
+                    i = end + 1;
+                }
+                _ => return Err(error(i, "expected `.` or `[`")),
+            }
+        }
+        Ok(JsonPath { segments })
+    }
+}
+
+impl JsonPath {
+    /// The value at this path, if there is one
+    pub fn get<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |value, segment| match (segment, value) {
+                (Segment::Name(name), Value::Object(map)) => map.get(name),
+                (Segment::Index(index), Value::Array(items)) => {
+                    let index = if *index < 0 {
+                        items.len().checked_sub(index.unsigned_abs() as usize)?
+                    } else {
+                        *index as usize
+                    };
+                    items.get(index)
+                }
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::JsonPath;
+
+    #[test]
+    fn singular_paths_are_resolved() {
+        let value = json!({"data": {"tokens": [{"access_token": "t1"}, {"access_token": "t2"}]}});
+        let get = |path: &str| path.parse::<JsonPath>().unwrap().get(&value).cloned();
+        assert_eq!(get("$.data.tokens[0].access_token"), Some(json!("t1")));
+        assert_eq!(
+            get("$['data'][\"tokens\"][-1].access_token"),
+            Some(json!("t2"))
+        );
+        assert_eq!(get("$.data.missing"), None);
+        assert_eq!(get("$.data.tokens.access_token"), None);
+        assert_eq!(get("$"), Some(value.clone()));
+    }
+    #[test]
+    fn paths_selecting_several_values_are_rejected() {
+        for path in ["data", "$.data[*]", "$..token", "$.a[0:2]", "$."] {
+            assert!(path.parse::<JsonPath>().is_err(), "{}", path);
+        }
+    }
+}
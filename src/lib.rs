@@ -0,0 +1,691 @@
+//! Making HTTP requests the way the `rq` command does, for embedding in other tools.
+//!
+//! A [`Request`] is built with [`Request::new`] and its builder methods, sent with [`run`] and
+//! the [`Response`] body formatted for display with [`output::format_body`].
+//! [`main`] runs a whole `rq` command line.
+use reqwest::Client;
+use std::time::Duration;
+
+pub mod alias;
+pub mod assert;
+pub mod audit;
+pub mod batch;
+pub mod bench;
+pub mod cache;
+pub mod cert;
+pub mod chain;
+pub mod checksum;
+pub mod cli;
+pub mod collection;
+pub mod compression;
+pub mod conditional;
+pub mod config;
+pub mod connect;
+pub mod cookie;
+pub mod cors;
+pub mod curl;
+pub mod datetime;
+pub mod diff;
+pub mod dns;
+pub mod dotenv;
+pub mod editor;
+pub mod environment;
+pub mod expect;
+pub mod fetch;
+pub mod filter;
+pub mod glob;
+pub mod graphql;
+pub mod har;
+pub mod har_replay;
+pub mod history;
+pub mod http;
+pub mod interface;
+pub mod items;
+pub mod jsonpath;
+pub mod jwt;
+pub mod links;
+pub mod local;
+pub mod logging;
+pub mod man;
+pub mod markup;
+pub mod media_type;
+pub mod middleware;
+pub mod msgpack;
+pub mod multipart;
+pub mod negotiate;
+pub mod openapi;
+pub mod output;
+pub mod paths;
+pub mod pipe;
+pub mod poll;
+pub mod proxy;
+pub mod proxy_env;
+pub mod range;
+pub mod rate;
+pub mod raw;
+pub mod redirect;
+pub mod report;
+pub mod request;
+pub mod response;
+pub mod scraper;
+pub mod script;
+pub mod secret;
+pub mod server;
+pub mod session;
+pub mod size;
+pub mod sse;
+pub mod stats;
+pub mod template;
+pub mod testsuite;
+pub mod tls;
+pub mod trace;
+pub mod tui;
+pub mod unix;
+pub mod upload;
+pub mod uri_template;
+pub mod watch;
+pub mod wire;
+pub mod ws;
+pub mod xml;
+pub mod yaml;
+pub mod zstd;
+
+use cli::{normalize_url, AliasAction, CliArgs, Command, EnvAction, SecretAction, SessionAction};
+pub use http::{guess_content_type, AuthType, ContentType, HttpMethod};
+pub use request::Request;
+pub use response::Response;
+use upload::Upload;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("ReqwestError: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+    #[error("IoError: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("{0}")]
+    AliasError(#[from] alias::Error),
+    #[error("{0}")]
+    CollectionError(#[from] collection::Error),
+    #[error("{0}")]
+    HistoryError(#[from] history::Error),
+    #[error("{0}")]
+    CurlError(#[from] curl::Error),
+    #[error("{0}")]
+    HarError(#[from] har::Error),
+    #[error("{0}")]
+    OpenApiError(#[from] openapi::Error),
+    #[error("{0}")]
+    WsError(#[from] ws::Error),
+    #[error("{0}")]
+    RawError(#[from] raw::Error),
+    #[error("{0}")]
+    TestSuiteError(#[from] testsuite::Error),
+    #[error("{0}")]
+    TraceError(#[from] trace::Error),
+    #[error("{0}")]
+    ChainError(#[from] chain::Error),
+    #[error("{0}")]
+    BatchError(#[from] batch::Error),
+    #[error("{0}")]
+    JwtError(#[from] jwt::Error),
+    #[error("{0}")]
+    LinksError(#[from] links::Error),
+    #[error("{0}")]
+    LocalError(#[from] local::Error),
+    #[error("{0}")]
+    LoggingError(#[from] logging::Error),
+    #[error("{0}")]
+    NegotiateError(#[from] negotiate::Error),
+    #[error("{0}")]
+    SecretError(#[from] secret::Error),
+    #[error("{0}")]
+    ServerError(#[from] server::Error),
+    #[error("{0}")]
+    SessionError(#[from] session::Error),
+    #[error("{0}")]
+    UnixSocketError(#[from] unix::Error),
+    #[error("{0}")]
+    ExpectContinueError(#[from] expect::Error),
+    #[error("{0}")]
+    DnsError(#[from] dns::Error),
+    #[error("{0}")]
+    MiddlewareError(#[from] middleware::Error),
+    #[error("{0}")]
+    EnvironmentError(#[from] environment::Error),
+    #[error("{0}")]
+    DotenvError(#[from] dotenv::Error),
+    #[error("{0}")]
+    EditorError(#[from] editor::Error),
+    #[error("{0}")]
+    PipeError(#[from] pipe::Error),
+    #[error("{0}")]
+    XmlError(#[from] xml::Error),
+    #[error("{0}")]
+    ZstdError(#[from] zstd::Error),
+    #[error("{0} request(s) failed")]
+    Failed(usize),
+    #[error("The response is larger than the {0} allowed by --max-response-size")]
+    SizeLimitExceeded(size::ByteSize),
+    #[error("{0} response(s) were larger than --max-response-size")]
+    ResponseTooLarge(usize),
+    #[error("{0} certificate(s) expire within --cert-expiry-warn")]
+    CertificateExpiring(usize),
+    #[error("Stopped after {0} redirects")]
+    TooManyRedirects(usize),
+    #[error("{0} response(s) did not match the checksum")]
+    ChecksumMismatch(usize),
+    #[error("{0} response(s) had an error status")]
+    HttpStatus(usize),
+    #[error("{0} response(s) failed the assertions")]
+    AssertionFailed(usize),
+    #[error("{0} test case(s) failed")]
+    TestsFailed(usize),
+    #[error("{0} security header check(s) failed")]
+    AuditFailed(usize),
+    #[error("{0} CORS check(s) would block the request")]
+    CorsBlocked(usize),
+    #[error("The condition was not met within {0}")]
+    PollTimeout(watch::Interval),
+    #[error("{0} request(s) did not meet their condition in time")]
+    ConditionNotMet(usize),
+    #[error("The --pipe command exited with {0}")]
+    PipeExit(i32),
+}
+
+impl Error {
+    /// The exit status of the process failing with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ChecksumMismatch(_) => 3,
+            Error::ResponseTooLarge(_) => 4,
+            Error::CertificateExpiring(_) => 5,
+            Error::AssertionFailed(_) | Error::TestsFailed(_) => 6,
+            Error::MiddlewareError(e) if e.is_rejection() => 6,
+            Error::PollTimeout(_) | Error::ConditionNotMet(_) => 7,
+            // As curl does with `--fail`
+            Error::HttpStatus(_) => 22,
+            Error::PipeExit(code) => *code,
+            _ => 1,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The `Accept-Encoding` header sent by the client, matching the decompressions it enables
+pub const ACCEPT_ENCODING_VALUE: &str = "gzip, br, zstd";
+
+/// The `User-Agent` header sent unless another one is given
+pub const USER_AGENT_VALUE: &str = concat!("rq/", env!("CARGO_PKG_VERSION"));
+
+/// How long to wait for a response, or only to connect for event streams, throttled transfers and
+/// streamed uploads
+pub const TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// Whether the requests are sent with `Accept-Encoding`, which reqwest adds when decompression is
+/// enabled but the connections made with hyper directly don't
+pub fn accepts_encoding(args: &CliArgs) -> bool {
+    !args.no_decompress && args.unix_socket.is_none() && args.trace.is_none()
+}
+
+/// Create the client shared by all requests, configured from the command line
+pub fn create_client(args: &CliArgs) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT_VALUE)
+        .gzip(!args.no_decompress)
+        .brotli(!args.no_decompress);
+    // Event streams are expected to stay open indefinitely, throttled transfers and streamed
+    // uploads to be slow, so only connecting is bounded for them
+    let streamed = args.data_binary.as_ref().is_some_and(Upload::is_streamed);
+    if args.sse || args.limit_rate.is_some() || streamed {
+        builder = builder.connect_timeout(TIMEOUT);
+    } else {
+        builder = builder.timeout(TIMEOUT);
+    }
+    // Redirects are followed by `Request::send`, event streams are not sent with it
+    if !args.sse {
+        builder = builder.redirect(reqwest::redirect::Policy::none());
+    }
+    if let Some(addr) = connect::local_address(args) {
+        builder = builder.local_address(addr);
+    }
+    if let Some(secs) = args.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(Duration::from_secs_f64(secs));
+    }
+    if let Some(max) = args.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(secs) = args.tcp_keepalive {
+        builder = builder.tcp_keepalive(Duration::from_secs_f64(secs));
+    }
+    if let Some(enabled) = args.tcp_nodelay {
+        builder = builder.tcp_nodelay(enabled);
+    }
+    if let Some(version) = args.tls_min {
+        builder = builder.min_tls_version(version.to_reqwest());
+    }
+    if let Some(version) = args.tls_max {
+        builder = builder.max_tls_version(version.to_reqwest());
+    }
+    if let Some(pins) = &args.pinned_pubkey {
+        let config = tls::pinned_config(pins, args.tls_min, args.tls_max)?;
+        builder = builder.use_preconfigured_tls(config);
+    }
+    for resolve in &args.resolve {
+        builder = builder.resolve(&resolve.host, resolve.addr);
+    }
+    // reqwest reads the proxy variables but not `no_proxy`
+    let proxies = proxy_env::Proxies::from_env();
+    if !proxies.is_empty() {
+        builder = builder
+            .no_proxy()
+            .proxy(proxies.to_reqwest(args.proxy_user.as_ref()));
+    }
+    builder.build().map_err(Error::ReqwestError)
+}
+
+/// Send the request with the client, following redirects, and read the whole response
+pub async fn run(client: &Client, request: &Request) -> Result<Response> {
+    request.send(client).await
+}
+
+/// Run the command line `args`, as the `rq` command does
+pub async fn main(mut args: CliArgs) -> Result<()> {
+    let log_level = args
+        .log_level
+        .or_else(|| args.log_file.as_ref().map(|_| logging::Level::Debug));
+    if let Some(level) = log_level {
+        logging::init(level, args.log_file.as_deref())?;
+    }
+    if args.graphql {
+        args.data = Some(graphql::request_body(args.data.as_deref(), &args.gql_vars)?);
+    }
+    let dotenv = dotenv::load(&args)?;
+    let client = create_client(&args)?;
+    let mut requests = match &args.command {
+        Some(Command::Run {
+            name,
+            vars,
+            collection,
+        }) => {
+            let (template, path) = collection::find(name, collection.as_deref())?;
+            let mut variables = dotenv.clone();
+            variables.extend(environment::with_vars(
+                args.env.as_deref(),
+                Some(&path),
+                template::to_map(vars),
+            )?);
+            vec![template.render(&variables)?]
+        }
+        Some(Command::FromCurl { command, print }) => {
+            let request = curl::from_curl(command)?;
+            if *print {
+                println!("{}", curl::to_rq_command(&request));
+                return Ok(());
+            }
+            vec![request]
+        }
+        Some(Command::ReplayHar {
+            file,
+            filter,
+            concurrency,
+        }) => {
+            return har_replay::run(
+                &client,
+                &args.pipeline,
+                file,
+                filter.as_deref(),
+                *concurrency,
+            )
+            .await;
+        }
+        Some(Command::Batch { file, concurrency }) => {
+            return batch::run(
+                &client,
+                &args,
+                file,
+                *concurrency,
+                args.output_template.as_ref(),
+            )
+            .await;
+        }
+        Some(Command::Chain { file, vars }) => {
+            let chain = chain::Chain::load(file)?;
+            let mut variables = dotenv.clone();
+            variables.extend(environment::with_vars(
+                args.env.as_deref(),
+                Some(file),
+                template::to_map(vars),
+            )?);
+            return Ok(chain
+                .run(&client, &args.pipeline, &variables, args.raw)
+                .await?);
+        }
+        Some(Command::Test { file, vars }) => {
+            let suite = testsuite::Suite::load(file)?;
+            let mut variables = dotenv.clone();
+            variables.extend(environment::with_vars(
+                args.env.as_deref(),
+                Some(file),
+                template::to_map(vars),
+            )?);
+            let summary = suite.run(&client, &args.pipeline, &variables).await;
+            if summary.failed > 0 {
+                return Err(Error::TestsFailed(summary.failed));
+            }
+            return Ok(());
+        }
+        Some(Command::Diff { old, new }) => {
+            let mut urls = [old.clone(), new.clone()];
+            for url in &mut urls {
+                normalize_url(url);
+            }
+            let [old, new] = urls.map(|url| Request::from_args(&args, &url));
+            let (old, new) = futures_util::try_join!(old.send(&client), new.send(&client))?;
+            print!("{}", diff::responses(&old, &new, fetch::use_color()));
+            return Ok(());
+        }
+        Some(Command::Links {
+            url,
+            depth,
+            same_host,
+            concurrency,
+        }) => {
+            let mut url = url.clone();
+            normalize_url(&mut url);
+            let options = links::Options {
+                depth: *depth,
+                same_host: *same_host,
+                concurrency: *concurrency,
+            };
+            return links::run(&client, &url, options).await;
+        }
+        Some(Command::Audit { url }) => {
+            let mut url = url.clone();
+            normalize_url(&mut url);
+            let response = Request::from_args(&args, &url).send(&client).await?;
+            let failed = audit::report(&response);
+            if failed > 0 {
+                return Err(Error::AuditFailed(failed));
+            }
+            return Ok(());
+        }
+        Some(Command::Cors {
+            url,
+            origin,
+            request_method,
+            request_headers,
+            credentials,
+        }) => {
+            let mut url = url.clone();
+            normalize_url(&mut url);
+            let preflight = cors::Preflight {
+                origin: origin.clone(),
+                method: request_method.clone(),
+                headers: request_headers
+                    .iter()
+                    .map(|h| h.trim().to_lowercase())
+                    .filter(|h| !h.is_empty())
+                    .collect(),
+                credentials: *credentials,
+            };
+            let blocked = cors::run(&client, &url, &preflight).await?;
+            if blocked > 0 {
+                return Err(Error::CorsBlocked(blocked));
+            }
+            return Ok(());
+        }
+        Some(Command::Alias { action }) => {
+            match action {
+                AliasAction::Add { name, words } => alias::add(name, words)?,
+                AliasAction::List => alias::list()?,
+                AliasAction::Rm { name } => alias::remove(name)?,
+            }
+            return Ok(());
+        }
+        Some(Command::Env { action }) => {
+            match action {
+                EnvAction::List { collection } => environment::list(collection.as_deref())?,
+                EnvAction::Show { name, collection } => {
+                    environment::show(name, collection.as_deref())?
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Secret { action }) => {
+            match action {
+                SecretAction::Set { name } => secret::set(name, &secret::read_value(name)?)?,
+                SecretAction::Get { name } => println!("{}", secret::get(name)?),
+                SecretAction::Rm { name } => secret::remove(name)?,
+            }
+            return Ok(());
+        }
+        Some(Command::Session { action }) => {
+            match action {
+                SessionAction::List => session::list()?,
+                SessionAction::Show { name } => session::show(name)?,
+                SessionAction::Edit { name } => session::edit(name)?,
+                SessionAction::Rm { name } => session::remove(name)?,
+            }
+            return Ok(());
+        }
+        Some(Command::Raw { file, target, tls }) => {
+            return raw::run(file, target.as_deref(), *tls).await;
+        }
+        Some(Command::Serve {
+            port,
+            bind,
+            respond,
+        }) => {
+            let rules = server::Rule::from_pairs(respond)?;
+            return Ok(server::run((*bind, *port).into(), rules).await?);
+        }
+        Some(Command::Proxy {
+            port,
+            bind,
+            record,
+            replay,
+        }) => {
+            let addr = (*bind, *port).into();
+            return proxy::run(addr, record.clone(), replay.clone()).await;
+        }
+        Some(Command::Man) => {
+            print!("{}", man::render());
+            return Ok(());
+        }
+        Some(Command::History { limit }) => {
+            history::print(&history::entries()?, *limit);
+            return Ok(());
+        }
+        Some(Command::Replay {
+            id,
+            method,
+            content_type,
+            data,
+            url,
+        }) => {
+            let mut request = history::find(id)?.request;
+            request.apply_overrides(method, content_type, data, url);
+            vec![request]
+        }
+        Some(Command::Api {
+            spec,
+            operation_id,
+            params,
+            server,
+            data,
+        }) => {
+            let spec = openapi::Spec::load(spec)?;
+            vec![spec.build_request(
+                operation_id,
+                &template::to_map(params),
+                server.as_deref(),
+                data.clone(),
+                openapi::prompt,
+            )?]
+        }
+        Some(Command::Ws { url, messages }) => {
+            let mut url = url.clone();
+            normalize_url(&mut url);
+            let mut request = Request::from_args(&args, &url);
+            args.pipeline.prepare(&mut request).await?;
+            let headers = request.headers;
+            let options = connect::Options::from_args(&args);
+            return Ok(ws::run(&url, &headers, messages, &options).await?);
+        }
+        // `rq head`, `rq get` and the other verbs are turned into requests for the URLs by
+        // `cli::args()`
+        Some(
+            Command::Head { .. }
+            | Command::Get(_)
+            | Command::Post(_)
+            | Command::Put(_)
+            | Command::Patch(_)
+            | Command::Delete(_)
+            | Command::Options(_),
+        )
+        | None => fetch::collect_urls(&args)?
+            .iter()
+            .map(|url| Request::from_args(&args, url))
+            .collect(),
+    };
+    if args.edit {
+        for request in &mut requests {
+            editor::edit_body(request)?;
+        }
+    }
+    if let Some(name) = &args.session {
+        let session = session::Session::load(name)?;
+        for request in &mut requests {
+            session.apply(request);
+        }
+    }
+    for request in &mut requests {
+        dotenv::apply(request, &dotenv);
+        // The requests read from files are sent through the middlewares of the command line too
+        request.pipeline = args.pipeline.clone();
+    }
+    if args.method_override {
+        for request in &mut requests {
+            request.override_method();
+        }
+    }
+    let proxies = proxy_env::Proxies::from_env();
+    if args.verbose && !proxies.is_empty() {
+        for request in &requests {
+            if let Ok(url) = reqwest::Url::parse(&request.url) {
+                output::note(format!(
+                    "{} is sent {}",
+                    url.host_str().unwrap_or_default(),
+                    proxies.decide(&url)
+                ));
+            }
+        }
+    }
+    // The hosts are resolved up front and the client rebuilt with the addresses as overrides
+    let client = match dns::Resolver::from_args(&args) {
+        Some(resolver) if !args.print_curl && !args.offline => {
+            let ipv6 = match (args.ipv4, args.ipv6) {
+                (true, _) => Some(false),
+                (_, true) => Some(true),
+                _ => args.local_address.map(|addr| addr.is_ipv6()),
+            };
+            let resolved = resolver
+                .resolve_hosts(&client, &requests, &args.resolve, ipv6)
+                .await?;
+            args.resolve.extend(resolved);
+            for request in &mut requests {
+                request.connect = Some(connect::Options::from_args(&args));
+            }
+            create_client(&args)?
+        }
+        _ => client,
+    };
+    if args.print_curl || args.offline || args.sse {
+        for request in &mut requests {
+            args.pipeline.prepare(request).await?;
+        }
+    }
+    if args.print_curl {
+        for request in &requests {
+            println!("{}", curl::to_curl(request));
+        }
+        return Ok(());
+    }
+    if args.offline {
+        for (i, request) in requests.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let request = request.build(&client).build()?;
+            println!(
+                "{}",
+                wire::format_request(&request, accepts_encoding(&args))
+            );
+        }
+        return Ok(());
+    }
+    if args.tui {
+        return tui::run(&client, &args, requests).await;
+    }
+    if args.stdin_urls {
+        return match fetch::fetch_stdin(&client, &args).await? {
+            0 => Ok(()),
+            failed => Err(Error::Failed(failed)),
+        };
+    }
+    if args.sse {
+        let streams = requests
+            .iter()
+            .map(|request| sse::run(&client, request, args.ndjson));
+        futures_util::future::try_join_all(streams).await?;
+        return Ok(());
+    }
+    if args.bench {
+        if !requests.is_empty() {
+            let report = bench::run(&client, &args, &requests).await;
+            if let Some(path) = &args.bench_output {
+                let format = bench::SampleFormat::from_path(path);
+                std::fs::write(path, bench::samples(&report, format))?;
+            }
+            match (&args.metrics_format, &args.metrics_file) {
+                (_, Some(path)) => {
+                    std::fs::write(path, bench::openmetrics(&report))?;
+                    bench::print_report(&report);
+                }
+                (Some(_), None) => print!("{}", bench::openmetrics(&report)),
+                (None, None) => bench::print_report(&report),
+            }
+        }
+        return Ok(());
+    }
+    if let Some(interval) = args.watch {
+        return watch::run(&client, &args, requests, interval).await;
+    }
+    let failures = fetch::fetch_all(&client, &args, requests).await?;
+    if failures.expiring > 0 {
+        return Err(Error::CertificateExpiring(failures.expiring));
+    }
+    if failures.too_large > 0 {
+        return Err(Error::ResponseTooLarge(failures.too_large));
+    }
+    if failures.timed_out > 0 {
+        return Err(Error::ConditionNotMet(failures.timed_out));
+    }
+    if failures.assertions > 0 {
+        return Err(Error::AssertionFailed(failures.assertions));
+    }
+    if failures.http_errors > 0 {
+        return Err(Error::HttpStatus(failures.http_errors));
+    }
+    if failures.checksums > 0 {
+        return Err(Error::ChecksumMismatch(failures.checksums));
+    }
+    if failures.pipe_status != 0 {
+        return Err(Error::PipeExit(failures.pipe_status));
+    }
+    if failures.requests > 0 {
+        return Err(Error::Failed(failures.requests));
+    }
+    Ok(())
+}
@@ -0,0 +1,209 @@
+//! `rq links`: check the links of a page and report the broken ones, following the links to the
+//! pages of the same host up to a depth.
+use std::collections::{HashMap, HashSet};
+
+use futures_util::stream::{self, StreamExt};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode, Url};
+
+use crate::{
+    http::HttpMethod,
+    scraper::{self, Selector},
+    Request, Result,
+};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid URL `{0}`: {1}")]
+pub struct Error(String, String);
+
+/// The elements linking to other resources, and the attribute holding the URL
+const LINKING: &[(&str, &str)] = &[
+    ("a[href]", "href"),
+    ("area[href]", "href"),
+    ("link[href]", "href"),
+    ("img[src]", "src"),
+    ("script[src]", "src"),
+    ("iframe[src]", "src"),
+    ("source[src]", "src"),
+];
+
+fn select(html: &str, selector: &str, attr: &str) -> Vec<String> {
+    let selector: Selector = selector.parse().expect("valid selector");
+    scraper::select(html, &selector, Some(attr))
+}
+
+/// The HTTP(S) URLs linked from the page at `base`, without their fragment, each once
+pub fn extract(html: &str, base: &Url) -> Vec<Url> {
+    // `<base href>` changes the URL the relative links are resolved against
+    let base = select(html, "base[href]", "href")
+        .first()
+        .and_then(|href| base.join(href.trim()).ok())
+        .unwrap_or_else(|| base.clone());
+    let mut seen = HashSet::new();
+    let mut urls = Vec::new();
+    for (selector, attr) in LINKING {
+        for href in select(html, selector, attr) {
+            let mut url = match base.join(href.trim()) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+                _ => continue,
+            };
+            url.set_fragment(None);
+            if seen.insert(url.clone()) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// What `rq links` checks
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// 1 checks the links of the page, 2 also the links of the pages it links to, and so on
+    pub depth: usize,
+    /// Only check the links to the host of the page
+    pub same_host: bool,
+    pub concurrency: usize,
+}
+
+/// How a link was found to be
+enum Checked {
+    /// Working, with the HTML body if the page is to be crawled
+    Ok(Option<String>),
+    Broken(String),
+}
+
+/// Check the link with HEAD, or GET if the body is needed or HEAD is not supported
+async fn check(client: &Client, url: &Url, crawl: bool) -> Checked {
+    let mut method = if crawl {
+        HttpMethod::Get
+    } else {
+        HttpMethod::Head
+    };
+    loop {
+        let response = match Request::new(method.clone(), url.as_str())
+            .send(client)
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => return Checked::Broken(format!("error: {}", e)),
+        };
+        let status = response.status;
+        let head_unsupported = matches!(
+            status,
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+        );
+        if head_unsupported && matches!(method, HttpMethod::Head) {
+            method = HttpMethod::Get;
+            continue;
+        }
+        if status.is_client_error() || status.is_server_error() {
+            return Checked::Broken(status.to_string());
+        }
+        let html = response
+            .header(CONTENT_TYPE.as_str())
+            .is_some_and(|t| t.contains("html"));
+        return Checked::Ok(
+            (crawl && html).then(|| String::from_utf8_lossy(&response.body).into()),
+        );
+    }
+}
+
+/// Check the links of the page at `url` and print the broken ones with the page they were found
+/// on. Return an error if some links are broken.
+pub async fn run(client: &Client, url: &str, options: Options) -> Result<()> {
+    let start = Url::parse(url).map_err(|e| Error(url.to_string(), e.to_string()))?;
+    let same_host = |link: &Url| link.host_str() == start.host_str();
+    // Where each link was first found
+    let mut found_on: HashMap<Url, Url> = HashMap::new();
+    let mut pages = match check(client, &start, true).await {
+        Checked::Ok(html) => vec![(start.clone(), html.unwrap_or_default())],
+        Checked::Broken(reason) => {
+            println!("!! {}  {}", reason, start);
+            return Err(crate::Error::Failed(1));
+        }
+    };
+    found_on.insert(start.clone(), start.clone());
+    let (mut checked, mut broken) = (1, 0);
+    for level in 1..=options.depth.max(1) {
+        let mut links = Vec::new();
+        for (page, html) in &pages {
+            for link in extract(html, page) {
+                if (options.same_host && !same_host(&link)) || found_on.contains_key(&link) {
+                    continue;
+                }
+                found_on.insert(link.clone(), page.clone());
+                links.push(link);
+            }
+        }
+        let crawl = level < options.depth;
+        let mut results = stream::iter(links)
+            .map(|link| async move {
+                let checked = check(client, &link, crawl && same_host(&link)).await;
+                (link, checked)
+            })
+            .buffer_unordered(options.concurrency.max(1));
+        let mut next_pages = Vec::new();
+        while let Some((link, result)) = results.next().await {
+            checked += 1;
+            match result {
+                Checked::Ok(Some(html)) => next_pages.push((link, html)),
+                Checked::Ok(None) => {}
+                Checked::Broken(reason) => {
+                    broken += 1;
+                    println!("!! {}  {}  (on {})", reason, link, found_on[&link]);
+                }
+            }
+        }
+        pages = next_pages;
+        if pages.is_empty() {
+            break;
+        }
+    }
+    println!("{} checked, {} broken", checked, broken);
+    if broken > 0 {
+        return Err(crate::Error::Failed(broken));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    #[test]
+    fn links_are_resolved_and_deduplicated() {
+        let html = r##"
+            <a href="/about">About</a>
+            <a href="/about#team">Team</a>
+            <a href="contact.html">Contact</a>
+            <a href="mailto:a@example.com">Mail</a>
+            <a href="#top">Top</a>
+            <img src="https://cdn.example.com/logo.png">
+            <link rel="stylesheet" href="style.css">
+        "##;
+        let base = Url::parse("https://example.com/docs/index.html").unwrap();
+        let links: Vec<_> = super::extract(html, &base)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            links,
+            [
+                "https://example.com/about",
+                "https://example.com/docs/contact.html",
+                "https://example.com/docs/index.html",
+                "https://example.com/docs/style.css",
+                "https://cdn.example.com/logo.png",
+            ]
+        );
+    }
+    #[test]
+    fn base_element_changes_the_base() {
+        let html = r#"<base href="https://other.example/v2/"><a href="page">P</a>"#;
+        let base = Url::parse("https://example.com/").unwrap();
+        assert_eq!(
+            super::extract(html, &base),
+            [Url::parse("https://other.example/v2/page").unwrap()]
+        );
+    }
+}
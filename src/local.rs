@@ -0,0 +1,137 @@
+//! `file:` and `data:` URLs (https://www.rfc-editor.org/rfc/rfc2397), answered without a network
+//! so that formatters, filters and checksums can be tried on local content. They are read as a
+//! 200 response to GET or HEAD.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use percent_encoding::percent_decode_str;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    StatusCode, Url, Version,
+};
+
+use crate::{http::HttpMethod, media_type, response::Response};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid file URL `{0}`")]
+    InvalidFileUrl(String),
+    #[error("Can't read {}: {source}", .path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Invalid data URL `{0}`: {1}")]
+    InvalidDataUrl(String, String),
+    #[error("Only GET and HEAD can be sent to {0} URLs")]
+    Method(&'static str),
+}
+
+/// Whether the URL is answered locally instead of being sent
+pub fn is_local(url: &str) -> bool {
+    let scheme = url.split_once(':').map(|(scheme, _)| scheme);
+    scheme.is_some_and(|s| s.eq_ignore_ascii_case("file") || s.eq_ignore_ascii_case("data"))
+}
+
+/// The media type and the content of a `data:` URL
+fn parse_data(url: &str) -> Result<(String, Vec<u8>), Error> {
+    let invalid = |message: &str| Error::InvalidDataUrl(url.to_string(), message.to_string());
+    let rest = &url["data:".len()..];
+    // The fragment is not part of the data
+    let rest = rest.split('#').next().unwrap_or(rest);
+    let (meta, data) = rest.split_once(',').ok_or_else(|| invalid("no comma"))?;
+    let (media_type, base64) = match meta.strip_suffix(";base64") {
+        Some(media_type) => (media_type, true),
+        None => (meta, false),
+    };
+    let media_type = match media_type.trim() {
+        "" => "text/plain;charset=US-ASCII".to_string(),
+        // `;charset=utf-8` alone keeps the default type
+        t if t.starts_with(';') => format!("text/plain{}", t),
+        t => percent_decode_str(t).decode_utf8_lossy().into_owned(),
+    };
+    let data: Vec<u8> = percent_decode_str(data).collect();
+    let data = if base64 {
+        let encoded: Vec<u8> = data
+            .into_iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        base64::decode(encoded).map_err(|e| invalid(&e.to_string()))?
+    } else {
+        data
+    };
+    Ok((media_type, data))
+}
+
+fn read_file(url: &str) -> Result<(String, Vec<u8>), Error> {
+    let path = Url::parse(url)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| Error::InvalidFileUrl(url.to_string()))?;
+    let body = fs::read(&path).map_err(|source| Error::Read {
+        path: path.clone(),
+        source,
+    })?;
+    Ok((media_type::guess(Path::new(&path), &body), body))
+}
+
+/// Answer the request to a `file:` or `data:` URL
+pub fn send(method: &HttpMethod, url: &str) -> Result<Response, Error> {
+    let start = Instant::now();
+    let data = url[..5].eq_ignore_ascii_case("data:");
+    let scheme = if data { "data:" } else { "file:" };
+    if !matches!(method, HttpMethod::Get | HttpMethod::Head) {
+        return Err(Error::Method(scheme));
+    }
+    let (media_type, mut body) = if data {
+        parse_data(url)?
+    } else {
+        read_file(url)?
+    };
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&media_type) {
+        headers.insert(CONTENT_TYPE, value);
+    }
+    headers.insert(CONTENT_LENGTH, body.len().into());
+    if matches!(method, HttpMethod::Head) {
+        body.clear();
+    }
+    Ok(Response {
+        url: url.to_string(),
+        status: StatusCode::OK,
+        version: Version::default(),
+        headers,
+        body,
+        wait: start.elapsed(),
+        receive: Default::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn data_urls_are_decoded() {
+        assert_eq!(
+            super::parse_data("data:,hello%20world").unwrap(),
+            (
+                "text/plain;charset=US-ASCII".into(),
+                b"hello world".to_vec()
+            )
+        );
+        assert_eq!(
+            super::parse_data("data:application/json;base64,eyJhIjoxfQ==").unwrap(),
+            ("application/json".into(), br#"{"a":1}"#.to_vec())
+        );
+        assert_eq!(
+            super::parse_data("data:;charset=utf-8,%C3%A9").unwrap().0,
+            "text/plain;charset=utf-8"
+        );
+        assert!(super::parse_data("data:text/plain").is_err());
+    }
+    #[test]
+    fn only_file_and_data_urls_are_local() {
+        assert!(super::is_local("file:///etc/hosts"));
+        assert!(super::is_local("DATA:,x"));
+        assert!(!super::is_local("http://localhost/file:"));
+    }
+}
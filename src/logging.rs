@@ -0,0 +1,235 @@
+//! `--log-level` and `--log-file`: the events of rq and of the libraries it uses, like the
+//! connections opened and reused by hyper, the TLS handshakes of rustls, the redirects followed
+//! and the decisions of the cache, written one per line to stderr or a file.
+//!
+//! Both `tracing` events (rq, hyper) and `log` records (reqwest, rustls) are written.
+use std::{
+    fmt::{self, Write as _},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, Event, Metadata, Subscriber,
+};
+
+use crate::datetime;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown log level `{0}`, expected error, warn, info, debug or trace")]
+    UnknownLevel(String),
+    #[error("Can't open the log file {path}: {source}")]
+    Open { path: PathBuf, source: io::Error },
+    #[error("A logger is already installed")]
+    AlreadySet,
+}
+
+/// The most detailed events written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return Err(Error::UnknownLevel(s.to_string())),
+        })
+    }
+}
+
+impl Level {
+    fn tracing(self) -> LevelFilter {
+        match self {
+            Level::Error => LevelFilter::ERROR,
+            Level::Warn => LevelFilter::WARN,
+            Level::Info => LevelFilter::INFO,
+            Level::Debug => LevelFilter::DEBUG,
+            Level::Trace => LevelFilter::TRACE,
+        }
+    }
+
+    fn log(self) -> log::LevelFilter {
+        match self {
+            Level::Error => log::LevelFilter::Error,
+            Level::Warn => log::LevelFilter::Warn,
+            Level::Info => log::LevelFilter::Info,
+            Level::Debug => log::LevelFilter::Debug,
+            Level::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Where the lines go, shared by the `tracing` subscriber and the `log` logger
+type Sink = Arc<Mutex<Box<dyn Write + Send>>>;
+
+fn write_line(sink: &Sink, level: &dyn fmt::Display, target: &str, message: &str) {
+    let now = datetime::format_rfc3339_millis(datetime::unix_millis(SystemTime::now()));
+    let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+    // There is nowhere to report a failure to log
+    let _ = writeln!(sink, "{} {:<5} {}: {}", now, level, target, message);
+}
+
+/// The fields of an event as `message key=value ...`
+#[derive(Default)]
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        let _ = match field.name() {
+            "message" => write!(self.0, "{:?}", value),
+            name => write!(self.0, "{}={:?}", name, value),
+        };
+    }
+}
+
+struct Logger {
+    level: Level,
+    sink: Sink,
+    next_span: AtomicU64,
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level.tracing()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.level.tracing())
+    }
+
+    // Spans are not written, only their ids are needed
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        write_line(&self.sink, metadata.level(), metadata.target(), &fields.0);
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level.log()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if log::Log::enabled(self, record.metadata()) {
+            let message = record.args().to_string();
+            write_line(&self.sink, &record.level(), record.target(), &message);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.sink.lock().unwrap_or_else(|e| e.into_inner()).flush();
+    }
+}
+
+/// Write the events up to `level` to stderr, or appended to `file`
+pub fn init(level: Level, file: Option<&Path>) -> Result<(), Error> {
+    let writer: Box<dyn Write + Send> = match file {
+        Some(path) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|source| Error::Open {
+                    path: path.to_path_buf(),
+                    source,
+                })?,
+        ),
+        None => Box::new(io::stderr()),
+    };
+    let sink: Sink = Arc::new(Mutex::new(writer));
+    let logger = || Logger {
+        level,
+        sink: sink.clone(),
+        next_span: AtomicU64::new(1),
+    };
+    log::set_boxed_logger(Box::new(logger())).map_err(|_| Error::AlreadySet)?;
+    log::set_max_level(level.log());
+    tracing::subscriber::set_global_default(logger()).map_err(|_| Error::AlreadySet)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::Level;
+
+    #[test]
+    fn levels_are_case_insensitive() {
+        assert_eq!("DEBUG".parse::<Level>().unwrap(), Level::Debug);
+        assert_eq!("trace".parse::<Level>().unwrap(), Level::Trace);
+        assert!("verbose".parse::<Level>().is_err());
+    }
+    #[test]
+    fn fields_are_written_after_the_message() {
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let buffer = Buffer::default();
+        let logger = super::Logger {
+            level: Level::Debug,
+            sink: Arc::new(Mutex::new(Box::new(buffer.clone()))),
+            next_span: 1.into(),
+        };
+        tracing::subscriber::with_default(logger, || {
+            tracing::debug!(url = "http://a/", status = 200, "response received");
+            tracing::trace!("not written");
+        });
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written
+            .ends_with("DEBUG rq::logging::tests: response received url=http://a/ status=200\n"));
+    }
+}
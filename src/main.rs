@@ -1,36 +1,11 @@
-use reqwest::Client;
-use std::time::Duration;
+use std::process;
 
-mod cli;
-mod http;
-
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("ReqwestError")]
-    ReqwestError(#[from] reqwest::Error),
-}
-
-pub type Result<T> = std::result::Result<T, Error>;
-
-fn create_client(timeout: Duration) -> Result<Client> {
-    Client::builder()
-        .gzip(true)
-        .brotli(true)
-        .timeout(timeout)
-        .build()
-        .map_err(Error::ReqwestError)
-}
+use rq::cli;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = cli::args();
-    let client = create_client(Duration::from_millis(5000))?;
-    let res = client
-        .request(args.method.into(), args.url)
-        .send()
-        .await?
-        .text()
-        .await?;
-    println!("{}", res);
-    Ok(())
+async fn main() {
+    if let Err(e) = rq::main(cli::args()).await {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
+    }
 }
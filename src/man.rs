@@ -0,0 +1,197 @@
+//! The man page printed by `rq man`, generated from the command line definitions so it stays in
+//! sync with `--help`.
+use structopt::StructOpt;
+
+use crate::cli::CliArgs;
+
+const CONTENT_TYPES: &[(&str, &str)] = &[
+    (
+        "application/json",
+        "The body starts with an object whose first key is a string without escapes, \
+        or is an empty object",
+    ),
+    (
+        "application/x-www-form-urlencoded",
+        "The body starts with a key made of unreserved, + and %XX characters followed by =",
+    ),
+    (
+        "multipart/form-data",
+        "The body starts with the 5 hyphens of a boundary",
+    ),
+    ("text/plain", "Anything else"),
+];
+
+const EXIT_STATUSES: &[(&str, &str)] = &[
+    ("0", "All requests got a response"),
+    (
+        "1",
+        "Invalid arguments, or a request failed: no response, or a response that could not be \
+        saved or filtered",
+    ),
+    ("3", "A response body did not match --verify-checksum"),
+    ("4", "A response body was larger than --max-response-size"),
+    (
+        "5",
+        "A certificate expires within the window of --cert-expiry-warn",
+    ),
+    (
+        "6",
+        "A response failed one of the --assert-* assertions, or a case of rq test failed",
+    ),
+    (
+        "7",
+        "The condition of --until-status or --until-jsonpath was not met within --poll-timeout",
+    ),
+    (
+        "22",
+        "A response had a 4xx or 5xx status with --fail or --fail-with-body",
+    ),
+];
+
+/// Escape text for roff: backslashes, hyphens (which would become typographic dashes) and
+/// control characters at the start of a line
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+fn section(page: &mut String, title: &str) {
+    page.push_str(&format!(".SH {}\n", title));
+}
+
+fn item(page: &mut String, term: &str, description: &str) {
+    page.push_str(&format!(
+        ".TP\n\\fB{}\\fR\n{}\n",
+        escape(term),
+        escape(description)
+    ));
+}
+
+/// The sections of `--help` with their lines, without the name and version line
+fn help_sections() -> Vec<(String, Vec<String>)> {
+    let mut help = Vec::new();
+    // A width of 0 keeps each description on one line
+    CliArgs::clap()
+        .set_term_width(0)
+        .write_help(&mut help)
+        .expect("writing to memory can't fail");
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    for line in String::from_utf8_lossy(&help).lines() {
+        match (line.strip_suffix(':'), sections.last_mut()) {
+            (Some(title), _) if !line.starts_with(' ') => {
+                sections.push((title.to_string(), vec![]))
+            }
+            (_, Some((_, lines))) if !line.trim().is_empty() => lines.push(line.to_string()),
+            _ => {}
+        }
+    }
+    sections
+}
+
+/// Split a line of `--help` into the argument and its description
+fn split_entry(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find("  ") {
+        Some(end) => (&line[..end], line[end..].trim()),
+        None => (line, ""),
+    }
+}
+
+/// The man page in roff format
+pub fn render() -> String {
+    let mut page = format!(
+        ".TH RQ 1 \"\" \"rq {}\" \"User Commands\"\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    section(&mut page, "NAME");
+    page.push_str(&format!(
+        "rq \\- {}\n",
+        escape(env!("CARGO_PKG_DESCRIPTION"))
+    ));
+    let sections = help_sections();
+    for (title, lines) in &sections {
+        if title == "USAGE" {
+            section(&mut page, "SYNOPSIS");
+            for line in lines {
+                page.push_str(&format!("{}\n.br\n", escape(line.trim())));
+            }
+        }
+    }
+    for (name, titles) in [
+        ("ARGUMENTS", &["ARGS"][..]),
+        ("OPTIONS", &["FLAGS", "OPTIONS"]),
+        ("COMMANDS", &["SUBCOMMANDS"]),
+    ] {
+        let entries: Vec<_> = sections
+            .iter()
+            .filter(|(title, _)| titles.contains(&title.as_str()))
+            .flat_map(|(_, lines)| lines)
+            .map(|line| split_entry(line))
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+        section(&mut page, name);
+        for (term, description) in entries {
+            item(&mut page, term, description);
+        }
+    }
+    section(&mut page, "CONTENT TYPE");
+    page.push_str(
+        "Unless given with \\fB\\-\\-type\\fR or a Content\\-Type header, the content type of \
+        the body is guessed from its beginning, trying the rules in order:\n",
+    );
+    for (content_type, rule) in CONTENT_TYPES {
+        item(&mut page, content_type, rule);
+    }
+    section(&mut page, "EXIT STATUS");
+    for (status, meaning) in EXIT_STATUSES {
+        item(&mut page, status, meaning);
+    }
+    section(&mut page, "FILES");
+    item(
+        &mut page,
+        "$XDG_CONFIG_HOME/rq/collections/*.yaml",
+        "Request collections, for rq run",
+    );
+    item(
+        &mut page,
+        "$XDG_CONFIG_HOME/rq/hooks.yaml",
+        "Commands and scripts run before each request and after each response",
+    );
+    item(
+        &mut page,
+        "$XDG_DATA_HOME/rq/history.jsonl",
+        "The requests sent, for rq history and rq replay",
+    );
+    item(
+        &mut page,
+        "$XDG_CACHE_HOME/rq",
+        "The responses stored with --cache",
+    );
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn text_is_escaped_for_roff() {
+        assert_eq!(super::escape("--ipv4"), "\\-\\-ipv4");
+        assert_eq!(super::escape(".a\\b"), "\\&.a\\eb");
+    }
+    #[test]
+    fn options_are_taken_from_the_help() {
+        assert_eq!(
+            super::split_entry("    -4, --ipv4    Only connect to IPv4 addresses"),
+            ("-4, --ipv4", "Only connect to IPv4 addresses")
+        );
+        let page = super::render();
+        assert!(page.contains(".SH OPTIONS\n"));
+        assert!(page.contains("\\fB\\-4, \\-\\-ipv4\\fR\n"));
+        assert!(page.contains("\\fBhistory\\fR\n"));
+    }
+}
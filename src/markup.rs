@@ -0,0 +1,265 @@
+//! Indenting XML and HTML documents for display.
+//!
+//! The formatter works on tokens (tags, text, comments...) without building a tree, so it also
+//! copes with the invalid markup that is common in HTML pages.
+
+const INDENT: &str = "  ";
+const TAG_COLOR: &str = "\x1b[34m";
+const COMMENT_COLOR: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+/// HTML elements that never have content nor a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+/// HTML elements whose content is printed as is
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "pre", "textarea"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Xml,
+    Html,
+}
+
+/// The kind of markup for a `Content-Type`, if it is XML or HTML
+pub fn kind_of(content_type: &str) -> Option<Kind> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match essence.as_str() {
+        "text/html" | "application/xhtml+xml" => Some(Kind::Html),
+        "application/xml" | "text/xml" => Some(Kind::Xml),
+        s if s.ends_with("+xml") => Some(Kind::Xml),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Token<'a> {
+    Open {
+        name: String,
+        source: &'a str,
+        self_closing: bool,
+    },
+    Close {
+        name: String,
+        source: &'a str,
+    },
+    /// Comments, doctypes, CDATA sections and processing instructions
+    Other(&'a str),
+    Text(&'a str),
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches(&['<', '/'][..])
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Find the end of the tag starting at `start`, skipping `>` inside quoted attribute values
+fn tag_end(input: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (i, c) in input[start..].char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return start + i + 1,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Split the document into tags, text and other constructs. Tag names are lowercased.
+pub fn tokenize(input: &str, kind: Kind) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        if !rest.starts_with('<') {
+            let end = rest.find('<').map_or(input.len(), |i| pos + i);
+            tokens.push(Token::Text(&input[pos..end]));
+            pos = end;
+            continue;
+        }
+        let delimited = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")]
+            .iter()
+            .find(|(open, _)| rest.starts_with(open));
+        let end = match delimited {
+            Some((_, close)) => rest
+                .find(close)
+                .map_or(input.len(), |i| pos + i + close.len()),
+            None => tag_end(input, pos),
+        };
+        let source = &input[pos..end];
+        pos = end;
+        if delimited.is_some() || source.starts_with("<!") {
+            tokens.push(Token::Other(source));
+        } else if source.starts_with("</") {
+            tokens.push(Token::Close {
+                name: tag_name(source),
+                source,
+            });
+        } else {
+            let name = tag_name(source);
+            let self_closing = source.ends_with("/>")
+                || (kind == Kind::Html && VOID_ELEMENTS.contains(&name.as_str()));
+            let raw = kind == Kind::Html && RAW_TEXT_ELEMENTS.contains(&name.as_str());
+            tokens.push(Token::Open {
+                name: name.clone(),
+                source,
+                self_closing,
+            });
+            if raw && !self_closing {
+                // The content of raw text elements may contain `<`, which is not a tag
+                let closing = format!("</{}", name);
+                let content_end = input[pos..]
+                    .to_ascii_lowercase()
+                    .find(&closing)
+                    .map_or(input.len(), |i| pos + i);
+                if content_end > pos {
+                    tokens.push(Token::Other(&input[pos..content_end]));
+                }
+                pos = content_end;
+            }
+        }
+    }
+    tokens
+}
+
+/// Indent the document, one tag per line. Elements containing only text stay on one line.
+pub fn format(input: &str, kind: Kind, color: bool) -> String {
+    let paint = |s: &str, code: &str| {
+        if color {
+            format!("{}{}{}", code, s, RESET)
+        } else {
+            s.to_string()
+        }
+    };
+    let tokens = tokenize(input, kind);
+    let mut output = String::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let indent = |depth: usize| INDENT.repeat(depth);
+        match &tokens[i] {
+            Token::Open {
+                name,
+                source,
+                self_closing: false,
+            } => {
+                // `<a>text</a>` is kept on one line
+                if let (
+                    Some(Token::Text(text)),
+                    Some(Token::Close {
+                        name: close,
+                        source: end,
+                    }),
+                ) = (tokens.get(i + 1), tokens.get(i + 2))
+                {
+                    if close == name && !text.trim().contains('\n') {
+                        output.push_str(&format!(
+                            "{}{}{}{}\n",
+                            indent(depth),
+                            paint(source, TAG_COLOR),
+                            text.trim(),
+                            paint(end, TAG_COLOR)
+                        ));
+                        i += 3;
+                        continue;
+                    }
+                }
+                output.push_str(&format!("{}{}\n", indent(depth), paint(source, TAG_COLOR)));
+                depth += 1;
+            }
+            Token::Open { source, .. } => {
+                output.push_str(&format!("{}{}\n", indent(depth), paint(source, TAG_COLOR)));
+            }
+            Token::Close { source, .. } => {
+                depth = depth.saturating_sub(1);
+                output.push_str(&format!("{}{}\n", indent(depth), paint(source, TAG_COLOR)));
+            }
+            Token::Other(source) if source.starts_with("<!--") => {
+                output.push_str(&format!(
+                    "{}{}\n",
+                    indent(depth),
+                    paint(source, COMMENT_COLOR)
+                ));
+            }
+            Token::Other(source) if source.starts_with('<') => {
+                output.push_str(&format!("{}{}\n", indent(depth), source));
+            }
+            // Content of raw text elements
+            Token::Other(source) => {
+                output.push_str(source);
+                if !source.ends_with('\n') {
+                    output.push('\n');
+                }
+            }
+            Token::Text(text) => {
+                for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    output.push_str(&format!("{}{}\n", indent(depth), line));
+                }
+            }
+        }
+        i += 1;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kind;
+
+    #[test]
+    fn kind_is_detected_from_content_type() {
+        assert_eq!(super::kind_of("text/html; charset=utf-8"), Some(Kind::Html));
+        assert_eq!(super::kind_of("application/atom+xml"), Some(Kind::Xml));
+        assert_eq!(super::kind_of("application/json"), None);
+    }
+    #[test]
+    fn xml_is_indented() {
+        let input =
+            r#"<?xml version="1.0"?><a x="1>2"><b>text</b><c/><!-- note --><d><e>1</e></d></a>"#;
+        assert_eq!(
+            super::format(input, Kind::Xml, false),
+            r#"<?xml version="1.0"?>
+<a x="1>2">
+  <b>text</b>
+  <c/>
+  <!-- note -->
+  <d>
+    <e>1</e>
+  </d>
+</a>
+"#
+        );
+    }
+    #[test]
+    fn html_void_and_raw_text_elements() {
+        let input =
+            "<html><body><br><p>Hi<img src=a.png></p><script>if (a<b) {}</script></body></html>";
+        assert_eq!(
+            super::format(input, Kind::Html, false),
+            "<html>
+  <body>
+    <br>
+    <p>
+      Hi
+      <img src=a.png>
+    </p>
+    <script>
+if (a<b) {}
+    </script>
+  </body>
+</html>
+"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Guessing the media type of a file sent as a body, with `--data-binary @file` or as a file part
+//! of a form, so that e.g. a PNG image is not sent as text.
+//!
+//! The extension of the file is looked up first, then the first bytes are compared with the
+//! signatures of common binary formats. Text content falls back to the guess made for `-d`.
+use std::{fs::File, io::Read, path::Path};
+
+use crate::http::guess_content_type;
+
+const BY_EXTENSION: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("avif", "image/avif"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("wasm", "application/wasm"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("csv", "text/csv"),
+    ("md", "text/markdown"),
+    ("txt", "text/plain"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+];
+
+/// The signatures at the start of binary files
+const BY_SIGNATURE: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+];
+
+fn by_signature(head: &[u8]) -> Option<&'static str> {
+    // RIFF is a container, the format comes after the size
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        return Some("image/webp");
+    }
+    BY_SIGNATURE
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, media_type)| *media_type)
+}
+
+/// Enough of the file to recognize the signatures and guess the type of text
+const HEAD_SIZE: usize = 512;
+
+/// The media type of the file `path` starting with `head`
+pub fn guess(path: &Path, head: &[u8]) -> String {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    let by_extension = BY_EXTENSION
+        .iter()
+        .find(|(known, _)| extension.as_deref() == Some(*known))
+        .map(|(_, media_type)| *media_type);
+    if let Some(media_type) = by_extension.or_else(|| by_signature(head)) {
+        return media_type.to_string();
+    }
+    match std::str::from_utf8(head) {
+        Ok(text) => guess_content_type(text).to_string(),
+        // The head may end in the middle of a character
+        Err(e) if e.error_len().is_none() && e.valid_up_to() > 0 => {
+            guess_content_type(&String::from_utf8_lossy(&head[..e.valid_up_to()])).to_string()
+        }
+        Err(_) => "application/octet-stream".to_string(),
+    }
+}
+
+/// The media type of the file `path`, reading its first bytes if needed. `None` if it can't be
+/// read, the request then fails when it is sent.
+pub fn guess_file(path: &Path) -> Option<String> {
+    let mut head = Vec::with_capacity(HEAD_SIZE);
+    File::open(path)
+        .ok()?
+        .take(HEAD_SIZE as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    Some(guess(path, &head))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn extension_comes_first() {
+        assert_eq!(super::guess(Path::new("photo.PNG"), b""), "image/png");
+        assert_eq!(
+            super::guess(Path::new("a.json"), b"xyz"),
+            "application/json"
+        );
+    }
+    #[test]
+    fn signatures_are_recognized() {
+        let guess = |head: &[u8]| super::guess(Path::new("upload"), head);
+        assert_eq!(guess(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(guess(b"RIFF\x10\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(
+            guess(b"RIFF\xa0\xff\0\0WAVEfmt "),
+            "application/octet-stream"
+        );
+        assert_eq!(guess(b"%PDF-1.7"), "application/pdf");
+    }
+    #[test]
+    fn text_falls_back_to_the_body_guess() {
+        let guess = |head: &[u8]| super::guess(Path::new("upload"), head);
+        assert_eq!(guess(br#"{"a": 1}"#), "application/json");
+        assert_eq!(guess(b"a=1&b=2"), "application/x-www-form-urlencoded");
+        assert_eq!(guess(b"caf\xc3"), "text/plain");
+    }
+}
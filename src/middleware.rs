@@ -0,0 +1,909 @@
+//! Hooks run around each exchange by `Request::send`, so features acting on every request or
+//! response compose instead of being hardcoded in the commands sending them.
+//!
+//! The authentication, `--etag-save`, the logging of the exchanges and the cache are built-in
+//! middlewares enabled from the command line. Users can also register shell commands in
+//! `hooks.yaml` in the config directory:
+//!
+//! ```yaml
+//! on_request:
+//!   - 'echo "X-Request-Id: $(uuidgen)"'
+//! on_response:
+//!   - 'notify-send "$RQ_URL: $RQ_STATUS"'
+//! ```
+//!
+//! The commands run with `sh -c` and get the exchange in `RQ_METHOD`, `RQ_URL`, the headers in
+//! `RQ_HEADER_<NAME>` (e.g. `RQ_HEADER_CONTENT_TYPE`) and, for response hooks, `RQ_STATUS`, with
+//! the request or response body on stdin. The `Name: value` lines they print set headers of the
+//! request or response, and what follows an empty line replaces its body. A failing request hook
+//! stops the request from being sent, a failing response hook rejects the response like a failed
+//! assertion.
+//!
+//! Scripts in the language of [`crate::script`] run in-process, listed under `pre_request` and
+//! `post_response` with paths relative to the config directory:
+//!
+//! ```yaml
+//! pre_request:
+//!   - sign.rhai
+//! post_response:
+//!   - check.rhai
+//! ```
+//!
+//! They get the `request` as a map of its `method`, `url`, `headers` and `body`, and post-response
+//! scripts the `response` too, with its `status`, `url`, `headers` and `body`. Header names are in
+//! lowercase. What the scripts change in them is sent or output instead. A pre-request script
+//! failing stops the request from being sent, a post-response script calling `fail` or failing an
+//! `assert` rejects the response like a failed assertion.
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+};
+
+use futures_util::future::{self, BoxFuture, FutureExt};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH},
+    Client, StatusCode,
+};
+use serde_json::{json, Map, Value};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    cache::Cache,
+    cli::CliArgs,
+    conditional,
+    http::AuthType,
+    jwt, negotiate, output, paths,
+    poll::Poll,
+    request::Request,
+    response::Response,
+    script::{self, Script},
+    yaml,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {source}", .path.display())]
+    Yaml { path: PathBuf, source: yaml::Error },
+    #[error("{}: {message}", .path.display())]
+    Invalid { path: PathBuf, message: String },
+    #[error("{0}")]
+    Jwt(#[from] jwt::Error),
+    #[error("Can't run hook `{command}`: {source}")]
+    Spawn { command: String, source: io::Error },
+    #[error("Hook `{command}` failed with {status}")]
+    HookFailed { command: String, status: ExitStatus },
+    #[error("Hook `{command}` rejected the response with {status}")]
+    Rejected { command: String, status: ExitStatus },
+    #[error("Can't read {}: {source}", .path.display())]
+    ReadScript { path: PathBuf, source: io::Error },
+    #[error("{}: {source}", .path.display())]
+    Script {
+        path: PathBuf,
+        source: script::Error,
+    },
+    #[error("{} rejected the response, {source}", .path.display())]
+    ScriptRejected {
+        path: PathBuf,
+        source: script::Error,
+    },
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether a response hook or script rejected the response, like a failed assertion
+    pub fn is_rejection(&self) -> bool {
+        matches!(self, Error::Rejected { .. } | Error::ScriptRejected { .. })
+    }
+}
+
+/// Code run before each request is sent and after its response is received
+pub trait Middleware: Send + Sync {
+    /// Prepare the request, or answer it without sending it
+    fn on_request<'a>(
+        &'a self,
+        _request: &'a mut Request,
+    ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+        future::ready(Ok(None)).boxed()
+    }
+
+    /// Handle the response, `request` being the request as this middleware was given it
+    fn on_response<'a>(
+        &'a self,
+        _request: &'a Request,
+        _response: &'a mut Response,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        future::ready(Ok(())).boxed()
+    }
+}
+
+/// The middlewares in the order they prepare the requests, the responses going through them in
+/// reverse order
+#[derive(Clone, Default)]
+pub struct Pipeline(Vec<Arc<dyn Middleware>>);
+
+impl Debug for Pipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Pipeline({} middlewares)", self.0.len())
+    }
+}
+
+impl Pipeline {
+    /// The middlewares enabled on the command line, with the user hooks before the cache so it
+    /// stores the requests and responses as they changed them
+    pub fn from_args(args: &CliArgs) -> Result<Self> {
+        let mut pipeline = Pipeline::default();
+        if let Some(auth) = Auth::from_args(args)? {
+            pipeline.push(auth);
+        }
+        if let Some(path) = &args.etag_save {
+            pipeline.push(SaveEtag(path.clone()));
+        }
+        let path = paths::config_dir().join("hooks.yaml");
+        if let Some(value) = read_hooks(&path)? {
+            let hooks = Hooks::from_value(&value).map_err(|message| Error::Invalid {
+                path: path.clone(),
+                message,
+            })?;
+            pipeline.push(hooks);
+            pipeline.push(Scripts::load(&path, &value)?);
+        }
+        pipeline.push(Logging);
+        // Polling waits for the server to change, it can't be answered from the cache
+        if args.cache && Poll::from_args(args).is_none() {
+            pipeline.push(Cache::default());
+        }
+        Ok(pipeline)
+    }
+
+    pub fn push(&mut self, middleware: impl Middleware + 'static) {
+        self.0.push(Arc::new(middleware));
+    }
+
+    /// Send the request through the middlewares. A middleware answering the request stops it
+    /// from going further, only the middlewares before it get the response. `sent` keeps the
+    /// request that went out.
+    pub async fn send(
+        &self,
+        request: &Request,
+        client: &Client,
+        sent: &mut Option<reqwest::Request>,
+    ) -> crate::Result<Response> {
+        let mut request = request.clone();
+        let mut given = Vec::new();
+        let mut answer = None;
+        for middleware in &self.0 {
+            let before = request.clone();
+            answer = middleware.on_request(&mut request).await?;
+            if answer.is_some() {
+                break;
+            }
+            given.push((middleware, before));
+        }
+        let mut response = match answer {
+            Some(response) => {
+                // Nothing went out, the request is recorded as the middlewares left it
+                *sent = request.build(client).build().ok();
+                response
+            }
+            None => request.exchange(client, sent).await?,
+        };
+        for (middleware, request) in given.iter().rev() {
+            middleware.on_response(request, &mut response).await?;
+        }
+        Ok(response)
+    }
+
+    /// Prepare the request as it would be sent, for the commands printing or sending it
+    /// themselves
+    pub async fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+        for middleware in &self.0 {
+            if middleware.on_request(request).await?.is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The credentials given on the command line, for the requests not carrying their own
+pub enum Auth {
+    /// The `Authorization` header of `--auth`
+    Basic(String),
+    /// SPNEGO with the Kerberos tickets of the user, for `--auth-type negotiate`
+    Negotiate,
+    /// A new token signed for each request, for `--jwt-sign`
+    Jwt(Box<jwt::Signer>),
+}
+
+impl Auth {
+    pub fn from_args(args: &CliArgs) -> Result<Option<Self>> {
+        if let Some(key) = &args.jwt_sign {
+            let algorithm = args.jwt_alg.unwrap_or(jwt::Algorithm::Rs256);
+            let claims = args.jwt_claims.as_deref().unwrap_or("{}");
+            let signer = jwt::Signer::load(key, algorithm, claims)?;
+            return Ok(Some(Auth::Jwt(Box::new(signer))));
+        }
+        if args.auth_type == AuthType::Negotiate {
+            return Ok(Some(Auth::Negotiate));
+        }
+        Ok(args
+            .auth
+            .as_ref()
+            .map(|auth| Auth::Basic(auth.header_value())))
+    }
+}
+
+impl Middleware for Auth {
+    fn on_request<'a>(
+        &'a self,
+        request: &'a mut Request,
+    ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+        let result = match self {
+            Auth::Basic(value) => {
+                let given = request
+                    .headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case(AUTHORIZATION.as_str()));
+                if !given {
+                    request.set_header(AUTHORIZATION.as_str(), value.clone());
+                }
+                Ok(())
+            }
+            Auth::Negotiate => negotiate::authorize(request).map_err(Into::into),
+            Auth::Jwt(signer) => jwt::authorize(request, signer).map_err(Into::into),
+        };
+        future::ready(result.map(|()| None)).boxed()
+    }
+}
+
+/// Write the `ETag` of the responses to a file for `--etag-save`, keeping the previous one when
+/// the response is `304 Not Modified`
+pub struct SaveEtag(PathBuf);
+
+impl Middleware for SaveEtag {
+    fn on_response<'a>(
+        &'a self,
+        _request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        if response.status != StatusCode::NOT_MODIFIED {
+            if let Err(e) = conditional::save_etag(&self.0, response) {
+                output::note(format!(
+                    "Failed to save the ETag to {}: {}",
+                    self.0.display(),
+                    e
+                ));
+            }
+        }
+        future::ready(Ok(())).boxed()
+    }
+}
+
+/// Log the requests as they are sent and the responses as they are received, or served from the
+/// cache
+pub struct Logging;
+
+impl Middleware for Logging {
+    fn on_request<'a>(
+        &'a self,
+        request: &'a mut Request,
+    ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+        tracing::debug!(method = %request.method, url = %request.url, "sending request");
+        for (name, value) in &request.headers {
+            tracing::trace!(%name, %value, "request header");
+        }
+        future::ready(Ok(None)).boxed()
+    }
+
+    fn on_response<'a>(
+        &'a self,
+        _request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        tracing::debug!(
+            status = %response.status,
+            version = ?response.version,
+            url = %response.url,
+            bytes = response.body.len(),
+            "response received"
+        );
+        future::ready(Ok(())).boxed()
+    }
+}
+
+/// The content of the user's hooks file, if it exists
+fn read_hooks(path: &Path) -> Result<Option<Value>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    yaml::parse(&content)
+        .map(Some)
+        .map_err(|source| Error::Yaml {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// The strings listed under `key` of the hooks file
+fn list(value: &Value, key: &str, what: &str) -> std::result::Result<Vec<String>, String> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(format!("`{}` must only contain {}", key, what)),
+            })
+            .collect(),
+        Some(_) => Err(format!("`{}` must be a list of {}", key, what)),
+    }
+}
+
+/// The shell commands of the user's hooks file
+#[derive(Debug, Default)]
+pub struct Hooks {
+    on_request: Vec<String>,
+    on_response: Vec<String>,
+}
+
+impl Hooks {
+    fn from_value(value: &Value) -> std::result::Result<Self, String> {
+        Ok(Hooks {
+            on_request: list(value, "on_request", "commands")?,
+            on_response: list(value, "on_response", "commands")?,
+        })
+    }
+
+    /// Run the command with the exchange in its environment and `input` on stdin, and return
+    /// its output and exit status
+    async fn run(
+        command: &str,
+        request: &Request,
+        headers: impl Iterator<Item = (&str, &str)>,
+        status: Option<StatusCode>,
+        input: &[u8],
+    ) -> Result<(Vec<u8>, ExitStatus)> {
+        let mut process = Command::new("sh");
+        process
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .env("RQ_METHOD", request.method.to_string())
+            .env("RQ_URL", &request.url);
+        for (name, value) in headers {
+            let name = name.to_ascii_uppercase().replace('-', "_");
+            process.env(format!("RQ_HEADER_{}", name), value);
+        }
+        if let Some(status) = status {
+            process.env("RQ_STATUS", status.as_str());
+        }
+        let spawn_error = |source| Error::Spawn {
+            command: command.to_string(),
+            source,
+        };
+        let mut child = process.spawn().map_err(spawn_error)?;
+        let mut stdin = child.stdin.take();
+        // Written while the output is read so a hook printing before reading can't deadlock. The
+        // hook may not read its input, which is fine.
+        let write = async {
+            if let Some(stdin) = &mut stdin {
+                let _ = stdin.write_all(input).await;
+            }
+            drop(stdin);
+        };
+        let (_, output) = tokio::join!(write, child.wait_with_output());
+        let output = output.map_err(spawn_error)?;
+        Ok((output.stdout, output.status))
+    }
+}
+
+/// The `Name: value` lines printed by a hook, and the body following an empty line
+fn parse_output(output: &[u8]) -> (Vec<(String, String)>, Option<&[u8]>) {
+    let (head, body) = match output.windows(2).position(|w| w == b"\n\n") {
+        Some(end) => (&output[..end], Some(&output[end + 2..])),
+        None => (output, None),
+    };
+    let headers = String::from_utf8_lossy(head)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    (headers, body)
+}
+
+impl Middleware for Hooks {
+    fn on_request<'a>(
+        &'a self,
+        request: &'a mut Request,
+    ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+        async move {
+            for command in &self.on_request {
+                let body = request.body.clone().unwrap_or_default();
+                let headers = request.headers.clone();
+                let headers = headers.iter().map(|(n, v)| (n.as_str(), v.as_str()));
+                let (output, status) =
+                    Hooks::run(command, request, headers, None, body.as_bytes()).await?;
+                if !status.success() {
+                    return Err(Error::HookFailed {
+                        command: command.clone(),
+                        status,
+                    }
+                    .into());
+                }
+                let (headers, body) = parse_output(&output);
+                for (name, value) in headers {
+                    request.set_header(&name, value);
+                }
+                if let Some(body) = body {
+                    request.body = Some(String::from_utf8_lossy(body).into_owned());
+                }
+            }
+            Ok(None)
+        }
+        .boxed()
+    }
+
+    fn on_response<'a>(
+        &'a self,
+        request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        async move {
+            for command in &self.on_response {
+                let headers = response
+                    .headers
+                    .iter()
+                    .filter_map(|(n, v)| Some((n.as_str(), v.to_str().ok()?)));
+                let status = Some(response.status);
+                let (output, status) =
+                    Hooks::run(command, request, headers, status, &response.body).await?;
+                if !status.success() {
+                    return Err(Error::Rejected {
+                        command: command.clone(),
+                        status,
+                    }
+                    .into());
+                }
+                let (headers, body) = parse_output(&output);
+                for (name, value) in headers {
+                    match (name.parse::<HeaderName>(), HeaderValue::from_str(&value)) {
+                        (Ok(name), Ok(value)) => {
+                            response.headers.insert(name, value);
+                        }
+                        _ => output::note(format!(
+                            "Hook `{}` printed an invalid header `{}`",
+                            command, name
+                        )),
+                    }
+                }
+                if let Some(body) = body {
+                    response.headers.remove(CONTENT_LENGTH);
+                    response.body = body.to_vec();
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// The scripts of the user's hooks file, run in-process
+#[derive(Debug, Default)]
+pub struct Scripts {
+    pre_request: Vec<(PathBuf, Script)>,
+    post_response: Vec<(PathBuf, Script)>,
+}
+
+impl Scripts {
+    /// Read and parse the scripts listed in the hooks file at `path`, whose content is `value`
+    fn load(path: &Path, value: &Value) -> Result<Self> {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let scripts = |key| -> Result<Vec<(PathBuf, Script)>> {
+            let paths = list(value, key, "paths").map_err(|message| Error::Invalid {
+                path: path.to_path_buf(),
+                message,
+            })?;
+            paths
+                .iter()
+                .map(|script| {
+                    let path = dir.join(script);
+                    let source = fs::read_to_string(&path).map_err(|source| Error::ReadScript {
+                        path: path.clone(),
+                        source,
+                    })?;
+                    match Script::parse(&source) {
+                        Ok(script) => Ok((path, script)),
+                        Err(source) => Err(Error::Script { path, source }),
+                    }
+                })
+                .collect()
+        };
+        Ok(Scripts {
+            pre_request: scripts("pre_request")?,
+            post_response: scripts("post_response")?,
+        })
+    }
+
+    fn prepare(&self, request: &mut Request) -> Result<()> {
+        for (path, script) in &self.pre_request {
+            let mut scope = Map::new();
+            scope.insert("request".to_string(), request_value(request));
+            script.run(&mut scope).map_err(|source| Error::Script {
+                path: path.clone(),
+                source,
+            })?;
+            update_request(request, &scope["request"]).map_err(|message| Error::Invalid {
+                path: path.clone(),
+                message,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, request: &Request, response: &mut Response) -> Result<()> {
+        for (path, script) in &self.post_response {
+            let mut scope = Map::new();
+            scope.insert("request".to_string(), request_value(request));
+            scope.insert("response".to_string(), response_value(response));
+            script.run(&mut scope).map_err(|source| match source {
+                script::Error::Failed { .. } => Error::ScriptRejected {
+                    path: path.clone(),
+                    source,
+                },
+                source => Error::Script {
+                    path: path.clone(),
+                    source,
+                },
+            })?;
+            update_response(response, &scope["response"]).map_err(|message| Error::Invalid {
+                path: path.clone(),
+                message,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// The header names in lowercase, the values of repeated headers joined with commas
+fn headers_value<'a>(headers: impl Iterator<Item = (&'a str, String)>) -> Value {
+    let mut map = Map::new();
+    for (name, value) in headers {
+        let name = name.to_ascii_lowercase();
+        let value = match map.remove(&name) {
+            Some(Value::String(previous)) => format!("{}, {}", previous, value),
+            _ => value,
+        };
+        map.insert(name, value.into());
+    }
+    Value::Object(map)
+}
+
+/// The request as the `request` variable of the scripts
+fn request_value(request: &Request) -> Value {
+    let headers = request
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()));
+    json!({
+        "method": request.method.to_string(),
+        "url": request.url,
+        "headers": headers_value(headers),
+        "body": request.body,
+    })
+}
+
+/// The response as the `response` variable of the scripts
+fn response_value(response: &Response) -> Value {
+    let headers = response.headers.iter().map(|(name, value)| {
+        (
+            name.as_str(),
+            String::from_utf8_lossy(value.as_bytes()).into(),
+        )
+    });
+    json!({
+        "status": response.status.as_u16(),
+        "url": response.url,
+        "headers": headers_value(headers),
+        "body": String::from_utf8_lossy(&response.body),
+    })
+}
+
+/// The text of a value set by a script, `None` for `()`
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Apply the changes a script made to the `request` variable
+fn update_request(request: &mut Request, value: &Value) -> std::result::Result<(), String> {
+    let method = text(&value["method"]).unwrap_or_default();
+    request.method = method
+        .parse()
+        .map_err(|e: crate::http::Error| format!("`request.method`: {}", e))?;
+    request.url = text(&value["url"]).ok_or("`request.url` must be set")?;
+    let before = request_value(request);
+    if value["headers"] != before["headers"] {
+        let headers = value["headers"]
+            .as_object()
+            .ok_or("`request.headers` must be a map")?;
+        request.headers = headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), text(value)?)))
+            .collect();
+    }
+    request.body = text(&value["body"]);
+    Ok(())
+}
+
+/// Apply the changes a script made to the `response` variable
+fn update_response(response: &mut Response, value: &Value) -> std::result::Result<(), String> {
+    response.status = value["status"]
+        .as_u64()
+        .and_then(|status| StatusCode::from_u16(u16::try_from(status).ok()?).ok())
+        .ok_or("`response.status` must be an HTTP status")?;
+    let before = response_value(response);
+    if value["headers"] != before["headers"] {
+        let headers = value["headers"]
+            .as_object()
+            .ok_or("`response.headers` must be a map")?;
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            let value = match text(value) {
+                Some(value) => value,
+                None => continue,
+            };
+            match (name.parse::<HeaderName>(), HeaderValue::from_str(&value)) {
+                (Ok(name), Ok(value)) => {
+                    map.insert(name, value);
+                }
+                _ => return Err(format!("invalid header `{}`", name)),
+            }
+        }
+        response.headers = map;
+    }
+    if value["body"] != before["body"] {
+        response.headers.remove(CONTENT_LENGTH);
+        response.body = text(&value["body"]).unwrap_or_default().into_bytes();
+    }
+    Ok(())
+}
+
+impl Middleware for Scripts {
+    fn on_request<'a>(
+        &'a self,
+        request: &'a mut Request,
+    ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+        let result = self.prepare(request).map(|()| None).map_err(Into::into);
+        future::ready(result).boxed()
+    }
+
+    fn on_response<'a>(
+        &'a self,
+        request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        future::ready(self.check(request, response).map_err(Into::into)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use futures_util::future::{self, BoxFuture, FutureExt};
+    use reqwest::{header::HeaderMap, Client, StatusCode, Version};
+    use serde_json::json;
+
+    use super::{Hooks, Middleware, Pipeline, Scripts};
+    use crate::{http::HttpMethod, request::Request, response::Response, script::Script};
+
+    /// Adds a header to the requests, and to the responses whether the request it was given had it
+    struct Mark(&'static str);
+
+    impl Middleware for Mark {
+        fn on_request<'a>(
+            &'a self,
+            request: &'a mut Request,
+        ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+            request.set_header(self.0, "1".to_string());
+            future::ready(Ok(None)).boxed()
+        }
+
+        fn on_response<'a>(
+            &'a self,
+            request: &'a Request,
+            response: &'a mut Response,
+        ) -> BoxFuture<'a, crate::Result<()>> {
+            let seen = request.headers.iter().any(|(name, _)| name == self.0);
+            response
+                .headers
+                .insert(self.0, seen.to_string().parse().unwrap());
+            future::ready(Ok(())).boxed()
+        }
+    }
+
+    /// Answers the requests with their headers
+    struct Answer;
+
+    impl Middleware for Answer {
+        fn on_request<'a>(
+            &'a self,
+            request: &'a mut Request,
+        ) -> BoxFuture<'a, crate::Result<Option<Response>>> {
+            let response = Response {
+                url: request.url.clone(),
+                status: StatusCode::OK,
+                version: Version::HTTP_11,
+                headers: HeaderMap::new(),
+                body: format!("{:?}", request.headers).into_bytes(),
+                wait: Duration::ZERO,
+                receive: Duration::ZERO,
+            };
+            future::ready(Ok(Some(response))).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn answered_requests_go_no_further() {
+        let mut pipeline = Pipeline::default();
+        pipeline.push(Mark("x-a"));
+        pipeline.push(Answer);
+        pipeline.push(Mark("x-b"));
+        let request = Request::new(HttpMethod::Get, "http://localhost");
+        let response = pipeline
+            .send(&request, &Client::new(), &mut None)
+            .await
+            .unwrap();
+        assert_eq!(response.body, br#"[("x-a", "1")]"#);
+        assert_eq!(response.headers["x-a"], "false");
+        assert!(!response.headers.contains_key("x-b"));
+    }
+
+    #[test]
+    fn hooks_are_read_from_lists_of_commands() {
+        let hooks = Hooks::from_value(&json!({"on_response": ["true"]})).unwrap();
+        assert!(hooks.on_request.is_empty());
+        assert_eq!(hooks.on_response, vec!["true"]);
+        assert!(Hooks::from_value(&json!({"on_request": "true"})).is_err());
+        assert!(Hooks::from_value(&json!({"on_request": [1]})).is_err());
+    }
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn request_hooks_add_headers() {
+        let hooks = Hooks {
+            on_request: vec!["echo \"X-Method: $RQ_METHOD\"".to_string()],
+            on_response: Vec::new(),
+        };
+        let mut request = Request::new(HttpMethod::Put, "http://localhost");
+        hooks.on_request(&mut request).await.unwrap();
+        assert_eq!(
+            request.headers,
+            vec![("X-Method".to_string(), "PUT".to_string())]
+        );
+    }
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn request_hooks_can_replace_the_body() {
+        let hooks = Hooks {
+            on_request: vec!["printf 'X-Length: %s\\n\\n' \"$(wc -c)\"; echo new".to_string()],
+            on_response: Vec::new(),
+        };
+        let mut request = Request::new(HttpMethod::Post, "http://localhost");
+        request.body = Some("abc".to_string());
+        hooks.on_request(&mut request).await.unwrap();
+        assert_eq!(request.headers[0].1, "3");
+        assert_eq!(request.body.as_deref(), Some("new\n"));
+    }
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn response_hooks_can_transform_and_reject_the_response() {
+        let hooks = Hooks {
+            on_request: Vec::new(),
+            on_response: vec![
+                "printf 'X-Type: %s\\n\\n' \"$RQ_HEADER_CONTENT_TYPE\"; tr a-z A-Z".to_string(),
+                "grep -q HELLO".to_string(),
+            ],
+        };
+        let request = Request::new(HttpMethod::Get, "http://localhost");
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/plain".parse().unwrap());
+        let mut response = Response {
+            url: request.url.clone(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers,
+            body: b"hello".to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        hooks.on_response(&request, &mut response).await.unwrap();
+        assert_eq!(response.body, b"HELLO");
+        assert_eq!(response.headers["x-type"], "text/plain");
+        response.body = b"bye".to_vec();
+        let error = hooks
+            .on_response(&request, &mut response)
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+    }
+    #[test]
+    fn scripts_are_read_relative_to_the_hooks_file() {
+        let dir = std::env::temp_dir().join(format!("rq-scripts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("sign.rhai"), "request.headers[\"x-a\"] = \"1\"").unwrap();
+        std::fs::write(dir.join("broken.rhai"), "let = 1").unwrap();
+        let hooks = dir.join("hooks.yaml");
+        let scripts = Scripts::load(&hooks, &json!({"pre_request": ["sign.rhai"]})).unwrap();
+        assert_eq!(scripts.pre_request[0].0, dir.join("sign.rhai"));
+        assert!(scripts.post_response.is_empty());
+        let broken = Scripts::load(&hooks, &json!({"post_response": ["broken.rhai"]}));
+        let missing = Scripts::load(&hooks, &json!({"pre_request": ["missing.rhai"]}));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(broken, Err(super::Error::Script { .. })));
+        assert!(matches!(missing, Err(super::Error::ReadScript { .. })));
+    }
+    #[tokio::test]
+    async fn scripts_sign_requests_and_check_responses() {
+        let script = |source| (PathBuf::from("test.rhai"), Script::parse(source).unwrap());
+        let scripts = Scripts {
+            pre_request: vec![script(
+                r#"request.headers["x-signature"] = sha256(request.method + request.body);
+                request.body = to_json(#{ data: request.body })"#,
+            )],
+            post_response: vec![script(
+                r#"assert(response.status == 200, "status " + response.status);
+                let body = parse_json(response.body);
+                response.body = body.items;
+                response.headers["x-count"] = len(body.items);"#,
+            )],
+        };
+        let mut request = Request::new(HttpMethod::Post, "http://localhost");
+        request.set_header("Content-Type", "text/plain".to_string());
+        request.body = Some("a".to_string());
+        scripts.on_request(&mut request).await.unwrap();
+        assert_eq!(
+            request.headers[1],
+            (
+                "x-signature".to_string(),
+                "d8f8578d394e18e164831a8ee5e2feb473efe8a175b7f0eed5385249eab3b68c".to_string()
+            )
+        );
+        assert_eq!(request.body.as_deref(), Some(r#"{"data":"a"}"#));
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", "24".parse().unwrap());
+        let mut response = Response {
+            url: request.url.clone(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers,
+            body: br#"{"items": [1, 2], "n": 2}"#.to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        scripts.on_response(&request, &mut response).await.unwrap();
+        assert_eq!(response.body, b"[1,2]");
+        assert_eq!(response.headers["x-count"], "2");
+        assert!(!response.headers.contains_key("content-length"));
+        response.status = StatusCode::NOT_FOUND;
+        let error = scripts
+            .on_response(&request, &mut response)
+            .await
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+        assert!(error.to_string().ends_with("line 1: status 404"));
+    }
+}
@@ -0,0 +1,293 @@
+//! MessagePack bodies: `--type msgpack` sends the JSON body given with `-d` encoded as
+//! MessagePack, and `application/msgpack` responses are printed as JSON.
+//!
+//! Binary values are printed as base64 strings, extension values as `{"type": n, "data": base64}`
+//! and map keys that are not strings as their JSON text.
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+use serde_json::{Map, Number, Value};
+
+use crate::Response;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid MessagePack: the data ends in the middle of a value")]
+    Truncated,
+    #[error("Invalid MessagePack: unknown marker 0x{0:02x}")]
+    InvalidMarker(u8),
+    #[error("Invalid MessagePack: {0} byte(s) after the value")]
+    TrailingBytes(usize),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Whether the `Content-Type` is one of the names MessagePack goes by
+pub fn is_msgpack(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        essence.as_str(),
+        "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack"
+    )
+}
+
+/// Write the marker for a length: in the marker itself below `fix_max`, else in 1, 2 or 4 bytes
+/// after one of `markers`, the 1 byte form being absent from arrays and maps
+fn write_len(out: &mut Vec<u8>, len: usize, fix: u8, fix_max: usize, markers: [Option<u8>; 3]) {
+    match (len, markers) {
+        (len, _) if len <= fix_max => out.push(fix | len as u8),
+        (len, [Some(marker), _, _]) if len <= u8::MAX as usize => out.extend([marker, len as u8]),
+        (len, [_, Some(marker), _]) if len <= u16::MAX as usize => {
+            out.push(marker);
+            out.extend((len as u16).to_be_bytes());
+        }
+        (len, [_, _, marker]) => {
+            out.push(marker.expect("every kind of value has a 4 bytes length"));
+            out.extend((len as u32).to_be_bytes());
+        }
+    }
+}
+
+fn encode_into(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) if n < 0x80 => out.push(n as u8),
+            (Some(n), _) if n <= u8::MAX as u64 => out.extend([0xcc, n as u8]),
+            (Some(n), _) if n <= u16::MAX as u64 => {
+                out.push(0xcd);
+                out.extend((n as u16).to_be_bytes());
+            }
+            (Some(n), _) if n <= u32::MAX as u64 => {
+                out.push(0xce);
+                out.extend((n as u32).to_be_bytes());
+            }
+            (Some(n), _) => {
+                out.push(0xcf);
+                out.extend(n.to_be_bytes());
+            }
+            (None, Some(n)) if n >= -32 => out.push(n as u8),
+            (None, Some(n)) if n >= i8::MIN as i64 => out.extend([0xd0, n as u8]),
+            (None, Some(n)) if n >= i16::MIN as i64 => {
+                out.push(0xd1);
+                out.extend((n as i16).to_be_bytes());
+            }
+            (None, Some(n)) if n >= i32::MIN as i64 => {
+                out.push(0xd2);
+                out.extend((n as i32).to_be_bytes());
+            }
+            (None, Some(n)) => {
+                out.push(0xd3);
+                out.extend(n.to_be_bytes());
+            }
+            (None, None) => {
+                out.push(0xcb);
+                out.extend(n.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        },
+        Value::String(s) => {
+            write_len(out, s.len(), 0xa0, 31, [Some(0xd9), Some(0xda), Some(0xdb)]);
+            out.extend(s.as_bytes());
+        }
+        Value::Array(values) => {
+            write_len(out, values.len(), 0x90, 15, [None, Some(0xdc), Some(0xdd)]);
+            for value in values {
+                encode_into(out, value);
+            }
+        }
+        Value::Object(object) => {
+            write_len(out, object.len(), 0x80, 15, [None, Some(0xde), Some(0xdf)]);
+            for (key, value) in object {
+                encode_into(out, &Value::String(key.clone()));
+                encode_into(out, value);
+            }
+        }
+    }
+}
+
+/// The MessagePack encoding of the JSON value
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(&mut out, value);
+    out
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or(Error::Truncated)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(Error::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// The big-endian unsigned integer of `len` bytes
+    fn uint(&mut self, len: usize) -> Result<u64> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |n, &byte| (n << 8) | byte as u64))
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value> {
+        Ok(Value::String(
+            String::from_utf8_lossy(self.take(len)?).into_owned(),
+        ))
+    }
+
+    fn binary(&mut self, len: usize) -> Result<Value> {
+        Ok(Value::String(base64::encode(self.take(len)?)))
+    }
+
+    fn extension(&mut self, len: usize) -> Result<Value> {
+        let kind = self.take(1)?[0] as i8;
+        let data = base64::encode(self.take(len)?);
+        Ok(serde_json::json!({ "type": kind, "data": data }))
+    }
+
+    /// Fail early on lengths the remaining bytes can't hold, as each value takes a byte at least
+    fn check_count(&self, count: usize) -> Result<()> {
+        if count > self.bytes.len() - self.pos {
+            return Err(Error::Truncated);
+        }
+        Ok(())
+    }
+
+    fn array(&mut self, len: usize) -> Result<Value> {
+        self.check_count(len)?;
+        (0..len)
+            .map(|_| self.value())
+            .collect::<Result<_>>()
+            .map(Value::Array)
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value> {
+        self.check_count(len)?;
+        let mut object = Map::new();
+        for _ in 0..len {
+            let key = match self.value()? {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            object.insert(key, self.value()?);
+        }
+        Ok(Value::Object(object))
+    }
+
+    fn float(n: f64) -> Value {
+        Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let marker = self.take(1)?[0];
+        Ok(match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize)?,
+            0x90..=0x9f => self.array((marker & 0x0f) as usize)?,
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize)?,
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let n = self.uint(1 << (marker - 0xc4))?;
+                self.binary(n as usize)?
+            }
+            0xc7..=0xc9 => {
+                let n = self.uint(1 << (marker - 0xc7))?;
+                self.extension(n as usize)?
+            }
+            0xca => Self::float(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => Self::float(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => Value::from(self.uint(1 << (marker - 0xcc))?),
+            0xd0 => Value::from(self.uint(1)? as u8 as i8),
+            0xd1 => Value::from(self.uint(2)? as u16 as i16),
+            0xd2 => Value::from(self.uint(4)? as u32 as i32),
+            0xd3 => Value::from(self.uint(8)? as i64),
+            0xd4..=0xd8 => self.extension(1 << (marker - 0xd4))?,
+            0xd9..=0xdb => {
+                let n = self.uint(1 << (marker - 0xd9))?;
+                self.string(n as usize)?
+            }
+            0xdc | 0xdd => {
+                let n = self.uint(2 << (marker - 0xdc))?;
+                self.array(n as usize)?
+            }
+            0xde | 0xdf => {
+                let n = self.uint(2 << (marker - 0xde))?;
+                self.map(n as usize)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            marker => return Err(Error::InvalidMarker(marker)),
+        })
+    }
+}
+
+/// The JSON value of the MessagePack data, which must hold a single value
+pub fn decode(bytes: &[u8]) -> Result<Value> {
+    let mut decoder = Decoder { bytes, pos: 0 };
+    let value = decoder.value()?;
+    match bytes.len() - decoder.pos {
+        0 => Ok(value),
+        trailing => Err(Error::TrailingBytes(trailing)),
+    }
+}
+
+/// The response with its MessagePack body decoded to JSON, pretty-printed if `pretty`, or `None`
+/// if the body is not MessagePack
+pub fn to_json(response: &Response, pretty: bool) -> Result<Option<Response>> {
+    if !response
+        .header(CONTENT_TYPE.as_str())
+        .is_some_and(is_msgpack)
+    {
+        return Ok(None);
+    }
+    let value = decode(&response.body)?;
+    let json = if pretty {
+        serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string())
+    } else {
+        value.to_string()
+    };
+    let mut decoded = response.clone();
+    decoded.body = json.into_bytes();
+    decoded
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(Some(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn values_are_encoded_and_decoded_back() {
+        let value = json!({
+            "id": 1, "big": 70000, "neg": -5, "min": -200, "pi": 1.5, "ok": true, "none": null,
+            "name": "a".repeat(40), "tags": ["x", 300, -40000]
+        });
+        let encoded = super::encode(&value);
+        assert_eq!(
+            super::encode(&json!({"a": [1, -1]})),
+            b"\x81\xa1a\x92\x01\xff"
+        );
+        assert_eq!(super::decode(&encoded).unwrap(), value);
+        assert!(super::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(super::decode(b"\x01\x02").is_err());
+    }
+    #[test]
+    fn binary_and_extensions_become_strings() {
+        assert_eq!(
+            super::decode(b"\x82\xc4\x02hi\x01\xa1e\xd4\x05\x07").unwrap(),
+            json!({"aGk=": 1, "e": {"type": 5, "data": "Bw=="}})
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! `multipart/form-data` bodies (https://www.rfc-editor.org/rfc/rfc7578) built from `-F` fields.
+use std::{fs, path::PathBuf, str::FromStr};
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::media_type;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid form field, expected `name=value` or `name@path`: {0}")]
+    Syntax(String),
+    #[error("Unknown form field option `{0}`, expected `type` or `filename`")]
+    UnknownOption(String),
+    #[error("Can't read {}: {source}", .path.display())]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// A field of the form. Files are read when the field is parsed.
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// The file the data was read from
+    pub path: Option<PathBuf>,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+impl FromStr for Part {
+    type Err = Error;
+
+    /// Parse `name=value`, `name@path` or curl's `name=@path`, optionally followed by
+    /// `;type=mime/type` and `;filename=name`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, options) = match s.split_once(';') {
+            Some((field, options)) => (field, Some(options)),
+            None => (s, None),
+        };
+        let separator = field
+            .find(['=', '@'])
+            .filter(|i| *i > 0)
+            .ok_or_else(|| Error::Syntax(s.to_string()))?;
+        let name = field[..separator].to_string();
+        let value = &field[separator + 1..];
+        let file = if &field[separator..=separator] == "@" {
+            Some(value)
+        } else {
+            value.strip_prefix('@')
+        };
+        let mut part = match file {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                let data = fs::read(&path).map_err(|source| Error::ReadFile {
+                    path: path.clone(),
+                    source,
+                })?;
+                Part {
+                    name,
+                    data,
+                    filename: path.file_name().map(|f| f.to_string_lossy().into_owned()),
+                    path: Some(path),
+                    content_type: None,
+                }
+            }
+            None => Part {
+                name,
+                data: value.as_bytes().to_vec(),
+                path: None,
+                filename: None,
+                content_type: None,
+            },
+        };
+        for option in options.into_iter().flat_map(|o| o.split(';')) {
+            match option.trim().split_once('=') {
+                Some(("type", value)) => part.content_type = Some(value.to_string()),
+                Some(("filename", value)) => part.filename = Some(value.to_string()),
+                _ => return Err(Error::UnknownOption(option.to_string())),
+            }
+        }
+        Ok(part)
+    }
+}
+
+impl Part {
+    /// The field as written for `-F`, for curl as well as rq
+    pub fn to_arg(&self) -> String {
+        let mut arg = match &self.path {
+            Some(path) => format!("{}=@{}", self.name, path.display()),
+            None => format!("{}={}", self.name, String::from_utf8_lossy(&self.data)),
+        };
+        if let Some(content_type) = &self.content_type {
+            arg.push_str(&format!(";type={}", content_type));
+        }
+        let default_filename = self
+            .path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|f| f.to_string_lossy().into_owned());
+        if let (Some(filename), false) = (&self.filename, self.filename == default_filename) {
+            arg.push_str(&format!(";filename={}", filename));
+        }
+        arg
+    }
+}
+
+/// A random boundary, unlikely to appear in the data
+pub fn boundary() -> String {
+    let mut bytes = [0; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("the system random number generator failed");
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("rq-boundary-{}", hex)
+}
+
+/// Quote a parameter value of `Content-Disposition`
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Encode the parts, separated by `boundary`
+pub fn encode(parts: &[Part], boundary: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let mut disposition = format!("Content-Disposition: form-data; name={}", quote(&part.name));
+        if let Some(filename) = &part.filename {
+            disposition.push_str(&format!("; filename={}", quote(filename)));
+        }
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(b"\r\n");
+        let content_type = part.content_type.clone().or_else(|| match &part.path {
+            Some(path) => Some(media_type::guess(path, &part.data)),
+            None => part
+                .filename
+                .as_ref()
+                .map(|_| "application/octet-stream".to_string()),
+        });
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(&part.data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Part;
+
+    #[test]
+    fn fields_and_files_are_encoded() {
+        let path = std::env::temp_dir().join("rq-multipart-test.png");
+        fs::write(&path, b"PNG").unwrap();
+        let parts: Vec<Part> = vec![
+            "name=Ann".parse().unwrap(),
+            format!("avatar@{};type=image/png;filename=me.png", path.display())
+                .parse()
+                .unwrap(),
+        ];
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            String::from_utf8(super::encode(&parts, "XX")).unwrap(),
+            "--XX\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            Ann\r\n\
+            --XX\r\n\
+            Content-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\
+            Content-Type: image/png\r\n\
+            \r\n\
+            PNG\r\n\
+            --XX--\r\n"
+        );
+    }
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert!("novalue".parse::<Part>().is_err());
+        assert!("=x".parse::<Part>().is_err());
+        assert!("a=b;size=3".parse::<Part>().is_err());
+        assert!("a@/does/not/exist".parse::<Part>().is_err());
+    }
+}
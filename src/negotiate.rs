@@ -0,0 +1,307 @@
+//! `--auth-type negotiate`: SPNEGO authentication (https://www.rfc-editor.org/rfc/rfc4559) with
+//! the Kerberos tickets of the user, as obtained with `kinit`, for the intranet services that
+//! accept nothing else.
+//!
+//! The token is made by the system GSSAPI library, loaded when needed so that rq runs where it is
+//! not installed. It is sent with the first request, the token the server may answer with for
+//! mutual authentication is not checked.
+use crate::Request;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Negotiate authentication needs the GSSAPI library (libgssapi_krb5): {0}")]
+    Library(String),
+    #[error("Negotiate authentication is not supported on this platform")]
+    Unsupported,
+    #[error("The URL `{0}` has no host to authenticate to")]
+    NoHost(String),
+    #[error("Can't get a Kerberos ticket for HTTP@{host}: {message}. Run `kinit` first?")]
+    Gssapi { host: String, message: String },
+}
+
+#[cfg(unix)]
+mod gssapi {
+    use std::{
+        ffi::{c_void, CString},
+        ptr,
+    };
+
+    type Status = u32;
+    type Name = *mut c_void;
+    type Context = *mut c_void;
+
+    #[repr(C)]
+    struct Buffer {
+        length: usize,
+        value: *mut c_void,
+    }
+
+    impl Buffer {
+        fn empty() -> Self {
+            Buffer {
+                length: 0,
+                value: ptr::null_mut(),
+            }
+        }
+    }
+
+    #[repr(C)]
+    struct Oid {
+        length: u32,
+        elements: *const c_void,
+    }
+
+    /// GSS_C_NT_HOSTBASED_SERVICE, 1.2.840.113554.1.2.1.4: a name like `HTTP@host`
+    const HOSTBASED_SERVICE: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x04";
+    /// SPNEGO, 1.3.6.1.5.5.2
+    const SPNEGO: &[u8] = b"\x2b\x06\x01\x05\x05\x02";
+    const MUTUAL_FLAG: u32 = 2;
+    const GSS_CODE: i32 = 1;
+    const MECH_CODE: i32 = 2;
+
+    fn oid(bytes: &'static [u8]) -> Oid {
+        Oid {
+            length: bytes.len() as u32,
+            elements: bytes.as_ptr() as *const c_void,
+        }
+    }
+
+    fn failed(major: Status) -> bool {
+        major & 0xffff_0000 != 0
+    }
+
+    type ImportName =
+        unsafe extern "C" fn(*mut Status, *mut Buffer, *const Oid, *mut Name) -> Status;
+    type InitSecContext = unsafe extern "C" fn(
+        *mut Status,
+        *mut c_void,
+        *mut Context,
+        Name,
+        *const Oid,
+        u32,
+        u32,
+        *mut c_void,
+        *mut Buffer,
+        *mut *mut Oid,
+        *mut Buffer,
+        *mut u32,
+        *mut u32,
+    ) -> Status;
+    type ReleaseBuffer = unsafe extern "C" fn(*mut Status, *mut Buffer) -> Status;
+    type ReleaseName = unsafe extern "C" fn(*mut Status, *mut Name) -> Status;
+    type DeleteSecContext = unsafe extern "C" fn(*mut Status, *mut Context, *mut Buffer) -> Status;
+    type DisplayStatus =
+        unsafe extern "C" fn(*mut Status, Status, i32, *mut Oid, *mut u32, *mut Buffer) -> Status;
+
+    const LIBRARIES: &[&str] = &[
+        "libgssapi_krb5.so.2",
+        "libgssapi_krb5.so",
+        "libgssapi_krb5.dylib",
+    ];
+
+    pub struct Library {
+        import_name: ImportName,
+        init_sec_context: InitSecContext,
+        release_buffer: ReleaseBuffer,
+        release_name: ReleaseName,
+        delete_sec_context: DeleteSecContext,
+        display_status: DisplayStatus,
+    }
+
+    /// The function `name` of the library `handle`.
+    ///
+    /// # Safety
+    ///
+    /// `F` must be a function pointer type matching the signature of the function.
+    unsafe fn symbol<F: Copy>(handle: *mut c_void, name: &str) -> Result<F, String> {
+        let c_name = CString::new(name).expect("no NUL in symbol names");
+        // `handle` was returned by `dlopen` and is never closed
+        let address = libc::dlsym(handle, c_name.as_ptr());
+        if address.is_null() {
+            return Err(format!("missing function {}", name));
+        }
+        Ok(std::mem::transmute_copy::<*mut c_void, F>(&address))
+    }
+
+    impl Library {
+        pub fn load() -> Result<Self, String> {
+            let handle = LIBRARIES
+                .iter()
+                .map(|name| {
+                    let name = CString::new(*name).expect("no NUL in library names");
+                    // SAFETY: loading the GSSAPI library runs no initialization we depend on
+                    unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) }
+                })
+                .find(|handle| !handle.is_null())
+                .ok_or_else(|| format!("none of {} found", LIBRARIES.join(", ")))?;
+            // SAFETY: the types are the signatures of RFC 2744
+            unsafe {
+                Ok(Library {
+                    import_name: symbol(handle, "gss_import_name")?,
+                    init_sec_context: symbol(handle, "gss_init_sec_context")?,
+                    release_buffer: symbol(handle, "gss_release_buffer")?,
+                    release_name: symbol(handle, "gss_release_name")?,
+                    delete_sec_context: symbol(handle, "gss_delete_sec_context")?,
+                    display_status: symbol(handle, "gss_display_status")?,
+                })
+            }
+        }
+
+        /// The messages of a status code, of the GSSAPI or of the mechanism
+        fn status_messages(&self, status: Status, kind: i32, messages: &mut Vec<String>) {
+            let mut context = 0;
+            loop {
+                let (mut minor, mut buffer) = (0, Buffer::empty());
+                // SAFETY: the buffer is released after its content is copied
+                unsafe {
+                    let major = (self.display_status)(
+                        &mut minor,
+                        status,
+                        kind,
+                        ptr::null_mut(),
+                        &mut context,
+                        &mut buffer,
+                    );
+                    if failed(major) {
+                        return;
+                    }
+                    if !buffer.value.is_null() {
+                        let bytes =
+                            std::slice::from_raw_parts(buffer.value as *const u8, buffer.length);
+                        messages.push(String::from_utf8_lossy(bytes).trim_end_matches('\0').into());
+                    }
+                    (self.release_buffer)(&mut minor, &mut buffer);
+                }
+                if context == 0 {
+                    return;
+                }
+            }
+        }
+
+        fn error(&self, major: Status, minor: Status) -> String {
+            let mut messages = Vec::new();
+            self.status_messages(major, GSS_CODE, &mut messages);
+            if minor != 0 {
+                self.status_messages(minor, MECH_CODE, &mut messages);
+            }
+            messages.join(": ")
+        }
+
+        /// The initial SPNEGO token for the service `HTTP@host`
+        pub fn initial_token(&self, host: &str) -> Result<Vec<u8>, String> {
+            let service = CString::new(format!("HTTP@{}", host)).map_err(|e| e.to_string())?;
+            let mut service_buffer = Buffer {
+                length: service.as_bytes().len(),
+                value: service.as_ptr() as *mut c_void,
+            };
+            let (mut minor, mut name): (Status, Name) = (0, ptr::null_mut());
+            let name_type = oid(HOSTBASED_SERVICE);
+            // SAFETY: the name is copied from the buffer, which outlives the call
+            let major = unsafe {
+                (self.import_name)(&mut minor, &mut service_buffer, &name_type, &mut name)
+            };
+            if failed(major) {
+                return Err(self.error(major, minor));
+            }
+            let mut context: Context = ptr::null_mut();
+            let mut token = Buffer::empty();
+            let mechanism = oid(SPNEGO);
+            // SAFETY: the name, context and token are released below whatever the result
+            let major = unsafe {
+                (self.init_sec_context)(
+                    &mut minor,
+                    ptr::null_mut(),
+                    &mut context,
+                    name,
+                    &mechanism,
+                    MUTUAL_FLAG,
+                    0,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    &mut token,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                )
+            };
+            let result = if failed(major) {
+                Err(self.error(major, minor))
+            } else if token.value.is_null() {
+                Err("no token was produced".to_string())
+            } else {
+                // SAFETY: the token holds `length` bytes until it is released
+                Ok(
+                    unsafe { std::slice::from_raw_parts(token.value as *const u8, token.length) }
+                        .to_vec(),
+                )
+            };
+            let mut ignored = 0;
+            // SAFETY: each of them was allocated by the library, or is null
+            unsafe {
+                (self.release_buffer)(&mut ignored, &mut token);
+                if !context.is_null() {
+                    (self.delete_sec_context)(&mut ignored, &mut context, ptr::null_mut());
+                }
+                (self.release_name)(&mut ignored, &mut name);
+            }
+            result
+        }
+    }
+}
+
+/// The value of the `Authorization` header for `host`, from the Kerberos tickets of the user
+#[cfg(unix)]
+pub fn header_value(host: &str) -> Result<String, Error> {
+    let library = gssapi::Library::load().map_err(Error::Library)?;
+    let token = library
+        .initial_token(host)
+        .map_err(|message| Error::Gssapi {
+            host: host.to_string(),
+            message,
+        })?;
+    Ok(format!("Negotiate {}", base64::encode(token)))
+}
+
+#[cfg(not(unix))]
+pub fn header_value(_host: &str) -> Result<String, Error> {
+    Err(Error::Unsupported)
+}
+
+/// Authenticate the request to the host of its URL, unless it already has credentials
+pub fn authorize(request: &mut Request) -> Result<(), Error> {
+    let authorization = reqwest::header::AUTHORIZATION.as_str();
+    if request
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(authorization))
+    {
+        return Ok(());
+    }
+    let host = reqwest::Url::parse(&request.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .ok_or_else(|| Error::NoHost(request.url.clone()))?;
+    request.set_header(authorization, header_value(&host)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{HttpMethod, Request};
+
+    #[test]
+    fn given_credentials_are_kept() {
+        let mut request =
+            Request::new(HttpMethod::Get, "http://intranet/").header("authorization", "Basic x");
+        super::authorize(&mut request).unwrap();
+        assert_eq!(
+            request.headers,
+            [("authorization".into(), "Basic x".into())]
+        );
+        let mut request = Request::new(HttpMethod::Get, "unix:/run/socket");
+        assert!(matches!(
+            super::authorize(&mut request),
+            Err(super::Error::NoHost(_))
+        ));
+    }
+}
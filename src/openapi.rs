@@ -0,0 +1,421 @@
+//! Building requests from the operations described in an OpenAPI 3 specification.
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Map, Value};
+
+use crate::{cli::normalize_url, http::ContentType, multipart, request::Request, yaml};
+
+/// Characters kept as is in path segments and query values: the unreserved characters of
+/// https://datatracker.ietf.org/doc/html/rfc3986#section-2.3
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Nesting limit when generating a body skeleton, which stops recursive schemas
+const MAX_SCHEMA_DEPTH: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid JSON in the specification: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid YAML in the specification: {0}")]
+    Yaml(#[from] yaml::Error),
+    #[error("Operation `{0}` not found in the specification")]
+    OperationNotFound(String),
+    #[error("Operation `{0}` uses a method rq does not support: {1}")]
+    UnsupportedMethod(String, String),
+    #[error("No server URL in the specification, use --server to provide one")]
+    NoServer,
+    #[error("Missing values for required parameters: {}", .0.join(", "))]
+    MissingParams(Vec<String>),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A parameter of an operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    /// Where the parameter goes: `path`, `query`, `header` or `cookie`
+    pub location: String,
+    pub required: bool,
+    pub description: Option<String>,
+}
+
+pub struct Spec {
+    root: Value,
+}
+
+impl Spec {
+    /// Read a specification written in JSON or YAML
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let is_json = path.extension().is_some_and(|ext| ext == "json")
+            || content.trim_start().starts_with('{');
+        let root = if is_json {
+            serde_json::from_str(&content)?
+        } else {
+            yaml::parse(&content)?
+        };
+        Ok(Spec { root })
+    }
+
+    /// Follow `$ref` pointing inside the document, e.g. `#/components/schemas/User`
+    fn resolve<'a>(&'a self, value: &'a Value) -> &'a Value {
+        let mut value = value;
+        // Bound the number of hops so a cycle of references can't loop forever
+        for _ in 0..MAX_SCHEMA_DEPTH {
+            match value["$ref"].as_str().and_then(|r| r.strip_prefix('#')) {
+                Some(pointer) => match self.root.pointer(pointer) {
+                    Some(target) => value = target,
+                    None => break,
+                },
+                None => break,
+            }
+        }
+        value
+    }
+
+    /// Find the path, method and definition of an operation
+    fn find_operation(&self, operation_id: &str) -> Option<(&str, &str, &Value, &Value)> {
+        const METHODS: &[&str] = &[
+            "get", "put", "post", "delete", "options", "head", "patch", "trace",
+        ];
+        let paths = self.root["paths"].as_object()?;
+        paths.iter().find_map(|(path, item)| {
+            let item = self.resolve(item);
+            METHODS.iter().find_map(|method| {
+                let op = &item[*method];
+                if op["operationId"].as_str() == Some(operation_id) {
+                    Some((path.as_str(), *method, op, item))
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    /// The parameters of the operation, those of the operation overriding those of the path
+    fn parameters(&self, path_item: &Value, op: &Value) -> Vec<Param> {
+        let mut params: Vec<Param> = Vec::new();
+        let defined = path_item["parameters"]
+            .as_array()
+            .into_iter()
+            .chain(op["parameters"].as_array())
+            .flatten();
+        for param in defined {
+            let param = self.resolve(param);
+            let (name, location) = match (param["name"].as_str(), param["in"].as_str()) {
+                (Some(name), Some(location)) => (name.to_string(), location.to_string()),
+                _ => continue,
+            };
+            params.retain(|p| !(p.name == name && p.location == location));
+            params.push(Param {
+                required: location == "path" || param["required"].as_bool().unwrap_or(false),
+                description: param["description"].as_str().map(str::to_string),
+                name,
+                location,
+            });
+        }
+        params
+    }
+
+    /// Generate a JSON value matching the schema, using the examples and defaults it provides
+    pub fn skeleton(&self, schema: &Value, depth: usize) -> Value {
+        let schema = self.resolve(schema);
+        if depth > MAX_SCHEMA_DEPTH {
+            return Value::Null;
+        }
+        for key in &["example", "default"] {
+            if let Some(value) = schema.get(*key) {
+                return value.clone();
+            }
+        }
+        if let Some(first) = schema["enum"].as_array().and_then(|e| e.first()) {
+            return first.clone();
+        }
+        if let Some(all_of) = schema["allOf"].as_array() {
+            let mut merged = Map::new();
+            for part in all_of {
+                if let Value::Object(map) = self.skeleton(part, depth + 1) {
+                    merged.extend(map);
+                }
+            }
+            return Value::Object(merged);
+        }
+        if let Some(first) = ["oneOf", "anyOf"]
+            .iter()
+            .find_map(|key| schema[*key].as_array().and_then(|a| a.first()))
+        {
+            return self.skeleton(first, depth + 1);
+        }
+        let has_properties = schema.get("properties").is_some();
+        match schema["type"].as_str() {
+            Some("object") | None if has_properties => {
+                let properties = schema["properties"].as_object().into_iter().flatten();
+                Value::Object(
+                    properties
+                        .map(|(name, prop)| (name.clone(), self.skeleton(prop, depth + 1)))
+                        .collect(),
+                )
+            }
+            Some("object") => json!({}),
+            Some("array") => json!([self.skeleton(&schema["items"], depth + 1)]),
+            Some("string") => json!(""),
+            Some("integer") | Some("number") => json!(0),
+            Some("boolean") => json!(false),
+            _ => Value::Null,
+        }
+    }
+
+    /// Build the request for an operation.
+    ///
+    /// Parameter values are taken from `values`. For required parameters without a value,
+    /// `prompt` is called, and the parameters for which it returns `None` are reported as
+    /// missing. `body` replaces the skeleton generated from the request body schema.
+    pub fn build_request<F>(
+        &self,
+        operation_id: &str,
+        values: &HashMap<String, String>,
+        server: Option<&str>,
+        body: Option<String>,
+        mut prompt: F,
+    ) -> Result<Request>
+    where
+        F: FnMut(&Param) -> Option<String>,
+    {
+        let (path, method, op, path_item) = self
+            .find_operation(operation_id)
+            .ok_or_else(|| Error::OperationNotFound(operation_id.to_string()))?;
+        let method = method
+            .parse()
+            .map_err(|_| Error::UnsupportedMethod(operation_id.to_string(), method.to_string()))?;
+        let server = match server {
+            Some(server) => server.to_string(),
+            None => self.root["servers"][0]["url"]
+                .as_str()
+                .ok_or(Error::NoServer)?
+                .to_string(),
+        };
+        let mut url_path = path.to_string();
+        let mut query = Vec::new();
+        let mut headers = Vec::new();
+        let mut cookies = Vec::new();
+        let mut missing = Vec::new();
+        for param in self.parameters(path_item, op) {
+            let value = match values.get(&param.name) {
+                Some(value) => value.clone(),
+                None if param.required => match prompt(&param) {
+                    Some(value) => value,
+                    None => {
+                        missing.push(param.name.clone());
+                        continue;
+                    }
+                },
+                None => continue,
+            };
+            let encoded = utf8_percent_encode(&value, COMPONENT).to_string();
+            match param.location.as_str() {
+                "path" => url_path = url_path.replace(&format!("{{{}}}", param.name), &encoded),
+                "query" => query.push(format!("{}={}", param.name, encoded)),
+                "header" => headers.push((param.name, value)),
+                "cookie" => cookies.push(format!("{}={}", param.name, value)),
+                _ => {}
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::MissingParams(missing));
+        }
+        if !cookies.is_empty() {
+            headers.push(("Cookie".to_string(), cookies.join("; ")));
+        }
+        let mut url = format!("{}{}", server.trim_end_matches('/'), url_path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        normalize_url(&mut url);
+        let body = match body {
+            Some(body) => Some(body),
+            None => {
+                let content = &self.resolve(&op["requestBody"])["content"];
+                let schema = &content[ContentType::Json.to_string()]["schema"];
+                if schema.is_null() {
+                    None
+                } else {
+                    headers.push((CONTENT_TYPE.to_string(), ContentType::Json.to_string()));
+                    Some(serde_json::to_string_pretty(&self.skeleton(schema, 0))?)
+                }
+            }
+        };
+        Ok(Request {
+            method,
+            url,
+            headers,
+            body,
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Default::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Default::default(),
+        })
+    }
+}
+
+/// Ask for the value of a parameter on the terminal. Returns `None` if stdin is not a terminal or
+/// the answer is empty.
+pub fn prompt(param: &Param) -> Option<String> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return None;
+    }
+    match &param.description {
+        Some(description) => eprint!("{} ({}, {}): ", param.name, param.location, description),
+        None => eprint!("{} ({}): ", param.name, param.location),
+    }
+    io::stderr().flush().ok()?;
+    let mut line = String::new();
+    stdin.read_line(&mut line).ok()?;
+    let value = line.trim_end_matches(&['\r', '\n'][..]);
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    fn spec() -> super::Spec {
+        let root = crate::yaml::parse(
+            r#"
+openapi: 3.0.0
+servers:
+  - url: https://api.example.com/v1/
+paths:
+  /users/{id}:
+    parameters:
+      - $ref: '#/components/parameters/Id'
+    put:
+      operationId: updateUser
+      parameters:
+        - name: notify
+          in: query
+        - name: X-Request-Id
+          in: header
+          required: true
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: '#/components/schemas/User'
+components:
+  parameters:
+    Id:
+      name: id
+      in: path
+      required: true
+  schemas:
+    User:
+      type: object
+      properties:
+        name:
+          type: string
+        age:
+          type: integer
+        role:
+          enum: [admin, user]
+        tags:
+          type: array
+          items:
+            type: string
+        manager:
+          $ref: '#/components/schemas/User'
+"#,
+        )
+        .unwrap();
+        super::Spec { root }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn request_is_built_from_operation() {
+        let req = spec()
+            .build_request(
+                "updateUser",
+                &values(&[("id", "a b"), ("notify", "1"), ("X-Request-Id", "r")]),
+                None,
+                Some("{}".to_string()),
+                |_| None,
+            )
+            .unwrap();
+        assert_eq!(req.url, "https://api.example.com/v1/users/a%20b?notify=1");
+        assert_eq!(
+            req.headers,
+            vec![("X-Request-Id".to_string(), "r".to_string())]
+        );
+        assert_eq!(req.body.as_deref(), Some("{}"));
+    }
+    #[test]
+    fn missing_required_params_are_prompted_then_reported() {
+        let mut prompted = Vec::new();
+        let result = spec().build_request("updateUser", &values(&[]), None, None, |p| {
+            prompted.push(p.name.clone());
+            if p.name == "id" {
+                Some("1".to_string())
+            } else {
+                None
+            }
+        });
+        assert_eq!(prompted, vec!["id", "X-Request-Id"]);
+        match result {
+            Err(super::Error::MissingParams(names)) => assert_eq!(names, vec!["X-Request-Id"]),
+            _ => panic!("expected missing parameters"),
+        }
+    }
+    #[test]
+    fn body_skeleton_follows_schema() {
+        let spec = spec();
+        let skeleton = spec.skeleton(&json!({"$ref": "#/components/schemas/User"}), 0);
+        assert_eq!(skeleton["name"], "");
+        assert_eq!(skeleton["age"], 0);
+        assert_eq!(skeleton["role"], "admin");
+        assert_eq!(skeleton["tags"], json!([""]));
+        // Recursive schemas stop at the depth limit
+        assert_eq!(skeleton["manager"]["manager"]["name"], "");
+    }
+    #[test]
+    fn unknown_operation_is_reported() {
+        assert!(spec()
+            .build_request("nope", &HashMap::new(), None, None, |_| None)
+            .is_err());
+    }
+}
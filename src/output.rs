@@ -0,0 +1,248 @@
+//! Printing the parts of an exchange. The response body goes to stdout and everything else to
+//! stderr, so the output can be piped without the metadata getting in the way.
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use reqwest::header::CONTENT_TYPE;
+
+use crate::{
+    markup,
+    response::{Charset, Response},
+    wire,
+};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Unknown part `{0}`, expected H (request headers), B (request body), \
+    h (response headers) or b (response body)"
+)]
+pub struct Error(char);
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Unknown format `{0}`, expected text or ndjson")]
+pub struct UnknownFormat(String);
+
+/// How the responses are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The bodies as received, or reformatted for display
+    Text,
+    /// One JSON object per request with its URL, status, timing and body
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// How much is printed, from `-q` to `-v`. Errors are always printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// `-q`: not even the response body
+    Quiet,
+    /// `-s`: the response body, without progress messages and warnings
+    Silent,
+    Normal,
+    /// `-v`: the headers and bodies of both the request and the response
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set the verbosity for the whole process
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Silent,
+        2 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Print a progress message or a warning to stderr, unless `-s` or `-q` is given
+pub fn note(message: impl Display) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{}", message);
+    }
+}
+
+/// The parts of the exchange to print, selected with letters like httpie's `--print`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Print {
+    pub request_headers: bool,
+    pub request_body: bool,
+    pub response_headers: bool,
+    pub response_body: bool,
+}
+
+impl Print {
+    /// Everything, for `-v`
+    pub fn all() -> Self {
+        Print {
+            request_headers: true,
+            request_body: true,
+            response_headers: true,
+            response_body: true,
+        }
+    }
+}
+
+impl FromStr for Print {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut print = Print::default();
+        for c in s.chars() {
+            match c {
+                'H' => print.request_headers = true,
+                'B' => print.request_body = true,
+                'h' => print.response_headers = true,
+                'b' => print.response_body = true,
+                c => return Err(Error(c)),
+            }
+        }
+        Ok(print)
+    }
+}
+
+/// The wire format uses CRLF, which shows up as `^M` in some pagers
+fn display(head: &str) -> String {
+    head.replace("\r\n", "\n")
+}
+
+/// Print the request headers and body to stderr, if selected
+pub fn print_request(request: &reqwest::Request, print: Print, accept_encoding: bool) {
+    if print.request_headers {
+        eprint!(
+            "{}",
+            display(&wire::format_request_head(request, accept_encoding))
+        );
+    }
+    if print.request_body {
+        if let Some(body) = request.body().and_then(|b| b.as_bytes()) {
+            eprintln!("{}\n", String::from_utf8_lossy(body));
+        }
+    }
+}
+
+/// Print the status line and headers of the response to stderr, if selected
+pub fn print_response_head(response: &Response, print: Print) {
+    if print.response_headers {
+        eprint!("{}", display(&wire::format_response_head(response)));
+    }
+}
+
+/// The response body as text ending with a newline, with XML and HTML indented when `pretty`.
+/// The body is decoded with `charset`, or else the one of the response.
+pub fn format_body(
+    response: &Response,
+    charset: Option<Charset>,
+    pretty: bool,
+    color: bool,
+) -> String {
+    let body = response.text(charset);
+    let markup = response
+        .header(CONTENT_TYPE.as_str())
+        .and_then(markup::kind_of)
+        .filter(|_| pretty);
+    match markup {
+        Some(kind) => markup::format(&body, kind, color),
+        None => format!("{}\n", body),
+    }
+}
+
+/// The bytes as offset, hexadecimal and ASCII columns of 16 bytes, in the format of `xxd`
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = line
+            .chunks(2)
+            .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
+            .collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "{:08x}: {:<39}  {}\n",
+            i * 16,
+            hex.join(" "),
+            ascii
+        ));
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use super::{Format, Print};
+    use crate::response::Response;
+
+    #[test]
+    fn parts_are_parsed_from_letters() {
+        let print: Print = "Hb".parse().unwrap();
+        assert!(print.request_headers && print.response_body);
+        assert!(!print.request_body && !print.response_headers);
+        assert!("hx".parse::<Print>().is_err());
+    }
+    #[test]
+    fn hexdump_matches_xxd() {
+        assert_eq!(
+            super::hexdump(b"{\"a\":1}\n\x00\xffABCDEFGHIJ"),
+            "00000000: 7b22 6122 3a31 7d0a 00ff 4142 4344 4546  {\"a\":1}...ABCDEF\n\
+            00000010: 4748 494a                                GHIJ\n"
+        );
+    }
+    #[test]
+    fn formats_are_parsed() {
+        assert_eq!("ndjson".parse::<Format>().unwrap(), Format::Ndjson);
+        assert!("json".parse::<Format>().is_err());
+    }
+    #[test]
+    fn markup_body_is_indented_when_pretty() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/xml".parse().unwrap());
+        let response = Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers,
+            body: b"<a><b/></a>".to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        assert_eq!(
+            super::format_body(&response, None, false, false),
+            "<a><b/></a>\n"
+        );
+        assert_ne!(
+            super::format_body(&response, None, true, false),
+            "<a><b/></a>\n"
+        );
+    }
+}
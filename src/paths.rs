@@ -0,0 +1,48 @@
+//! Locations of the files used by rq, following the XDG base directory specification.
+use std::{env, path::PathBuf};
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(fallback),
+    }
+    .join("rq")
+}
+
+/// Directory for user-written files: `$XDG_CONFIG_HOME/rq` or `~/.config/rq`
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Directory for files written by rq: `$XDG_DATA_HOME/rq` or `~/.local/share/rq`
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Directory for files that can be deleted at any time: `$XDG_CACHE_HOME/rq` or `~/.cache/rq`
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// The config file, holding the aliases
+pub fn config_file() -> PathBuf {
+    config_dir().join("config")
+}
+
+/// File recording the executed requests
+pub fn history_file() -> PathBuf {
+    data_dir().join("history.jsonl")
+}
+
+/// Directory containing the request collections
+pub fn collections_dir() -> PathBuf {
+    config_dir().join("collections")
+}
+
+/// Directory containing the sessions saved with `--session`
+pub fn sessions_dir() -> PathBuf {
+    data_dir().join("sessions")
+}
@@ -0,0 +1,103 @@
+//! `--pipe`: stream the response body into a command, e.g. `--pipe 'jq .items'`. The command is
+//! split into words and run without a shell, so its arguments need no extra quoting, and rq exits
+//! with its exit status.
+use std::{
+    fmt, io,
+    io::Write,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use crate::curl;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The --pipe command is empty")]
+    Empty,
+    #[error("Invalid --pipe command: {0}")]
+    Syntax(String),
+    #[error("Can't run `{0}`: {1}")]
+    Spawn(String, io::Error),
+}
+
+/// The command the response bodies are piped into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipe {
+    program: String,
+    args: Vec<String>,
+}
+
+impl FromStr for Pipe {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = curl::split_shell_words(s)
+            .map_err(|e| Error::Syntax(e.to_string()))?
+            .into_iter();
+        let program = words.next().ok_or(Error::Empty)?;
+        Ok(Pipe {
+            program,
+            args: words.collect(),
+        })
+    }
+}
+
+impl fmt::Display for Pipe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+impl Pipe {
+    /// Run the command with `body` on its stdin, its output going to the terminal, and return its
+    /// exit code
+    pub fn run(&self, body: &[u8]) -> Result<i32, Error> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Spawn(self.to_string(), e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // Commands like `head` stop reading before the end, which is not an error
+            match stdin.write_all(body) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                    return Err(Error::Spawn(self.to_string(), e))
+                }
+                _ => {}
+            }
+        }
+        let status = child
+            .wait()
+            .map_err(|e| Error::Spawn(self.to_string(), e))?;
+        // Killed by a signal, as a shell reports it
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            return Ok(128 + signal);
+        }
+        Ok(status.code().unwrap_or(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipe;
+
+    #[test]
+    fn commands_are_split_like_a_shell() {
+        let pipe: Pipe = "jq '.items[] | .name' -r".parse().unwrap();
+        assert_eq!(pipe.program, "jq");
+        assert_eq!(pipe.args, [".items[] | .name", "-r"]);
+        assert!(matches!("  ".parse::<Pipe>(), Err(super::Error::Empty)));
+    }
+    #[cfg(unix)]
+    #[test]
+    fn exit_code_of_the_command_is_returned() {
+        let pipe: Pipe = "grep -q needle".parse().unwrap();
+        assert_eq!(pipe.run(b"hay\nneedle\n").unwrap(), 0);
+        assert_eq!(pipe.run(b"hay\n").unwrap(), 1);
+    }
+}
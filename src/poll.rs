@@ -0,0 +1,69 @@
+//! `--until-status` and `--until-jsonpath`: sending a request again until its response meets a
+//! condition, e.g. to wait for an asynchronous job to be done.
+use std::time::Instant;
+
+use reqwest::Client;
+
+use crate::{
+    assert::Assertions, cli::CliArgs, output, request::Request, response::Response,
+    watch::Interval, Error, Result,
+};
+
+/// What the response must be for the polling to stop
+#[derive(Debug, Clone)]
+pub struct Poll {
+    condition: Assertions,
+    interval: Interval,
+    timeout: Option<Interval>,
+}
+
+impl Poll {
+    /// The polling asked on the command line, if a condition is given
+    pub fn from_args(args: &CliArgs) -> Option<Self> {
+        let condition = Assertions {
+            status: args.until_status,
+            json: args.until_jsonpath.clone(),
+            ..Assertions::default()
+        };
+        if condition.is_empty() {
+            return None;
+        }
+        Some(Poll {
+            condition,
+            interval: args.poll_interval,
+            timeout: args.poll_timeout,
+        })
+    }
+
+    /// Send the request until the response meets the condition, and return that response. Errors
+    /// are retried too, the server may not be up yet. `sent` keeps the last request that went out.
+    pub async fn run(
+        &self,
+        client: &Client,
+        request: &Request,
+        sent: &mut Option<reqwest::Request>,
+    ) -> Result<Response> {
+        let start = Instant::now();
+        loop {
+            let outcome = match request.send_recording(client, sent).await {
+                Ok(response) => match self.condition.check(&response).first() {
+                    None => return Ok(response),
+                    Some(failure) => format!("{}: got {}", failure.assertion, failure.actual),
+                },
+                Err(e) => e.to_string(),
+            };
+            tracing::debug!(url = %request.url, %outcome, "condition not met");
+            if let Some(timeout) = self.timeout {
+                if start.elapsed() + self.interval.0 > timeout.0 {
+                    eprintln!("{}: {}", request.url, outcome);
+                    return Err(Error::PollTimeout(timeout));
+                }
+            }
+            output::note(format!(
+                "{}: {}, retrying in {}",
+                request.url, outcome, self.interval
+            ));
+            tokio::time::sleep(self.interval.0).await;
+        }
+    }
+}
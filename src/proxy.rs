@@ -0,0 +1,294 @@
+//! `rq proxy`: a forward HTTP proxy recording the exchanges to a HAR file with `--record`, or
+//! answering from such a file without contacting the servers with `--replay`.
+//!
+//! Only plain HTTP goes through the proxy: HTTPS would be tunnelled with `CONNECT`, whose
+//! encrypted traffic can't be recorded, so `CONNECT` is refused. The bodies are forwarded and
+//! recorded as received, still compressed if they were.
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime},
+};
+
+use hyper::{header::HeaderName, Body, Method, StatusCode};
+use reqwest::{header::HeaderMap, Client};
+
+use crate::{
+    har,
+    request::Request,
+    response::Response,
+    server::{self, Error},
+    TIMEOUT,
+};
+
+/// Headers that only concern one connection, which are not forwarded
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "proxy-connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+fn is_forwarded(name: &HeaderName) -> bool {
+    !HOP_BY_HOP_HEADERS.contains(&name.as_str())
+}
+
+fn plain_response(status: StatusCode, message: String) -> hyper::Response<Body> {
+    hyper::Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(message + "\n"))
+        .unwrap_or_default()
+}
+
+/// The recorded responses by method and URL. A request sent several times gets the responses in
+/// the order they were recorded, the last one being repeated.
+struct Recordings {
+    entries: HashMap<(String, String), Vec<har::Entry>>,
+    served: HashMap<(String, String), usize>,
+}
+
+impl Recordings {
+    fn new(entries: Vec<har::Entry>) -> Self {
+        let mut by_request: HashMap<_, Vec<_>> = HashMap::new();
+        for entry in entries {
+            let key = (
+                entry.request.method.to_uppercase(),
+                entry.request.url.clone(),
+            );
+            by_request.entry(key).or_default().push(entry);
+        }
+        Recordings {
+            entries: by_request,
+            served: HashMap::new(),
+        }
+    }
+
+    fn next(&mut self, method: &str, url: &str) -> Option<&har::Entry> {
+        let key = (method.to_uppercase(), url.to_string());
+        let entries = self.entries.get(&key)?;
+        let served = self.served.entry(key).or_default();
+        let entry = entries.get(*served).or_else(|| entries.last());
+        *served += 1;
+        entry
+    }
+}
+
+/// The response recorded in the entry
+fn replayed(entry: &har::Entry) -> hyper::Response<Body> {
+    let content = &entry.response.content;
+    let text = content.text.clone().unwrap_or_default();
+    let body = match content.encoding.as_deref() {
+        Some("base64") => base64::decode(&text).unwrap_or_default(),
+        _ => text.into_bytes(),
+    };
+    let mut builder = hyper::Response::builder().status(entry.response.status);
+    for header in &entry.response.headers {
+        match HeaderName::from_bytes(header.name.as_bytes()) {
+            Ok(name) if is_forwarded(&name) && name != hyper::header::CONTENT_LENGTH => {
+                builder = builder.header(name, header.value.as_str());
+            }
+            _ => {}
+        }
+    }
+    builder.body(Body::from(body)).unwrap_or_default()
+}
+
+enum Mode {
+    Record(PathBuf, Mutex<()>),
+    Replay(Mutex<Recordings>),
+    Forward,
+}
+
+struct Proxy {
+    client: Client,
+    mode: Mode,
+}
+
+impl Proxy {
+    async fn handle(&self, request: hyper::Request<Body>) -> hyper::Response<Body> {
+        let (parts, body) = request.into_parts();
+        if parts.method == Method::CONNECT {
+            return plain_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "rq proxy only handles plain HTTP, HTTPS can't be recorded".to_string(),
+            );
+        }
+        let url = parts.uri.to_string();
+        if parts.uri.scheme().is_none() {
+            return plain_response(
+                StatusCode::BAD_REQUEST,
+                format!("{} is not an absolute URL, configure rq as a proxy", url),
+            );
+        }
+        if let Mode::Replay(recordings) = &self.mode {
+            let mut recordings = recordings.lock().unwrap_or_else(|e| e.into_inner());
+            let response = match recordings.next(parts.method.as_str(), &url) {
+                Some(entry) => replayed(entry),
+                None => plain_response(
+                    StatusCode::BAD_GATEWAY,
+                    format!("No response recorded for {} {}", parts.method, url),
+                ),
+            };
+            eprintln!(
+                "{} {} -> {} (replayed)",
+                parts.method,
+                url,
+                response.status().as_u16()
+            );
+            return response;
+        }
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(e) => return plain_response(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in parts.headers.iter().filter(|(name, _)| is_forwarded(name)) {
+            headers.append(name, value.clone());
+        }
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let sent = self
+            .client
+            .request(parts.method.clone(), &url)
+            .headers(headers.clone())
+            .body(body.to_vec())
+            .send()
+            .await;
+        let res = match sent {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("{} {}: {}", parts.method, url, e);
+                return plain_response(StatusCode::BAD_GATEWAY, e.to_string());
+            }
+        };
+        let wait = start.elapsed();
+        let (status, version, res_headers) = (res.status(), res.version(), res.headers().clone());
+        let res_body = match res.bytes().await {
+            Ok(bytes) => bytes.to_vec(),
+            Err(e) => return plain_response(StatusCode::BAD_GATEWAY, e.to_string()),
+        };
+        eprintln!("{} {} -> {}", parts.method, url, status.as_u16());
+        if let Mode::Record(path, lock) = &self.mode {
+            let response = Response {
+                url: url.clone(),
+                status,
+                version,
+                headers: res_headers.clone(),
+                body: res_body.clone(),
+                wait,
+                receive: start.elapsed() - wait,
+            };
+            match to_request(&parts.method, &url, &headers, &body) {
+                Some(request) => {
+                    let entry = har::Entry::new(&request, &response, started);
+                    // Appending rewrites the file, one exchange at a time
+                    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+                    if let Err(e) = har::append(path, &[entry]) {
+                        eprintln!("Failed to record {} {}: {}", parts.method, url, e);
+                    }
+                }
+                None => eprintln!("{} {} is not recorded, rq can't send it", parts.method, url),
+            }
+        }
+        let mut builder = hyper::Response::builder().status(status);
+        for (name, value) in res_headers.iter().filter(|(name, _)| is_forwarded(name)) {
+            builder = builder.header(name, value);
+        }
+        builder.body(Body::from(res_body)).unwrap_or_default()
+    }
+}
+
+/// The request as rq records it, if its method is one rq supports
+fn to_request(method: &Method, url: &str, headers: &HeaderMap, body: &[u8]) -> Option<Request> {
+    let mut request = Request::new(method.as_str().parse().ok()?, url);
+    request.headers = headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), value)
+        })
+        .collect();
+    if !body.is_empty() {
+        request.body = Some(String::from_utf8_lossy(body).into_owned());
+    }
+    Some(request)
+}
+
+/// Run the proxy on `addr` until interrupted, recording to `record` or replaying from `replay`
+pub async fn run(
+    addr: SocketAddr,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+) -> crate::Result<()> {
+    let mode = match (record, replay) {
+        (_, Some(path)) => Mode::Replay(Mutex::new(Recordings::new(har::read(&path)?))),
+        (Some(path), None) => Mode::Record(path, Mutex::new(())),
+        (None, None) => Mode::Forward,
+    };
+    let client = Client::builder()
+        .timeout(TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .gzip(false)
+        .brotli(false)
+        .build()?;
+    let proxy = Arc::new(Proxy { client, mode });
+    server::serve(addr, move |request| {
+        let proxy = proxy.clone();
+        async move { proxy.handle(request).await }
+    })
+    .await
+    .map_err(|e: Error| e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use crate::{har, http::HttpMethod, request::Request, response::Response};
+
+    fn entry(url: &str, body: &[u8]) -> har::Entry {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-a", "1".parse().unwrap());
+        headers.insert("transfer-encoding", "chunked".parse().unwrap());
+        let response = Response {
+            url: url.to_string(),
+            status: StatusCode::CREATED,
+            version: Version::HTTP_11,
+            headers,
+            body: body.to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        let request = Request::new(HttpMethod::Get, url);
+        har::Entry::new(&request, &response, SystemTime::now())
+    }
+
+    #[test]
+    fn recordings_are_served_in_order() {
+        let mut recordings =
+            super::Recordings::new(vec![entry("http://a/", b"1"), entry("http://a/", b"2")]);
+        let text = |entry: Option<&har::Entry>| entry.unwrap().response.content.text.clone();
+        assert_eq!(text(recordings.next("get", "http://a/")), Some("1".into()));
+        assert_eq!(text(recordings.next("GET", "http://a/")), Some("2".into()));
+        assert_eq!(text(recordings.next("GET", "http://a/")), Some("2".into()));
+        assert!(recordings.next("POST", "http://a/").is_none());
+    }
+    #[test]
+    fn replayed_responses_drop_hop_by_hop_headers() {
+        let response = super::replayed(&entry("http://a/", &[0xff, 0]));
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["x-a"], "1");
+        assert!(!response.headers().contains_key("transfer-encoding"));
+    }
+}
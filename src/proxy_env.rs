@@ -0,0 +1,264 @@
+//! The proxies to send the requests through, from `http_proxy`, `https_proxy` and `all_proxy`,
+//! except for the hosts matching `no_proxy`, as curl reads them. Lowercase variables take
+//! precedence; `HTTP_PROXY` is ignored when run as a CGI script, where it could come from the
+//! `Proxy` header of a request.
+//!
+//! `no_proxy` is a comma-separated list of:
+//! - `*`, matching all hosts;
+//! - domain names, matching the domain and its subdomains, with or without a leading dot;
+//! - IP addresses, and CIDR ranges like `10.0.0.0/8` or `fd00::/8`.
+use std::{env, fmt, net::IpAddr};
+
+use reqwest::Url;
+
+use crate::http::BasicAuth;
+
+/// One entry of `no_proxy`
+#[derive(Debug, Clone, PartialEq)]
+enum Rule {
+    All,
+    Domain(String),
+    Network(IpAddr, u8),
+}
+
+impl Rule {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return None;
+        }
+        if entry == "*" {
+            return Some(Rule::All);
+        }
+        let (address, prefix) = match entry.split_once('/') {
+            Some((address, prefix)) => (address, prefix.parse().ok()),
+            None => (entry, None),
+        };
+        let address = address.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = address.parse::<IpAddr>() {
+            let bits = if ip.is_ipv4() { 32 } else { 128 };
+            return Some(Rule::Network(ip, prefix.unwrap_or(bits).min(bits)));
+        }
+        // A port is allowed by some tools but not compared, as by curl
+        let domain = entry.split(':').next().unwrap_or(entry);
+        Some(Rule::Domain(domain.trim_start_matches('.').to_lowercase()))
+    }
+
+    fn matches(&self, host: &str, ip: Option<IpAddr>) -> bool {
+        match (self, ip) {
+            (Rule::All, _) => true,
+            (Rule::Domain(domain), None) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            }
+            (Rule::Network(network, prefix), Some(ip)) => in_network(ip, *network, *prefix),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::All => f.write_str("*"),
+            Rule::Domain(domain) => f.write_str(domain),
+            Rule::Network(ip, prefix) => write!(f, "{}/{}", ip, prefix),
+        }
+    }
+}
+
+/// Whether the first `prefix` bits of the addresses are the same
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - u32::from(prefix);
+    ip >> shift == network >> shift
+}
+
+/// Whether a request goes through a proxy, and why
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Proxy(Url),
+    /// Sent directly, because of this `no_proxy` entry, or because no proxy is set
+    Direct(Option<String>),
+}
+
+/// The proxies of the environment
+#[derive(Debug, Clone, Default)]
+pub struct Proxies {
+    http: Option<Url>,
+    https: Option<Url>,
+    no_proxy: Vec<Rule>,
+}
+
+fn var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+fn proxy_url(value: Option<String>) -> Option<Url> {
+    let value = value?;
+    let value = value.trim();
+    // A proxy without a scheme is an HTTP proxy
+    if value.contains("://") {
+        Url::parse(value).ok()
+    } else {
+        Url::parse(&format!("http://{}", value)).ok()
+    }
+}
+
+impl Proxies {
+    pub fn from_env() -> Self {
+        let cgi = env::var_os("REQUEST_METHOD").is_some();
+        let http_names: &[&str] = if cgi {
+            &["http_proxy"]
+        } else {
+            &["http_proxy", "HTTP_PROXY"]
+        };
+        let all = var(&["all_proxy", "ALL_PROXY"]);
+        Proxies::new(
+            var(http_names).or_else(|| all.clone()),
+            var(&["https_proxy", "HTTPS_PROXY"]).or(all),
+            var(&["no_proxy", "NO_PROXY"])
+                .as_deref()
+                .unwrap_or_default(),
+        )
+    }
+
+    fn new(http: Option<String>, https: Option<String>, no_proxy: &str) -> Self {
+        Proxies {
+            http: proxy_url(http),
+            https: proxy_url(https),
+            no_proxy: no_proxy.split(',').filter_map(Rule::parse).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none()
+    }
+
+    /// The proxy to send a request to `url` through
+    pub fn decide(&self, url: &Url) -> Decision {
+        let proxy = match url.scheme() {
+            "http" => &self.http,
+            "https" => &self.https,
+            _ => &None,
+        };
+        let proxy = match proxy {
+            Some(proxy) => proxy,
+            None => return Decision::Direct(None),
+        };
+        let host = url
+            .host_str()
+            .unwrap_or_default()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.')
+            .to_lowercase();
+        let ip = host.parse().ok();
+        match self.no_proxy.iter().find(|rule| rule.matches(&host, ip)) {
+            Some(rule) => Decision::Direct(Some(rule.to_string())),
+            None => Decision::Proxy(proxy.clone()),
+        }
+    }
+
+    /// The proxy for reqwest, authenticating with `user` if given, or else with the credentials
+    /// of the proxy URL
+    pub fn to_reqwest(&self, user: Option<&BasicAuth>) -> reqwest::Proxy {
+        let proxies = self.clone();
+        let proxy = reqwest::Proxy::custom(move |url| match proxies.decide(url) {
+            Decision::Proxy(proxy) => Some(proxy),
+            Decision::Direct(_) => None,
+        });
+        match user {
+            Some(user) => {
+                proxy.basic_auth(&user.user, user.password.as_deref().unwrap_or_default())
+            }
+            None => proxy,
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Proxy(proxy) => {
+                // The credentials of the proxy are not printed
+                let mut proxy = proxy.clone();
+                let _ = proxy.set_username("");
+                let _ = proxy.set_password(None);
+                write!(f, "through the proxy {}", proxy)
+            }
+            Decision::Direct(Some(rule)) => {
+                write!(f, "directly, matching no_proxy entry `{}`", rule)
+            }
+            Decision::Direct(None) => f.write_str("directly, no proxy is set for the scheme"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::{Decision, Proxies};
+
+    fn decide(proxies: &Proxies, url: &str) -> Decision {
+        proxies.decide(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn no_proxy_matches_domains_and_networks() {
+        let proxies = Proxies::new(
+            Some("proxy:3128".into()),
+            None,
+            "localhost, .example.com,internal.net:8080, 10.0.0.0/8, ::1",
+        );
+        let proxy = Decision::Proxy(Url::parse("http://proxy:3128").unwrap());
+        let direct = |rule: &str| Decision::Direct(Some(rule.to_string()));
+        assert_eq!(
+            decide(&proxies, "http://localhost:8000/"),
+            direct("localhost")
+        );
+        assert_eq!(
+            decide(&proxies, "http://api.example.com/"),
+            direct("example.com")
+        );
+        assert_eq!(
+            decide(&proxies, "http://example.com/"),
+            direct("example.com")
+        );
+        assert_eq!(decide(&proxies, "http://badexample.com/"), proxy);
+        assert_eq!(
+            decide(&proxies, "http://a.internal.net/"),
+            direct("internal.net")
+        );
+        assert_eq!(decide(&proxies, "http://10.1.2.3/"), direct("10.0.0.0/8"));
+        assert_eq!(decide(&proxies, "http://11.1.2.3/"), proxy);
+        assert_eq!(decide(&proxies, "http://[::1]:80/"), direct("::1/128"));
+        assert_eq!(
+            decide(&proxies, "https://example.org/"),
+            Decision::Direct(None)
+        );
+    }
+    #[test]
+    fn star_disables_the_proxies() {
+        let proxies = Proxies::new(Some("http://proxy".into()), None, "*");
+        assert_eq!(
+            decide(&proxies, "http://example.org/"),
+            Decision::Direct(Some("*".into()))
+        );
+    }
+}
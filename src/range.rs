@@ -0,0 +1,91 @@
+//! Range requests (https://www.rfc-editor.org/rfc/rfc9110#section-14): `--range` asks for parts
+//! of the body, e.g. to sample a large file or to check that a CDN supports ranges.
+use std::{fmt, str::FromStr};
+
+use reqwest::StatusCode;
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid range `{0}`, expected FIRST-LAST, FIRST- or -LENGTH in bytes, e.g. 0-1023")]
+pub struct Error(String);
+
+/// A range of bytes, both ends included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// `0-1023`, or `1024-` to the end
+    From(u64, Option<u64>),
+    /// `-500`: the last 500 bytes
+    Suffix(u64),
+}
+
+impl FromStr for ByteRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error(s.to_string());
+        let (first, last) = s.trim().split_once('-').ok_or_else(invalid)?;
+        let number = |n: &str| n.parse::<u64>().map_err(|_| invalid());
+        match (first, last) {
+            ("", length) => Ok(ByteRange::Suffix(number(length)?)),
+            (first, "") => Ok(ByteRange::From(number(first)?, None)),
+            (first, last) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if last < first {
+                    return Err(invalid());
+                }
+                Ok(ByteRange::From(first, Some(last)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteRange::From(first, Some(last)) => write!(f, "{}-{}", first, last),
+            ByteRange::From(first, None) => write!(f, "{}-", first),
+            ByteRange::Suffix(length) => write!(f, "-{}", length),
+        }
+    }
+}
+
+/// The value of the `Range` header asking for all the ranges
+pub fn header_value(ranges: &[ByteRange]) -> String {
+    let ranges: Vec<_> = ranges.iter().map(ByteRange::to_string).collect();
+    format!("bytes={}", ranges.join(","))
+}
+
+/// What is wrong with the response to a range request, if it is not a partial content
+pub fn check(status: StatusCode) -> Option<&'static str> {
+    match status {
+        StatusCode::PARTIAL_CONTENT => None,
+        StatusCode::OK => Some("the server ignored --range and sent the whole body"),
+        StatusCode::RANGE_NOT_SATISFIABLE => Some("the range is not satisfiable"),
+        _ => Some("the server did not answer with 206 Partial Content"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteRange;
+
+    #[test]
+    fn ranges_are_parsed() {
+        assert_eq!(
+            "0-1023".parse::<ByteRange>().unwrap(),
+            ByteRange::From(0, Some(1023))
+        );
+        assert_eq!(
+            "1024-".parse::<ByteRange>().unwrap(),
+            ByteRange::From(1024, None)
+        );
+        assert_eq!("-500".parse::<ByteRange>().unwrap(), ByteRange::Suffix(500));
+        assert!("10-5".parse::<ByteRange>().is_err());
+        assert!("-".parse::<ByteRange>().is_err());
+        assert!("abc".parse::<ByteRange>().is_err());
+    }
+    #[test]
+    fn ranges_are_joined_in_one_header() {
+        let ranges = ["0-99", "200-", "-10"].map(|r| r.parse().unwrap());
+        assert_eq!(super::header_value(&ranges), "bytes=0-99,200-,-10");
+    }
+}
@@ -0,0 +1,71 @@
+//! `--limit-rate`: pacing the bytes sent and received, so that a large transfer doesn't take all
+//! the bandwidth.
+use std::time::{Duration, Instant};
+
+use futures_util::{stream, StreamExt};
+
+use crate::{size::ByteSize, upload::Chunks};
+
+/// Keeps a transfer under a number of bytes per second on average since its start
+#[derive(Debug)]
+pub struct Throttle {
+    rate: u64,
+    start: Instant,
+    transferred: u64,
+}
+
+impl Throttle {
+    pub fn new(rate: ByteSize) -> Self {
+        Throttle {
+            rate: rate.0.max(1),
+            start: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    /// How long to wait once `bytes` more are transferred, to get back under the rate
+    fn delay(&mut self, bytes: usize) -> Duration {
+        self.transferred += bytes as u64;
+        let due = Duration::from_secs_f64(self.transferred as f64 / self.rate as f64);
+        due.saturating_sub(self.start.elapsed())
+    }
+
+    /// Wait as long as needed after transferring `bytes`
+    pub async fn pace(&mut self, bytes: usize) {
+        let delay = self.delay(bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// The chunks of a body sent no faster than `rate`
+pub fn throttle(chunks: Chunks, rate: ByteSize) -> Chunks {
+    let throttled = stream::unfold(
+        (chunks, Throttle::new(rate)),
+        |(mut chunks, mut throttle)| async move {
+            let chunk = chunks.next().await?;
+            if let Ok(chunk) = &chunk {
+                throttle.pace(chunk.len()).await;
+            }
+            Some((chunk, (chunks, throttle)))
+        },
+    );
+    Box::pin(throttled)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::size::ByteSize;
+
+    #[test]
+    fn delay_grows_with_the_bytes_transferred() {
+        let mut throttle = super::Throttle::new(ByteSize(1000));
+        let delay = throttle.delay(500);
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+        let delay = throttle.delay(1500);
+        assert!(delay > Duration::from_millis(1900) && delay <= Duration::from_millis(2000));
+    }
+}
@@ -0,0 +1,190 @@
+//! `rq raw`: send a hand-written HTTP/1.1 request byte for byte, as exported by the browser
+//! devtools or Burp, and print the raw response, to reproduce what a well-behaved client can't
+//! send: malformed headers, conflicting lengths, odd line endings...
+//!
+//! The only change made is for files saved with bare LF line endings, as most editors do: the
+//! line endings of their head are turned into CRLF. The response is read until its end as told
+//! by its headers, or until the server closes the connection.
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{connect, TIMEOUT};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't read {}: {source}", .path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("The request has no Host header, give the server with --target")]
+    NoTarget,
+    #[error("Invalid target `{0}`, expected host:port")]
+    InvalidTarget(String),
+    #[error("{0}")]
+    Connect(#[from] connect::Error),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("No response within {} ms", TIMEOUT.as_millis())]
+    Timeout,
+}
+
+/// The request with CRLF line endings in its head, if the file has none at all
+fn fix_line_endings(request: Vec<u8>) -> Vec<u8> {
+    if request.windows(2).any(|w| w == b"\r\n") {
+        return request;
+    }
+    let head_end = request
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .map_or(request.len(), |i| i + 2);
+    let (head, body) = request.split_at(head_end);
+    let mut fixed = Vec::with_capacity(request.len() + 32);
+    for &b in head {
+        if b == b'\n' {
+            fixed.push(b'\r');
+        }
+        fixed.push(b);
+    }
+    fixed.extend_from_slice(body);
+    fixed
+}
+
+/// The value of the header `name` in the head of a message
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Whether the response read so far is complete, from its status and headers. A response whose
+/// length is not known ends when the connection is closed.
+fn is_complete(mut response: &[u8], head_request: bool) -> bool {
+    loop {
+        let head_end = match response.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(i) => i + 4,
+            None => return false,
+        };
+        let head = String::from_utf8_lossy(&response[..head_end]);
+        let body = &response[head_end..];
+        let status: u16 = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+        // Interim responses are followed by the final one
+        if (100..200).contains(&status) && status != 101 {
+            response = body;
+            continue;
+        }
+        if head_request || status == 204 || status == 304 || status == 101 {
+            return true;
+        }
+        let chunked = header(&head, "transfer-encoding")
+            .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"));
+        if chunked {
+            return body.starts_with(b"0\r\n\r\n")
+                || body.windows(7).any(|w| w == b"\r\n0\r\n\r\n");
+        }
+        return match header(&head, "content-length").and_then(|v| v.parse::<usize>().ok()) {
+            Some(length) => body.len() >= length,
+            None => false,
+        };
+    }
+}
+
+/// The URL of the server to connect to: `target`, or else the `Host` header of the request
+fn server_url(request: &[u8], target: Option<&str>, tls: bool) -> Result<Url, Error> {
+    let head_end = request
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .unwrap_or(request.len());
+    let head = String::from_utf8_lossy(&request[..head_end]);
+    let host = match target {
+        Some(target) => target,
+        None => header(&head, "host").ok_or(Error::NoTarget)?,
+    };
+    let scheme = if tls { "https" } else { "http" };
+    Url::parse(&format!("{}://{}/", scheme, host))
+        .ok()
+        .filter(|url| url.host_str().is_some())
+        .ok_or_else(|| Error::InvalidTarget(host.to_string()))
+}
+
+/// Send the request of the file at `path` and print the response as received
+pub async fn run(path: &Path, target: Option<&str>, tls: bool) -> crate::Result<()> {
+    let request = fs::read(path).map_err(|source| Error::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let request = fix_line_endings(request);
+    let url = server_url(&request, target, tls)?;
+    let head_request = request.starts_with(b"HEAD ");
+    let exchange = async {
+        let mut stream = connect::Options::default().connect(&url).await?.stream;
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+        let mut response = Vec::new();
+        let mut buffer = [0; 16 * 1024];
+        while !is_complete(&response, head_request) {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            response.extend_from_slice(&buffer[..read]);
+        }
+        Ok::<_, Error>(response)
+    };
+    let response = tokio::time::timeout(TIMEOUT, exchange)
+        .await
+        .map_err(|_| Error::Timeout)??;
+    io::stdout().write_all(&response)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn bare_line_feeds_of_the_head_are_fixed() {
+        assert_eq!(
+            super::fix_line_endings(b"GET / HTTP/1.1\nHost: a\n\nx\ny".to_vec()),
+            b"GET / HTTP/1.1\r\nHost: a\r\n\r\nx\ny"
+        );
+        let verbatim = b"GET / HTTP/1.1\r\nHost: a\n\n".to_vec();
+        assert_eq!(super::fix_line_endings(verbatim.clone()), verbatim);
+    }
+    #[test]
+    fn end_of_response_is_found_from_its_headers() {
+        let complete = |response: &[u8]| super::is_complete(response, false);
+        assert!(!complete(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nabc"
+        ));
+        assert!(complete(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nabcde"
+        ));
+        assert!(!complete(
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n"
+        ));
+        assert!(complete(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\n0\r\n\r\n"
+        ));
+        assert!(!complete(b"HTTP/1.0 200 OK\r\n\r\nuntil closed"));
+        assert!(super::is_complete(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            true
+        ));
+    }
+    #[test]
+    fn server_is_the_host_header_unless_given() {
+        let request = b"GET / HTTP/1.1\r\nHost: example.com:8080\r\n\r\n";
+        let url = super::server_url(request, None, false).unwrap();
+        assert_eq!(url.as_str(), "http://example.com:8080/");
+        let url = super::server_url(request, Some("127.0.0.1:8443"), true).unwrap();
+        assert_eq!(url.as_str(), "https://127.0.0.1:8443/");
+        assert!(super::server_url(b"GET / HTTP/1.1\r\n\r\n", None, false).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Following redirects the way curl does, which reqwest can't be configured to do: `POST` only
+//! becomes `GET` after a 301, 302 or 303 unless `--post301`, `--post302` or `--post303` is given,
+//! and the credentials are only forwarded to another origin with `--location-trusted`.
+use reqwest::{
+    header::{
+        HeaderMap, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE,
+        PROXY_AUTHORIZATION, TRANSFER_ENCODING, WWW_AUTHENTICATE,
+    },
+    Body, Method, StatusCode, Url,
+};
+
+use crate::cli::CliArgs;
+
+/// How many redirects are followed before giving up
+pub const MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Redirects {
+    /// Keep `POST` after a 301, 302 and 303 respectively
+    pub post301: bool,
+    pub post302: bool,
+    pub post303: bool,
+    /// Send the credentials to the other origins redirected to
+    pub trusted: bool,
+}
+
+/// What is needed of a request to follow its redirect, as sending it consumes it
+pub struct Sent {
+    method: Method,
+    url: Url,
+    headers: HeaderMap,
+    /// `Some(None)` for a streamed body, which can't be sent again
+    body: Option<Option<Body>>,
+}
+
+impl Sent {
+    pub fn new(request: &reqwest::Request) -> Self {
+        Sent {
+            method: request.method().clone(),
+            url: request.url().clone(),
+            headers: request.headers().clone(),
+            body: request
+                .body()
+                .map(|body| body.as_bytes().map(|bytes| Body::from(bytes.to_vec()))),
+        }
+    }
+}
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme()
+        && a.host_str() == b.host_str()
+        && a.port_or_known_default() == b.port_or_known_default()
+}
+
+impl Redirects {
+    pub fn from_args(args: &CliArgs) -> Self {
+        Redirects {
+            post301: args.post301,
+            post302: args.post302,
+            post303: args.post303,
+            trusted: args.location_trusted,
+        }
+    }
+
+    /// The request to send to follow a response with this status and `Location`, if it is a
+    /// redirect that can be followed
+    pub fn follow(
+        &self,
+        sent: Sent,
+        status: StatusCode,
+        location: &str,
+    ) -> Option<reqwest::Request> {
+        let post = sent.method == Method::POST;
+        let to_get = match status {
+            StatusCode::MOVED_PERMANENTLY => post && !self.post301,
+            StatusCode::FOUND => post && !self.post302,
+            StatusCode::SEE_OTHER => sent.method != Method::HEAD && !(post && self.post303),
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => false,
+            _ => return None,
+        };
+        let url = sent.url.join(location).ok()?;
+        let mut headers = sent.headers;
+        if !self.trusted && !same_origin(&sent.url, &url) {
+            for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE] {
+                headers.remove(name);
+            }
+        }
+        let (method, body) = if to_get {
+            for name in [
+                CONTENT_TYPE,
+                CONTENT_LENGTH,
+                CONTENT_ENCODING,
+                TRANSFER_ENCODING,
+            ] {
+                headers.remove(name);
+            }
+            (Method::GET, None)
+        } else {
+            // A streamed body is gone, the redirect is returned rather than followed
+            match sent.body {
+                Some(None) => return None,
+                Some(body) => (sent.method, body),
+                None => (sent.method, None),
+            }
+        };
+        let mut request = reqwest::Request::new(method, url);
+        *request.headers_mut() = headers;
+        *request.body_mut() = body;
+        Some(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, Method, StatusCode};
+
+    use super::{Redirects, Sent};
+
+    fn post() -> Sent {
+        let request = Client::new()
+            .post("http://example.com/a/b")
+            .header("authorization", "Bearer x")
+            .header("content-type", "text/plain")
+            .body("hello")
+            .build()
+            .unwrap();
+        Sent::new(&request)
+    }
+
+    #[test]
+    fn post_becomes_get_unless_asked() {
+        let redirects = Redirects::default();
+        let next = redirects.follow(post(), StatusCode::FOUND, "c").unwrap();
+        assert_eq!(next.method(), Method::GET);
+        assert_eq!(next.url().as_str(), "http://example.com/a/c");
+        assert!(next.body().is_none());
+        assert!(next.headers().get("content-type").is_none());
+        let redirects = Redirects {
+            post302: true,
+            ..Redirects::default()
+        };
+        let next = redirects.follow(post(), StatusCode::FOUND, "/c").unwrap();
+        assert_eq!(next.method(), Method::POST);
+        assert_eq!(next.body().unwrap().as_bytes(), Some(&b"hello"[..]));
+        let next = redirects.follow(post(), StatusCode::TEMPORARY_REDIRECT, "/c");
+        assert_eq!(next.unwrap().method(), Method::POST);
+        assert!(redirects.follow(post(), StatusCode::OK, "/c").is_none());
+    }
+    #[test]
+    fn credentials_stay_on_the_origin_unless_trusted() {
+        let redirects = Redirects::default();
+        let next = redirects.follow(post(), StatusCode::FOUND, "/c").unwrap();
+        assert!(next.headers().contains_key("authorization"));
+        let next = redirects.follow(post(), StatusCode::FOUND, "https://example.com/");
+        assert!(!next.unwrap().headers().contains_key("authorization"));
+        let redirects = Redirects {
+            trusted: true,
+            ..Redirects::default()
+        };
+        let next = redirects.follow(post(), StatusCode::FOUND, "http://other.org/");
+        assert!(next.unwrap().headers().contains_key("authorization"));
+    }
+}
@@ -0,0 +1,160 @@
+//! `--output-template`: a line per response built from its values, e.g.
+//! `'{{status}} id={{body.$.id}} took {{timing.total_ms}}ms'`, for readable reports of batches,
+//! watches and benchmarks.
+//!
+//! The placeholders are `url`, `method`, `status`, `reason`, `error`, `size`, `header.NAME`,
+//! `body`, `body.JSONPATH` and `timing.total_ms`, `timing.wait_ms` and `timing.receive_ms`. The
+//! values a response doesn't have are left empty. `\n` and `\t` are replaced by a newline and a
+//! tab.
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde_json::Value;
+
+use crate::{jsonpath::JsonPath, template, Request, Response, Result};
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown placeholders in the output template: {}", .0.join(", "))]
+    UnknownPlaceholders(Vec<String>),
+}
+
+const NAMES: &[&str] = &[
+    "url",
+    "method",
+    "status",
+    "reason",
+    "error",
+    "size",
+    "body",
+    "timing.total_ms",
+    "timing.wait_ms",
+    "timing.receive_ms",
+];
+
+fn is_known(name: &str) -> bool {
+    NAMES.contains(&name)
+        || name.strip_prefix("header.").is_some_and(|h| !h.is_empty())
+        || name
+            .strip_prefix("body.")
+            .is_some_and(|path| path.parse::<JsonPath>().is_ok())
+}
+
+/// The template given with `--output-template`
+#[derive(Debug, Clone)]
+pub struct Template(String);
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        template::render(s, |name| is_known(name).then(String::new))
+            .map_err(Error::UnknownPlaceholders)?;
+        Ok(Template(s.replace("\\n", "\n").replace("\\t", "\t")))
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    duration.as_millis().to_string()
+}
+
+/// The value at the path of the JSON body, strings without their quotes
+fn json_value(response: &Response, path: &str) -> Option<String> {
+    let path: JsonPath = path.parse().ok()?;
+    let json: Value = serde_json::from_slice(&response.body).ok()?;
+    Some(match path.get(&json)? {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    })
+}
+
+impl Template {
+    /// The line for the response to `request`, or the error, received after `elapsed`
+    pub fn render(
+        &self,
+        request: &Request,
+        result: &Result<Response>,
+        elapsed: Duration,
+    ) -> String {
+        let response = result.as_ref().ok();
+        let value = |name: &str| -> Option<String> {
+            Some(match name {
+                "url" => response.map_or(&request.url, |r| &r.url).clone(),
+                "method" => request.method.to_string(),
+                "error" => result.as_ref().err()?.to_string(),
+                "timing.total_ms" => millis(elapsed),
+                _ => {
+                    let response = response?;
+                    match name {
+                        "status" => response.status.as_str().to_string(),
+                        "reason" => response.status.canonical_reason()?.to_string(),
+                        "size" => response.body.len().to_string(),
+                        "body" => response.text(None),
+                        "timing.wait_ms" => millis(response.wait),
+                        "timing.receive_ms" => millis(response.receive),
+                        _ => match name.split_once('.')? {
+                            ("header", header) => response.header(header)?.to_string(),
+                            ("body", path) => json_value(response, path)?,
+                            _ => return None,
+                        },
+                    }
+                }
+            })
+        };
+        template::render(&self.0, |name| Some(value(name).unwrap_or_default())).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use super::Template;
+    use crate::{http::HttpMethod, Request, Response};
+
+    #[test]
+    fn placeholders_are_checked_when_parsed() {
+        assert!("{{status}} {{header.etag}} {{body.$.a}}"
+            .parse::<Template>()
+            .is_ok());
+        assert!("{{status}} {{nope}} {{body.a}}"
+            .parse::<Template>()
+            .is_err());
+    }
+    #[test]
+    fn response_values_are_rendered() {
+        let template: Template =
+            "{{method}} {{status}} {{reason}} id={{body.$.id}} {{body.$.tags}} x={{header.x}}\\t\
+            {{timing.total_ms}}ms {{error}}"
+                .parse()
+                .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x", "1".parse().unwrap());
+        let response = Response {
+            url: "http://x/".to_string(),
+            status: StatusCode::CREATED,
+            version: Version::HTTP_11,
+            headers,
+            body: br#"{"id":"a1","tags":[1,2]}"#.to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        let request = Request::new(HttpMethod::Post, "http://x/");
+        assert_eq!(
+            template.render(&request, &Ok(response), Duration::from_millis(12)),
+            "POST 201 Created id=a1 [1,2] x=1\t12ms "
+        );
+        let error = Err(crate::Error::Failed(1));
+        assert_eq!(
+            template.render(&request, &error, Duration::from_millis(3)),
+            "POST   id=  x=\t3ms 1 request(s) failed"
+        );
+    }
+}
@@ -0,0 +1,524 @@
+//! The request to send, independent of where it was defined (command line, collections...).
+use std::{path::PathBuf, time::Instant};
+
+use futures_util::{future, stream};
+use reqwest::{
+    header::{
+        HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE,
+        IF_MODIFIED_SINCE, IF_NONE_MATCH, LOCATION, RANGE, USER_AGENT,
+    },
+    Client, RequestBuilder,
+};
+
+use crate::{
+    cli::{normalize_url, CliArgs},
+    compression::Coding,
+    connect, cookie, expect,
+    http::{guess_content_type, ContentType, HttpMethod, UserAgentPreset},
+    local, media_type,
+    middleware::Pipeline,
+    msgpack,
+    multipart::{self, Part},
+    range,
+    rate::{self, Throttle},
+    redirect::{Redirects, Sent, MAX_REDIRECTS},
+    response::Response,
+    secret,
+    size::ByteSize,
+    trace, unix,
+    upload::{Chunks, Upload},
+    zstd, Error, Result, ACCEPT_ENCODING_VALUE,
+};
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: HttpMethod,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Fields of a `multipart/form-data` body, which replaces `body` when not empty
+    pub form: Vec<Part>,
+    /// The boundary of the form, chosen once so that every build of the request has the same
+    pub boundary: String,
+    /// A body streamed from a file or stdin, which replaces `body` when set
+    pub upload: Option<Upload>,
+    /// The coding the body is compressed with before it is sent
+    pub compress: Option<Coding>,
+    /// Send the request over this Unix domain socket instead of TCP
+    pub unix_socket: Option<PathBuf>,
+    /// Send `Expect: 100-continue` and hold the body back until the server accepts it
+    pub expect_continue: bool,
+    /// The options of the client, for the connections made outside of it
+    pub connect: Option<connect::Options>,
+    /// How redirects are followed
+    pub redirects: Redirects,
+    /// Fail once the response body is larger than this
+    pub max_response_size: Option<ByteSize>,
+    /// The most bytes per second sent and received
+    pub limit_rate: Option<ByteSize>,
+    /// Log the bytes of the exchange to this file, `-` for stderr
+    pub trace: Option<PathBuf>,
+    /// The middlewares the request is sent through
+    pub pipeline: Pipeline,
+}
+
+impl Request {
+    /// A request without headers nor body, to complete with the builder methods
+    pub fn new(method: HttpMethod, url: &str) -> Self {
+        Request {
+            method,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            form: Vec::new(),
+            boundary: multipart::boundary(),
+            upload: None,
+            compress: None,
+            unix_socket: None,
+            expect_continue: false,
+            connect: None,
+            redirects: Redirects::default(),
+            max_response_size: None,
+            limit_rate: None,
+            trace: None,
+            pipeline: Pipeline::default(),
+        }
+    }
+
+    /// Set the header `name`, replacing the value it had
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.set_header(name, value.to_string());
+        self
+    }
+
+    /// Set the body, along with its guessed content type unless one was given
+    pub fn body(mut self, body: &str) -> Self {
+        if !self
+            .headers
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case(CONTENT_TYPE.as_str()))
+        {
+            self.set_header(CONTENT_TYPE.as_str(), guess_content_type(body).to_string());
+        }
+        self.body = Some(body.to_string());
+        self
+    }
+
+    /// Build the request for `url` from the method, headers and body given on the command line.
+    /// A `Content-Type` given with `-H` takes precedence over the one from `--type`.
+    pub fn from_args(args: &CliArgs, url: &str) -> Self {
+        let mut req = Request {
+            method: args.method.clone(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: args.data.clone(),
+            form: args.form.clone(),
+            boundary: multipart::boundary(),
+            upload: args.data_binary.clone(),
+            compress: args.compress.map(|coding| coding.unwrap_or(Coding::Gzip)),
+            unix_socket: args.unix_socket.clone(),
+            expect_continue: args.expect_continue,
+            connect: Some(connect::Options::from_args(args)),
+            redirects: Redirects::from_args(args),
+            max_response_size: args.max_response_size,
+            limit_rate: args.limit_rate,
+            trace: args.trace.clone(),
+            pipeline: args.pipeline.clone(),
+        };
+        if let Some(content_type) = &args.content_type {
+            req.set_header(CONTENT_TYPE.as_str(), content_type.to_string());
+        } else if let Some(Upload::File { path, .. }) = &args.data_binary {
+            if let Some(media_type) = media_type::guess_file(path) {
+                req.set_header(CONTENT_TYPE.as_str(), media_type);
+            }
+        }
+        // Without decompression the client stops advertising the codings, keep them
+        let accept_encoding = match (&args.accept_encoding, args.no_decompress) {
+            (Some(accepted), _) => Some(accepted.to_string()),
+            (None, true) => Some(ACCEPT_ENCODING_VALUE.to_string()),
+            (None, false) => None,
+        };
+        if let Some(accept_encoding) = accept_encoding {
+            req.set_header(ACCEPT_ENCODING.as_str(), accept_encoding);
+        }
+        let user_agent = args
+            .user_agent
+            .as_deref()
+            .or_else(|| args.ua.as_ref().map(UserAgentPreset::value));
+        if let Some(user_agent) = user_agent {
+            req.set_header(USER_AGENT.as_str(), user_agent.to_string());
+        }
+        if let Some(etag) = &args.if_none_match {
+            req.set_header(IF_NONE_MATCH.as_str(), etag.clone());
+        }
+        if let Some(time_cond) = &args.time_cond {
+            req.set_header(
+                IF_MODIFIED_SINCE.as_str(),
+                time_cond.header_value().to_string(),
+            );
+        }
+        if !args.range.is_empty() {
+            req.set_header(RANGE.as_str(), range::header_value(&args.range));
+        }
+        if let Some(key) = &args.api_key {
+            args.api_key_in.apply(key, &mut req);
+        }
+        if let Some(cookies) = cookie::header_value(&args.cookies, url) {
+            req.set_header(COOKIE.as_str(), cookies);
+        }
+        for header in &args.headers {
+            req.set_header(&header.name, header.value.clone());
+        }
+        req
+    }
+
+    /// Send the request as POST with its method in `X-HTTP-Method-Override`, for the servers
+    /// behind proxies or gateways letting only GET, HEAD and POST through
+    pub fn override_method(&mut self) {
+        if !matches!(
+            self.method,
+            HttpMethod::Get | HttpMethod::Head | HttpMethod::Post
+        ) {
+            self.set_header("X-HTTP-Method-Override", self.method.to_string());
+            self.method = HttpMethod::Post;
+        }
+    }
+
+    /// Replace the header `name` (case-insensitive), or add it if it is not set yet
+    pub fn set_header(&mut self, name: &str, value: String) {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value));
+    }
+
+    /// Replace the parts given on the command line when replaying a request. When the body is
+    /// replaced without giving the content type, the content type is guessed again.
+    pub fn apply_overrides(
+        &mut self,
+        method: &Option<HttpMethod>,
+        content_type: &Option<ContentType>,
+        data: &Option<String>,
+        url: &Option<String>,
+    ) {
+        if let Some(method) = method {
+            self.method = method.clone();
+        }
+        if let Some(data) = data {
+            self.body = Some(data.clone());
+        }
+        let content_type = content_type
+            .clone()
+            .or_else(|| data.as_deref().map(guess_content_type));
+        if let Some(content_type) = content_type {
+            self.set_header(CONTENT_TYPE.as_str(), content_type.to_string());
+        }
+        if let Some(url) = url {
+            let mut url = url.clone();
+            normalize_url(&mut url);
+            self.url = url;
+        }
+    }
+
+    /// The bytes of the body, the JSON of MessagePack requests encoded
+    fn body_bytes(&self, body: &str) -> Vec<u8> {
+        let is_msgpack = self.headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()) && msgpack::is_msgpack(value)
+        });
+        match serde_json::from_str(body) {
+            Ok(json) if is_msgpack => msgpack::encode(&json),
+            _ => body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn build(&self, client: &Client) -> RequestBuilder {
+        let mut req = client.request(self.method.clone().into(), &self.url);
+        let has_form = !self.form.is_empty();
+        for (name, value) in &self.headers {
+            // The boundary is part of the content type of a form
+            if has_form && name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()) {
+                continue;
+            }
+            req = req.header(name.as_str(), value.as_str());
+        }
+        let compress = |data: Vec<u8>| match self.compress {
+            Some(coding) => coding.compress(&data),
+            None => data,
+        };
+        if has_form {
+            req = req
+                .header(
+                    CONTENT_TYPE,
+                    format!("{}; boundary={}", ContentType::Multipart, self.boundary),
+                )
+                .body(compress(multipart::encode(&self.form, &self.boundary)));
+        } else if let Some(upload) = &self.upload {
+            let (body, length) = upload.body(self.compress);
+            if let Some(length) = length {
+                req = req.header(CONTENT_LENGTH, length);
+            }
+            req = req.body(body);
+        } else if let Some(body) = &self.body {
+            req = req.body(compress(self.body_bytes(body)));
+        } else {
+            return req;
+        }
+        if let Some(coding) = self.compress {
+            req = req.header(CONTENT_ENCODING, coding.to_string());
+        }
+        req
+    }
+
+    /// Read the body as it arrives, failing as soon as it is larger than `max_response_size` and
+    /// no faster than `limit_rate`
+    async fn read_body(&self, mut res: reqwest::Response) -> Result<Vec<u8>> {
+        let max = self.max_response_size;
+        if let Some(max) = max.filter(|max| res.content_length().is_some_and(|l| l > max.0)) {
+            return Err(Error::SizeLimitExceeded(max));
+        }
+        if max.is_none() && self.limit_rate.is_none() {
+            return Ok(res.bytes().await?.to_vec());
+        }
+        let mut throttle = self.limit_rate.map(Throttle::new);
+        let mut body = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if let Some(max) = max.filter(|max| body.len() as u64 > max.0) {
+                return Err(Error::SizeLimitExceeded(max));
+            }
+            if let Some(throttle) = &mut throttle {
+                throttle.pace(chunk.len()).await;
+            }
+        }
+        Ok(body)
+    }
+
+    /// Whether compressed responses are asked for and decoded
+    fn decompress(&self) -> bool {
+        self.connect
+            .as_ref()
+            .is_none_or(|options| options.decompress)
+    }
+
+    /// Build the request as it goes out, with the `Accept-Encoding` of the client
+    fn build_sent(&self, client: &Client) -> Result<reqwest::Request> {
+        let mut request = self.build(client).build()?;
+        // reqwest only asks for the codings it decodes itself, zstd is decoded once read. The
+        // connections made with hyper directly don't decode anything.
+        let headers = request.headers_mut();
+        let hyper = self.unix_socket.is_some() || self.trace.is_some();
+        if self.decompress()
+            && !hyper
+            && !headers.contains_key(ACCEPT_ENCODING)
+            && !headers.contains_key(RANGE)
+        {
+            headers.insert(
+                ACCEPT_ENCODING,
+                HeaderValue::from_static(ACCEPT_ENCODING_VALUE),
+            );
+        }
+        Ok(request)
+    }
+
+    /// Send the request through its middlewares and read the whole response
+    pub async fn send(&self, client: &Client) -> Result<Response> {
+        self.send_recording(client, &mut None).await
+    }
+
+    /// Send the request through its middlewares and read the whole response, keeping in `sent`
+    /// the request that went out, even when no response came back, to print and record it
+    pub async fn send_recording(
+        &self,
+        client: &Client,
+        sent: &mut Option<reqwest::Request>,
+    ) -> Result<Response> {
+        self.pipeline.send(self, client, sent).await
+    }
+
+    /// Send the request as it is and read the whole response, keeping in `sent` the request that
+    /// went out
+    pub(crate) async fn exchange(
+        &self,
+        client: &Client,
+        sent: &mut Option<reqwest::Request>,
+    ) -> Result<Response> {
+        if secret::has_references(self) {
+            let result = Box::pin(secret::resolve(self)?.exchange(client, sent)).await;
+            // The references are kept rather than the secrets
+            *sent = self.build_sent(client).ok().as_ref().map(copy);
+            return result;
+        }
+        if local::is_local(&self.url) {
+            return Ok(local::send(&self.method, &self.url)?);
+        }
+        let request = self.build_sent(client)?;
+        *sent = Some(copy(&request));
+        let bytes = request.body().and_then(|b| b.as_bytes());
+        if let Some(path) = &self.unix_socket {
+            let body = match (bytes, &self.upload) {
+                (Some(bytes), _) => hyper::Body::from(bytes.to_vec()),
+                (None, Some(upload)) => hyper::Body::wrap_stream(upload.chunks(self.compress).0),
+                (None, None) => hyper::Body::empty(),
+            };
+            return Ok(unix::send(path, &request, body).await?);
+        }
+        if let Some(path) = &self.trace {
+            let body = match (bytes, &self.upload) {
+                (Some(bytes), _) => hyper::Body::from(bytes.to_vec()),
+                (None, Some(upload)) => hyper::Body::wrap_stream(upload.chunks(self.compress).0),
+                (None, None) => hyper::Body::empty(),
+            };
+            let options = self.connect.clone().unwrap_or_default();
+            return Ok(trace::send(path, &request, body, &options).await?);
+        }
+        let body: Option<(Chunks, Option<u64>)> = match (bytes, &self.upload) {
+            (Some(bytes), _) => Some((
+                Box::pin(stream::once(future::ready(Ok(bytes.to_vec())))),
+                Some(bytes.len() as u64),
+            )),
+            (None, Some(upload)) => Some(upload.chunks(self.compress)),
+            (None, None) => None,
+        };
+        let body = body.map(|(chunks, length)| match self.limit_rate {
+            Some(rate) => (rate::throttle(chunks, rate), length),
+            None => (chunks, length),
+        });
+        let mut request = request;
+        // The body held back for `100 Continue`, sent over a connection of our own
+        let mut expecting = None;
+        match body {
+            Some(body) if self.expect_continue => expecting = Some(body),
+            // The body given as a whole is sent as a stream to be paced
+            Some((chunks, length)) if self.limit_rate.is_some() => {
+                *request.body_mut() = Some(reqwest::Body::wrap_stream(chunks));
+                if let Some(length) = length {
+                    request.headers_mut().insert(CONTENT_LENGTH, length.into());
+                }
+            }
+            _ => {}
+        }
+        let start = Instant::now();
+        let mut redirects = 0;
+        // The URL of the response, which reqwest can't tell for those received with hyper
+        let mut url;
+        let res = loop {
+            let sent = Sent::new(&request);
+            url = request.url().to_string();
+            let res = match expecting.take() {
+                Some((chunks, length)) => {
+                    let options = self.connect.clone().unwrap_or_default();
+                    expect::send(&request, chunks, length, &options).await?
+                }
+                None => client.execute(request).await?,
+            };
+            let location = res.headers().get(LOCATION).and_then(|l| l.to_str().ok());
+            let next = location.and_then(|l| self.redirects.follow(sent, res.status(), l));
+            match next {
+                Some(_) if redirects == MAX_REDIRECTS => {
+                    return Err(Error::TooManyRedirects(redirects))
+                }
+                Some(next) => {
+                    tracing::debug!(method = %next.method(), url = %next.url(), "following redirect");
+                    request = next
+                }
+                None => break res,
+            }
+            // Read to the end so the connection can be reused
+            res.bytes().await?;
+            redirects += 1;
+        };
+        let wait = start.elapsed();
+        let status = res.status();
+        let version = res.version();
+        let mut headers = res.headers().clone();
+        let mut body = self.read_body(res).await?;
+        let zstd = headers
+            .get(CONTENT_ENCODING)
+            .is_some_and(|coding| coding.as_bytes().eq_ignore_ascii_case(b"zstd"));
+        if self.decompress() && zstd {
+            let max = self.max_response_size;
+            body = zstd::decode(&body, max.map(|max| max.0)).map_err(|e| match (e, max) {
+                (zstd::Error::TooLarge(_), Some(max)) => Error::SizeLimitExceeded(max),
+                (e, _) => e.into(),
+            })?;
+            headers.remove(CONTENT_ENCODING);
+            headers.remove(CONTENT_LENGTH);
+        }
+        Ok(Response {
+            url,
+            status,
+            version,
+            headers,
+            body,
+            wait,
+            receive: start.elapsed() - wait,
+        })
+    }
+}
+
+/// A copy of the request to print and record, with an empty stream in place of a streamed body
+fn copy(request: &reqwest::Request) -> reqwest::Request {
+    request.try_clone().unwrap_or_else(|| {
+        let mut copy = reqwest::Request::new(request.method().clone(), request.url().clone());
+        *copy.headers_mut() = request.headers().clone();
+        let empty = stream::empty::<std::result::Result<Vec<u8>, std::io::Error>>();
+        *copy.body_mut() = Some(reqwest::Body::wrap_stream(empty));
+        copy
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::CONTENT_TYPE;
+
+    use super::Request;
+    use crate::{http::HttpMethod, multipart::Part};
+
+    #[test]
+    fn overridden_method_is_sent_in_a_header() {
+        let mut request = Request::new(HttpMethod::Patch, "http://localhost");
+        request.override_method();
+        assert!(matches!(request.method, HttpMethod::Post));
+        assert_eq!(
+            request.headers,
+            [("X-HTTP-Method-Override".to_string(), "PATCH".to_string())]
+        );
+        let mut request = Request::new(HttpMethod::Get, "http://localhost");
+        request.override_method();
+        assert!(matches!(request.method, HttpMethod::Get));
+        assert!(request.headers.is_empty());
+    }
+    #[test]
+    fn builder_guesses_the_content_type_unless_given() {
+        let request = Request::new(HttpMethod::Post, "http://localhost").body("{\"a\":1}");
+        assert_eq!(request.headers[0].0, "content-type");
+        assert!(request.headers[0].1.contains("json"));
+        let request = Request::new(HttpMethod::Post, "http://localhost")
+            .header("Content-Type", "text/plain")
+            .body("{\"a\":1}");
+        assert_eq!(
+            request.headers,
+            vec![("Content-Type".to_string(), "text/plain".to_string())]
+        );
+    }
+    #[test]
+    fn form_is_built_with_the_same_boundary_each_time() {
+        let mut request = Request::new(HttpMethod::Post, "http://localhost");
+        request.form.push(Part {
+            name: "a".to_string(),
+            data: b"1".to_vec(),
+            path: None,
+            filename: None,
+            content_type: None,
+        });
+        let client = reqwest::Client::new();
+        let build = || request.build(&client).build().unwrap();
+        let (first, second) = (build(), build());
+        assert_eq!(
+            first.headers()[CONTENT_TYPE],
+            second.headers()[CONTENT_TYPE]
+        );
+        assert_eq!(
+            first.body().and_then(|body| body.as_bytes()),
+            second.body().and_then(|body| body.as_bytes())
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! The response received, with the metadata collected while receiving it.
+use std::{str::FromStr, time::Duration};
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    StatusCode, Version,
+};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Unknown charset: {0}")]
+pub struct UnknownCharset(String);
+
+/// A character encoding given by its label, e.g. `iso-8859-1` or `shift_jis`
+#[derive(Debug, Clone, Copy)]
+pub struct Charset(pub &'static Encoding);
+
+impl FromStr for Charset {
+    type Err = UnknownCharset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Encoding::for_label(s.trim().as_bytes())
+            .map(Charset)
+            .ok_or_else(|| UnknownCharset(s.to_string()))
+    }
+}
+
+/// The value of the `charset` parameter of a media type
+fn charset_param(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Guess the encoding of a body without charset: from its byte order mark, then UTF-8 if it is
+/// valid, then the `<meta charset>` of HTML, and windows-1252 as browsers do otherwise
+fn detect(body: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return encoding;
+    }
+    if std::str::from_utf8(body).is_ok() {
+        return UTF_8;
+    }
+    let head = String::from_utf8_lossy(&body[..body.len().min(1024)]).to_ascii_lowercase();
+    let meta = head.find("charset=").and_then(|start| {
+        let label = head[start + "charset=".len()..].trim_start_matches(['"', '\'']);
+        let end = label
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-_:.".contains(c)))
+            .unwrap_or(label.len());
+        Encoding::for_label(&label.as_bytes()[..end])
+    });
+    meta.unwrap_or(WINDOWS_1252)
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// The URL the response came from, after following redirects
+    pub url: String,
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    /// Time from sending the request until the response headers were received
+    pub wait: Duration,
+    /// Time spent receiving the body
+    pub receive: Duration,
+}
+
+impl Response {
+    /// The value of the header as a string, if it is present and valid UTF-8
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// The body decoded with `charset`, or else the charset of the `Content-Type`, or else the
+    /// detected one
+    pub fn text(&self, charset: Option<Charset>) -> String {
+        let encoding = charset.map(|c| c.0).or_else(|| {
+            self.header(CONTENT_TYPE.as_str())
+                .and_then(charset_param)
+                .and_then(|label| Encoding::for_label(label.as_bytes()))
+        });
+        let encoding = encoding.unwrap_or_else(|| detect(&self.body));
+        encoding.decode(&self.body).0.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use super::{Charset, Response};
+
+    fn response(content_type: &str, body: &[u8]) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", content_type.parse().unwrap());
+        Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers,
+            body: body.to_vec(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn body_is_decoded_with_the_charset() {
+        let latin1 = response("text/plain; charset=\"ISO-8859-1\"", b"caf\xe9");
+        assert_eq!(latin1.text(None), "café");
+        let sjis = response("text/plain", b"\x83\x65\x83\x58\x83\x67");
+        assert_eq!(sjis.text(Some("shift_jis".parse().unwrap())), "テスト");
+        assert!("klingon".parse::<Charset>().is_err());
+    }
+    #[test]
+    fn charset_is_detected_without_parameter() {
+        assert_eq!(response("text/plain", "café".as_bytes()).text(None), "café");
+        let html = b"<meta charset=\"iso-8859-15\"><p>\xa4</p>";
+        assert_eq!(
+            response("text/html", html).text(None),
+            "<meta charset=\"iso-8859-15\"><p>€</p>"
+        );
+        assert_eq!(response("text/plain", b"caf\xe9").text(None), "café");
+    }
+}
@@ -0,0 +1,548 @@
+//! Extracting elements from HTML documents with CSS selectors.
+//!
+//! Supported selectors: `*`, type (`a`), `#id`, `.class`, attributes (`[href]`, `[rel=next]`,
+//! `^=`, `$=`, `*=` and `~=`), compound selectors (`a.external[href]`), the descendant (` `) and
+//! child (`>`) combinators, and selector lists (`h1, h2`).
+use std::str::FromStr;
+
+use crate::markup::{self, Kind, Token};
+
+/// Elements whose start tag implicitly closes an open element of the same name
+const SELF_NESTING_FORBIDDEN: &[&str] = &["li", "p", "option", "dt", "dd", "tr", "td", "th"];
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid CSS selector: {0}")]
+pub struct Error(String);
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn push_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(e) => e.push_text(text),
+            }
+        }
+    }
+
+    /// The text content, with whitespace collapsed
+    fn text(&self) -> String {
+        let mut text = String::new();
+        self.push_text(&mut text);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Decode the character references most commonly found in documents
+pub(crate) fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    let mut output = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => match entity.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(&['x', 'X'][..]) => {
+                        u32::from_str_radix(&hex[1..], 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                    }
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                output.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Parse the attributes of a start tag, e.g. `<a href="/" class=nav hidden>`, keeping the case
+/// of their names
+pub(crate) fn parse_attrs(tag: &str) -> Vec<(String, String)> {
+    let inner = tag
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim_end_matches('/');
+    let chars: Vec<char> = inner.chars().collect();
+    // Skip the tag name
+    let mut i = chars
+        .iter()
+        .position(|c| c.is_whitespace())
+        .unwrap_or(chars.len());
+    let mut attrs = Vec::new();
+    loop {
+        while i < chars.len() && (chars[i].is_whitespace() || chars[i] == '/') {
+            i += 1;
+        }
+        if i >= chars.len() {
+            return attrs;
+        }
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '=' {
+            i += 1;
+        }
+        let name: String = chars[start..i].iter().collect();
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        let mut value = String::new();
+        if chars.get(i) == Some(&'=') {
+            i += 1;
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            match chars.get(i) {
+                Some(&q) if q == '"' || q == '\'' => {
+                    let end = chars[i + 1..]
+                        .iter()
+                        .position(|c| *c == q)
+                        .map_or(chars.len(), |len| i + 1 + len);
+                    value = chars[i + 1..end].iter().collect();
+                    i = end + 1;
+                }
+                _ => {
+                    let start = i;
+                    while i < chars.len() && !chars[i].is_whitespace() {
+                        i += 1;
+                    }
+                    value = chars[start..i].iter().collect();
+                }
+            }
+        }
+        attrs.push((name, decode_entities(&value)));
+    }
+}
+
+/// Build the element tree, closing the elements left open the way browsers mostly do
+fn parse_html(input: &str) -> Element {
+    let mut stack = vec![Element::default()];
+    let close_top = |stack: &mut Vec<Element>| {
+        let element = stack.pop().expect("the root is never closed");
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(Node::Element(element));
+        }
+    };
+    for token in markup::tokenize(input, Kind::Html) {
+        match token {
+            Token::Open {
+                name,
+                source,
+                self_closing,
+            } => {
+                let top = &stack[stack.len() - 1].name;
+                if top == &name && SELF_NESTING_FORBIDDEN.contains(&name.as_str()) {
+                    close_top(&mut stack);
+                }
+                let element = Element {
+                    attrs: parse_attrs(source)
+                        .into_iter()
+                        .map(|(name, value)| (name.to_ascii_lowercase(), value))
+                        .collect(),
+                    name,
+                    children: Vec::new(),
+                };
+                if self_closing {
+                    stack
+                        .last_mut()
+                        .expect("the root is never closed")
+                        .children
+                        .push(Node::Element(element));
+                } else {
+                    stack.push(element);
+                }
+            }
+            Token::Close { name, .. } => {
+                // A closing tag without a matching open element is ignored
+                if let Some(index) = stack.iter().skip(1).rposition(|e| e.name == name) {
+                    while stack.len() > index + 1 {
+                        close_top(&mut stack);
+                    }
+                }
+            }
+            Token::Text(text) => stack
+                .last_mut()
+                .expect("the root is never closed")
+                .children
+                .push(Node::Text(decode_entities(text))),
+            // Content of `<script>`, `<style>`...
+            Token::Other(source) if !source.starts_with('<') => stack
+                .last_mut()
+                .expect("the root is never closed")
+                .children
+                .push(Node::Text(source.to_string())),
+            Token::Other(_) => {}
+        }
+    }
+    while stack.len() > 1 {
+        close_top(&mut stack);
+    }
+    stack.pop().unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+enum AttrOp {
+    Exists,
+    Equals(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    /// `~=`: one of the whitespace-separated words
+    Word(String),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, AttrOp)>,
+}
+
+impl Compound {
+    fn matches(&self, element: &Element) -> bool {
+        let attr_matches = |(name, op): &(String, AttrOp)| match (element.attr(name), op) {
+            (None, _) => false,
+            (Some(_), AttrOp::Exists) => true,
+            (Some(v), AttrOp::Equals(x)) => v == x,
+            (Some(v), AttrOp::Prefix(x)) => !x.is_empty() && v.starts_with(x.as_str()),
+            (Some(v), AttrOp::Suffix(x)) => !x.is_empty() && v.ends_with(x.as_str()),
+            (Some(v), AttrOp::Contains(x)) => !x.is_empty() && v.contains(x.as_str()),
+            (Some(v), AttrOp::Word(x)) => v.split_whitespace().any(|w| w == x),
+        };
+        let classes = element.attr("class").unwrap_or_default();
+        self.tag.as_ref().is_none_or(|tag| *tag == element.name)
+            && self
+                .id
+                .as_ref()
+                .is_none_or(|id| element.attr("id") == Some(id.as_str()))
+            && self
+                .classes
+                .iter()
+                .all(|c| classes.split_whitespace().any(|w| w == c))
+            && self.attrs.iter().all(attr_matches)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+/// A parsed selector list
+#[derive(Debug, Clone)]
+pub struct Selector {
+    /// Each alternative is a sequence of compound selectors, each with the combinator that
+    /// relates it to the previous one
+    alternatives: Vec<Vec<(Combinator, Compound)>>,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+impl FromStr for Selector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |message: &str| Error(format!("{} in `{}`", message, s));
+        let chars: Vec<char> = s.chars().collect();
+        let mut i = 0;
+        let ident = |i: &mut usize| {
+            let start = *i;
+            while *i < chars.len() && is_ident_char(chars[*i]) {
+                *i += 1;
+            }
+            let name: String = chars[start..*i].iter().collect();
+            if name.is_empty() {
+                Err(error("expected a name"))
+            } else {
+                Ok(name)
+            }
+        };
+        let mut alternatives = Vec::new();
+        let mut sequence = Vec::new();
+        let mut compound = Compound::default();
+        let mut compound_started = false;
+        let mut combinator = Combinator::Descendant;
+        // Push the compound selector being parsed, if any, returning whether there was one
+        let end_compound = |sequence: &mut Vec<(Combinator, Compound)>,
+                            compound: &mut Compound,
+                            started: &mut bool,
+                            combinator: Combinator| {
+            let pushed = *started;
+            if pushed {
+                sequence.push((combinator, std::mem::take(compound)));
+                *started = false;
+            }
+            pushed
+        };
+        loop {
+            let c = chars.get(i).copied();
+            match c {
+                Some(c) if c.is_whitespace() => {
+                    if end_compound(
+                        &mut sequence,
+                        &mut compound,
+                        &mut compound_started,
+                        combinator,
+                    ) {
+                        combinator = Combinator::Descendant;
+                    }
+                    i += 1;
+                }
+                Some('>') => {
+                    if end_compound(
+                        &mut sequence,
+                        &mut compound,
+                        &mut compound_started,
+                        combinator,
+                    ) {
+                        combinator = Combinator::Descendant;
+                    }
+                    if sequence.is_empty() || combinator == Combinator::Child {
+                        return Err(error("`>` without a selector before it"));
+                    }
+                    combinator = Combinator::Child;
+                    i += 1;
+                }
+                Some(',') | None => {
+                    let pushed = end_compound(
+                        &mut sequence,
+                        &mut compound,
+                        &mut compound_started,
+                        combinator,
+                    );
+                    if sequence.is_empty() {
+                        return Err(error("empty selector"));
+                    }
+                    if !pushed && combinator == Combinator::Child {
+                        return Err(error("`>` without a selector after it"));
+                    }
+                    alternatives.push(std::mem::take(&mut sequence));
+                    combinator = Combinator::Descendant;
+                    i += 1;
+                    if c.is_none() {
+                        break;
+                    }
+                }
+                Some('*') => {
+                    compound_started = true;
+                    i += 1;
+                }
+                Some('#') => {
+                    i += 1;
+                    compound.id = Some(ident(&mut i)?);
+                    compound_started = true;
+                }
+                Some('.') => {
+                    i += 1;
+                    compound.classes.push(ident(&mut i)?);
+                    compound_started = true;
+                }
+                Some('[') => {
+                    let end = chars[i..]
+                        .iter()
+                        .position(|c| *c == ']')
+                        .map(|len| i + len)
+                        .ok_or_else(|| error("missing `]`"))?;
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    let (name, op) = match inner.find('=') {
+                        None => (inner.trim().to_string(), AttrOp::Exists),
+                        Some(eq) => {
+                            let (name, prefix) = match inner[..eq].chars().last() {
+                                Some(p @ ('^' | '$' | '*' | '~')) => (&inner[..eq - 1], Some(p)),
+                                _ => (&inner[..eq], None),
+                            };
+                            let value = inner[eq + 1..].trim();
+                            let value = ['"', '\'']
+                                .iter()
+                                .find_map(|q| {
+                                    value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q))
+                                })
+                                .unwrap_or(value)
+                                .to_string();
+                            let op = match prefix {
+                                Some('^') => AttrOp::Prefix(value),
+                                Some('$') => AttrOp::Suffix(value),
+                                Some('*') => AttrOp::Contains(value),
+                                Some('~') => AttrOp::Word(value),
+                                _ => AttrOp::Equals(value),
+                            };
+                            (name.trim().to_string(), op)
+                        }
+                    };
+                    if name.is_empty() || !name.chars().all(is_ident_char) {
+                        return Err(error("invalid attribute selector"));
+                    }
+                    compound.attrs.push((name.to_ascii_lowercase(), op));
+                    compound_started = true;
+                    i = end + 1;
+                }
+                Some(c) if is_ident_char(c) => {
+                    if compound_started {
+                        return Err(error("the type must come first in a compound selector"));
+                    }
+                    compound.tag = Some(ident(&mut i)?.to_ascii_lowercase());
+                    compound_started = true;
+                }
+                Some(c) => return Err(error(&format!("unexpected `{}`", c))),
+            }
+        }
+        Ok(Selector { alternatives })
+    }
+}
+
+/// Check the part of the selector before an element that matched, against its ancestors
+fn ancestors_match(
+    parts: &[(Combinator, Compound)],
+    combinator: Combinator,
+    ancestors: &[&Element],
+) -> bool {
+    let (last, rest) = match parts.split_last() {
+        Some(split) => split,
+        None => return true,
+    };
+    match combinator {
+        Combinator::Child => ancestors.split_last().is_some_and(|(parent, above)| {
+            last.1.matches(parent) && ancestors_match(rest, last.0, above)
+        }),
+        Combinator::Descendant => (0..ancestors.len()).rev().any(|i| {
+            last.1.matches(ancestors[i]) && ancestors_match(rest, last.0, &ancestors[..i])
+        }),
+    }
+}
+
+impl Selector {
+    fn matches(&self, element: &Element, ancestors: &[&Element]) -> bool {
+        self.alternatives
+            .iter()
+            .any(|parts| match parts.split_last() {
+                Some((last, rest)) => {
+                    last.1.matches(element) && ancestors_match(rest, last.0, ancestors)
+                }
+                None => false,
+            })
+    }
+}
+
+fn collect<'a>(
+    element: &'a Element,
+    selector: &Selector,
+    ancestors: &mut Vec<&'a Element>,
+    found: &mut Vec<&'a Element>,
+) {
+    for child in &element.children {
+        if let Node::Element(child) = child {
+            if selector.matches(child, ancestors) {
+                found.push(child);
+            }
+            ancestors.push(child);
+            collect(child, selector, ancestors, found);
+            ancestors.pop();
+        }
+    }
+}
+
+/// The text (or the value of `attr`) of each element matching the selector, in document order.
+/// With `attr`, elements without that attribute are skipped.
+pub fn select(html: &str, selector: &Selector, attr: Option<&str>) -> Vec<String> {
+    let root = parse_html(html);
+    let mut found = Vec::new();
+    collect(&root, selector, &mut Vec::new(), &mut found);
+    found
+        .into_iter()
+        .filter_map(|element| match attr {
+            Some(attr) => element.attr(&attr.to_ascii_lowercase()).map(str::to_string),
+            None => Some(element.text()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selector;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html><body>
+  <ul id="nav">
+    <li class="item first"><a href="/a">A &amp; B</a>
+    <li class="item"><a href="https://example.com/b" rel=external>B</a>
+  </ul>
+  <div><p>Para <b>bold</b></p><a>no link</a></div>
+</body></html>"#;
+
+    fn select(selector: &str, attr: Option<&str>) -> Vec<String> {
+        super::select(PAGE, &selector.parse::<Selector>().unwrap(), attr)
+    }
+
+    #[test]
+    fn text_of_matching_elements() {
+        assert_eq!(select("#nav .item", None), vec!["A & B", "B"]);
+        assert_eq!(select("li.first > a", None), vec!["A & B"]);
+        assert_eq!(
+            select("div p, ul > li > a[rel=external]", None),
+            vec!["B", "Para bold"]
+        );
+        assert_eq!(select("body > a", None), Vec::<String>::new());
+    }
+    #[test]
+    fn attribute_values() {
+        assert_eq!(
+            select("a", Some("href")),
+            vec!["/a", "https://example.com/b"]
+        );
+        assert_eq!(
+            select("a[href^=https]", Some("href")),
+            vec!["https://example.com/b"]
+        );
+    }
+    #[test]
+    fn invalid_selectors_are_rejected() {
+        for selector in ["", "a,", "> a", "a[href", ".", "a!"] {
+            assert!(selector.parse::<Selector>().is_err(), "{}", selector);
+        }
+    }
+}
@@ -0,0 +1,1149 @@
+//! The scripts of `hooks.yaml`: a small language run in-process before each request and after
+//! each response, to compute signatures, check responses and reshape them without spawning a
+//! program per exchange.
+//!
+//! The syntax is a subset of Rhai's:
+//!
+//! ```text
+//! // Sign the request with a secret of the environment
+//! let stamp = timestamp().to_string();
+//! let payload = request.method + "\n" + request.url + "\n" + stamp;
+//! request.headers["x-timestamp"] = stamp;
+//! request.headers["x-signature"] = hmac_sha256_base64(env("API_SECRET"), payload);
+//! ```
+//!
+//! Values are those of JSON: `()`, booleans, integers, floats, strings, arrays `[1, "a"]` and maps
+//! `#{ id: 1, "content-type": "text/plain" }`. The statements are `let`, assignments with `=`,
+//! `+=` and `-=`, `if`/`else`, `while`, `for x in` arrays, map keys or ranges `0..n`, `break`,
+//! `continue` and `return`. `x.f(a)` calls `f(x, a)`, except `list.push(x)` which appends to the
+//! list.
+//!
+//! Functions: `len`, `type_of`, `to_upper`, `to_lower`, `trim`, `contains`, `starts_with`,
+//! `ends_with`, `replace`, `split`, `join`, `keys`, `values`, `parse_json`, `to_json`,
+//! `to_string`, `parse_int`, `parse_float`, `to_int`, `to_float`, `sha256`, `hmac_sha256`,
+//! `hmac_sha256_base64`, `base64_encode`, `base64_decode`, `url_encode`, `timestamp`, `env`,
+//! `print`, `assert` and `fail`.
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use ring::{digest, hmac};
+use serde_json::{Map, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("line {line}: {message}")]
+    Runtime { line: usize, message: String },
+    /// `assert` or `fail` called by the script
+    #[error("line {line}: {message}")]
+    Failed { line: usize, message: String },
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The most statements and loop iterations a run may go through, so that a script looping
+/// forever fails instead of hanging every request
+const MAX_OPERATIONS: usize = 1_000_000;
+
+/// The unreserved characters of https://datatracker.ietf.org/doc/html/rfc3986#section-2.3, kept
+/// as is by `url_encode`
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// The operators and delimiters, the longest first so that `==` isn't read as two `=`
+const PUNCTUATION: &[&str] = &[
+    "#{", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "..", "(", ")", "[", "]", "{", "}", ",",
+    ";", ".", ":", "=", "<", ">", "+", "-", "*", "/", "%", "!",
+];
+
+/// The binary operators with their precedence, the higher binding tighter
+const BINARY: &[(&str, u8)] = &[
+    ("||", 1),
+    ("&&", 2),
+    ("==", 3),
+    ("!=", 3),
+    ("<", 4),
+    ("<=", 4),
+    (">", 4),
+    (">=", 4),
+    ("..", 5),
+    ("+", 6),
+    ("-", 6),
+    ("*", 7),
+    ("/", 7),
+    ("%", 7),
+];
+
+const KEYWORDS: &[&str] = &[
+    "let", "if", "else", "while", "for", "in", "break", "continue", "return", "true", "false",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Punct(&'static str),
+}
+
+/// Split the source into tokens, each with its line
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut rest = source;
+    let error = |line, message: &str| Error::Syntax {
+        line,
+        message: message.to_string(),
+    };
+    while let Some(c) = rest.chars().next() {
+        if c == '\n' {
+            line += 1;
+            rest = &rest[1..];
+        } else if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            let end = comment
+                .find("*/")
+                .ok_or_else(|| error(line, "unterminated comment"))?;
+            line += comment[..end].matches('\n').count();
+            rest = &comment[end + 2..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            // A dot followed by a digit makes a float, `0..n` is a range
+            let fraction = rest[end..]
+                .strip_prefix('.')
+                .filter(|f| f.starts_with(|c: char| c.is_ascii_digit()));
+            let end = match fraction {
+                Some(f) => end + 1 + f.find(|c: char| !c.is_ascii_digit()).unwrap_or(f.len()),
+                None => end,
+            };
+            let number = &rest[..end];
+            let token = if fraction.is_some() {
+                Token::Float(number.parse().map_err(|_| error(line, "invalid number"))?)
+            } else {
+                Token::Int(
+                    number
+                        .parse()
+                        .map_err(|_| error(line, "integer too large"))?,
+                )
+            };
+            tokens.push((token, line));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push((Token::Ident(rest[..end].to_string()), line));
+            rest = &rest[end..];
+        } else if c == '"' {
+            let (s, len) = string(&rest[1..]).ok_or_else(|| error(line, "unterminated string"))?;
+            tokens.push((Token::Str(s), line));
+            line += rest[..len + 1].matches('\n').count();
+            rest = &rest[len + 1..];
+        } else {
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| rest.starts_with(*p))
+                .ok_or_else(|| error(line, &format!("unexpected character `{}`", c)))?;
+            tokens.push((Token::Punct(punct), line));
+            rest = &rest[punct.len()..];
+        }
+    }
+    Ok(tokens)
+}
+
+/// The content of a string literal with its escapes resolved, and the length of the literal
+/// after the opening quote
+fn string(s: &str) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, i + 1)),
+            '\\' => value.push(match chars.next()?.1 {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                other => other,
+            }),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Var(String),
+    Array(Vec<Expr>),
+    Map(Vec<(String, Expr)>),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// `list.push(x)`, appending to the list in place
+    Push(Box<Expr>, Box<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Let(String, Expr),
+    /// The place, the operator if it is `+=` or `-=`, and the value
+    Assign(Expr, Option<&'static str>, Expr),
+    Expr(Expr),
+    Block(Vec<Line>),
+    If(Expr, Vec<Line>, Vec<Line>),
+    While(Expr, Vec<Line>),
+    For(String, Expr, Vec<Line>),
+    Break,
+    Continue,
+    Return,
+}
+
+/// A statement and the line it starts on
+#[derive(Debug)]
+struct Line(Statement, usize);
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Syntax {
+            line: self.line(),
+            message: message.into(),
+        }
+    }
+
+    fn is(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.is(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Ident(name)) if name == keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        Err(self.error(format!("expected `{}`", punct)))
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    /// The statements up to `}`, or to the end of the source for the top level
+    fn statements(&mut self, top: bool) -> Result<Vec<Line>> {
+        let mut lines = Vec::new();
+        loop {
+            while self.eat(";") {}
+            match self.peek() {
+                None if top => return Ok(lines),
+                None => return Err(self.error("expected `}`")),
+                Some(Token::Punct("}")) if !top => {
+                    self.pos += 1;
+                    return Ok(lines);
+                }
+                _ => lines.push(self.statement()?),
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Line>> {
+        self.expect("{")?;
+        self.statements(false)
+    }
+
+    fn statement(&mut self) -> Result<Line> {
+        let line = self.line();
+        let statement = if self.eat_keyword("let") {
+            let name = self.name()?;
+            self.expect("=")?;
+            Statement::Let(name, self.expr()?)
+        } else if self.eat_keyword("if") {
+            return self.if_statement(line);
+        } else if self.eat_keyword("while") {
+            let condition = self.expr()?;
+            Statement::While(condition, self.block()?)
+        } else if self.eat_keyword("for") {
+            let name = self.name()?;
+            if !self.eat_keyword("in") {
+                return Err(self.error("expected `in`"));
+            }
+            let items = self.expr()?;
+            Statement::For(name, items, self.block()?)
+        } else if self.eat_keyword("break") {
+            Statement::Break
+        } else if self.eat_keyword("continue") {
+            Statement::Continue
+        } else if self.eat_keyword("return") {
+            Statement::Return
+        } else if self.is("{") {
+            Statement::Block(self.block()?)
+        } else {
+            let expr = self.expr()?;
+            let op = if self.eat("=") {
+                None
+            } else if self.eat("+=") {
+                Some("+")
+            } else if self.eat("-=") {
+                Some("-")
+            } else {
+                return Ok(Line(Statement::Expr(expr), line));
+            };
+            Statement::Assign(expr, op, self.expr()?)
+        };
+        Ok(Line(statement, line))
+    }
+
+    fn if_statement(&mut self, line: usize) -> Result<Line> {
+        let condition = self.expr()?;
+        let then = self.block()?;
+        let otherwise = if !self.eat_keyword("else") {
+            Vec::new()
+        } else if self.eat_keyword("if") {
+            let line = self.line();
+            vec![self.if_statement(line)?]
+        } else {
+            self.block()?
+        };
+        Ok(Line(Statement::If(condition, then, otherwise), line))
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(1)
+    }
+
+    fn binary(&mut self, min: u8) -> Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = BINARY
+                .iter()
+                .find(|(op, precedence)| *precedence >= min && self.is(op));
+            let (op, precedence) = match op {
+                Some(op) => *op,
+                None => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.binary(precedence + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        for op in ["!", "-"] {
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat(".") {
+                let name = self.name()?;
+                if !self.eat("(") {
+                    expr = Expr::Field(Box::new(expr), name);
+                    continue;
+                }
+                let mut args = self.list(")")?;
+                expr = match (name.as_str(), args.len()) {
+                    ("push", 1) => Expr::Push(Box::new(expr), Box::new(args.remove(0))),
+                    _ => {
+                        args.insert(0, expr);
+                        Expr::Call(name, args)
+                    }
+                };
+            } else if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// The expressions separated by commas up to `end`
+    fn list(&mut self, end: &str) -> Result<Vec<Expr>> {
+        let mut items = Vec::new();
+        while !self.eat(end) {
+            items.push(self.expr()?);
+            if !self.eat(",") {
+                self.expect(end)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| self.error("unexpected end of script"))?;
+        self.pos += 1;
+        Ok(match token {
+            Token::Int(n) => Expr::Literal(n.into()),
+            Token::Float(n) => Expr::Literal(n.into()),
+            Token::Str(s) => Expr::Literal(s.into()),
+            Token::Ident(name) if name == "true" || name == "false" => {
+                Expr::Literal(Value::Bool(name == "true"))
+            }
+            Token::Ident(name) if KEYWORDS.contains(&name.as_str()) => {
+                self.pos -= 1;
+                return Err(self.error(format!("unexpected `{}`", name)));
+            }
+            Token::Ident(name) if self.eat("(") => Expr::Call(name, self.list(")")?),
+            Token::Ident(name) => Expr::Var(name),
+            Token::Punct("(") if self.eat(")") => Expr::Literal(Value::Null),
+            Token::Punct("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                expr
+            }
+            Token::Punct("[") => Expr::Array(self.list("]")?),
+            Token::Punct("#{") => {
+                let mut entries = Vec::new();
+                while !self.eat("}") {
+                    let key = match self.peek().cloned() {
+                        Some(Token::Ident(key)) | Some(Token::Str(key)) => key,
+                        _ => return Err(self.error("expected a key")),
+                    };
+                    self.pos += 1;
+                    self.expect(":")?;
+                    entries.push((key, self.expr()?));
+                    if !self.eat(",") {
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                Expr::Map(entries)
+            }
+            Token::Punct(punct) => {
+                self.pos -= 1;
+                return Err(self.error(format!("unexpected `{}`", punct)));
+            }
+        })
+    }
+}
+
+/// How a statement ended
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return,
+}
+
+/// A step into a value, for the places assigned to
+enum Key {
+    Field(String),
+    Index(Value),
+}
+
+struct Interpreter {
+    scopes: Vec<HashMap<String, Value>>,
+    /// The line of the statement being run, for the errors
+    line: usize,
+    operations: usize,
+}
+
+impl Interpreter {
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Runtime {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn count(&mut self) -> Result<()> {
+        self.operations += 1;
+        if self.operations > MAX_OPERATIONS {
+            return Err(self.error("too many operations, the script may loop forever"));
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, lines: &[Line], scope: HashMap<String, Value>) -> Result<Flow> {
+        self.scopes.push(scope);
+        let flow = self.lines(lines);
+        self.scopes.pop();
+        flow
+    }
+
+    fn lines(&mut self, lines: &[Line]) -> Result<Flow> {
+        for line in lines {
+            match self.statement(line)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, Line(statement, line): &Line) -> Result<Flow> {
+        self.line = *line;
+        self.count()?;
+        match statement {
+            Statement::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                let scope = self.scopes.last_mut().expect("no scope");
+                scope.insert(name.clone(), value);
+            }
+            Statement::Assign(place, op, expr) => {
+                let value = self.eval(expr)?;
+                let (name, keys) = self.place(place)?;
+                let line = self.line;
+                let target = self.find(&name, &keys)?;
+                let value = match op {
+                    Some(op) => binary(op, target, &value),
+                    None => Ok(value),
+                };
+                *target = value.map_err(|message| Error::Runtime { line, message })?;
+            }
+            Statement::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            Statement::Block(lines) => return self.block(lines, HashMap::new()),
+            Statement::If(condition, then, otherwise) => {
+                let branch = if self.condition(condition)? {
+                    then
+                } else {
+                    otherwise
+                };
+                return self.block(branch, HashMap::new());
+            }
+            Statement::While(condition, body) => {
+                while self.condition(condition)? {
+                    match self.block(body, HashMap::new())? {
+                        Flow::Break => break,
+                        Flow::Return => return Ok(Flow::Return),
+                        Flow::Next | Flow::Continue => self.count()?,
+                    }
+                }
+            }
+            Statement::For(name, items, body) => {
+                let items = match self.eval(items)? {
+                    Value::Array(items) => items,
+                    Value::Object(map) => map.into_iter().map(|(key, _)| key.into()).collect(),
+                    Value::String(s) => s.chars().map(|c| c.to_string().into()).collect(),
+                    other => {
+                        return Err(self.error(format!("can't iterate over {}", type_of(&other))))
+                    }
+                };
+                for item in items {
+                    let scope = HashMap::from([(name.clone(), item)]);
+                    match self.block(body, scope)? {
+                        Flow::Break => break,
+                        Flow::Return => return Ok(Flow::Return),
+                        Flow::Next | Flow::Continue => self.count()?,
+                    }
+                }
+            }
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Return => return Ok(Flow::Return),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<bool> {
+        match self.eval(expr)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(self.error(format!("expected a condition, got {}", type_of(&other)))),
+        }
+    }
+
+    /// The variable and the steps into it of an expression assigned to
+    fn place(&mut self, expr: &Expr) -> Result<(String, Vec<Key>)> {
+        match expr {
+            Expr::Var(name) => Ok((name.clone(), Vec::new())),
+            Expr::Field(target, field) => {
+                let (name, mut keys) = self.place(target)?;
+                keys.push(Key::Field(field.clone()));
+                Ok((name, keys))
+            }
+            Expr::Index(target, index) => {
+                let index = self.eval(index)?;
+                let (name, mut keys) = self.place(target)?;
+                keys.push(Key::Index(index));
+                Ok((name, keys))
+            }
+            _ => Err(self.error("can only assign to variables, fields and indexes")),
+        }
+    }
+
+    /// The value at the place, map entries being created as they are assigned to
+    fn find(&mut self, name: &str, keys: &[Key]) -> Result<&mut Value> {
+        let line = self.line;
+        let error = |message: String| Error::Runtime { line, message };
+        let mut value = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .ok_or_else(|| error(format!("unknown variable `{}`", name)))?;
+        for key in keys {
+            value = match (value, key) {
+                (Value::Object(map), Key::Field(key))
+                | (Value::Object(map), Key::Index(Value::String(key))) => {
+                    map.entry(key.clone()).or_insert(Value::Null)
+                }
+                (Value::Array(items), Key::Index(Value::Number(n))) => {
+                    let len = items.len();
+                    let i = n
+                        .as_i64()
+                        .and_then(|i| position(i, len))
+                        .ok_or_else(|| error(format!("index {} out of bounds", n)))?;
+                    &mut items[i]
+                }
+                (value, _) => return Err(error(format!("can't index {}", type_of(value)))),
+            };
+        }
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        let error = |line, message| Error::Runtime { line, message };
+        Ok(match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Var(name) => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .cloned()
+                .ok_or_else(|| self.error(format!("unknown variable `{}`", name)))?,
+            Expr::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.eval(item))
+                    .collect::<Result<_>>()?,
+            ),
+            Expr::Map(entries) => {
+                let mut map = Map::new();
+                for (key, expr) in entries {
+                    map.insert(key.clone(), self.eval(expr)?);
+                }
+                Value::Object(map)
+            }
+            Expr::Field(target, field) => {
+                let target = self.eval(target)?;
+                index(&target, &Value::String(field.clone())).map_err(|m| self.error(m))?
+            }
+            Expr::Index(target, key) => {
+                let target = self.eval(target)?;
+                let key = self.eval(key)?;
+                index(&target, &key).map_err(|m| self.error(m))?
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                self.count()?;
+                call(name, args).map_err(|e| match e {
+                    Call::Error(message) => self.error(message),
+                    Call::Failed(message) => Error::Failed {
+                        line: self.line,
+                        message,
+                    },
+                })?
+            }
+            Expr::Push(target, item) => {
+                let item = self.eval(item)?;
+                let (name, keys) = self.place(target)?;
+                let line = self.line;
+                match self.find(&name, &keys)? {
+                    Value::Array(items) => items.push(item),
+                    other => {
+                        let message = format!("can't push to {}", type_of(other));
+                        return Err(error(line, message));
+                    }
+                }
+                Value::Null
+            }
+            Expr::Unary(op, operand) => match (*op, self.eval(operand)?) {
+                ("!", Value::Bool(b)) => Value::Bool(!b),
+                ("-", Value::Number(n)) if n.is_i64() => {
+                    let n = n.as_i64().unwrap_or_default();
+                    n.checked_neg()
+                        .ok_or_else(|| self.error("integer overflow"))?
+                        .into()
+                }
+                ("-", Value::Number(n)) => (-n.as_f64().unwrap_or_default()).into(),
+                (op, other) => {
+                    return Err(self.error(format!("can't apply `{}` to {}", op, type_of(&other))))
+                }
+            },
+            Expr::Binary(op @ ("&&" | "||"), left, right) => {
+                let left = self.condition(left)?;
+                if left == (*op == "||") {
+                    Value::Bool(left)
+                } else {
+                    Value::Bool(self.condition(right)?)
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(op, &left, &right).map_err(|m| self.error(m))?
+            }
+        })
+    }
+}
+
+/// The position of the index `i` in a sequence of `len` items, negative indexes counting from
+/// the end
+fn position(i: i64, len: usize) -> Option<usize> {
+    let i = if i < 0 { len as i64 + i } else { i };
+    usize::try_from(i).ok().filter(|i| *i < len)
+}
+
+/// The field or item of the value, `()` for the missing fields of a map
+fn index(target: &Value, key: &Value) -> std::result::Result<Value, String> {
+    match (target, key) {
+        (Value::Object(map), Value::String(key)) => Ok(map.get(key).cloned().unwrap_or_default()),
+        (Value::Array(items), Value::Number(n)) => n
+            .as_i64()
+            .and_then(|i| position(i, items.len()))
+            .map(|i| items[i].clone())
+            .ok_or_else(|| format!("index {} out of bounds", n)),
+        (Value::String(s), Value::Number(n)) => {
+            let chars: Vec<char> = s.chars().collect();
+            n.as_i64()
+                .and_then(|i| position(i, chars.len()))
+                .map(|i| chars[i].to_string().into())
+                .ok_or_else(|| format!("index {} out of bounds", n))
+        }
+        (target, key) => Err(format!(
+            "can't index {} with {}",
+            type_of(target),
+            type_of(key)
+        )),
+    }
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "()",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "int",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "map",
+    }
+}
+
+/// The value as text, strings without quotes and `()` as nothing
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Whether the values are equal, integers and floats being compared by value
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (a, b) => a == b,
+    }
+}
+
+fn binary(op: &str, a: &Value, b: &Value) -> std::result::Result<Value, String> {
+    let invalid = || format!("can't apply `{}` to {} and {}", op, type_of(a), type_of(b));
+    match (op, a, b) {
+        ("==", a, b) => return Ok(equal(a, b).into()),
+        ("!=", a, b) => return Ok((!equal(a, b)).into()),
+        ("+", Value::String(_), _) | ("+", _, Value::String(_)) => {
+            return Ok(format!("{}{}", display(a), display(b)).into())
+        }
+        ("+", Value::Array(a), Value::Array(b)) => {
+            return Ok(Value::Array(a.iter().chain(b).cloned().collect()))
+        }
+        ("+", Value::Object(a), Value::Object(b)) => {
+            let mut map = a.clone();
+            map.extend(b.clone());
+            return Ok(Value::Object(map));
+        }
+        ("<" | "<=" | ">" | ">=", Value::String(a), Value::String(b)) => {
+            return Ok(compare(op, a.cmp(b)).into())
+        }
+        _ => {}
+    }
+    let (a, b) = match (a, b) {
+        (Value::Number(a), Value::Number(b)) => (a, b),
+        _ => return Err(invalid()),
+    };
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        let result = match op {
+            "+" => a.checked_add(b),
+            "-" => a.checked_sub(b),
+            "*" => a.checked_mul(b),
+            "/" | "%" if b == 0 => return Err("division by zero".to_string()),
+            "/" => a.checked_div(b),
+            "%" => a.checked_rem(b),
+            ".." => return Ok(Value::Array((a..b).map(Value::from).collect())),
+            _ => return Ok(compare(op, a.cmp(&b)).into()),
+        };
+        return result
+            .map(Value::from)
+            .ok_or_else(|| "integer overflow".to_string());
+    }
+    let (a, b) = (
+        a.as_f64().unwrap_or_default(),
+        b.as_f64().unwrap_or_default(),
+    );
+    Ok(match op {
+        "+" => (a + b).into(),
+        "-" => (a - b).into(),
+        "*" => (a * b).into(),
+        "/" => (a / b).into(),
+        "%" => (a % b).into(),
+        ".." => return Err(invalid()),
+        _ => match a.partial_cmp(&b) {
+            Some(ordering) => compare(op, ordering).into(),
+            None => false.into(),
+        },
+    })
+}
+
+fn compare(op: &str, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        "<" => ordering.is_lt(),
+        "<=" => ordering.is_le(),
+        ">" => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+/// Why a function call failed
+enum Call {
+    Error(String),
+    /// `assert` or `fail`
+    Failed(String),
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &str, message: &str) -> hmac::Tag {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+    hmac::sign(&key, message.as_bytes())
+}
+
+fn call(name: &str, args: Vec<Value>) -> std::result::Result<Value, Call> {
+    use Value::{Array, Bool, Null, Number, Object, String as Str};
+    let error = |message: String| Err(Call::Error(message));
+    Ok(match (name, args.as_slice()) {
+        ("len", [Str(s)]) => s.chars().count().into(),
+        ("len", [Array(items)]) => items.len().into(),
+        ("len", [Object(map)]) => map.len().into(),
+        ("type_of", [value]) => type_of(value).into(),
+        ("to_upper", [Str(s)]) => s.to_uppercase().into(),
+        ("to_lower", [Str(s)]) => s.to_lowercase().into(),
+        ("trim", [Str(s)]) => s.trim().into(),
+        ("contains", [Str(s), Str(part)]) => s.contains(part.as_str()).into(),
+        ("contains", [Array(items), item]) => items.iter().any(|i| equal(i, item)).into(),
+        ("contains", [Object(map), Str(key)]) => map.contains_key(key).into(),
+        ("starts_with", [Str(s), Str(prefix)]) => s.starts_with(prefix.as_str()).into(),
+        ("ends_with", [Str(s), Str(suffix)]) => s.ends_with(suffix.as_str()).into(),
+        ("replace", [Str(s), Str(from), Str(to)]) => s.replace(from.as_str(), to).into(),
+        ("split", [Str(s), Str(separator)]) => s.split(separator.as_str()).collect(),
+        ("join", [Array(items), Str(separator)]) => items
+            .iter()
+            .map(display)
+            .collect::<Vec<_>>()
+            .join(separator)
+            .into(),
+        ("keys", [Object(map)]) => map.keys().cloned().collect(),
+        ("values", [Object(map)]) => map.values().cloned().collect(),
+        ("parse_json", [Str(s)]) => match serde_json::from_str(s) {
+            Ok(value) => value,
+            Err(e) => return error(format!("invalid JSON: {}", e)),
+        },
+        ("to_json", [value]) => value.to_string().into(),
+        ("to_string", [value]) => display(value).into(),
+        ("parse_int", [Str(s)]) => match s.trim().parse::<i64>() {
+            Ok(n) => n.into(),
+            Err(_) => return error(format!("`{}` is not an integer", s)),
+        },
+        ("parse_float", [Str(s)]) => match s.trim().parse::<f64>() {
+            Ok(n) => n.into(),
+            Err(_) => return error(format!("`{}` is not a number", s)),
+        },
+        ("to_int", [Number(n)]) => match n.as_i64() {
+            Some(n) => n.into(),
+            None => (n.as_f64().unwrap_or_default().trunc() as i64).into(),
+        },
+        ("to_float", [Number(n)]) => n.as_f64().unwrap_or_default().into(),
+        ("sha256", [Str(s)]) => hex(digest::digest(&digest::SHA256, s.as_bytes()).as_ref()).into(),
+        ("hmac_sha256", [Str(key), Str(message)]) => hex(hmac_sha256(key, message).as_ref()).into(),
+        ("hmac_sha256_base64", [Str(key), Str(message)]) => {
+            base64::encode(hmac_sha256(key, message)).into()
+        }
+        ("base64_encode", [Str(s)]) => base64::encode(s).into(),
+        ("base64_decode", [Str(s)]) => match base64::decode(s.trim()) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned().into(),
+            Err(e) => return error(format!("invalid base64: {}", e)),
+        },
+        ("url_encode", [Str(s)]) => utf8_percent_encode(s, UNRESERVED).to_string().into(),
+        ("timestamp", []) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+            .into(),
+        ("env", [Str(name)]) => env::var(name).map_or(Null, Value::from),
+        ("print", [value]) => {
+            eprintln!("{}", display(value));
+            Null
+        }
+        ("assert", [Bool(true)]) | ("assert", [Bool(true), _]) => Null,
+        ("assert", [Bool(false)]) => return Err(Call::Failed("assertion failed".to_string())),
+        ("assert", [Bool(false), message]) => return Err(Call::Failed(display(message))),
+        ("fail", [message]) => return Err(Call::Failed(display(message))),
+        (name, args) if FUNCTIONS.contains(&name) => {
+            let types: Vec<_> = args.iter().map(type_of).collect();
+            return error(format!("`{}` can't take ({})", name, types.join(", ")));
+        }
+        (name, _) => return error(format!("unknown function `{}`", name)),
+    })
+}
+
+const FUNCTIONS: &[&str] = &[
+    "len",
+    "type_of",
+    "to_upper",
+    "to_lower",
+    "trim",
+    "contains",
+    "starts_with",
+    "ends_with",
+    "replace",
+    "split",
+    "join",
+    "keys",
+    "values",
+    "parse_json",
+    "to_json",
+    "to_string",
+    "parse_int",
+    "parse_float",
+    "to_int",
+    "to_float",
+    "sha256",
+    "hmac_sha256",
+    "hmac_sha256_base64",
+    "base64_encode",
+    "base64_decode",
+    "url_encode",
+    "timestamp",
+    "env",
+    "print",
+    "assert",
+    "fail",
+];
+
+/// A parsed script
+#[derive(Debug)]
+pub struct Script(Vec<Line>);
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        parser.statements(true).map(Script)
+    }
+
+    /// Run the script with the variables of `scope`, which get the values they have at the end
+    pub fn run(&self, scope: &mut Map<String, Value>) -> Result<()> {
+        let mut interpreter = Interpreter {
+            scopes: vec![scope.clone().into_iter().collect()],
+            line: 1,
+            operations: 0,
+        };
+        interpreter.lines(&self.0)?;
+        let variables = interpreter.scopes.pop().unwrap_or_default();
+        for (name, value) in scope.iter_mut() {
+            if let Some(new) = variables.get(name) {
+                *value = new.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use super::{Error, Script};
+
+    /// Run the script with `input` as the variable `x`, and return the value of `x` at the end
+    fn run(source: &str, input: Value) -> Result<Value, Error> {
+        let mut scope = Map::new();
+        scope.insert("x".to_string(), input);
+        Script::parse(source)?.run(&mut scope)?;
+        Ok(scope["x"].clone())
+    }
+
+    #[test]
+    fn expressions_follow_precedence() {
+        assert_eq!(run("x = 1 + 2 * 3 - 8 / 4 % 3;", json!(0)).unwrap(), 5);
+        assert_eq!(run("x = -(1 + 2) * 2.5", json!(0)).unwrap(), -7.5);
+        assert_eq!(
+            run("x = 1 < 2 && !(2 == 2.0) || \"b\" > \"a\"", json!(0)).unwrap(),
+            true
+        );
+        assert_eq!(run(r#"x = "n=" + 1 + [2][0]"#, json!(0)).unwrap(), "n=12");
+    }
+    #[test]
+    fn places_can_be_assigned_to() {
+        let source = r#"
+            x.headers["x-sig"] = "abc";   // new entry
+            x.items[-1] += 10;
+            x.items.push(#{ id: 4, "kind": "new" });
+            let name = x.name;
+            name = "changed";             /* only the copy */
+        "#;
+        let input = json!({"name": "a", "headers": {}, "items": [1, 2]});
+        assert_eq!(
+            run(source, input).unwrap(),
+            json!({
+                "name": "a",
+                "headers": {"x-sig": "abc"},
+                "items": [1, 12, {"id": 4, "kind": "new"}],
+            })
+        );
+    }
+    #[test]
+    fn loops_and_branches_run() {
+        let source = r#"
+            let total = 0;
+            for n in 0..10 {
+                if n % 2 == 0 { continue } else if n > 7 { break }
+                total += n;
+            }
+            let i = 0;
+            while true { i += 1; if i == 3 { break } }
+            for key in #{ a: 1, b: 2 } { x.push(key) }
+            x.push(total * 100 + i);
+        "#;
+        assert_eq!(run(source, json!([])).unwrap(), json!(["a", "b", 1603]));
+        assert!(matches!(
+            run("while true {}", json!(0)),
+            Err(Error::Runtime { .. })
+        ));
+    }
+    #[test]
+    fn functions_are_called_as_methods_too() {
+        assert_eq!(
+            run(
+                r#"x = x.trim().split(",").join("+").to_upper()"#,
+                json!(" a,b ")
+            )
+            .unwrap(),
+            "A+B"
+        );
+        assert_eq!(
+            run(
+                r#"x = parse_json(x).list.len()"#,
+                json!(r#"{"list": [1, 2]}"#)
+            )
+            .unwrap(),
+            2
+        );
+        assert_eq!(
+            run(
+                r#"x = hmac_sha256("key", "The quick brown fox jumps over the lazy dog")"#,
+                json!(0)
+            )
+            .unwrap(),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert_eq!(
+            run(r#"x = base64_encode("rq").base64_decode()"#, json!(0)).unwrap(),
+            "rq"
+        );
+        assert_eq!(
+            run(r#"x = url_encode("a b/c~")"#, json!(0)).unwrap(),
+            "a%20b%2Fc~"
+        );
+    }
+    #[test]
+    fn failures_have_the_line() {
+        match run("let a = 1;\nassert(a == 2, \"a is \" + a)", json!(0)) {
+            Err(Error::Failed { line: 2, message }) => assert_eq!(message, "a is 1"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match run("\n\nx = y", json!(0)) {
+            Err(Error::Runtime { line: 3, message }) => assert_eq!(message, "unknown variable `y`"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            Script::parse("let = 1"),
+            Err(Error::Syntax { line: 1, .. })
+        ));
+        assert!(Script::parse("x = \"open").is_err());
+        assert!(Script::parse("if x { ").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! `rq serve`: a server answering every request with a JSON description of it (method, path,
+//! query, headers and body), to see what a client actually sends.
+//!
+//! Rules given with `--respond STATUS PATH` change the status of the responses for a path, e.g.
+//! `--respond 404 /missing`. A path ending with `*` matches every path starting with the rest.
+use std::{convert::Infallible, future::Future, io, net::SocketAddr, sync::Arc};
+
+use hyper::{server::conn::Http, service::service_fn, Body, StatusCode};
+use serde_json::{Map, Value};
+use tokio::net::TcpListener;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't listen on {addr}: {source}")]
+    Bind { addr: SocketAddr, source: io::Error },
+    #[error("Invalid status `{0}` for --respond")]
+    InvalidStatus(String),
+    #[error("Invalid path `{0}` for --respond, expected a path starting with `/`")]
+    InvalidPath(String),
+}
+
+/// The status to answer with for the requests to a path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    status: StatusCode,
+    path: String,
+}
+
+impl Rule {
+    pub fn new(status: &str, path: &str) -> Result<Self, Error> {
+        let status = status
+            .parse()
+            .map_err(|_| Error::InvalidStatus(status.to_string()))?;
+        if !path.starts_with('/') {
+            return Err(Error::InvalidPath(path.to_string()));
+        }
+        Ok(Rule {
+            status,
+            path: path.to_string(),
+        })
+    }
+
+    /// The rules given as pairs of values of `--respond`
+    pub fn from_pairs(values: &[String]) -> Result<Vec<Self>, Error> {
+        values
+            .chunks(2)
+            .map(|pair| Rule::new(&pair[0], pair.get(1).map_or("", String::as_str)))
+            .collect()
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        match self.path.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.path,
+        }
+    }
+}
+
+/// The description of the request sent back as the response body. Repeated headers have an
+/// array of values.
+fn describe(parts: &hyper::http::request::Parts, body: &[u8]) -> Value {
+    let mut headers = Map::new();
+    for name in parts.headers.keys() {
+        let mut values: Vec<Value> = parts
+            .headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into())
+            .collect();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            values.into()
+        };
+        headers.insert(name.to_string(), value);
+    }
+    serde_json::json!({
+        "method": parts.method.as_str(),
+        "path": parts.uri.path(),
+        "query": parts.uri.query(),
+        "version": format!("{:?}", parts.version),
+        "headers": headers,
+        "body": String::from_utf8_lossy(body),
+    })
+}
+
+async fn respond(rules: &[Rule], request: hyper::Request<Body>) -> hyper::Response<Body> {
+    let (parts, body) = request.into_parts();
+    let (status, description) = match hyper::body::to_bytes(body).await {
+        Ok(body) => {
+            let status = rules
+                .iter()
+                .find(|rule| rule.matches(parts.uri.path()))
+                .map_or(StatusCode::OK, |rule| rule.status);
+            (status, describe(&parts, &body))
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({ "error": e.to_string() }),
+        ),
+    };
+    eprintln!("{} {} -> {}", parts.method, parts.uri, status.as_u16());
+    let mut body = serde_json::to_string_pretty(&description).unwrap_or_default();
+    body.push('\n');
+    hyper::Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+/// Answer the requests received on `addr` with `handler` until interrupted
+pub async fn serve<F, R>(addr: SocketAddr, handler: F) -> Result<(), Error>
+where
+    F: Fn(hyper::Request<Body>) -> R + Clone + Send + 'static,
+    R: Future<Output = hyper::Response<Body>> + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|source| Error::Bind { addr, source })?;
+    eprintln!("Listening on http://{}", addr);
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let response = handler(request);
+                async move { Ok::<_, Infallible>(response.await) }
+            });
+            if let Err(e) = Http::new().serve_connection(stream, service).await {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Serve the descriptions of the requests on `addr` until interrupted
+pub async fn run(addr: SocketAddr, rules: Vec<Rule>) -> Result<(), Error> {
+    let rules = Arc::new(rules);
+    serve(addr, move |request| {
+        let rules = rules.clone();
+        async move { respond(&rules, request).await }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rule;
+
+    #[test]
+    fn rules_match_exact_paths_or_prefixes() {
+        let values: Vec<String> = vec!["404", "/missing", "503", "/down/*"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let rules = Rule::from_pairs(&values).unwrap();
+        assert!(rules[0].matches("/missing"));
+        assert!(!rules[0].matches("/missing/a"));
+        assert!(rules[1].matches("/down/a"));
+        assert!(Rule::new("abc", "/").is_err());
+        assert!(Rule::new("404", "missing").is_err());
+    }
+    #[test]
+    fn requests_are_described() {
+        let request = hyper::Request::post("/a?b=1")
+            .header("x-a", "1")
+            .header("x-a", "2")
+            .body(())
+            .unwrap();
+        let (parts, ()) = request.into_parts();
+        let description = super::describe(&parts, b"hello");
+        assert_eq!(description["method"], "POST");
+        assert_eq!(description["path"], "/a");
+        assert_eq!(description["query"], "b=1");
+        assert_eq!(description["headers"]["x-a"], serde_json::json!(["1", "2"]));
+        assert_eq!(description["body"], "hello");
+    }
+}
@@ -0,0 +1,308 @@
+//! `--session NAME`: keep the headers, credentials, API key and cookies of the requests in a named
+//! session, sent again with the following requests using the session, as httpie does. Sessions
+//! are JSON files in the data directory, listed, shown, edited and removed with `rq session`.
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION, COOKIE},
+    Url,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    cli::CliArgs,
+    cookie::{self, Jar},
+    editor,
+    http::ApiKeyIn,
+    paths,
+    request::Request,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid session name `{0}`, use letters, digits, `-`, `_` and `.`")]
+    InvalidName(String),
+    #[error("No session named `{0}`")]
+    NotFound(String),
+    #[error("The session file {} is corrupted: {1}", .0.display())]
+    Corrupted(PathBuf, String),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Editor(#[from] editor::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What is sent again with the requests of a session
+#[derive(Debug, Default)]
+pub struct Session {
+    headers: Vec<(String, String)>,
+    /// Query parameters added to the URL, for API keys sent in the query
+    query: Vec<(String, String)>,
+    cookies: Jar,
+}
+
+fn path(name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+    Ok(paths::sessions_dir().join(format!("{}.json", name)))
+}
+
+fn pairs(value: &Value) -> Option<Vec<(String, String)>> {
+    match value {
+        Value::Null => Some(Vec::new()),
+        Value::Object(map) => map
+            .iter()
+            .map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Replace the pair named `name` (case-insensitive), or add it
+fn set(pairs: &mut Vec<(String, String)>, name: &str, value: String) {
+    pairs.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    pairs.push((name.to_string(), value));
+}
+
+impl Session {
+    fn to_json(&self) -> Value {
+        let object = |pairs: &[(String, String)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                .collect::<serde_json::Map<_, _>>()
+        };
+        json!({
+            "headers": object(&self.headers),
+            "query": object(&self.query),
+            "cookies": self
+                .cookies
+                .entries()
+                .iter()
+                .filter(|cookie| !cookie.is_expired())
+                .map(cookie::Entry::to_json)
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let cookies = match &value["cookies"] {
+            Value::Null => Vec::new(),
+            cookies => cookies
+                .as_array()?
+                .iter()
+                .map(cookie::Entry::from_json)
+                .collect::<Option<_>>()?,
+        };
+        Some(Session {
+            headers: pairs(&value["headers"])?,
+            query: pairs(&value["query"])?,
+            cookies: Jar::from_entries(cookies),
+        })
+    }
+
+    fn read(name: &str) -> Result<Option<Self>> {
+        let path = path(name)?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let value: Value = serde_json::from_str(&text)
+            .map_err(|e| Error::Corrupted(path.clone(), e.to_string()))?;
+        Session::from_json(&value)
+            .map(Some)
+            .ok_or_else(|| Error::Corrupted(path, "unexpected structure".to_string()))
+    }
+
+    /// The session named `name`, empty if it doesn't exist yet
+    pub fn load(name: &str) -> Result<Self> {
+        Ok(Session::read(name)?.unwrap_or_default())
+    }
+
+    /// Write the session, readable by the user only as it holds credentials
+    pub fn save(&self, name: &str) -> Result<()> {
+        let path = path(name)?;
+        fs::create_dir_all(paths::sessions_dir())?;
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        writeln!(file, "{:#}", self.to_json())?;
+        Ok(())
+    }
+
+    /// Add what the session holds to the request, unless the request sets it already
+    pub fn apply(&self, request: &mut Request) {
+        for (name, value) in &self.headers {
+            if !request
+                .headers
+                .iter()
+                .any(|(n, _)| n.eq_ignore_ascii_case(name))
+            {
+                request.headers.push((name.clone(), value.clone()));
+            }
+        }
+        if let (false, Ok(mut url)) = (self.query.is_empty(), Url::parse(&request.url)) {
+            let given: Vec<String> = url.query_pairs().map(|(name, _)| name.into()).collect();
+            for (name, value) in &self.query {
+                if !given.contains(name) {
+                    url.query_pairs_mut().append_pair(name, value);
+                }
+            }
+            request.url = url.to_string();
+        }
+        if let Some(cookies) = self.cookies.header_value(&request.url) {
+            let cookies = match request
+                .headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(COOKIE.as_str()))
+            {
+                Some((_, given)) => format!("{}; {}", given, cookies),
+                None => cookies,
+            };
+            request.set_header(COOKIE.as_str(), cookies);
+        }
+    }
+
+    /// Keep the headers, credentials and API key given on the command line
+    pub fn remember(&mut self, args: &CliArgs) {
+        for header in &args.headers {
+            set(&mut self.headers, &header.name, header.value.clone());
+        }
+        if let Some(auth) = &args.auth {
+            set(
+                &mut self.headers,
+                AUTHORIZATION.as_str(),
+                auth.header_value(),
+            );
+        }
+        match (&args.api_key, &args.api_key_in) {
+            (Some(key), ApiKeyIn::Header(name)) => set(&mut self.headers, name, key.clone()),
+            (Some(key), ApiKeyIn::Query(name)) => {
+                self.query.retain(|(n, _)| n != name);
+                self.query.push((name.clone(), key.clone()));
+            }
+            (None, _) => {}
+        }
+    }
+
+    /// Keep the cookies set by the response received from `url`
+    pub fn store_cookies(&mut self, url: &str, headers: &HeaderMap) {
+        self.cookies.store(url, headers);
+    }
+}
+
+/// Print the saved sessions with what they hold
+pub fn list() -> Result<()> {
+    let mut names = Vec::new();
+    match fs::read_dir(paths::sessions_dir()) {
+        Ok(entries) => {
+            for entry in entries {
+                let file_name = entry?.file_name();
+                if let Some(name) = file_name.to_string_lossy().strip_suffix(".json") {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    names.sort();
+    for name in names {
+        match Session::read(&name) {
+            Ok(Some(session)) => println!(
+                "{:<20} {} header(s), {} query parameter(s), {} cookie(s)",
+                name,
+                session.headers.len(),
+                session.query.len(),
+                session.cookies.entries().len()
+            ),
+            Ok(None) => {}
+            Err(e) => println!("{:<20} {}", name, e),
+        }
+    }
+    Ok(())
+}
+
+/// Print the session file
+pub fn show(name: &str) -> Result<()> {
+    let session = Session::read(name)?.ok_or_else(|| Error::NotFound(name.to_string()))?;
+    println!("{:#}", session.to_json());
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    match fs::remove_file(path(name)?) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::NotFound(name.to_string())),
+        result => Ok(result?),
+    }
+}
+
+/// Open the session file in the editor, creating it if needed, and check it once saved
+pub fn edit(name: &str) -> Result<()> {
+    let path = path(name)?;
+    if !path.exists() {
+        Session::default().save(name)?;
+    }
+    editor::edit_file(&path)?;
+    Session::read(name)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+
+    use super::Session;
+    use crate::{http::HttpMethod, request::Request};
+
+    #[test]
+    fn session_is_applied_without_replacing_what_is_given() {
+        let mut session = Session {
+            headers: vec![
+                ("Authorization".into(), "Bearer a".into()),
+                ("X-Team".into(), "core".into()),
+            ],
+            query: vec![("api_key".into(), "k".into())],
+            ..Session::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("set-cookie", "sid=1; Path=/".parse().unwrap());
+        session.store_cookies("http://example.com/login", &headers);
+        let session = Session::from_json(&session.to_json()).unwrap();
+
+        let mut request = Request::new(HttpMethod::Get, "http://example.com/me?page=2")
+            .header("authorization", "Basic b")
+            .header("Cookie", "theme=dark");
+        session.apply(&mut request);
+        assert_eq!(request.url, "http://example.com/me?page=2&api_key=k");
+        assert_eq!(
+            request.headers,
+            [
+                ("authorization".to_string(), "Basic b".to_string()),
+                ("X-Team".to_string(), "core".to_string()),
+                ("cookie".to_string(), "theme=dark; sid=1".to_string()),
+            ]
+        );
+    }
+    #[test]
+    fn names_are_file_names() {
+        assert!(super::path("staging-v2").is_ok());
+        assert!(super::path("../secrets").is_err());
+        assert!(super::path("").is_err());
+    }
+}
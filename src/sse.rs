@@ -0,0 +1,198 @@
+//! Server-Sent Events (https://html.spec.whatwg.org/multipage/server-sent-events.html): reading a
+//! `text/event-stream` response as it arrives, reconnecting when the connection is lost.
+use std::time::Duration;
+
+use reqwest::{header::ACCEPT, Client, StatusCode};
+use serde_json::json;
+
+use crate::{request::Request, Result};
+
+const EVENT_STREAM: &str = "text/event-stream";
+/// Reconnection delay until the server sets one with a `retry` field
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub id: Option<String>,
+    pub event: String,
+    pub data: String,
+}
+
+/// Incremental parser of the event stream format
+#[derive(Debug, Default)]
+struct Parser {
+    /// Bytes of the line being received
+    pending: Vec<u8>,
+    data: String,
+    event: Option<String>,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Parser {
+    /// Parse a chunk of the stream, returning the events it completes
+    fn feed(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.pending.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n' || *b == b'\r') {
+            // A CR at the end of the chunk may be the first half of a CRLF
+            if self.pending[end] == b'\r' && end + 1 == self.pending.len() {
+                break;
+            }
+            let terminator_len = match &self.pending[end..] {
+                [b'\r', b'\n', ..] => 2,
+                _ => 1,
+            };
+            let line: Vec<u8> = self
+                .pending
+                .drain(..end + terminator_len)
+                .take(end)
+                .collect();
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        Some(Event {
+            id: self.last_event_id.clone(),
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+        })
+    }
+}
+
+fn print_event(event: &Event, url: &str, ndjson: bool) {
+    if ndjson {
+        println!(
+            "{}",
+            json!({"url": url, "id": event.id, "event": event.event, "data": event.data})
+        );
+        return;
+    }
+    if let Some(id) = &event.id {
+        println!("id: {}", id);
+    }
+    println!("event: {}", event.event);
+    for line in event.data.split('\n') {
+        println!("data: {}", line);
+    }
+    println!();
+}
+
+/// Open the stream and print the events as they arrive, until the server answers 204 No Content.
+///
+/// Errors before the first connection succeeds are returned. Afterwards, the connection is
+/// opened again whenever it is lost, sending the ID of the last event in `Last-Event-ID`.
+pub async fn run(client: &Client, request: &Request, ndjson: bool) -> Result<()> {
+    let mut request = request.clone();
+    if !request
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(ACCEPT.as_str()))
+    {
+        request.set_header(ACCEPT.as_str(), EVENT_STREAM.to_string());
+    }
+    let mut parser = Parser::default();
+    let mut connected_once = false;
+    loop {
+        let result: Result<bool> = async {
+            let mut response = request.build(client).send().await?.error_for_status()?;
+            if response.status() == StatusCode::NO_CONTENT {
+                return Ok(false);
+            }
+            connected_once = true;
+            while let Some(chunk) = response.chunk().await? {
+                for event in parser.feed(&chunk) {
+                    print_event(&event, &request.url, ndjson);
+                }
+            }
+            Ok(true)
+        }
+        .await;
+        match result {
+            Ok(false) => return Ok(()),
+            Ok(true) => {}
+            Err(e) if !connected_once => return Err(e),
+            Err(e) => eprintln!("{}: {}", request.url, e),
+        }
+        // Events cut by the disconnection are incomplete and must be dropped
+        parser.pending.clear();
+        parser.data.clear();
+        parser.event = None;
+        if let Some(id) = &parser.last_event_id {
+            request.set_header("Last-Event-ID", id.clone());
+        }
+        tokio::time::sleep(parser.retry.unwrap_or(DEFAULT_RETRY)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Parser};
+
+    #[test]
+    fn events_are_parsed_across_chunks() {
+        let mut parser = Parser::default();
+        assert!(parser.feed(b": comment\r").is_empty());
+        assert!(parser.feed(b"\nid: 1\nevent: up").is_empty());
+        let events = parser.feed(b"date\ndata: a\ndata:b\r\n\ndata: c\n\n");
+        assert_eq!(
+            events,
+            vec![
+                Event {
+                    id: Some("1".to_string()),
+                    event: "update".to_string(),
+                    data: "a\nb".to_string(),
+                },
+                Event {
+                    id: Some("1".to_string()),
+                    event: "message".to_string(),
+                    data: "c".to_string(),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn retry_must_be_a_number() {
+        let mut parser = Parser::default();
+        parser.feed(b"retry: soon\nretry: 500\nretry: 1.5\n\n");
+        assert_eq!(parser.retry, Some(std::time::Duration::from_millis(500)));
+    }
+}
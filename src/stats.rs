@@ -0,0 +1,98 @@
+//! Timing breakdown of a request.
+//!
+//! reqwest doesn't report when the connection phases end, so DNS resolution, TCP connect and TLS
+//! handshake are measured on a separate connection opened just before the request, with the
+//! options of the client: the `--resolve` overrides, the proxies, the local address and the TLS
+//! settings. Behind a proxy, the phases are those of reaching the proxy and, for HTTPS, of the
+//! tunnel and the handshake through it. No request is sent on that connection, so its phases are
+//! reported apart and are not part of the time to first byte and the total, which come from the
+//! request itself.
+use std::time::Duration;
+
+use reqwest::Url;
+
+use crate::{request::Request, response::Response};
+
+/// Durations of the connection phases
+#[derive(Debug, Clone)]
+pub struct Phases {
+    pub dns: Duration,
+    pub connect: Duration,
+    /// `None` for plain HTTP
+    pub tls: Option<Duration>,
+}
+
+/// Open a connection as the client would for the request and time its phases. `None` for
+/// requests sent through a Unix socket, and when the connection fails, which the request itself
+/// reports.
+pub async fn probe(request: &Request) -> Option<Phases> {
+    if request.unix_socket.is_some() {
+        return None;
+    }
+    let url = Url::parse(&request.url).ok()?;
+    let options = request.connect.clone().unwrap_or_default();
+    let timings = options.connect(&url).await.ok()?.timings;
+    Some(Phases {
+        dns: timings.dns,
+        connect: timings.connect,
+        tls: timings.tls,
+    })
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:>9.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Format the breakdown, one phase per line, the phases of the separate connection last
+pub fn format(phases: Option<&Phases>, response: &Response) -> String {
+    let mut lines = vec![
+        format!("ttfb      {}", millis(response.wait)),
+        format!("total     {}", millis(response.wait + response.receive)),
+        format!("size      {:>9} bytes", response.body.len()),
+    ];
+    if let Some(phases) = phases {
+        lines.push("separate connection, not part of the total:".to_string());
+        lines.push(format!("  dns     {}", millis(phases.dns)));
+        lines.push(format!("  connect {}", millis(phases.connect)));
+        if let Some(tls) = phases.tls {
+            lines.push(format!("  tls     {}", millis(tls)));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::{header::HeaderMap, StatusCode, Version};
+
+    use crate::response::Response;
+
+    #[test]
+    fn breakdown_lists_totals_then_separate_phases() {
+        let response = Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body: vec![0; 42],
+            wait: Duration::from_millis(20),
+            receive: Duration::from_micros(1500),
+        };
+        let phases = super::Phases {
+            dns: Duration::from_millis(1),
+            connect: Duration::from_millis(2),
+            tls: None,
+        };
+        assert_eq!(
+            super::format(Some(&phases), &response),
+            "ttfb          20.00 ms\n\
+            total         21.50 ms\n\
+            size             42 bytes\n\
+            separate connection, not part of the total:\n  \
+            dns          1.00 ms\n  \
+            connect      2.00 ms"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! `{{variable}}` placeholders used in collections and other request definitions.
+use std::{collections::HashMap, str::FromStr};
+
+/// A `name=value` pair given on the command line with `--var`
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for Var {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => Ok(Var {
+                name: name.trim().to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("Expected `name=value` but got `{}`", s)),
+        }
+    }
+}
+
+/// Collect variables into a map, later ones overriding earlier ones
+pub fn to_map(vars: &[Var]) -> HashMap<String, String> {
+    vars.iter()
+        .map(|v| (v.name.clone(), v.value.clone()))
+        .collect()
+}
+
+/// Replace every `{{name}}` (spaces around the name are allowed) with the value returned by
+/// `lookup`. If some variables can't be resolved, return the deduplicated list of their names.
+pub fn render<F>(s: &str, lookup: F) -> Result<String, Vec<String>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut output = String::with_capacity(s.len());
+    let mut missing: Vec<String> = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        let name = rest[start + 2..end].trim();
+        match lookup(name) {
+            Some(value) => output.push_str(&value),
+            None => {
+                if !missing.iter().any(|m| m == name) {
+                    missing.push(name.to_string());
+                }
+            }
+        }
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+    if missing.is_empty() {
+        Ok(output)
+    } else {
+        Err(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    fn lookup(name: &str) -> Option<String> {
+        let vars: HashMap<_, _> = vec![("name", "bob"), ("id", "1")].into_iter().collect();
+        vars.get(name).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        assert_eq!(
+            super::render("/users/{{id}}?name={{ name }}", lookup).unwrap(),
+            "/users/1?name=bob"
+        );
+    }
+    #[test]
+    fn unterminated_placeholder_is_kept() {
+        assert_eq!(super::render("a {{id", lookup).unwrap(), "a {{id");
+    }
+    #[test]
+    fn missing_variables_are_listed_once() {
+        assert_eq!(
+            super::render("{{a}} {{id}} {{b}} {{a}}", lookup).unwrap_err(),
+            vec!["a", "b"]
+        );
+    }
+    #[test]
+    fn var_must_have_name_and_equal_sign() {
+        assert!("name".parse::<super::Var>().is_err());
+        assert!("=value".parse::<super::Var>().is_err());
+        let var: super::Var = "name=a=b".parse().unwrap();
+        assert_eq!((var.name.as_str(), var.value.as_str()), ("name", "a=b"));
+    }
+}
@@ -0,0 +1,414 @@
+//! Test suites run with `rq test`: a YAML file listing requests and what their responses must be.
+//!
+//! ```yaml
+//! vars:
+//!   base: http://localhost:8080
+//! setup:
+//!   - name: login
+//!     method: POST
+//!     url: "{{base}}/login"
+//!     body: {user: admin, password: secret}
+//!     extract:
+//!       token: $.token
+//! cases:
+//!   - name: get the current user
+//!     url: "{{base}}/me"
+//!     headers:
+//!       Authorization: Bearer {{token}}
+//!     assert:
+//!       status: 200
+//!       headers:
+//!         content-type: application/json
+//!       body_contains: admin
+//!       jsonpath:
+//!         - $.name == "admin"
+//! teardown:
+//!   - url: "{{base}}/logout"
+//! ```
+//!
+//! Requests are written as in collections. The values picked with `extract` become variables for
+//! the following requests, and the assertions can use variables too. The cases are skipped when a
+//! setup request fails, the teardown requests are always sent.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::{
+    assert::{Assertions, Failure},
+    collection::Template,
+    http::Header,
+    jsonpath::JsonPath,
+    middleware::Pipeline,
+    template, yaml,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {source}", .path.display())]
+    Yaml { path: PathBuf, source: yaml::Error },
+    #[error("Invalid test suite: {0}")]
+    Invalid(String),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A request of the suite, with what to check and extract from its response
+#[derive(Debug, Clone)]
+struct Step {
+    name: String,
+    template: Template,
+    /// The `assert` section, whose placeholders are filled when the step runs
+    assertions: Value,
+    extract: Vec<(String, JsonPath)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Suite {
+    vars: HashMap<String, String>,
+    setup: Vec<Step>,
+    cases: Vec<Step>,
+    teardown: Vec<Step>,
+}
+
+/// How many cases passed and failed
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+impl Step {
+    fn from_value(index: usize, value: &Value) -> Result<Self> {
+        let name = match value.get("name").and_then(scalar_to_string) {
+            Some(name) => name,
+            None => format!("#{}", index + 1),
+        };
+        let invalid = |message: String| Error::Invalid(format!("{}: {}", name, message));
+        let template = Template::from_value(&name, value).map_err(|e| invalid(e.to_string()))?;
+        let extract = match value.get("extract") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Object(map)) => map
+                .iter()
+                .map(|(var, path)| {
+                    let path = path
+                        .as_str()
+                        .ok_or_else(|| invalid(format!("`extract.{}` must be a path", var)))?;
+                    let path = path.parse().map_err(|e| invalid(format!("{}", e)))?;
+                    Ok((var.clone(), path))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(invalid("`extract` must be a mapping".to_string())),
+        };
+        Ok(Step {
+            template,
+            assertions: value.get("assert").cloned().unwrap_or(Value::Null),
+            extract,
+            name,
+        })
+    }
+
+    /// Send the request, check the response and add the extracted values to `vars`
+    async fn run(
+        &self,
+        client: &Client,
+        pipeline: &Pipeline,
+        vars: &mut HashMap<String, String>,
+    ) -> std::result::Result<(), Vec<String>> {
+        let mut request = self
+            .template
+            .render(vars)
+            .map_err(|e| vec![e.to_string()])?;
+        request.pipeline = pipeline.clone();
+        let mut missing = Vec::new();
+        let assertions = fill(&self.assertions, vars, &mut missing);
+        if !missing.is_empty() {
+            return Err(vec![format!(
+                "Missing values for variables: {}",
+                missing.join(", ")
+            )]);
+        }
+        let assertions = parse_assertions(&assertions).map_err(|e| vec![e])?;
+        let response = request
+            .send(client)
+            .await
+            .map_err(|e| vec![e.to_string()])?;
+        let mut failures: Vec<_> = assertions
+            .check(&response)
+            .iter()
+            .map(Failure::to_string)
+            .collect();
+        if !self.extract.is_empty() {
+            let json: Option<Value> = serde_json::from_slice(&response.body).ok();
+            for (var, path) in &self.extract {
+                match json.as_ref().and_then(|json| path.get(json)) {
+                    Some(Value::String(s)) => {
+                        vars.insert(var.clone(), s.clone());
+                    }
+                    Some(value) => {
+                        vars.insert(var.clone(), value.to_string());
+                    }
+                    None => failures.push(format!("Nothing to extract for `{}`", var)),
+                }
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+}
+
+fn steps(value: Option<&Value>, section: &str) -> Result<Vec<Step>> {
+    match value {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(items)) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| Step::from_value(i, item))
+            .collect(),
+        Some(_) => Err(Error::Invalid(format!("`{}` must be a sequence", section))),
+    }
+}
+
+/// The items of a field that is either a single value or a sequence of them
+fn one_or_many(value: Option<&Value>) -> Vec<&Value> {
+    match value {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(value) => vec![value],
+    }
+}
+
+/// Read the `assert` section once its placeholders are filled
+fn parse_assertions(value: &Value) -> std::result::Result<Assertions, String> {
+    let mut assertions = Assertions::default();
+    if value.is_null() {
+        return Ok(assertions);
+    }
+    let map = value
+        .as_object()
+        .ok_or("`assert` must be a mapping".to_string())?;
+    if let Some(status) = map.get("status").and_then(scalar_to_string) {
+        assertions.status = Some(status.parse().map_err(|e| format!("{}", e))?);
+    }
+    match map.get("headers") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(headers)) => {
+            for (name, value) in headers {
+                let value = scalar_to_string(value)
+                    .ok_or("`assert.headers` must be a mapping of strings")?;
+                assertions.headers.push(Header {
+                    name: name.clone(),
+                    value,
+                });
+            }
+        }
+        Some(_) => return Err("`assert.headers` must be a mapping".to_string()),
+    }
+    for text in one_or_many(map.get("body_contains")) {
+        let text = scalar_to_string(text).ok_or("`assert.body_contains` must be strings")?;
+        assertions.body_contains.push(text);
+    }
+    for assertion in one_or_many(map.get("jsonpath")) {
+        let assertion = assertion
+            .as_str()
+            .ok_or("`assert.jsonpath` must be strings")?;
+        assertions
+            .json
+            .push(assertion.parse().map_err(|e| format!("{}", e))?);
+    }
+    Ok(assertions)
+}
+
+/// Fill the placeholders in every string of the value, collecting the missing variables
+fn fill(value: &Value, vars: &HashMap<String, String>, missing: &mut Vec<String>) -> Value {
+    match value {
+        Value::String(s) => Value::String(
+            template::render(s, |name| vars.get(name).cloned()).unwrap_or_else(|names| {
+                for name in names {
+                    if !missing.contains(&name) {
+                        missing.push(name);
+                    }
+                }
+                String::new()
+            }),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| fill(v, vars, missing)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), fill(v, vars, missing)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+impl Suite {
+    pub fn from_value(value: &Value) -> Result<Self> {
+        let suite = value
+            .as_object()
+            .ok_or_else(|| Error::Invalid("expected a mapping".to_string()))?;
+        let vars = match suite.get("vars") {
+            None | Some(Value::Null) => HashMap::new(),
+            Some(Value::Object(map)) => map
+                .iter()
+                .map(|(k, v)| {
+                    let v = scalar_to_string(v).ok_or_else(|| {
+                        Error::Invalid("`vars` must be a mapping of strings".to_string())
+                    })?;
+                    Ok((k.clone(), v))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(Error::Invalid("`vars` must be a mapping".to_string())),
+        };
+        Ok(Suite {
+            vars,
+            setup: steps(suite.get("setup"), "setup")?,
+            cases: steps(suite.get("cases"), "cases")?,
+            teardown: steps(suite.get("teardown"), "teardown")?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let value = yaml::parse(&content).map_err(|source| Error::Yaml {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Suite::from_value(&value)
+    }
+
+    /// Run the setup, the cases and the teardown in order, printing the outcome of each case.
+    /// `vars` override the variables of the suite.
+    pub async fn run(
+        &self,
+        client: &Client,
+        pipeline: &Pipeline,
+        vars: &HashMap<String, String>,
+    ) -> Summary {
+        let mut vars = self
+            .vars
+            .iter()
+            .chain(vars)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let mut summary = Summary::default();
+        let mut setup_failed = false;
+        for step in &self.setup {
+            if let Err(failures) = step.run(client, pipeline, &mut vars).await {
+                report("SETUP FAILED", &step.name, &failures);
+                setup_failed = true;
+                break;
+            }
+        }
+        for case in &self.cases {
+            if setup_failed {
+                summary.failed += 1;
+                println!("SKIP {}", case.name);
+                continue;
+            }
+            let start = Instant::now();
+            let result = case.run(client, pipeline, &mut vars).await;
+            let label = format!("{} ({} ms)", case.name, start.elapsed().as_millis());
+            match result {
+                Ok(()) => {
+                    summary.passed += 1;
+                    println!("PASS {}", label);
+                }
+                Err(failures) => {
+                    summary.failed += 1;
+                    report("FAIL", &label, &failures);
+                }
+            }
+        }
+        for step in &self.teardown {
+            if let Err(failures) = step.run(client, pipeline, &mut vars).await {
+                report("TEARDOWN FAILED", &step.name, &failures);
+            }
+        }
+        println!();
+        println!("{} passed, {} failed", summary.passed, summary.failed);
+        summary
+    }
+}
+
+fn report(outcome: &str, name: &str, failures: &[String]) {
+    println!("{} {}", outcome, name);
+    for failure in failures {
+        for line in failure.lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::assert::Status;
+
+    #[test]
+    fn suites_are_read_from_yaml() {
+        let value = crate::yaml::parse(
+            r#"
+vars:
+  base: http://localhost
+setup:
+  - url: "{{base}}/login"
+    extract:
+      token: $.token
+cases:
+  - name: me
+    url: "{{base}}/me"
+    assert:
+      status: 2xx
+      jsonpath: $.id == 1
+"#,
+        )
+        .unwrap();
+        let suite = super::Suite::from_value(&value).unwrap();
+        assert_eq!(suite.vars["base"], "http://localhost");
+        assert_eq!(suite.setup[0].name, "#1");
+        assert_eq!(suite.setup[0].extract[0].0, "token");
+        assert_eq!(suite.cases[0].name, "me");
+        assert!(suite.teardown.is_empty());
+        let assertions = super::parse_assertions(&suite.cases[0].assertions).unwrap();
+        assert_eq!(assertions.status, Some(Status::Class(2)));
+        assert_eq!(assertions.json.len(), 1);
+        assert!(super::Suite::from_value(&json!({"cases": {"url": "a"}})).is_err());
+    }
+    #[test]
+    fn assertions_are_filled_with_vars() {
+        let vars: HashMap<_, _> = vec![("id".to_string(), "7".to_string())]
+            .into_iter()
+            .collect();
+        let mut missing = Vec::new();
+        let filled = super::fill(
+            &json!({"jsonpath": ["$.id == {{id}}", "$.x == {{x}}"]}),
+            &vars,
+            &mut missing,
+        );
+        assert_eq!(filled["jsonpath"][0], "$.id == 7");
+        assert_eq!(missing, vec!["x"]);
+    }
+}
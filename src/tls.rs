@@ -0,0 +1,310 @@
+//! TLS connections made outside of reqwest, trusting the same native root certificates, and the
+//! TLS configuration given to reqwest for `--pinnedpubkey`.
+use std::{io, str::FromStr, sync::Arc};
+
+use ring::digest;
+use rustls::{
+    Certificate, ClientConfig, ProtocolVersion, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, TLSError, WebPKIVerifier,
+};
+use tokio_rustls::{
+    webpki::{self, DNSNameRef},
+    TlsConnector,
+};
+
+use crate::cert;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown TLS version `{0}`, expected 1.0, 1.1, 1.2 or 1.3")]
+    UnknownVersion(String),
+    #[error("Invalid public key pin `{0}`, expected sha256//BASE64 hashes separated by `;`")]
+    InvalidPin(String),
+}
+
+/// A TLS protocol version for `--tls-min` and `--tls-max`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Version {
+    Tls1_0,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches("tls").trim_start_matches("TLS") {
+            "1.0" | "1" => Ok(Version::Tls1_0),
+            "1.1" => Ok(Version::Tls1_1),
+            "1.2" => Ok(Version::Tls1_2),
+            "1.3" => Ok(Version::Tls1_3),
+            _ => Err(Error::UnknownVersion(s.to_string())),
+        }
+    }
+}
+
+impl Version {
+    pub fn to_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            Version::Tls1_0 => reqwest::tls::Version::TLS_1_0,
+            Version::Tls1_1 => reqwest::tls::Version::TLS_1_1,
+            Version::Tls1_2 => reqwest::tls::Version::TLS_1_2,
+            Version::Tls1_3 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+
+    /// The version in rustls, which only implements TLS 1.2 and 1.3
+    fn to_rustls(self) -> Option<ProtocolVersion> {
+        match self {
+            Version::Tls1_2 => Some(ProtocolVersion::TLSv1_2),
+            Version::Tls1_3 => Some(ProtocolVersion::TLSv1_3),
+            Version::Tls1_0 | Version::Tls1_1 => None,
+        }
+    }
+}
+
+/// The protocol versions to offer, between `min` and `max` when given
+pub fn versions(min: Option<Version>, max: Option<Version>) -> Vec<ProtocolVersion> {
+    [Version::Tls1_2, Version::Tls1_3]
+        .iter()
+        .filter(|&&version| min.is_none_or(|min| version >= min))
+        .filter(|&&version| max.is_none_or(|max| version <= max))
+        .filter_map(|version| version.to_rustls())
+        .collect()
+}
+
+/// The SHA-256 hashes of the public keys accepted with `--pinnedpubkey`, as curl takes them:
+/// `sha256//BASE64`, several separated by `;`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinnedKeys(Vec<Vec<u8>>);
+
+impl FromStr for PinnedKeys {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidPin(s.to_string());
+        s.split(';')
+            .map(|pin| {
+                let hash = pin.trim().strip_prefix("sha256//").ok_or_else(invalid)?;
+                let hash = base64::decode(hash).map_err(|_| invalid())?;
+                if hash.len() != digest::SHA256_OUTPUT_LEN {
+                    return Err(invalid());
+                }
+                Ok(hash)
+            })
+            .collect::<Result<_, _>>()
+            .map(PinnedKeys)
+    }
+}
+
+impl PinnedKeys {
+    /// Whether the public key of the DER encoded certificate is one of the pinned ones
+    fn matches(&self, certificate: &[u8]) -> bool {
+        match cert::Certificate::public_key_info(certificate) {
+            Ok(spki) => {
+                let hash = digest::digest(&digest::SHA256, spki);
+                self.0.iter().any(|pin| pin.as_slice() == hash.as_ref())
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// The usual verification of the chain, followed by the check of the public key of the server
+struct PinVerifier {
+    pins: PinnedKeys,
+    webpki: WebPKIVerifier,
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let verified =
+            self.webpki
+                .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)?;
+        match presented_certs.first() {
+            Some(leaf) if self.pins.matches(&leaf.0) => Ok(verified),
+            _ => Err(TLSError::General(
+                "the public key of the server doesn't match --pinnedpubkey".to_string(),
+            )),
+        }
+    }
+}
+
+/// The configuration trusting the native root certificates and offering the protocol versions
+/// between `min` and `max`
+pub(crate) fn config(min: Option<Version>, max: Option<Version>) -> io::Result<ClientConfig> {
+    let mut config = ClientConfig::new();
+    config.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) | Err((Some(store), _)) => store,
+        Err((None, e)) => return Err(e),
+    };
+    config.versions = versions(min, max);
+    Ok(config)
+}
+
+/// The configuration for reqwest, only accepting servers whose public key is pinned
+pub fn pinned_config(
+    pins: &PinnedKeys,
+    min: Option<Version>,
+    max: Option<Version>,
+) -> io::Result<ClientConfig> {
+    let mut config = config(min, max)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(PinVerifier {
+            pins: pins.clone(),
+            webpki: WebPKIVerifier::new(),
+        }));
+    Ok(config)
+}
+
+/// The signature algorithms rustls accepts in certificates
+static SIGNATURE_ALGORITHMS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Verifies the server as the client does, except that an expired certificate is accepted if it
+/// was valid when it expired, so that the certificate probe can report the expiry
+struct ExpiryTolerantVerifier {
+    pins: Option<PinnedKeys>,
+    webpki: WebPKIVerifier,
+}
+
+/// Verify the chain as of the time the leaf certificate expired
+fn verify_at_expiry(
+    roots: &RootCertStore,
+    presented_certs: &[Certificate],
+    dns_name: DNSNameRef,
+) -> Result<ServerCertVerified, TLSError> {
+    let leaf = presented_certs
+        .first()
+        .ok_or(TLSError::NoCertificatesPresented)?;
+    let not_after = cert::Certificate::parse(&leaf.0)
+        .map_err(|e| TLSError::General(e.to_string()))?
+        .not_after;
+    let time = webpki::Time::from_seconds_since_unix_epoch(not_after.max(0) as u64);
+    let leaf = webpki::EndEntityCert::from(&leaf.0).map_err(TLSError::WebPKIError)?;
+    let anchors: Vec<_> = roots
+        .roots
+        .iter()
+        .map(|root| root.to_trust_anchor())
+        .collect();
+    let intermediates: Vec<_> = presented_certs[1..]
+        .iter()
+        .map(|certificate| certificate.0.as_slice())
+        .collect();
+    leaf.verify_is_valid_tls_server_cert(
+        SIGNATURE_ALGORITHMS,
+        &webpki::TLSServerTrustAnchors(&anchors),
+        &intermediates,
+        time,
+    )
+    .and_then(|_| leaf.verify_is_valid_for_dns_name(dns_name))
+    .map_err(TLSError::WebPKIError)?;
+    Ok(ServerCertVerified::assertion())
+}
+
+impl ServerCertVerifier for ExpiryTolerantVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let verified =
+            match self
+                .webpki
+                .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)
+            {
+                Err(TLSError::WebPKIError(webpki::Error::CertExpired)) => {
+                    verify_at_expiry(roots, presented_certs, dns_name)?
+                }
+                verified => verified?,
+            };
+        match (&self.pins, presented_certs.first()) {
+            (Some(pins), Some(leaf)) if !pins.matches(&leaf.0) => Err(TLSError::General(
+                "the public key of the server doesn't match --pinnedpubkey".to_string(),
+            )),
+            _ => Ok(verified),
+        }
+    }
+}
+
+/// The configuration for the certificate probe of `--cert-info` and `--cert-expiry-warn`,
+/// accepting the servers the client accepts, and those whose certificate has expired
+pub(crate) fn probe_config(
+    pins: Option<&PinnedKeys>,
+    min: Option<Version>,
+    max: Option<Version>,
+) -> io::Result<ClientConfig> {
+    let mut config = config(min, max)?;
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(ExpiryTolerantVerifier {
+            pins: pins.cloned(),
+            webpki: WebPKIVerifier::new(),
+        }));
+    Ok(config)
+}
+
+pub fn connector() -> io::Result<TlsConnector> {
+    connector_with(None, None)
+}
+
+/// A connector only offering the protocol versions between `min` and `max`
+pub fn connector_with(min: Option<Version>, max: Option<Version>) -> io::Result<TlsConnector> {
+    Ok(TlsConnector::from(Arc::new(config(min, max)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::ProtocolVersion;
+
+    use super::{PinnedKeys, Version};
+
+    #[test]
+    fn versions_are_bounded() {
+        assert_eq!("1.2".parse::<Version>().unwrap(), Version::Tls1_2);
+        assert_eq!("tls1.3".parse::<Version>().unwrap(), Version::Tls1_3);
+        assert!("2".parse::<Version>().is_err());
+        assert_eq!(
+            super::versions(None, Some(Version::Tls1_2)),
+            vec![ProtocolVersion::TLSv1_2]
+        );
+        assert_eq!(
+            super::versions(Some(Version::Tls1_0), None),
+            vec![ProtocolVersion::TLSv1_2, ProtocolVersion::TLSv1_3]
+        );
+        assert!(super::versions(None, Some(Version::Tls1_1)).is_empty());
+    }
+    #[test]
+    fn pins_are_sha256_hashes() {
+        let pin = "sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+        assert_eq!(pin.parse::<PinnedKeys>().unwrap().0.len(), 1);
+        let pins = format!("{};{}", pin, pin).parse::<PinnedKeys>().unwrap();
+        assert_eq!(pins.0.len(), 2);
+        assert!("sha1//AAAA".parse::<PinnedKeys>().is_err());
+        assert!("sha256//AAAA".parse::<PinnedKeys>().is_err());
+    }
+}
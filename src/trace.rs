@@ -0,0 +1,323 @@
+//! `--trace`: log the bytes sent and received on the connection with their time, as
+//! `curl --trace` does, for debugging at the protocol level. HTTPS traffic is logged decrypted.
+//!
+//! The headers are logged as text, the bodies up to `BODY_LIMIT` bytes in each direction, as text
+//! if they are printable or else as a hex dump. As with `--unix-socket`, the request is sent with
+//! hyper directly, redirects are not followed and responses are not decompressed.
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Instant, SystemTime},
+};
+
+use hyper::{client::conn, header::PROXY_AUTHORIZATION, Body};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{connect, datetime, output, response::Response, unix, TIMEOUT};
+
+/// The most bytes of each body logged
+const BODY_LIMIT: usize = 4096;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Connect(#[from] connect::Error),
+    #[error("{0}")]
+    Http(#[from] hyper::Error),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] hyper::http::Error),
+    #[error("Can't write the trace to {}: {source}", .path.display())]
+    Write { path: PathBuf, source: io::Error },
+    #[error("No response within {} ms", TIMEOUT.as_millis())]
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Send,
+    Recv,
+}
+
+/// What is known of the bytes of one direction so far
+#[derive(Debug, Default)]
+struct Flow {
+    /// The bytes of the headers, until the empty line ending them
+    head: Vec<u8>,
+    in_body: bool,
+    body_logged: usize,
+    body_skipped: usize,
+}
+
+/// The log of one exchange, written once it is over so that concurrent requests don't interleave
+#[derive(Debug, Default)]
+struct Log {
+    text: String,
+    send: Flow,
+    recv: Flow,
+}
+
+fn timestamp() -> String {
+    let millis = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64);
+    datetime::format_rfc3339_millis(millis)
+}
+
+/// Whether the bytes read well as text
+fn is_text(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes)
+        .is_ok_and(|s| s.chars().all(|c| !c.is_control() || "\r\n\t".contains(c)))
+}
+
+impl Log {
+    fn info(&mut self, message: &str) {
+        self.text
+            .push_str(&format!("{} == Info: {}\n", timestamp(), message));
+    }
+
+    fn data(&mut self, direction: Direction, mut bytes: &[u8]) {
+        let (arrow, name) = match direction {
+            Direction::Send => ("=>", "Send"),
+            Direction::Recv => ("<=", "Recv"),
+        };
+        let time = timestamp();
+        let flow = match direction {
+            Direction::Send => &mut self.send,
+            Direction::Recv => &mut self.recv,
+        };
+        if !flow.in_body {
+            let before = flow.head.len();
+            flow.head.extend_from_slice(bytes);
+            // The end of the headers may be split over two reads
+            let start = before.saturating_sub(3);
+            let end = flow.head[start..]
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .map(|i| start + i + 4);
+            let header_len = match end {
+                Some(end) => {
+                    flow.in_body = true;
+                    flow.head.clear();
+                    end - before
+                }
+                None => bytes.len(),
+            };
+            let header = &bytes[..header_len];
+            self.text.push_str(&format!(
+                "{} {} {} header, {} bytes\n{}",
+                time,
+                arrow,
+                name,
+                header.len(),
+                String::from_utf8_lossy(header).replace("\r\n", "\n")
+            ));
+            bytes = &bytes[header_len..];
+            if bytes.is_empty() {
+                return;
+            }
+        }
+        let flow = match direction {
+            Direction::Send => &mut self.send,
+            Direction::Recv => &mut self.recv,
+        };
+        let shown = bytes
+            .len()
+            .min(BODY_LIMIT - flow.body_logged.min(BODY_LIMIT));
+        flow.body_logged += shown;
+        flow.body_skipped += bytes.len() - shown;
+        let mut entry = format!("{} {} {} data, {} bytes\n", time, arrow, name, bytes.len());
+        let shown = &bytes[..shown];
+        if is_text(shown) {
+            entry.push_str(&String::from_utf8_lossy(shown).replace("\r\n", "\n"));
+            if !entry.ends_with('\n') {
+                entry.push('\n');
+            }
+        } else {
+            entry.push_str(&output::hexdump(shown));
+        }
+        if shown.len() < bytes.len() {
+            entry.push_str(&format!(
+                "[{} bytes not shown]\n",
+                bytes.len() - shown.len()
+            ));
+        }
+        self.text.push_str(&entry);
+    }
+}
+
+/// A connection logging what goes through it
+struct Tapped {
+    inner: Box<dyn connect::Stream>,
+    log: Arc<Mutex<Log>>,
+}
+
+impl Tapped {
+    fn log(&self, direction: Direction, bytes: &[u8]) {
+        self.log
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .data(direction, bytes);
+    }
+}
+
+impl AsyncRead for Tapped {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        let read = &buf.filled()[filled..];
+        if let (Poll::Ready(Ok(())), false) = (&poll, read.is_empty()) {
+            this.log(Direction::Recv, read);
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for Tapped {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.log(Direction::Send, &buf[..written]);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Append the log to `path`, or write it to stderr for `-`
+fn write_log(path: &Path, text: &str) -> Result<(), Error> {
+    let error = |source| Error::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    if path.as_os_str() == "-" {
+        return io::stderr().write_all(text.as_bytes()).map_err(error);
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(error)
+}
+
+/// Send the request over a connection made with `options`, logging the exchange to `path`, and
+/// read the whole response
+pub async fn send(
+    path: &Path,
+    request: &reqwest::Request,
+    body: Body,
+    options: &connect::Options,
+) -> Result<Response, Error> {
+    let url = request.url();
+    let log = Arc::new(Mutex::new(Log::default()));
+    let exchange = async {
+        let start = Instant::now();
+        let connection = options.connect(url).await?;
+        log.lock().unwrap_or_else(|e| e.into_inner()).info(&format!(
+            "Connected to {}",
+            url.origin().ascii_serialization()
+        ));
+        let mut builder = hyper::Request::builder()
+            .method(request.method().clone())
+            .uri(connection.target(url));
+        for (name, value) in &unix::default_headers(request) {
+            builder = builder.header(name, value);
+        }
+        if let Some(authorization) = connection.proxy_authorization() {
+            builder = builder.header(PROXY_AUTHORIZATION, authorization);
+        }
+        let hyper_request = builder.body(body)?;
+        let stream = connection.stream;
+        let stream = Tapped {
+            inner: stream,
+            log: log.clone(),
+        };
+        let (mut sender, connection) = conn::handshake(stream).await?;
+        let connection = tokio::spawn(connection);
+        let response = sender.send_request(hyper_request).await?;
+        let wait = start.elapsed();
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?.to_vec();
+        connection.abort();
+        Ok(Response {
+            url: url.to_string(),
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+            wait,
+            receive: start.elapsed() - wait,
+        })
+    };
+    let result = tokio::time::timeout(TIMEOUT, exchange)
+        .await
+        .unwrap_or(Err(Error::Timeout));
+    let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = &result {
+        log.info(&e.to_string());
+    }
+    write_log(path, &log.text)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Log, BODY_LIMIT};
+
+    fn entries(log: &Log) -> Vec<String> {
+        // Without the timestamps
+        log.text
+            .lines()
+            .filter(|line| line.contains(" bytes") && !line.starts_with('['))
+            .map(|line| line.split_once(' ').unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn headers_are_told_from_bodies_across_reads() {
+        let mut log = Log::default();
+        log.data(
+            Direction::Recv,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r",
+        );
+        log.data(Direction::Recv, b"\nbody");
+        assert_eq!(
+            entries(&log),
+            [
+                "<= Recv header, 37 bytes",
+                "<= Recv header, 1 bytes",
+                "<= Recv data, 4 bytes"
+            ]
+        );
+        assert!(log.text.ends_with("body\n"));
+    }
+    #[test]
+    fn binary_bodies_are_dumped_up_to_the_limit() {
+        let mut log = Log::default();
+        log.data(Direction::Send, b"POST / HTTP/1.1\r\n\r\n");
+        log.data(Direction::Send, &vec![0; BODY_LIMIT + 10]);
+        assert!(log.text.contains("00000000: 0000 0000"));
+        assert!(log.text.ends_with("[10 bytes not shown]\n"));
+    }
+}
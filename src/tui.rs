@@ -0,0 +1,539 @@
+//! A full-screen response browser for `--tui`: the history on the left, the request line and
+//! response headers at the top and the body below, scrollable and searchable.
+//!
+//! There is no terminal UI library available to the build, so the screen is drawn with ANSI
+//! escape sequences on the alternate screen while the terminal is in raw mode.
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::CONTENT_TYPE, Client};
+
+use crate::{
+    cli::{normalize_url, CliArgs},
+    history::{self, Entry},
+    output,
+    request::Request,
+    response::Response,
+    Result,
+};
+
+const SIDEBAR_WIDTH: usize = 32;
+const HELP: &str =
+    "q quit  Tab focus  j/k scroll  / search  n/N next/previous  e edit URL  r resend";
+
+/// Raw mode on the alternate screen, until dropped
+#[cfg(unix)]
+struct Terminal {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        // SAFETY: `termios` is plain data, filled by `tcgetattr` before being read
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // SAFETY: `raw` is a valid `termios` obtained from `tcgetattr`
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(Terminal { original })
+    }
+
+    /// The number of columns and rows
+    fn size() -> (usize, usize) {
+        // SAFETY: `winsize` is plain data, filled by the `TIOCGWINSZ` request
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        // SAFETY: `original` is the state returned by `tcgetattr`
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Raw mode is only implemented with the terminal interface of Unix
+#[cfg(not(unix))]
+struct Terminal;
+
+#[cfg(not(unix))]
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--tui is not supported on this platform",
+        ))
+    }
+
+    fn size() -> (usize, usize) {
+        (80, 24)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Interrupt,
+}
+
+/// The keys in a chunk read from the terminal, escape sequences included
+fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(input);
+    let mut keys = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' if chars.peek() == Some(&'[') => {
+                chars.next();
+                match chars.next() {
+                    Some('A') => Key::Up,
+                    Some('B') => Key::Down,
+                    Some(c @ ('5' | '6')) if chars.next_if_eq(&'~').is_some() => {
+                        if c == '5' {
+                            Key::PageUp
+                        } else {
+                            Key::PageDown
+                        }
+                    }
+                    _ => continue,
+                }
+            }
+            '\x1b' => Key::Escape,
+            '\r' | '\n' => Key::Enter,
+            '\t' => Key::Tab,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x03' => Key::Interrupt,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// The first line containing `search`, from `from` onwards or backwards from before it
+fn find_line(lines: &[String], search: &str, from: usize, forward: bool) -> Option<usize> {
+    if search.is_empty() {
+        return None;
+    }
+    if forward {
+        (from..lines.len()).find(|&i| lines[i].contains(search))
+    } else {
+        (0..from.min(lines.len()))
+            .rev()
+            .find(|&i| lines[i].contains(search))
+    }
+}
+
+/// At most `width` characters of `s`, padded with spaces to exactly `width`
+fn fit(s: &str, width: usize) -> String {
+    format!(
+        "{:<width$}",
+        s.chars().take(width).collect::<String>(),
+        width = width
+    )
+}
+
+/// Color the keys, strings and literals of a line of pretty-printed JSON
+fn highlight_json(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::from('"');
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    string.push(c);
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => break,
+                        _ => escaped = false,
+                    }
+                }
+                let is_key = chars.clone().find(|c| *c != ' ') == Some(':');
+                let color = if is_key { 34 } else { 32 };
+                out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, string));
+            }
+            c if c.is_ascii_digit() || c == '-' || c.is_ascii_alphabetic() => {
+                let mut literal = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || ".+-".contains(*c))
+                {
+                    literal.push(c);
+                }
+                out.push_str(&format!("\x1b[33m{}\x1b[0m", literal));
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus {
+    History,
+    Body,
+}
+
+enum Prompt {
+    Search(String),
+    Url(String),
+}
+
+enum Action {
+    None,
+    Send,
+    Quit,
+}
+
+struct App {
+    /// Newest first
+    history: Vec<Entry>,
+    selected: usize,
+    request: Option<Request>,
+    /// The request built from the command line, to send when a URL is entered without request
+    blank: Request,
+    response: Option<std::result::Result<Response, String>>,
+    elapsed: Duration,
+    lines: Vec<String>,
+    json: bool,
+    scroll: usize,
+    /// Rows of the body pane at the last draw
+    page: usize,
+    search: String,
+    focus: Focus,
+    prompt: Option<Prompt>,
+    message: Option<String>,
+}
+
+impl App {
+    fn new(args: &CliArgs, request: Option<Request>) -> Self {
+        let mut app = App {
+            history: Vec::new(),
+            selected: 0,
+            request,
+            blank: Request::from_args(args, ""),
+            response: None,
+            elapsed: Duration::ZERO,
+            lines: Vec::new(),
+            json: false,
+            scroll: 0,
+            page: 1,
+            search: String::new(),
+            focus: Focus::Body,
+            prompt: None,
+            message: None,
+        };
+        app.load_history();
+        app
+    }
+
+    fn load_history(&mut self) {
+        self.history = history::entries().unwrap_or_default();
+        self.history.reverse();
+        self.selected = self.selected.min(self.history.len().saturating_sub(1));
+    }
+
+    async fn send(&mut self, client: &Client, args: &CliArgs) {
+        let request = match &self.request {
+            Some(request) => request,
+            None => return,
+        };
+        let start = Instant::now();
+        let result = request.send(client).await;
+        self.elapsed = start.elapsed();
+        if !args.no_history {
+            let status = result.as_ref().ok().map(|r| r.status.as_u16());
+            if let Err(e) = history::record(request, status, self.elapsed) {
+                self.message = Some(format!("Failed to record the request: {}", e));
+            }
+            self.load_history();
+        }
+        self.scroll = 0;
+        self.response = Some(result.map_err(|e| e.to_string()));
+        let (text, json) = match &self.response {
+            Some(Ok(response)) => {
+                let json = response
+                    .header(CONTENT_TYPE.as_str())
+                    .is_some_and(|t| t.contains("json"));
+                match serde_json::from_slice::<serde_json::Value>(&response.body) {
+                    Ok(value) if json => (serde_json::to_string_pretty(&value).unwrap(), true),
+                    _ => (output::format_body(response, None, true, false), false),
+                }
+            }
+            _ => (String::new(), false),
+        };
+        self.json = json;
+        self.lines = text.lines().map(|l| l.replace('\t', "    ")).collect();
+    }
+
+    fn scroll_to(&mut self, line: usize) {
+        self.scroll = line.min(self.lines.len().saturating_sub(1));
+    }
+
+    fn find(&mut self, from: usize, forward: bool) {
+        match find_line(&self.lines, &self.search, from, forward) {
+            Some(line) => self.scroll_to(line),
+            None => self.message = Some(format!("Not found: {}", self.search)),
+        }
+    }
+
+    fn handle(&mut self, key: Key) -> Action {
+        self.message = None;
+        if let Some(prompt) = &mut self.prompt {
+            let text = match prompt {
+                Prompt::Search(text) | Prompt::Url(text) => text,
+            };
+            match key {
+                Key::Char(c) => text.push(c),
+                Key::Backspace => {
+                    text.pop();
+                }
+                Key::Escape | Key::Interrupt => self.prompt = None,
+                Key::Enter => match self.prompt.take() {
+                    Some(Prompt::Search(text)) => {
+                        self.search = text;
+                        self.find(self.scroll, true);
+                    }
+                    Some(Prompt::Url(mut url)) => {
+                        normalize_url(&mut url);
+                        let mut request = self.request.take().unwrap_or_else(|| self.blank.clone());
+                        request.url = url;
+                        self.request = Some(request);
+                        return Action::Send;
+                    }
+                    None => {}
+                },
+                _ => {}
+            }
+            return Action::None;
+        }
+        match (key, self.focus) {
+            (Key::Char('q') | Key::Interrupt, _) => return Action::Quit,
+            (Key::Tab, Focus::History) => self.focus = Focus::Body,
+            (Key::Tab, Focus::Body) => self.focus = Focus::History,
+            (Key::Char('/'), _) => self.prompt = Some(Prompt::Search(String::new())),
+            (Key::Char('n'), _) => self.find(self.scroll + 1, true),
+            (Key::Char('N'), _) => self.find(self.scroll, false),
+            (Key::Char('e'), _) => {
+                let url = self.request.as_ref().map(|r| r.url.clone());
+                self.prompt = Some(Prompt::Url(url.unwrap_or_default()));
+            }
+            (Key::Char('r'), _) if self.request.is_some() => return Action::Send,
+            (Key::Enter, Focus::History) => {
+                if let Some(entry) = self.history.get(self.selected) {
+                    self.request = Some(entry.request.clone());
+                    return Action::Send;
+                }
+            }
+            (Key::Char('j') | Key::Down, Focus::History) => {
+                self.selected = (self.selected + 1).min(self.history.len().saturating_sub(1));
+            }
+            (Key::Char('k') | Key::Up, Focus::History) => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            (Key::Char('j') | Key::Down, Focus::Body) => self.scroll_to(self.scroll + 1),
+            (Key::Char('k') | Key::Up, Focus::Body) => self.scroll = self.scroll.saturating_sub(1),
+            (Key::Char(' ') | Key::PageDown, Focus::Body) => {
+                self.scroll_to(self.scroll + self.page)
+            }
+            (Key::Char('b') | Key::PageUp, Focus::Body) => {
+                self.scroll = self.scroll.saturating_sub(self.page)
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// The rows above the body, with their style: request line, status and headers
+    fn head_rows(&self, rows: usize) -> Vec<(&'static str, String)> {
+        let mut head = vec![match &self.request {
+            Some(request) => ("\x1b[1m", format!("{} {}", request.method, request.url)),
+            None => (
+                "",
+                "Press e to enter a URL or Tab to pick a request from the history".to_string(),
+            ),
+        }];
+        match &self.response {
+            Some(Ok(response)) => {
+                head.push((
+                    "",
+                    format!(
+                        "{:?} {}  {} ms  {} bytes",
+                        response.version,
+                        response.status,
+                        self.elapsed.as_millis(),
+                        response.body.len()
+                    ),
+                ));
+                let max = (rows / 3).max(1);
+                let headers: Vec<_> = response.headers.iter().collect();
+                for (i, (name, value)) in headers.iter().enumerate() {
+                    if i + 1 == max && headers.len() > max {
+                        head.push(("", format!("… {} more headers", headers.len() - i)));
+                        break;
+                    }
+                    let value = String::from_utf8_lossy(value.as_bytes());
+                    head.push(("\x1b[36m", format!("{}: {}", name, value)));
+                }
+            }
+            Some(Err(e)) => head.push(("\x1b[31m", e.clone())),
+            None => {}
+        }
+        head
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (width, height) = Terminal::size();
+        let sidebar = SIDEBAR_WIDTH.min(width / 3);
+        let main = width.saturating_sub(sidebar + 1);
+        let rows = height.saturating_sub(1);
+        let head = self.head_rows(rows);
+        let body_top = head.len() + 1;
+        self.page = rows.saturating_sub(body_top).max(1);
+        let history_offset = self.selected.saturating_sub(rows.saturating_sub(1));
+        let mut screen = String::new();
+        for row in 0..rows {
+            screen.push_str(&format!("\x1b[{};1H", row + 1));
+            match self.history.get(history_offset + row) {
+                Some(entry) => {
+                    let status = entry
+                        .status
+                        .map_or_else(|| "ERR".to_string(), |s| s.to_string());
+                    let text = format!("{} {} {}", status, entry.request.method, entry.request.url);
+                    let style = match (history_offset + row == self.selected, self.focus) {
+                        (true, Focus::History) => "\x1b[7m",
+                        (true, Focus::Body) => "\x1b[1m",
+                        (false, _) => "",
+                    };
+                    screen.push_str(&format!("{}{}\x1b[0m", style, fit(&text, sidebar)));
+                }
+                None => screen.push_str(&fit("", sidebar)),
+            }
+            screen.push('│');
+            if let Some((style, line)) = head.get(row) {
+                screen.push_str(style);
+                screen.push_str(&line.chars().take(main).collect::<String>());
+            } else if row + 1 == body_top {
+                screen.push_str(&"─".repeat(main));
+            } else if let Some(line) = self.lines.get(self.scroll + row - body_top) {
+                let line: String = line.chars().take(main).collect();
+                if !self.search.is_empty() && line.contains(&self.search) {
+                    let marked = format!("\x1b[7m{}\x1b[0m", self.search);
+                    screen.push_str(&line.replace(&self.search, &marked));
+                } else if self.json {
+                    screen.push_str(&highlight_json(&line));
+                } else {
+                    screen.push_str(&line);
+                }
+            }
+            screen.push_str("\x1b[0m\x1b[K");
+        }
+        let footer = match (&self.prompt, &self.message) {
+            (Some(Prompt::Search(text)), _) => format!("/{}", text),
+            (Some(Prompt::Url(text)), _) => format!("URL: {}", text),
+            (None, Some(message)) => message.clone(),
+            (None, None) => HELP.to_string(),
+        };
+        screen.push_str(&format!(
+            "\x1b[{};1H\x1b[7m{}\x1b[0m",
+            height,
+            fit(&footer, width)
+        ));
+        let mut stdout = io::stdout();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// Browse the responses to the request and the ones picked from the history until `q` is pressed
+pub async fn run(client: &Client, args: &CliArgs, requests: Vec<Request>) -> Result<()> {
+    let mut app = App::new(args, requests.into_iter().next());
+    let _terminal = Terminal::enter()?;
+    app.send(client, args).await;
+    let mut buf = [0; 64];
+    loop {
+        app.draw()?;
+        // Read synchronously: tokio's stdin would keep the runtime alive after quitting
+        let len = io::stdin().lock().read(&mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+        for key in parse_keys(&buf[..len]) {
+            match app.handle(key) {
+                Action::None => {}
+                Action::Send => app.send(client, args).await,
+                Action::Quit => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Key;
+
+    #[test]
+    fn keys_and_escape_sequences_are_parsed() {
+        assert_eq!(
+            super::parse_keys(b"j\x1b[B\x1b[6~\t\r\x1b\x7f"),
+            vec![
+                Key::Char('j'),
+                Key::Down,
+                Key::PageDown,
+                Key::Tab,
+                Key::Enter,
+                Key::Escape,
+                Key::Backspace
+            ]
+        );
+    }
+    #[test]
+    fn lines_are_searched_in_both_directions() {
+        let lines: Vec<String> = ["a", "b", "ab", "c"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(super::find_line(&lines, "a", 1, true), Some(2));
+        assert_eq!(super::find_line(&lines, "a", 2, false), Some(0));
+        assert_eq!(super::find_line(&lines, "d", 0, true), None);
+        assert_eq!(super::find_line(&lines, "", 0, true), None);
+    }
+    #[test]
+    fn json_keys_and_values_are_colored() {
+        assert_eq!(
+            super::highlight_json(r#"  "a": "b\"", 1"#),
+            "  \x1b[34m\"a\"\x1b[0m: \x1b[32m\"b\\\"\"\x1b[0m, \x1b[33m1\x1b[0m"
+        );
+    }
+}
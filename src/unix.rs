@@ -0,0 +1,125 @@
+//! Sending requests over a Unix domain socket with `--unix-socket`, to talk to local daemons such
+//! as Docker. The URL still gives the path and the `Host` header, only the connection differs.
+//!
+//! reqwest can't use other transports than TCP, so the request is sent with hyper directly.
+//! Redirects are not followed and responses are not decompressed.
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use hyper::{
+    client::conn,
+    header::{ACCEPT, HOST, USER_AGENT},
+    Body, HeaderMap,
+};
+use reqwest::header::HeaderValue;
+
+use crate::{connect::Stream, response::Response, TIMEOUT, USER_AGENT_VALUE};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't connect to {}: {source}", .path.display())]
+    Connect { path: PathBuf, source: io::Error },
+    #[error("{0}")]
+    Http(#[from] hyper::Error),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] hyper::http::Error),
+    #[error("No response within {} ms", TIMEOUT.as_millis())]
+    Timeout,
+    #[error("Unix domain sockets are not supported on this platform")]
+    Unsupported,
+}
+
+/// The headers reqwest would add when sending over TCP
+pub fn default_headers(request: &reqwest::Request) -> HeaderMap {
+    let mut headers = request.headers().clone();
+    let url = request.url();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    if let (false, Ok(host)) = (headers.contains_key(HOST), HeaderValue::from_str(&host)) {
+        headers.insert(HOST, host);
+    }
+    if !headers.contains_key(USER_AGENT) {
+        headers.insert(USER_AGENT, HeaderValue::from_static(USER_AGENT_VALUE));
+    }
+    if !headers.contains_key(ACCEPT) {
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+    }
+    headers
+}
+
+#[cfg(unix)]
+async fn connect(path: &Path) -> Result<Box<dyn Stream>, Error> {
+    let stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|source| Error::Connect {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(unix))]
+async fn connect(_path: &Path) -> Result<Box<dyn Stream>, Error> {
+    Err(Error::Unsupported)
+}
+
+/// Send the request over the socket at `path` and read the whole response
+pub async fn send(path: &Path, request: &reqwest::Request, body: Body) -> Result<Response, Error> {
+    let url = request.url();
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let mut builder = hyper::Request::builder()
+        .method(request.method().clone())
+        .uri(target);
+    for (name, value) in &default_headers(request) {
+        builder = builder.header(name, value);
+    }
+    let hyper_request = builder.body(body)?;
+    let exchange = async {
+        let start = Instant::now();
+        let stream = connect(path).await?;
+        let (mut sender, connection) = conn::handshake(stream).await?;
+        tokio::spawn(connection);
+        let response = sender.send_request(hyper_request).await?;
+        let wait = start.elapsed();
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?.to_vec();
+        Ok(Response {
+            url: url.to_string(),
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+            wait,
+            receive: start.elapsed() - wait,
+        })
+    };
+    tokio::time::timeout(TIMEOUT, exchange)
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    #[test]
+    fn host_and_user_agent_are_added() {
+        let request = Client::new()
+            .get("http://localhost/v1.41/info")
+            .header("user-agent", "docker")
+            .build()
+            .unwrap();
+        let headers = super::default_headers(&request);
+        assert_eq!(headers["host"], "localhost");
+        assert_eq!(headers["user-agent"], "docker");
+        assert_eq!(headers["accept"], "*/*");
+    }
+}
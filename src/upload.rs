@@ -0,0 +1,164 @@
+//! Request bodies streamed from a file or stdin with `--data-binary`, so that uploads of any size
+//! don't have to fit in memory.
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+    pin::Pin,
+    str::FromStr,
+};
+
+use futures_util::{
+    future,
+    stream::{self, Stream},
+    TryStreamExt,
+};
+use reqwest::Body;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+use crate::compression::Coding;
+
+/// Size of the chunks read from the source and sent with chunked transfer encoding
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub type Chunks = Pin<Box<dyn Stream<Item = io::Result<Vec<u8>>> + Send + Sync>>;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid upload source `{0}`, expected `@path`, or `@-` for stdin")]
+pub struct Error(String);
+
+#[derive(Debug, Clone)]
+pub enum Upload {
+    /// Streamed from the file, with a `Content-Length` when `sized`
+    File { path: PathBuf, sized: bool },
+    /// Streamed from stdin with chunked transfer encoding
+    Stdin,
+    /// Stdin read up front, to be sent with a `Content-Length`
+    Buffered(Vec<u8>),
+}
+
+impl FromStr for Upload {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('@') {
+            Some("-") => Ok(Upload::Stdin),
+            Some(path) if !path.is_empty() => Ok(Upload::File {
+                path: PathBuf::from(path),
+                sized: false,
+            }),
+            _ => Err(Error(s.to_string())),
+        }
+    }
+}
+
+impl Upload {
+    /// Send the length with the body rather than using chunked transfer encoding. The length of
+    /// stdin is not known in advance, so it is read into memory.
+    pub fn sized(self) -> io::Result<Self> {
+        Ok(match self {
+            Upload::File { path, .. } => Upload::File { path, sized: true },
+            Upload::Stdin => {
+                let mut data = Vec::new();
+                io::stdin().read_to_end(&mut data)?;
+                Upload::Buffered(data)
+            }
+            buffered => buffered,
+        })
+    }
+
+    /// Whether the body is read while it is sent, so that sending it takes as long as reading it
+    pub fn is_streamed(&self) -> bool {
+        matches!(self, Upload::File { .. } | Upload::Stdin)
+    }
+
+    /// The source as written for `--data-binary`, for curl as well as rq
+    pub fn to_arg(&self) -> String {
+        match self {
+            Upload::File { path, .. } => format!("@{}", path.display()),
+            Upload::Stdin | Upload::Buffered(_) => "@-".to_string(),
+        }
+    }
+
+    /// The body, compressed with `coding` if given, and its length when it is sent with a
+    /// `Content-Length`. Files are opened when the body is first read, so an error opening them
+    /// fails the request when it is sent.
+    pub fn body(&self, coding: Option<Coding>) -> (Body, Option<u64>) {
+        let (chunks, length) = self.chunks(coding);
+        (Body::wrap_stream(chunks), length)
+    }
+
+    /// The chunks of the body, for `body`, and its length if it is known in advance
+    pub fn chunks(&self, coding: Option<Coding>) -> (Chunks, Option<u64>) {
+        match self {
+            // The compressed length is only known once the whole file is compressed
+            Upload::File { path, sized } => {
+                let length = if *sized && coding.is_none() {
+                    fs::metadata(path).ok().map(|m| m.len())
+                } else {
+                    None
+                };
+                let chunks = stream::once(tokio::fs::File::open(path.clone()))
+                    .map_ok(move |file| read_chunks(compress(file, coding)))
+                    .try_flatten();
+                (Box::pin(chunks), length)
+            }
+            Upload::Stdin => (
+                Box::pin(read_chunks(compress(tokio::io::stdin(), coding))),
+                None,
+            ),
+            Upload::Buffered(data) => {
+                let data = match coding {
+                    Some(coding) => coding.compress(data),
+                    None => data.clone(),
+                };
+                let length = data.len() as u64;
+                (
+                    Box::pin(stream::once(future::ready(Ok(data)))),
+                    Some(length),
+                )
+            }
+        }
+    }
+}
+
+fn compress<R>(reader: R, coding: Option<Coding>) -> Box<dyn AsyncRead + Unpin + Send + Sync>
+where
+    R: AsyncRead + Unpin + Send + Sync + 'static,
+{
+    match coding {
+        Some(coding) => coding.compress_reader(BufReader::new(reader)),
+        None => Box::new(reader),
+    }
+}
+
+fn read_chunks<R>(reader: R) -> impl Stream<Item = io::Result<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    stream::try_unfold(reader, |mut reader| async move {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        chunk.truncate(read);
+        Ok(Some((chunk, reader)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Upload;
+
+    #[test]
+    fn sources_are_parsed() {
+        assert!(matches!("@-".parse(), Ok(Upload::Stdin)));
+        assert!(matches!(
+            "@big.bin".parse(),
+            Ok(Upload::File { path, sized: false }) if path.as_os_str() == "big.bin"
+        ));
+        assert!("big.bin".parse::<Upload>().is_err());
+        assert!("@".parse::<Upload>().is_err());
+    }
+}
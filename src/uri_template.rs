@@ -0,0 +1,207 @@
+//! URI templates (https://www.rfc-editor.org/rfc/rfc6570), e.g.
+//! `https://api.example.com/users/{id}/posts{?page,per_page}`, expanded with the values of
+//! `--var`.
+//!
+//! All the operators are supported, as well as the `:n` prefix modifier. The values are strings,
+//! so the explode modifier `*` makes no difference. The variables of query expressions (`?` and
+//! `&`) are optional and left out when not given, the others are required.
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("Unclosed expression in the URL template `{0}`")]
+    Unclosed(String),
+    #[error("Invalid expression `{{{0}}}` in the URL template")]
+    InvalidExpression(String),
+    #[error("Missing --var for the URL template variable(s): {}", .0.join(", "))]
+    Missing(Vec<String>),
+}
+
+/// How an operator expands its variables
+struct Operator {
+    first: &'static str,
+    separator: &'static str,
+    named: bool,
+    if_empty: &'static str,
+    allow_reserved: bool,
+    optional: bool,
+}
+
+fn operator(c: char) -> Option<Operator> {
+    let (first, separator, named, if_empty, allow_reserved) = match c {
+        '+' => ("", ",", false, "", true),
+        '#' => ("#", ",", false, "", true),
+        '.' => (".", ".", false, "", false),
+        '/' => ("/", "/", false, "", false),
+        ';' => (";", ";", true, "", false),
+        '?' => ("?", "&", true, "=", false),
+        '&' => ("&", "&", true, "=", false),
+        _ => return None,
+    };
+    Some(Operator {
+        first,
+        separator,
+        named,
+        if_empty,
+        allow_reserved,
+        optional: matches!(c, '?' | '&'),
+    })
+}
+
+const SIMPLE: Operator = Operator {
+    first: "",
+    separator: ",",
+    named: false,
+    if_empty: "",
+    allow_reserved: false,
+    optional: false,
+};
+
+/// Percent-encode everything but the unreserved characters, and the reserved ones and the
+/// percent-encoded triplets if `allow_reserved`
+fn encode(value: &str, allow_reserved: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut encoded = String::with_capacity(value.len());
+    for (i, &b) in bytes.iter().enumerate() {
+        let unreserved = b.is_ascii_alphanumeric() || b"-._~".contains(&b);
+        let reserved = b":/?#[]@!$&'()*+,;=".contains(&b)
+            || (b == b'%'
+                && bytes
+                    .get(i + 1..i + 3)
+                    .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)));
+        if unreserved || (allow_reserved && reserved) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Expand one expression, the text between braces. The names of the required variables without
+/// a value are added to `missing`.
+fn expand_expression(
+    expression: &str,
+    vars: &HashMap<String, String>,
+    missing: &mut Vec<String>,
+) -> Result<String, Error> {
+    let invalid = || Error::InvalidExpression(expression.to_string());
+    let (operator, list) = match expression.chars().next().and_then(operator) {
+        Some(operator) => (operator, &expression[1..]),
+        None => (SIMPLE, expression),
+    };
+    let mut expanded = Vec::new();
+    for spec in list.split(',') {
+        let spec = spec.strip_suffix('*').unwrap_or(spec);
+        let (name, prefix) = match spec.split_once(':') {
+            Some((name, length)) => (name, Some(length.parse::<usize>().map_err(|_| invalid())?)),
+            None => (spec, None),
+        };
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '%');
+        if !valid_name {
+            return Err(invalid());
+        }
+        let value = match vars.get(name) {
+            Some(value) => value,
+            None if operator.optional => continue,
+            None => {
+                if !missing.iter().any(|m| m == name) {
+                    missing.push(name.to_string());
+                }
+                continue;
+            }
+        };
+        let value: String = match prefix {
+            Some(length) => value.chars().take(length).collect(),
+            None => value.clone(),
+        };
+        let value = encode(&value, operator.allow_reserved);
+        expanded.push(match (operator.named, value.is_empty()) {
+            (false, _) => value,
+            (true, true) => format!("{}{}", name, operator.if_empty),
+            (true, false) => format!("{}={}", name, value),
+        });
+    }
+    if expanded.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(format!(
+        "{}{}",
+        operator.first,
+        expanded.join(operator.separator)
+    ))
+}
+
+/// Expand the expressions of the template with the values of `vars`
+pub fn expand(template: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut missing = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| Error::Unclosed(template.to_string()))?;
+        expanded.push_str(&rest[..start]);
+        let expression = &rest[start + 1..start + end];
+        expanded.push_str(&expand_expression(expression, vars, &mut missing)?);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    if !missing.is_empty() {
+        return Err(Error::Missing(missing));
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Error;
+
+    fn vars() -> HashMap<String, String> {
+        [
+            ("var", "value"),
+            ("hello", "Hello World!"),
+            ("path", "/foo/bar"),
+            ("x", "1024"),
+            ("y", "768"),
+            ("empty", ""),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn operators_follow_the_rfc_examples() {
+        let expand = |template| super::expand(template, &vars()).unwrap();
+        assert_eq!(expand("{var}"), "value");
+        assert_eq!(expand("{hello}"), "Hello%20World%21");
+        assert_eq!(expand("{+path}/here"), "/foo/bar/here");
+        assert_eq!(expand("{#path,x}/here"), "#/foo/bar,1024/here");
+        assert_eq!(expand("X{.var}"), "X.value");
+        assert_eq!(expand("{/var,x}/here"), "/value/1024/here");
+        assert_eq!(expand("{;x,y,empty}"), ";x=1024;y=768;empty");
+        assert_eq!(expand("{?x,y,empty}"), "?x=1024&y=768&empty=");
+        assert_eq!(expand("?fixed=yes{&x}"), "?fixed=yes&x=1024");
+        assert_eq!(expand("{var:3}"), "val");
+    }
+    #[test]
+    fn missing_variables_are_listed_unless_optional() {
+        let vars = vars();
+        assert_eq!(
+            super::expand("/users/{id}/posts{?page,x}", &vars),
+            Err(Error::Missing(vec!["id".to_string()]))
+        );
+        assert_eq!(
+            super::expand("/users/{var}/posts{?page,per_page}", &vars).unwrap(),
+            "/users/value/posts"
+        );
+        assert!(super::expand("/users/{id", &vars).is_err());
+        assert!(super::expand("/users/{a b}", &vars).is_err());
+    }
+}
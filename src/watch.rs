@@ -0,0 +1,138 @@
+//! `--watch`: sending the requests again and again at a fixed interval, e.g. to follow a deploy or
+//! a health endpoint.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use reqwest::Client;
+
+use crate::{cli::CliArgs, datetime, diff, fetch, output, request::Request, Result};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid interval `{0}`, expected a number with an optional unit: ms, s, m or h")]
+pub struct Error(String);
+
+/// A duration given as a number of seconds, or with a unit, e.g. `500ms`, `5s`, `2m` or `1h`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || Error(s.to_string());
+        let trimmed = s.trim();
+        let end = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(end);
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let unit = match unit.trim() {
+            "ms" => 0.001,
+            "" | "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        Ok(Interval(Duration::from_secs_f64(number * unit)))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}s", self.0.as_secs_f64())
+    }
+}
+
+/// Mark the start of a round: clear the screen with `--watch-clear`, or else print a separator
+fn print_round(args: &CliArgs, interval: Interval) {
+    let now = datetime::format_rfc3339_millis(datetime::unix_millis(SystemTime::now()));
+    if args.watch_clear {
+        print!("\x1b[2J\x1b[H");
+        println!("Every {}: {}\n", interval, now);
+    } else {
+        println!("--- {} ---", now);
+    }
+}
+
+/// Send the requests and print what changed in each body since the previous round, or the whole
+/// body the first time
+async fn print_changes(
+    client: &Client,
+    args: &CliArgs,
+    requests: &[Request],
+    previous: &mut [Option<Vec<u8>>],
+) {
+    let color = fetch::use_color();
+    for (request, previous) in requests.iter().zip(previous) {
+        let response = match request.send(client).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("{}: {}", request.url, e);
+                continue;
+            }
+        };
+        eprintln!("{}: {}", request.url, response.status);
+        match previous {
+            Some(previous) => match diff::bodies(previous, &response.body, color) {
+                changes if changes.is_empty() => println!("(unchanged)"),
+                changes => print!("{}", changes),
+            },
+            None => print!(
+                "{}",
+                output::format_body(&response, args.response_charset, !args.raw, color)
+            ),
+        }
+        *previous = Some(response.body);
+    }
+}
+
+/// Send the requests every `interval` until interrupted. With `--watch-diff`, only what changed in
+/// each body since the previous round is printed.
+pub async fn run(
+    client: &Client,
+    args: &CliArgs,
+    requests: Vec<Request>,
+    interval: Interval,
+) -> Result<()> {
+    let mut previous = vec![None; requests.len()];
+    loop {
+        let start = Instant::now();
+        print_round(args, interval);
+        if args.watch_diff {
+            print_changes(client, args, &requests, &mut previous).await;
+        } else {
+            // Failures are reported by `fetch_all`, they don't stop the watch
+            fetch::fetch_all(client, args, requests.clone()).await?;
+        }
+        tokio::time::sleep(interval.0.saturating_sub(start.elapsed())).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Interval;
+
+    #[test]
+    fn intervals_have_an_optional_unit() {
+        assert_eq!("5".parse::<Interval>().unwrap().0, Duration::from_secs(5));
+        assert_eq!(
+            "500ms".parse::<Interval>().unwrap().0,
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            "1.5m".parse::<Interval>().unwrap().0,
+            Duration::from_secs(90)
+        );
+        assert_eq!(
+            "2h".parse::<Interval>().unwrap().0,
+            Duration::from_secs(7200)
+        );
+        assert!("5 days".parse::<Interval>().is_err());
+        assert!("s".parse::<Interval>().is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Rendering of requests and responses in HTTP/1.1 wire format.
+use std::io::{self, Write};
+
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_LENGTH, HOST, RANGE,
+    TRANSFER_ENCODING, USER_AGENT,
+};
+
+use crate::{response::Response, ACCEPT_ENCODING_VALUE, USER_AGENT_VALUE};
+
+/// The `Host` header value for the URL: the host, followed by the port if it is not the default
+/// port of the scheme
+fn host_header(url: &reqwest::Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// The headers the request is sent with: its own and the ones the client adds unless the request
+/// sets them, `Host`, `User-Agent`, `Accept`, `Accept-Encoding` and the length of the body.
+/// `accept_encoding` tells whether the client asks for compressed responses, which reqwest doesn't
+/// do for range requests.
+pub fn sent_headers(req: &reqwest::Request, accept_encoding: bool) -> HeaderMap {
+    let own = req.headers();
+    let mut headers = HeaderMap::new();
+    if let (false, Ok(host)) = (
+        own.contains_key(HOST),
+        HeaderValue::from_str(&host_header(req.url())),
+    ) {
+        headers.insert(HOST, host);
+    }
+    for (name, value) in own {
+        headers.append(name.clone(), value.clone());
+    }
+    let mut add = |name: HeaderName, value: &str| {
+        if let (false, Ok(value)) = (headers.contains_key(&name), HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    };
+    add(USER_AGENT, USER_AGENT_VALUE);
+    add(ACCEPT, "*/*");
+    if accept_encoding && !own.contains_key(RANGE) {
+        add(ACCEPT_ENCODING, ACCEPT_ENCODING_VALUE);
+    }
+    match req.body().map(|b| b.as_bytes()) {
+        Some(Some(body)) => add(CONTENT_LENGTH, &body.len().to_string()),
+        // A streamed body of unknown length
+        Some(None) if !own.contains_key(CONTENT_LENGTH) => add(TRANSFER_ENCODING, "chunked"),
+        _ => {}
+    }
+    headers
+}
+
+/// Render the request line and the headers sent, up to the blank line before the body
+pub fn format_request_head(req: &reqwest::Request, accept_encoding: bool) -> String {
+    let url = req.url();
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let mut out = format!("{} {} HTTP/1.1\r\n", req.method(), target);
+    for (name, value) in &sent_headers(req, accept_encoding) {
+        out.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    out.push_str("\r\n");
+    out
+}
+
+/// Render the request the way it is sent on an HTTP/1.1 connection
+pub fn format_request(req: &reqwest::Request, accept_encoding: bool) -> String {
+    let mut out = format_request_head(req, accept_encoding);
+    if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+        out.push_str(&String::from_utf8_lossy(body));
+    }
+    out
+}
+
+/// Render the status line and the headers of the response, up to the blank line before the body
+pub fn format_response_head(response: &Response) -> String {
+    let mut out = format!("{:?} {}\r\n", response.version, response.status);
+    for (name, value) in &response.headers {
+        out.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    out.push_str("\r\n");
+    out
+}
+
+/// Write a whole exchange for `--dump`: the request, then the response or the error, each
+/// followed by a blank line. The bodies are written as received.
+pub fn dump(
+    out: &mut impl Write,
+    request: &reqwest::Request,
+    response: &crate::Result<Response>,
+    accept_encoding: bool,
+) -> io::Result<()> {
+    out.write_all(format_request_head(request, accept_encoding).as_bytes())?;
+    if let Some(body) = request.body().and_then(|b| b.as_bytes()) {
+        out.write_all(body)?;
+    }
+    out.write_all(b"\r\n\r\n")?;
+    match response {
+        Ok(response) => {
+            out.write_all(format_response_head(response).as_bytes())?;
+            out.write_all(&response.body)?;
+        }
+        Err(e) => write!(out, "(no response: {})", e)?,
+    }
+    out.write_all(b"\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use reqwest::{header::HeaderMap, Client, StatusCode, Version};
+
+    use crate::response::Response;
+
+    #[test]
+    fn request_is_rendered_with_implicit_headers() {
+        let req = Client::new()
+            .post("http://example.com:8080/a/b?c=d")
+            .header("content-type", "application/json")
+            .body("{}")
+            .build()
+            .unwrap();
+        assert_eq!(
+            super::format_request(&req, true),
+            "POST /a/b?c=d HTTP/1.1\r\n\
+            host: example.com:8080\r\n\
+            content-type: application/json\r\n\
+            user-agent: rq/0.1.0\r\n\
+            accept: */*\r\n\
+            accept-encoding: gzip, br, zstd\r\n\
+            content-length: 2\r\n\
+            \r\n\
+            {}"
+        );
+    }
+    #[test]
+    fn compression_is_not_asked_for_ranges_nor_without_decompression() {
+        let req = Client::new()
+            .get("http://example.com/")
+            .header("range", "bytes=0-9")
+            .build()
+            .unwrap();
+        assert!(!super::sent_headers(&req, true).contains_key("accept-encoding"));
+        let req = Client::new().get("http://example.com/").build().unwrap();
+        assert!(!super::sent_headers(&req, false).contains_key("accept-encoding"));
+    }
+    #[test]
+    fn default_port_is_omitted_from_host() {
+        let req = Client::new().get("https://example.com/").build().unwrap();
+        assert!(super::format_request(&req, true)
+            .starts_with("GET / HTTP/1.1\r\nhost: example.com\r\n"));
+    }
+    #[test]
+    fn response_head_has_status_line_and_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/plain".parse().unwrap());
+        let response = Response {
+            url: "http://localhost/".to_string(),
+            status: StatusCode::NOT_FOUND,
+            version: Version::HTTP_11,
+            headers,
+            body: Vec::new(),
+            wait: Duration::ZERO,
+            receive: Duration::ZERO,
+        };
+        assert_eq!(
+            super::format_response_head(&response),
+            "HTTP/1.1 404 Not Found\r\ncontent-type: text/plain\r\n\r\n"
+        );
+    }
+    #[test]
+    fn exchange_without_response_is_dumped_with_the_error() {
+        let req = Client::new()
+            .get("http://example.com/")
+            .header("accept", "text/plain")
+            .header("accept-encoding", "gzip")
+            .header("user-agent", "rq")
+            .build()
+            .unwrap();
+        let mut out = Vec::new();
+        let error = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        super::dump(&mut out, &req, &Err(error.into()), true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "GET / HTTP/1.1\r\n\
+            host: example.com\r\n\
+            accept: text/plain\r\n\
+            accept-encoding: gzip\r\n\
+            user-agent: rq\r\n\
+            \r\n\
+            \r\n\
+            \r\n\
+            (no response: IoError: timed out)\r\n\
+            \r\n"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! WebSocket client (https://datatracker.ietf.org/doc/html/rfc6455): the opening handshake over
+//! a plain or TLS connection, made with the options of the client, then stdin and stdout bridged
+//! to the socket, one message per line.
+use std::io;
+
+use reqwest::Url;
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::mpsc,
+};
+
+use crate::connect::{self, Stream};
+
+/// Appended to the key to compute `Sec-WebSocket-Accept`
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_HANDSHAKE_LEN: usize = 16 * 1024;
+const MAX_MESSAGE_LEN: u64 = 64 * 1024 * 1024;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// Status code of a normal closure
+const NORMAL_CLOSURE: u16 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid WebSocket URL: {0}")]
+    Url(String),
+    #[error("WebSocket handshake failed: {0}")]
+    Handshake(String),
+    #[error("WebSocket protocol error: {0}")]
+    Protocol(String),
+    #[error("{0}")]
+    Connect(#[from] connect::Error),
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// What the reading task reports to the main loop
+enum Event {
+    Message(Vec<u8>),
+    Ping(Vec<u8>),
+    Close,
+    Failed(Error),
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("the system random number generator failed");
+    bytes
+}
+
+/// The `Sec-WebSocket-Accept` value the server must answer with
+fn accept_key(key: &str) -> String {
+    let hash = digest::digest(
+        &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{}{}", key, GUID).as_bytes(),
+    );
+    base64::encode(hash.as_ref())
+}
+
+/// Encode a single final frame. Frames sent by a client must be masked.
+fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let len = payload.len();
+    if len < 126 {
+        frame.push(0x80 | len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(0x80 | 126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(0x80 | 127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).await?;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len).await?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len).await?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(Error::Protocol(format!(
+            "frame of {} bytes is too large",
+            len
+        )));
+    }
+    let mask = if head[1] & 0x80 != 0 {
+        let mut mask = [0; 4];
+        reader.read_exact(&mut mask).await?;
+        Some(mask)
+    } else {
+        None
+    };
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if let Some(mask) = mask {
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0F,
+        payload,
+    })
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: u8,
+    payload: &[u8],
+) -> Result<()> {
+    writer
+        .write_all(&encode_frame(opcode, payload, random_bytes()))
+        .await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Send the upgrade request and check the response
+async fn handshake(
+    stream: &mut Box<dyn Stream>,
+    url: &Url,
+    headers: &[(String, String)],
+) -> Result<()> {
+    let key = base64::encode(random_bytes::<16>());
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+        Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n",
+        target, host, key
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // Read byte by byte so that frames sent right after the response are left in the stream
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > MAX_HANDSHAKE_LEN {
+            return Err(Error::Handshake(
+                "response headers are too large".to_string(),
+            ));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Response::new(&mut headers);
+    parsed
+        .parse(&response)
+        .map_err(|e| Error::Handshake(e.to_string()))?;
+    if parsed.code != Some(101) {
+        return Err(Error::Handshake(format!(
+            "expected status 101, got {} {}",
+            parsed.code.unwrap_or_default(),
+            parsed.reason.unwrap_or_default()
+        )));
+    }
+    let accept = parsed
+        .headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("sec-websocket-accept"))
+        .map(|h| String::from_utf8_lossy(h.value));
+    if accept.as_deref() != Some(accept_key(&key).as_str()) {
+        return Err(Error::Handshake("invalid Sec-WebSocket-Accept".to_string()));
+    }
+    Ok(())
+}
+
+/// Read frames, reassemble fragmented messages and forward them to the main loop
+async fn read_messages<R: AsyncRead + Unpin>(mut reader: R, events: mpsc::Sender<Event>) {
+    let mut message = Vec::new();
+    loop {
+        let event = match read_frame(&mut reader).await {
+            Err(e) => Event::Failed(e),
+            Ok(frame) => match frame.opcode {
+                TEXT | BINARY | CONTINUATION
+                    if (message.len() + frame.payload.len()) as u64 > MAX_MESSAGE_LEN =>
+                {
+                    Event::Failed(Error::Protocol(format!(
+                        "message of more than {} bytes",
+                        MAX_MESSAGE_LEN
+                    )))
+                }
+                TEXT | BINARY | CONTINUATION => {
+                    message.extend(frame.payload);
+                    if !frame.fin {
+                        continue;
+                    }
+                    Event::Message(std::mem::take(&mut message))
+                }
+                PING => Event::Ping(frame.payload),
+                PONG => continue,
+                CLOSE => Event::Close,
+                opcode => Event::Failed(Error::Protocol(format!("unknown opcode {}", opcode))),
+            },
+        };
+        let last = matches!(event, Event::Close | Event::Failed(_));
+        if events.send(event).await.is_err() || last {
+            break;
+        }
+    }
+}
+
+/// Connect to `url` and exchange messages.
+///
+/// With `messages`, those messages are sent and as many replies are printed before closing.
+/// Otherwise every line of stdin is sent as a message and every message received is printed
+/// on its own line, until the server closes the connection or stdin is closed.
+pub async fn run(
+    url: &str,
+    headers: &[(String, String)],
+    messages: &[String],
+    options: &connect::Options,
+) -> Result<()> {
+    let mut url = Url::parse(url).map_err(|e| Error::Url(e.to_string()))?;
+    // Accept the HTTP URLs of the endpoint too, `normalize_url` adds `http://` by default
+    let scheme = match url.scheme() {
+        "ws" | "http" => "ws",
+        "wss" | "https" => "wss",
+        _ => return Err(Error::Url(url.to_string())),
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| Error::Url(url.to_string()))?;
+    let mut stream = options.connect(&url).await?.stream;
+    handshake(&mut stream, &url, headers).await?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let (sender, mut events) = mpsc::channel(16);
+    tokio::spawn(read_messages(reader, sender));
+
+    let one_shot = !messages.is_empty();
+    let mut reading_stdin = !one_shot;
+    // Whether we sent the close frame and are waiting for the one of the server
+    let mut closing = false;
+    let mut remaining_replies = messages.len();
+    for message in messages {
+        write_frame(&mut writer, TEXT, message.as_bytes()).await?;
+    }
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        tokio::select! {
+            line = stdin.next_line(), if reading_stdin => match line? {
+                Some(line) => write_frame(&mut writer, TEXT, line.as_bytes()).await?,
+                None => {
+                    reading_stdin = false;
+                    closing = true;
+                    write_frame(&mut writer, CLOSE, &NORMAL_CLOSURE.to_be_bytes()).await?;
+                }
+            },
+            event = events.recv() => match event {
+                Some(Event::Message(message)) => {
+                    println!("{}", String::from_utf8_lossy(&message));
+                    if one_shot && !closing {
+                        remaining_replies -= 1;
+                        if remaining_replies == 0 {
+                            closing = true;
+                            write_frame(&mut writer, CLOSE, &NORMAL_CLOSURE.to_be_bytes()).await?;
+                        }
+                    }
+                }
+                Some(Event::Ping(payload)) => write_frame(&mut writer, PONG, &payload).await?,
+                Some(Event::Close) | None => {
+                    if !closing {
+                        // Echo the close frame as required, the connection is over anyway
+                        let _ = write_frame(&mut writer, CLOSE, &NORMAL_CLOSURE.to_be_bytes()).await;
+                    }
+                    return Ok(());
+                }
+                Some(Event::Failed(e)) => return Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(
+            super::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+    #[tokio::test]
+    async fn encoded_frames_can_be_read_back() {
+        for len in [5, 300, 70_000] {
+            let payload = vec![b'x'; len];
+            let encoded = super::encode_frame(super::TEXT, &payload, [1, 2, 3, 4]);
+            let frame = super::read_frame(&mut &encoded[..]).await.unwrap();
+            assert!(frame.fin);
+            assert_eq!(frame.opcode, super::TEXT);
+            assert_eq!(frame.payload, payload);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! `--xml-to-json`: XML response bodies converted to JSON, so that `--filter` and `--extract` work
+//! the same against SOAP and other XML APIs.
+//!
+//! `<user id="1"><name>Ann</name><tag>a</tag><tag>b</tag></user>` becomes
+//! `{"user": {"@id": "1", "name": "Ann", "tag": ["a", "b"]}}`: attributes are prefixed with `@`,
+//! repeated elements become arrays, the text of elements that also have attributes or children
+//! is kept under `#text` and empty elements are `null`. Values stay strings, namespace prefixes
+//! are kept in the names, comments and processing instructions are dropped.
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+use serde_json::{Map, Value};
+
+use crate::{
+    markup::{self, Kind, Token},
+    scraper::{decode_entities, parse_attrs},
+    Response,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid XML: expected </{expected}>, found {found}")]
+    Mismatched { expected: String, found: String },
+    #[error("Invalid XML: <{0}> is not closed")]
+    Unclosed(String),
+    #[error("Invalid XML: no root element")]
+    NoRoot,
+    #[error("Invalid XML: content after the root element")]
+    TrailingContent,
+    #[error("The body is not UTF-8 text")]
+    NotText,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Default)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<(String, Value)>,
+    text: String,
+}
+
+impl Element {
+    fn into_json(self) -> Value {
+        let text = self.text.trim();
+        if self.attrs.is_empty() && self.children.is_empty() {
+            return match text {
+                "" => Value::Null,
+                text => Value::String(text.to_string()),
+            };
+        }
+        let mut object = Map::new();
+        for (name, value) in self.attrs {
+            object.insert(format!("@{}", name), Value::String(value));
+        }
+        for (name, value) in self.children {
+            match object.get_mut(&name) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(first) => *first = Value::Array(vec![first.take(), value]),
+                None => {
+                    object.insert(name, value);
+                }
+            }
+        }
+        if !text.is_empty() {
+            object.insert("#text".to_string(), Value::String(text.to_string()));
+        }
+        Value::Object(object)
+    }
+}
+
+/// The name of a tag, in the case it is written in
+fn tag_name(source: &str) -> String {
+    source
+        .trim_start_matches(['<', '/'])
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Convert the document to JSON, the root element as the only key of an object
+pub fn to_json(xml: &str) -> Result<Value> {
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+    for token in markup::tokenize(xml, Kind::Xml) {
+        let text = match token {
+            Token::Open {
+                source,
+                self_closing,
+                ..
+            } => {
+                if root.is_some() {
+                    return Err(Error::TrailingContent);
+                }
+                let element = Element {
+                    name: tag_name(source),
+                    attrs: parse_attrs(source),
+                    ..Element::default()
+                };
+                stack.push(element);
+                if !self_closing {
+                    continue;
+                }
+                None
+            }
+            Token::Close { source, .. } => {
+                let name = tag_name(source);
+                match stack.last() {
+                    Some(top) if top.name == name => None,
+                    top => {
+                        return Err(Error::Mismatched {
+                            expected: top.map_or_else(String::new, |top| top.name.clone()),
+                            found: source.to_string(),
+                        })
+                    }
+                }
+            }
+            Token::Text(text) => Some(decode_entities(text)),
+            Token::Other(other) => match other.strip_prefix("<![CDATA[") {
+                Some(cdata) => Some(cdata.trim_end_matches("]]>").to_string()),
+                None => continue,
+            },
+        };
+        match (text, stack.last_mut()) {
+            (Some(text), Some(top)) => top.text.push_str(&text),
+            (Some(text), None) if text.trim().is_empty() => {}
+            (Some(_), None) => return Err(Error::TrailingContent),
+            // An element ended
+            (None, _) => {
+                let element = stack.pop().expect("an element was opened");
+                let name = element.name.clone();
+                let value = element.into_json();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push((name, value)),
+                    None => root = Some((name, value)),
+                }
+            }
+        }
+    }
+    if let Some(open) = stack.pop() {
+        return Err(Error::Unclosed(open.name));
+    }
+    let (name, value) = root.ok_or(Error::NoRoot)?;
+    let mut object = Map::new();
+    object.insert(name, value);
+    Ok(Value::Object(object))
+}
+
+/// Whether the response is XML, by its `Content-Type` or, without one, by its body
+fn is_xml(response: &Response) -> bool {
+    match response.header(CONTENT_TYPE.as_str()) {
+        Some(content_type) => markup::kind_of(content_type) == Some(Kind::Xml),
+        None => response.body.trim_ascii_start().starts_with(b"<?xml"),
+    }
+}
+
+/// Replace the XML body of the response with its JSON conversion, leaving other bodies as they are
+pub fn convert(response: &mut Response) -> Result<()> {
+    if !is_xml(response) {
+        return Ok(());
+    }
+    let xml = std::str::from_utf8(&response.body).map_err(|_| Error::NotText)?;
+    response.body = to_json(xml)?.to_string().into_bytes();
+    response
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn elements_become_objects() {
+        let xml = r#"<?xml version="1.0"?>
+            <!-- users -->
+            <soap:Envelope xmlns:soap="urn:s">
+              <User id="1"><Name>Ann &amp; Bob</Name><tag>a</tag><tag>b</tag><tag/>
+                <note lang="en">hi</note><raw><![CDATA[<x>]]></raw></User>
+            </soap:Envelope>"#;
+        assert_eq!(
+            super::to_json(xml).unwrap(),
+            json!({"soap:Envelope": {
+                "@xmlns:soap": "urn:s",
+                "User": {
+                    "@id": "1",
+                    "Name": "Ann & Bob",
+                    "tag": ["a", "b", null],
+                    "note": {"@lang": "en", "#text": "hi"},
+                    "raw": "<x>"
+                }
+            }})
+        );
+    }
+    #[test]
+    fn invalid_documents_are_rejected() {
+        assert!(super::to_json("<a><b></a>").is_err());
+        assert!(super::to_json("<a>").is_err());
+        assert!(super::to_json("<a/><b/>").is_err());
+        assert!(super::to_json("text").is_err());
+    }
+}
@@ -0,0 +1,573 @@
+//! A parser for the subset of YAML used by rq's own files (collections, test suites...).
+//!
+//! Supported:
+//! - Block mappings and block sequences, including mappings nested inside sequence items
+//! - Plain, single-quoted and double-quoted scalars. Plain scalars are typed as null, booleans
+//!   and numbers the same way YAML 1.2 core schema does, everything else is a string.
+//! - Literal (`|`) and folded (`>`) block scalars with the `-` and `+` chomping indicators
+//! - Single line flow collections: `[1, 2]`, `{a: 1}`
+//! - Comments and the `---` document marker
+//!
+//! Not supported: anchors, aliases, tags, multiple documents and multi-line plain or flow
+//! values. The result is a `serde_json::Value` so it can be consumed the same way as JSON.
+use serde_json::{Map, Number, Value};
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("YAML error at line {line}: {message}")]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Parse a YAML document into a JSON value. An empty document is `null`.
+pub fn parse(s: &str) -> Result<Value> {
+    let mut parser = Parser {
+        lines: s.lines().map(|l| l.trim_end().to_string()).collect(),
+        pos: 0,
+    };
+    parser.skip_insignificant();
+    if parser.pos < parser.lines.len() && parser.lines[parser.pos] == "---" {
+        parser.pos += 1;
+    }
+    let value = match parser.peek_indent() {
+        Some(indent) => parser.parse_block(indent)?,
+        None => Value::Null,
+    };
+    if let Some(indent) = parser.peek_indent() {
+        return Err(parser.error(format!("unexpected content at indentation {}", indent)));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    lines: Vec<String>,
+    pos: usize,
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_insignificant(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn is_sequence_entry(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+impl Parser {
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error {
+            line: self.pos.min(self.lines.len().saturating_sub(1)) + 1,
+            message: message.into(),
+        }
+    }
+
+    fn skip_insignificant(&mut self) {
+        while self.pos < self.lines.len() && is_insignificant(&self.lines[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    /// Indentation of the next significant line, if any
+    fn peek_indent(&mut self) -> Option<usize> {
+        self.skip_insignificant();
+        self.lines.get(self.pos).map(|l| indent_of(l))
+    }
+
+    fn parse_block(&mut self, indent: usize) -> Result<Value> {
+        let content = self.lines[self.pos][indent..].to_string();
+        if is_sequence_entry(&content) {
+            self.parse_sequence(indent)
+        } else if split_key(&content)?.is_some() {
+            self.parse_mapping(indent)
+        } else {
+            self.pos += 1;
+            parse_inline(&content).map_err(|message| self.error(message))
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value> {
+        let mut items = Vec::new();
+        while self.peek_indent() == Some(indent) {
+            let line = self.lines[self.pos].clone();
+            if !is_sequence_entry(&line[indent..]) {
+                break;
+            }
+            let rest = line[indent + 1..].trim_start();
+            if rest.is_empty() || rest.starts_with('#') {
+                self.pos += 1;
+                items.push(self.parse_nested(indent)?);
+            } else {
+                // Replace the dash with a space so the item content can be parsed as a block
+                // at its own indentation, e.g. `- a: 1` becomes `  a: 1`
+                let item_indent = line.len() - rest.len();
+                self.lines[self.pos] = format!("{}{}", " ".repeat(item_indent), rest);
+                items.push(self.parse_block(item_indent)?);
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value> {
+        let mut map = Map::new();
+        while self.peek_indent() == Some(indent) {
+            let line = self.lines[self.pos].clone();
+            let content = &line[indent..];
+            if is_sequence_entry(content) {
+                break;
+            }
+            let (key, rest) = match split_key(content)? {
+                Some(entry) => entry,
+                None => return Err(self.error("expected a `key: value` entry")),
+            };
+            let rest = strip_comment(rest).trim();
+            let value = if rest.is_empty() {
+                self.pos += 1;
+                // A sequence is allowed to have the same indentation as its key
+                match self.peek_indent() {
+                    Some(i) if i == indent && is_sequence_entry(&self.lines[self.pos][i..]) => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => self.parse_nested(indent)?,
+                }
+            } else if rest.starts_with('|') || rest.starts_with('>') {
+                self.pos += 1;
+                self.parse_block_scalar(rest, indent)?
+            } else {
+                self.pos += 1;
+                parse_inline(rest).map_err(|message| Error {
+                    line: self.pos,
+                    message,
+                })?
+            };
+            if map.insert(key.clone(), value).is_some() {
+                return Err(Error {
+                    line: self.pos,
+                    message: format!("duplicated key `{}`", key),
+                });
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// Parse the block indented deeper than `parent_indent`, or null if there is none
+    fn parse_nested(&mut self, parent_indent: usize) -> Result<Value> {
+        match self.peek_indent() {
+            Some(indent) if indent > parent_indent => self.parse_block(indent),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    fn parse_block_scalar(&mut self, header: &str, parent_indent: usize) -> Result<Value> {
+        let folded = header.starts_with('>');
+        let chomping = header[1..].trim();
+        if !matches!(chomping, "" | "-" | "+") {
+            return Err(self.error(format!("unsupported block scalar header `{}`", header)));
+        }
+        let mut raw_lines = Vec::new();
+        let mut indent = None;
+        while self.pos < self.lines.len() {
+            let line = &self.lines[self.pos];
+            if line.trim().is_empty() {
+                raw_lines.push(String::new());
+                self.pos += 1;
+                continue;
+            }
+            let line_indent = indent_of(line);
+            let block_indent = *indent.get_or_insert(line_indent);
+            if line_indent <= parent_indent || line_indent < block_indent {
+                break;
+            }
+            raw_lines.push(line[block_indent..].to_string());
+            self.pos += 1;
+        }
+        // Trailing blank lines belong to the block only when keeping them with `+`
+        let mut trailing_blank = 0;
+        while raw_lines.last().map(|l| l.is_empty()) == Some(true) {
+            raw_lines.pop();
+            trailing_blank += 1;
+        }
+        let mut text = if folded {
+            fold_lines(&raw_lines)
+        } else {
+            raw_lines.join("\n")
+        };
+        match chomping {
+            "-" => {}
+            "+" => {
+                text.push('\n');
+                text.push_str(&"\n".repeat(trailing_blank));
+            }
+            _ if !raw_lines.is_empty() => text.push('\n'),
+            _ => {}
+        }
+        Ok(Value::String(text))
+    }
+}
+
+/// Join the lines of a folded block scalar: single line breaks become spaces, each blank line
+/// becomes a line break, line breaks around more indented lines are kept as is.
+fn fold_lines(lines: &[String]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&str> = None;
+    for line in lines {
+        match previous {
+            // A blank line already produced its line break, and the line break before it is
+            // discarded
+            Some(prev) if prev.is_empty() || (line.is_empty() && !prev.starts_with(' ')) => {}
+            Some(prev) if prev.starts_with(' ') || line.starts_with(' ') => text.push('\n'),
+            Some(_) => text.push(' '),
+            None => {}
+        }
+        if line.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(line);
+        previous = Some(line);
+    }
+    text
+}
+
+/// Split `key: rest` into the key and the rest. Return `None` if the line is not a mapping entry.
+fn split_key(content: &str) -> Result<Option<(String, &str)>> {
+    if content.starts_with('"') || content.starts_with('\'') {
+        let mut chars = Chars::new(content);
+        let key = match chars.quoted() {
+            Ok(key) => key,
+            Err(_) => return Ok(None),
+        };
+        let rest = &content[chars.pos..];
+        let rest = rest.trim_start_matches(' ');
+        return Ok(rest
+            .strip_prefix(':')
+            .filter(|r| r.is_empty() || r.starts_with(' '))
+            .map(|r| (key, r)));
+    }
+    if content.starts_with('[') || content.starts_with('{') {
+        return Ok(None);
+    }
+    let colon = content
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .find(|i| content[i + 1..].is_empty() || content[i + 1..].starts_with(' '));
+    Ok(colon
+        .filter(|i| !content[..*i].contains(" #"))
+        .map(|i| (content[..i].trim_end().to_string(), &content[i + 1..])))
+}
+
+/// Remove a trailing comment, which starts with ` #` outside of quotes
+fn strip_comment(s: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous == ' ' => return &s[..i],
+            None => {}
+        }
+        previous = c;
+    }
+    s
+}
+
+/// Parse a value written on a single line: a flow collection or a scalar
+fn parse_inline(s: &str) -> std::result::Result<Value, String> {
+    let s = strip_comment(s).trim();
+    let mut chars = Chars::new(s);
+    let value = chars.flow_value(false)?;
+    chars.skip_spaces();
+    if chars.pos != s.len() {
+        return Err(format!(
+            "unexpected trailing characters `{}`",
+            &s[chars.pos..]
+        ));
+    }
+    Ok(value)
+}
+
+/// Type a plain scalar according to the YAML 1.2 core schema
+fn plain_scalar(s: &str) -> Value {
+    match s {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    let looks_numeric = s
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        && s.chars().any(|c| c.is_ascii_digit());
+    if looks_numeric {
+        if let Ok(i) = s.parse::<i64>() {
+            return Value::Number(i.into());
+        }
+        if let Some(n) = s.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Value::Number(n);
+        }
+    }
+    Value::String(s.to_string())
+}
+
+/// Cursor over a single line
+struct Chars<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Chars<'a> {
+    fn new(s: &'a str) -> Self {
+        Chars { s, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> std::result::Result<(), String> {
+        self.skip_spaces();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected `{}` but found `{}`", expected, c)),
+            None => Err(format!("expected `{}` but found end of line", expected)),
+        }
+    }
+
+    /// Parse a value. Inside a flow collection, `,` `]` `}` and `: ` end a plain scalar.
+    fn flow_value(&mut self, in_flow: bool) -> std::result::Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => self.flow_sequence(),
+            Some('{') => self.flow_mapping(),
+            Some('"') | Some('\'') => self.quoted().map(Value::String),
+            _ => Ok(plain_scalar(self.plain(in_flow))),
+        }
+    }
+
+    fn plain(&mut self, in_flow: bool) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let rest = &self.s[self.pos..];
+            if in_flow && (matches!(c, ',' | ']' | '}') || rest.starts_with(": ")) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        self.s[start..self.pos].trim_end()
+    }
+
+    fn flow_sequence(&mut self) -> std::result::Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.flow_value(true)?);
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err("unterminated flow sequence".to_string()),
+            }
+        }
+    }
+
+    fn flow_mapping(&mut self) -> std::result::Result<Value, String> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Object(map));
+            }
+            let key = match self.flow_value(true)? {
+                Value::String(s) => s,
+                Value::Null => return Err("missing key in flow mapping".to_string()),
+                other => other.to_string(),
+            };
+            self.expect(':')?;
+            let value = self.flow_value(true)?;
+            map.insert(key, value);
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(map)),
+                _ => return Err("unterminated flow mapping".to_string()),
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> std::result::Result<String, String> {
+        let quote = self.next().ok_or("expected a quoted string")?;
+        let mut s = String::new();
+        loop {
+            let c = self.next().ok_or("unterminated quoted string")?;
+            match (quote, c) {
+                ('\'', '\'') if self.peek() == Some('\'') => {
+                    self.pos += 1;
+                    s.push('\'');
+                }
+                (q, c) if q == c => return Ok(s),
+                ('"', '\\') => s.push(self.escape()?),
+                (_, c) => s.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> std::result::Result<char, String> {
+        let c = self.next().ok_or("unterminated escape sequence")?;
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' | '\\' | '/' | ' ' => c,
+            'u' => {
+                let hex = self
+                    .s
+                    .get(self.pos..self.pos + 4)
+                    .ok_or("invalid \\u escape")?;
+                self.pos += 4;
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid \\u escape `{}`", hex))?
+            }
+            c => return Err(format!("unknown escape sequence `\\{}`", c)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn empty_document_is_null() {
+        assert_eq!(super::parse("").unwrap(), json!(null));
+        assert_eq!(
+            super::parse("# only a comment\n---\n").unwrap(),
+            json!(null)
+        );
+    }
+    #[test]
+    fn plain_scalars_are_typed() {
+        let yaml = "a: 1\nb: -1.5\nc: true\nd: ~\ne: hello world\nf: 1.2.3\ng: http://x.y/z";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({"a": 1, "b": -1.5, "c": true, "d": null, "e": "hello world",
+                   "f": "1.2.3", "g": "http://x.y/z"})
+        );
+    }
+    #[test]
+    fn quoted_scalars_are_strings() {
+        let yaml = r#"a: "1"
+b: 'it''s'
+c: "tab\there \u00e9"
+"quoted key": x"#;
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({"a": "1", "b": "it's", "c": "tab\there é", "quoted key": "x"})
+        );
+    }
+    #[test]
+    fn comments_are_ignored() {
+        let yaml = "# header\na: 1 # trailing\nb: 'not # a comment'\nc: a#b\n";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({"a": 1, "b": "not # a comment", "c": "a#b"})
+        );
+    }
+    #[test]
+    fn nested_mappings_and_sequences() {
+        let yaml = "
+user:
+  name: bob
+  tags:
+  - a
+  - b
+items:
+  - id: 1
+    name: x
+  - id: 2
+  -
+    - nested
+";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({
+                "user": {"name": "bob", "tags": ["a", "b"]},
+                "items": [{"id": 1, "name": "x"}, {"id": 2}, ["nested"]]
+            })
+        );
+    }
+    #[test]
+    fn flow_collections() {
+        let yaml = "a: [1, 'two', {b: c}]\nd: {}\ne: []";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({"a": [1, "two", {"b": "c"}], "d": {}, "e": []})
+        );
+    }
+    #[test]
+    fn block_scalars() {
+        let yaml = "
+literal: |
+  {
+    \"a\": 1
+  }
+stripped: |-
+  line
+folded: >
+  one
+  two
+
+  three
+next: x
+";
+        assert_eq!(
+            super::parse(yaml).unwrap(),
+            json!({
+                "literal": "{\n  \"a\": 1\n}\n",
+                "stripped": "line",
+                "folded": "one two\nthree\n",
+                "next": "x"
+            })
+        );
+    }
+    #[test]
+    fn key_without_value_is_null() {
+        assert_eq!(
+            super::parse("a:\nb: 1").unwrap(),
+            json!({"a": null, "b": 1})
+        );
+    }
+    #[test]
+    fn errors_report_the_line() {
+        assert_eq!(super::parse("a: 1\na: 2").unwrap_err().line, 2);
+        assert_eq!(super::parse("a: 1\n  b: 2").unwrap_err().line, 2);
+        assert_eq!(super::parse("a: \"open").unwrap_err().line, 1);
+    }
+}
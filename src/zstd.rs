@@ -0,0 +1,877 @@
+//! Decoding of `Content-Encoding: zstd` responses (https://www.rfc-editor.org/rfc/rfc8878).
+//!
+//! reqwest 0.11 has no `zstd` feature and no zstd crate is available to the build, so the frames
+//! are decoded here: raw, RLE and compressed blocks, with Huffman coded literals and FSE coded
+//! sequences. Frames needing a dictionary are rejected, skippable frames are skipped and the
+//! content checksums are verified.
+
+use std::convert::TryInto;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid zstd data: the data ends in the middle of a frame")]
+    Truncated,
+    #[error("Invalid zstd data: {0}")]
+    Corrupted(&'static str),
+    #[error("zstd frames needing a dictionary are not supported")]
+    Dictionary,
+    #[error("Invalid zstd data: the content checksum doesn't match")]
+    Checksum,
+    #[error("The decompressed body is larger than {0} bytes")]
+    TooLarge(u64),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+const FRAME_MAGIC: u32 = 0xFD2F_B528;
+const SKIPPABLE_MAGIC: std::ops::RangeInclusive<u32> = 0x184D_2A50..=0x184D_2A5F;
+
+const MAX_LITERALS_LOG: u8 = 9;
+const MAX_MATCH_LOG: u8 = 9;
+const MAX_OFFSET_LOG: u8 = 8;
+const MAX_WEIGHTS_LOG: u8 = 6;
+const MAX_HUFFMAN_BITS: u8 = 11;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+
+/// The distributions of the predefined FSE tables, with their accuracy logs
+const LITERALS_DISTRIBUTION: (&[i16], u8) = (
+    &[
+        4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1,
+        1, 1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const MATCH_DISTRIBUTION: (&[i16], u8) = (
+    &[
+        1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const OFFSET_DISTRIBUTION: (&[i16], u8) = (
+    &[
+        1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+    ],
+    5,
+);
+
+/// The baselines and extra bits of the literals length codes above 15
+const LITERALS_CODES: [(u32, u8); 20] = [
+    (16, 1),
+    (18, 1),
+    (20, 1),
+    (22, 1),
+    (24, 2),
+    (28, 2),
+    (32, 3),
+    (40, 3),
+    (48, 4),
+    (64, 6),
+    (128, 7),
+    (256, 8),
+    (512, 9),
+    (1024, 10),
+    (2048, 11),
+    (4096, 12),
+    (8192, 13),
+    (16384, 14),
+    (32768, 15),
+    (65536, 16),
+];
+/// The baselines and extra bits of the match length codes above 31
+const MATCH_CODES: [(u32, u8); 21] = [
+    (35, 1),
+    (37, 1),
+    (39, 1),
+    (41, 1),
+    (43, 2),
+    (47, 2),
+    (51, 3),
+    (59, 3),
+    (67, 4),
+    (83, 4),
+    (99, 5),
+    (131, 7),
+    (259, 8),
+    (515, 9),
+    (1027, 10),
+    (2051, 11),
+    (4099, 12),
+    (8195, 13),
+    (16387, 14),
+    (32771, 15),
+    (65539, 16),
+];
+
+/// The index of the highest bit set of a nonzero number
+fn highest_bit(n: u32) -> u32 {
+    31 - n.leading_zeros()
+}
+
+/// Reads bytes from the start of the data
+struct Input<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or(Error::Truncated)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(Error::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// The little-endian unsigned integer of `len` bytes
+    fn uint(&mut self, len: usize) -> Result<u64> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .rev()
+            .fold(0, |n, &byte| (n << 8) | byte as u64))
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+}
+
+/// The bits of `bytes` from `offset`, the first bit being the lowest of the first byte
+fn bits_at(bytes: &[u8], offset: usize, count: u32) -> u64 {
+    let mut value = 0;
+    for i in 0..count as usize {
+        let bit = offset + i;
+        value |= (((bytes[bit / 8] >> (bit % 8)) & 1) as u64) << i;
+    }
+    value
+}
+
+/// Reads bits forward, for the FSE table descriptions
+struct ForwardBits<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl ForwardBits<'_> {
+    fn read(&mut self, count: u32) -> Result<u32> {
+        if self.offset + count as usize > self.bytes.len() * 8 {
+            return Err(Error::Truncated);
+        }
+        let value = bits_at(self.bytes, self.offset, count) as u32;
+        self.offset += count as usize;
+        Ok(value)
+    }
+
+    /// The number of bytes read, the last one partially
+    fn bytes_read(&self) -> usize {
+        self.offset.div_ceil(8)
+    }
+}
+
+/// Reads bits backward from the end, where the highest set bit marks the start. Reading past the
+/// beginning gives zeros, the decoders checking where they stopped.
+struct BackwardBits<'a> {
+    bytes: &'a [u8],
+    offset: i64,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self> {
+        match bytes.last() {
+            Some(&last) if last != 0 => Ok(BackwardBits {
+                bytes,
+                offset: bytes.len() as i64 * 8 - 8 + highest_bit(last as u32) as i64,
+            }),
+            _ => Err(Error::Corrupted("bitstream without end mark")),
+        }
+    }
+
+    fn read(&mut self, count: u8) -> u64 {
+        self.offset -= count as i64;
+        if self.offset >= 0 {
+            return bits_at(self.bytes, self.offset as usize, count as u32);
+        }
+        let missing = -self.offset;
+        if missing >= count as i64 {
+            return 0;
+        }
+        bits_at(self.bytes, 0, (count as i64 - missing) as u32) << missing
+    }
+}
+
+/// A table decoding FSE states
+#[derive(Clone)]
+struct Fse {
+    log: u8,
+    symbols: Vec<u8>,
+    bits: Vec<u8>,
+    bases: Vec<u16>,
+}
+
+impl Fse {
+    /// Spread the symbols over the states according to their probabilities, -1 being "less than 1"
+    fn new(probabilities: &[i16], log: u8) -> Result<Self> {
+        let size = 1usize << log;
+        let mut symbols = vec![0; size];
+        let mut next = vec![0u32; probabilities.len()];
+        let mut high = size;
+        for (symbol, &probability) in probabilities.iter().enumerate() {
+            if probability == -1 {
+                high -= 1;
+                symbols[high] = symbol as u8;
+                next[symbol] = 1;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mask = size - 1;
+        let mut pos = 0;
+        for (symbol, &probability) in probabilities.iter().enumerate() {
+            if probability <= 0 {
+                continue;
+            }
+            next[symbol] = probability as u32;
+            for _ in 0..probability {
+                symbols[pos] = symbol as u8;
+                pos = (pos + step) & mask;
+                while pos >= high {
+                    pos = (pos + step) & mask;
+                }
+            }
+        }
+        if pos != 0 {
+            return Err(Error::Corrupted("invalid FSE distribution"));
+        }
+        let mut bits = vec![0; size];
+        let mut bases = vec![0; size];
+        for state in 0..size {
+            let symbol = symbols[state] as usize;
+            let next_state = next[symbol];
+            next[symbol] += 1;
+            bits[state] = log - highest_bit(next_state) as u8;
+            bases[state] = ((next_state << bits[state]) as usize - size) as u16;
+        }
+        Ok(Fse {
+            log,
+            symbols,
+            bits,
+            bases,
+        })
+    }
+
+    /// The table always giving `symbol`
+    fn rle(symbol: u8) -> Self {
+        Fse {
+            log: 0,
+            symbols: vec![symbol],
+            bits: vec![0],
+            bases: vec![0],
+        }
+    }
+
+    /// Read the description of a table with an accuracy log of `max_log` at most, and return it
+    /// with the number of bytes it takes
+    fn read(bytes: &[u8], max_log: u8, max_symbols: usize) -> Result<(Self, usize)> {
+        let mut bits = ForwardBits { bytes, offset: 0 };
+        let log = bits.read(4)? as u8 + 5;
+        if log > max_log {
+            return Err(Error::Corrupted("FSE accuracy log too large"));
+        }
+        let mut remaining = 1i32 << log;
+        let mut probabilities = Vec::new();
+        while remaining > 0 && probabilities.len() < max_symbols {
+            let count = highest_bit(remaining as u32 + 1) + 1;
+            let mut value = bits.read(count)?;
+            let lower_mask = (1 << (count - 1)) - 1;
+            let threshold = (1 << count) - 1 - (remaining as u32 + 1);
+            if value & lower_mask < threshold {
+                bits.offset -= 1;
+                value &= lower_mask;
+            } else if value > lower_mask {
+                value -= threshold;
+            }
+            let probability = value as i16 - 1;
+            remaining -= probability.abs() as i32;
+            probabilities.push(probability);
+            if probability == 0 {
+                loop {
+                    let repeat = bits.read(2)?;
+                    probabilities.resize(probabilities.len() + repeat as usize, 0);
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+        }
+        if remaining != 0 || probabilities.len() > max_symbols {
+            return Err(Error::Corrupted("invalid FSE distribution"));
+        }
+        Ok((Fse::new(&probabilities, log)?, bits.bytes_read()))
+    }
+
+    fn init(&self, bits: &mut BackwardBits) -> usize {
+        bits.read(self.log) as usize
+    }
+
+    fn symbol(&self, state: usize) -> u8 {
+        self.symbols[state]
+    }
+
+    fn update(&self, state: &mut usize, bits: &mut BackwardBits) {
+        *state = self.bases[*state] as usize + bits.read(self.bits[*state]) as usize;
+    }
+}
+
+/// A table decoding Huffman codes of `max_bits` bits at most
+#[derive(Clone)]
+struct Huffman {
+    max_bits: u8,
+    symbols: Vec<u8>,
+    bits: Vec<u8>,
+}
+
+impl Huffman {
+    /// The table of the weights of the symbols, the weight of the last one being implied
+    fn from_weights(weights: &[u8]) -> Result<Self> {
+        let corrupted = || Error::Corrupted("invalid Huffman weights");
+        if weights.is_empty() || weights.len() > 255 {
+            return Err(corrupted());
+        }
+        let mut sum = 0u32;
+        for &weight in weights {
+            if weight > MAX_HUFFMAN_BITS {
+                return Err(corrupted());
+            }
+            if weight > 0 {
+                sum += 1 << (weight - 1);
+            }
+        }
+        if sum == 0 {
+            return Err(corrupted());
+        }
+        let max_bits = highest_bit(sum) as u8 + 1;
+        let left = (1u32 << max_bits) - sum;
+        if max_bits > MAX_HUFFMAN_BITS || !left.is_power_of_two() {
+            return Err(corrupted());
+        }
+        let mut weights = weights.to_vec();
+        weights.push(highest_bit(left) as u8 + 1);
+        let lengths: Vec<u8> = weights
+            .iter()
+            .map(|&weight| if weight > 0 { max_bits + 1 - weight } else { 0 })
+            .collect();
+        let mut counts = [0u32; MAX_HUFFMAN_BITS as usize + 2];
+        for &length in &lengths {
+            counts[length as usize] += 1;
+        }
+        // The codes of each length start after those of the longer lengths
+        let mut starts = [0u32; MAX_HUFFMAN_BITS as usize + 2];
+        for length in (1..=max_bits as usize).rev() {
+            starts[length - 1] =
+                starts[length] + counts[length] * (1 << (max_bits as usize - length));
+        }
+        let size = 1usize << max_bits;
+        let mut symbols = vec![0; size];
+        let mut bits = vec![0; size];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let start = starts[length as usize] as usize;
+            let len = 1usize << (max_bits - length);
+            if start + len > size {
+                return Err(corrupted());
+            }
+            symbols[start..start + len].fill(symbol as u8);
+            bits[start..start + len].fill(length);
+            starts[length as usize] += len as u32;
+        }
+        Ok(Huffman {
+            max_bits,
+            symbols,
+            bits,
+        })
+    }
+
+    /// Read the description of a table, and return it with the number of bytes it takes
+    fn read(bytes: &[u8]) -> Result<(Self, usize)> {
+        let mut input = Input { bytes, pos: 0 };
+        let header = input.byte()? as usize;
+        let weights = if header < 128 {
+            // The weights are FSE coded, by two interleaved states
+            let data = input.take(header)?;
+            let (fse, len) = Fse::read(data, MAX_WEIGHTS_LOG, 256)?;
+            let mut bits = BackwardBits::new(data.get(len..).ok_or(Error::Truncated)?)?;
+            let mut states = [fse.init(&mut bits), fse.init(&mut bits)];
+            let mut weights = Vec::new();
+            for turn in (0..2).cycle() {
+                if weights.len() > 255 {
+                    return Err(Error::Corrupted("too many Huffman weights"));
+                }
+                weights.push(fse.symbol(states[turn]));
+                fse.update(&mut states[turn], &mut bits);
+                if bits.offset < 0 {
+                    weights.push(fse.symbol(states[1 - turn]));
+                    break;
+                }
+            }
+            weights
+        } else {
+            let count = header - 127;
+            let packed = input.take(count.div_ceil(2))?;
+            (0..count)
+                .map(|i| match i % 2 {
+                    0 => packed[i / 2] >> 4,
+                    _ => packed[i / 2] & 0xf,
+                })
+                .collect()
+        };
+        Ok((Huffman::from_weights(&weights)?, input.pos))
+    }
+
+    /// Decode a stream of `len` symbols
+    fn decode_stream(&self, data: &[u8], len: usize, out: &mut Vec<u8>) -> Result<()> {
+        let mut bits = BackwardBits::new(data)?;
+        let mask = (1usize << self.max_bits) - 1;
+        let mut state = bits.read(self.max_bits) as usize;
+        for _ in 0..len {
+            out.push(self.symbols[state]);
+            let count = self.bits[state];
+            state = ((state << count) | bits.read(count) as usize) & mask;
+        }
+        if bits.offset != -(self.max_bits as i64) {
+            return Err(Error::Corrupted("Huffman stream size mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// What a frame carries over from one block to the next
+struct FrameState {
+    huffman: Option<Huffman>,
+    literals: Option<Fse>,
+    offsets: Option<Fse>,
+    matches: Option<Fse>,
+    recent_offsets: [usize; 3],
+}
+
+fn read_literals(input: &mut Input, state: &mut FrameState) -> Result<Vec<u8>> {
+    let first = input.byte()?;
+    let kind = first & 3;
+    let size_format = (first >> 2) & 3;
+    if kind < 2 {
+        let size = match size_format {
+            0 | 2 => (first >> 3) as usize,
+            1 => (first >> 4) as usize + ((input.byte()? as usize) << 4),
+            _ => (first >> 4) as usize + ((input.uint(2)? as usize) << 4),
+        };
+        return match kind {
+            0 => Ok(input.take(size)?.to_vec()),
+            _ => Ok(vec![input.byte()?; size]),
+        };
+    }
+    let (header, size_bits) = match size_format {
+        0 | 1 => ((first as u64) | input.uint(2)? << 8, 10),
+        2 => ((first as u64) | input.uint(3)? << 8, 14),
+        _ => ((first as u64) | input.uint(4)? << 8, 18),
+    };
+    let mask = (1 << size_bits) - 1;
+    let size = ((header >> 4) & mask) as usize;
+    let compressed_size = ((header >> (4 + size_bits)) & mask) as usize;
+    let mut data = input.take(compressed_size)?;
+    if kind == 2 {
+        let (huffman, len) = Huffman::read(data)?;
+        state.huffman = Some(huffman);
+        data = &data[len..];
+    }
+    let huffman = state
+        .huffman
+        .as_ref()
+        .ok_or(Error::Corrupted("no previous Huffman table"))?;
+    let mut literals = Vec::with_capacity(size);
+    if size_format == 0 {
+        huffman.decode_stream(data, size, &mut literals)?;
+    } else {
+        let mut jumps = Input {
+            bytes: data,
+            pos: 0,
+        };
+        let sizes = [jumps.uint(2)?, jumps.uint(2)?, jumps.uint(2)?];
+        let mut streams = jumps;
+        let quarter = size.div_ceil(4);
+        let last = size
+            .checked_sub(3 * quarter)
+            .ok_or(Error::Corrupted("invalid literals size"))?;
+        for stream_size in sizes {
+            let stream = streams.take(stream_size as usize)?;
+            huffman.decode_stream(stream, quarter, &mut literals)?;
+        }
+        let rest = streams.take(streams.remaining())?;
+        huffman.decode_stream(rest, last, &mut literals)?;
+    }
+    Ok(literals)
+}
+
+/// The table of a sequence symbol, as its compression mode tells
+fn read_table(
+    input: &mut Input,
+    mode: u8,
+    previous: &mut Option<Fse>,
+    (distribution, log): (&[i16], u8),
+    max_log: u8,
+) -> Result<()> {
+    let table = match mode {
+        0 => Fse::new(distribution, log)?,
+        1 => Fse::rle(input.byte()?),
+        2 => {
+            let rest = &input.bytes[input.pos..];
+            let (table, len) = Fse::read(rest, max_log, distribution.len())?;
+            input.pos += len;
+            table
+        }
+        _ => {
+            return previous
+                .is_some()
+                .then_some(())
+                .ok_or(Error::Corrupted("no previous FSE table"))
+        }
+    };
+    *previous = Some(table);
+    Ok(())
+}
+
+/// The baseline and extra bits of a literals length code
+fn literals_code(code: u8) -> Result<(u32, u8)> {
+    match code {
+        0..=15 => Ok((code as u32, 0)),
+        _ => LITERALS_CODES
+            .get(code as usize - 16)
+            .copied()
+            .ok_or(Error::Corrupted("invalid literals length code")),
+    }
+}
+
+/// The baseline and extra bits of a match length code
+fn match_code(code: u8) -> Result<(u32, u8)> {
+    match code {
+        0..=31 => Ok((code as u32 + 3, 0)),
+        _ => MATCH_CODES
+            .get(code as usize - 32)
+            .copied()
+            .ok_or(Error::Corrupted("invalid match length code")),
+    }
+}
+
+/// The offset of a match, updating the recent offsets that the values up to 3 refer to
+fn match_offset(value: usize, literals_len: u32, recent: &mut [usize; 3]) -> usize {
+    if value > 3 {
+        recent.rotate_right(1);
+        recent[0] = value - 3;
+        return recent[0];
+    }
+    let index = value - 1 + (literals_len == 0) as usize;
+    let offset = match index {
+        0 => return recent[0],
+        3 => recent[0].saturating_sub(1),
+        index => recent[index],
+    };
+    if index > 1 {
+        recent[2] = recent[1];
+    }
+    recent[1] = recent[0];
+    recent[0] = offset;
+    offset
+}
+
+/// Decode a compressed block, appending its content to the frame decoded so far
+fn decode_block(
+    data: &[u8],
+    state: &mut FrameState,
+    out: &mut Vec<u8>,
+    start: usize,
+) -> Result<()> {
+    let mut input = Input {
+        bytes: data,
+        pos: 0,
+    };
+    let block_start = out.len();
+    let literals = read_literals(&mut input, state)?;
+    let first = input.byte()? as usize;
+    let count = match first {
+        0..=127 => first,
+        128..=254 => ((first - 128) << 8) + input.byte()? as usize,
+        _ => input.uint(2)? as usize + 0x7F00,
+    };
+    if count == 0 {
+        out.extend_from_slice(&literals);
+        return Ok(());
+    }
+    let modes = input.byte()?;
+    if modes & 3 != 0 {
+        return Err(Error::Corrupted("reserved bits set"));
+    }
+    read_table(
+        &mut input,
+        modes >> 6,
+        &mut state.literals,
+        LITERALS_DISTRIBUTION,
+        MAX_LITERALS_LOG,
+    )?;
+    read_table(
+        &mut input,
+        (modes >> 4) & 3,
+        &mut state.offsets,
+        OFFSET_DISTRIBUTION,
+        MAX_OFFSET_LOG,
+    )?;
+    read_table(
+        &mut input,
+        (modes >> 2) & 3,
+        &mut state.matches,
+        MATCH_DISTRIBUTION,
+        MAX_MATCH_LOG,
+    )?;
+    let (literals_table, offsets_table, matches_table) =
+        match (&state.literals, &state.offsets, &state.matches) {
+            (Some(l), Some(o), Some(m)) => (l, o, m),
+            _ => return Err(Error::Corrupted("missing FSE table")),
+        };
+    let mut bits = BackwardBits::new(&data[input.pos..])?;
+    let mut literals_state = literals_table.init(&mut bits);
+    let mut offsets_state = offsets_table.init(&mut bits);
+    let mut matches_state = matches_table.init(&mut bits);
+    let mut literals = &literals[..];
+    for i in 0..count {
+        let offset_code = offsets_table.symbol(offsets_state);
+        if offset_code > 31 {
+            return Err(Error::Corrupted("invalid offset code"));
+        }
+        let (match_base, match_bits) = match_code(matches_table.symbol(matches_state))?;
+        let (literals_base, literals_bits) = literals_code(literals_table.symbol(literals_state))?;
+        let offset_value = (1usize << offset_code) + bits.read(offset_code) as usize;
+        let match_len = match_base as usize + bits.read(match_bits) as usize;
+        let literals_len = literals_base + bits.read(literals_bits) as u32;
+        if i + 1 < count {
+            literals_table.update(&mut literals_state, &mut bits);
+            matches_table.update(&mut matches_state, &mut bits);
+            offsets_table.update(&mut offsets_state, &mut bits);
+        }
+        let offset = match_offset(offset_value, literals_len, &mut state.recent_offsets);
+        let (copied, rest) = literals
+            .split_at_checked(literals_len as usize)
+            .ok_or(Error::Corrupted("literals length beyond the literals"))?;
+        out.extend_from_slice(copied);
+        literals = rest;
+        if offset == 0 || offset > out.len() - start {
+            return Err(Error::Corrupted("match offset beyond the frame"));
+        }
+        if out.len() + match_len - block_start > MAX_BLOCK_SIZE {
+            return Err(Error::Corrupted("block larger than 128 KiB"));
+        }
+        let from = out.len() - offset;
+        for j in 0..match_len {
+            out.push(out[from + j]);
+        }
+    }
+    if bits.offset != 0 {
+        return Err(Error::Corrupted("sequences stream size mismatch"));
+    }
+    out.extend_from_slice(literals);
+    Ok(())
+}
+
+/// Decode a frame, its magic number read already, appending its content
+fn decode_frame(input: &mut Input, out: &mut Vec<u8>, limit: Option<u64>) -> Result<()> {
+    let start = out.len();
+    let descriptor = input.byte()?;
+    let size_flag = descriptor >> 6;
+    let single_segment = descriptor & 0x20 != 0;
+    let has_checksum = descriptor & 0x04 != 0;
+    if descriptor & 0x08 != 0 {
+        return Err(Error::Corrupted("reserved bit set"));
+    }
+    if !single_segment {
+        input.byte()?;
+    }
+    let dictionary_id = input.uint([0, 1, 2, 4][(descriptor & 3) as usize])?;
+    if dictionary_id != 0 {
+        return Err(Error::Dictionary);
+    }
+    match (size_flag, single_segment) {
+        (0, false) => {}
+        (0, true) => {
+            input.byte()?;
+        }
+        (flag, _) => {
+            input.uint(1 << flag)?;
+        }
+    }
+    let mut state = FrameState {
+        huffman: None,
+        literals: None,
+        offsets: None,
+        matches: None,
+        recent_offsets: [1, 4, 8],
+    };
+    loop {
+        let header = input.uint(3)? as usize;
+        let last = header & 1 != 0;
+        let size = header >> 3;
+        match (header >> 1) & 3 {
+            0 => out.extend_from_slice(input.take(size)?),
+            1 if size > MAX_BLOCK_SIZE => {
+                return Err(Error::Corrupted("block larger than 128 KiB"))
+            }
+            1 => {
+                let byte = input.byte()?;
+                out.resize(out.len() + size, byte);
+            }
+            2 => decode_block(input.take(size)?, &mut state, out, start)?,
+            _ => return Err(Error::Corrupted("reserved block type")),
+        }
+        if let Some(limit) = limit.filter(|&limit| out.len() as u64 > limit) {
+            return Err(Error::TooLarge(limit));
+        }
+        if last {
+            break;
+        }
+    }
+    if has_checksum {
+        let checksum = input.uint(4)? as u32;
+        if xxh64(&out[start..]) as u32 != checksum {
+            return Err(Error::Checksum);
+        }
+    }
+    Ok(())
+}
+
+/// Decode the zstd frames of the data, failing once the content is larger than `limit`
+pub fn decode(data: &[u8], limit: Option<u64>) -> Result<Vec<u8>> {
+    let mut input = Input {
+        bytes: data,
+        pos: 0,
+    };
+    let mut out = Vec::new();
+    while input.remaining() > 0 {
+        match input.uint(4)? as u32 {
+            FRAME_MAGIC => decode_frame(&mut input, &mut out, limit)?,
+            magic if SKIPPABLE_MAGIC.contains(&magic) => {
+                let size = input.uint(4)? as usize;
+                input.take(size)?;
+            }
+            _ => return Err(Error::Corrupted("unknown magic number")),
+        }
+    }
+    Ok(out)
+}
+
+const PRIME_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1)
+}
+
+fn xxh64_merge(acc: u64, value: u64) -> u64 {
+    (acc ^ xxh64_round(0, value))
+        .wrapping_mul(PRIME_1)
+        .wrapping_add(PRIME_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"))
+}
+
+/// The XXH64 hash with seed 0, which the content checksum is the low 32 bits of
+fn xxh64(data: &[u8]) -> u64 {
+    let mut rest = data;
+    let mut hash = if data.len() >= 32 {
+        let mut v = [
+            PRIME_1.wrapping_add(PRIME_2),
+            PRIME_2,
+            0,
+            0u64.wrapping_sub(PRIME_1),
+        ];
+        while rest.len() >= 32 {
+            for (i, v) in v.iter_mut().enumerate() {
+                *v = xxh64_round(*v, read_u64(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let hash = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(hash, |hash, &v| xxh64_merge(hash, v))
+    } else {
+        PRIME_5
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+    while rest.len() >= 8 {
+        hash ^= xxh64_round(0, read_u64(rest));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let word = u32::from_le_bytes(rest[..4].try_into().expect("4 bytes")) as u64;
+        hash ^= word.wrapping_mul(PRIME_1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(PRIME_2)
+            .wrapping_add(PRIME_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash ^= (byte as u64).wrapping_mul(PRIME_5);
+        hash = hash.rotate_left(11).wrapping_mul(PRIME_1);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    const TEXT: &str =
+        "The quick brown fox jumps over the lazy dog. The quick brown fox jumps over \
+        the lazy cat. The lazy dog sleeps while the quick brown fox jumps again and again.";
+    /// `TEXT` compressed with `zstd -19 --check`: Huffman coded literals and FSE coded sequences
+    const COMPRESSED: &[u8] = b"\x28\xb5\x2f\xfd\x24\x9e\x85\x02\x00\xf2\x04\x10\x11\x90\x3d\x06\
+        \x50\xfa\x43\xe9\x0f\xa5\xcf\xd7\xdd\xd5\xe3\xf9\xaf\x1c\xe0\x29\x81\xc4\xcb\x13\x7f\x4a\
+        \x20\x51\x6f\x9a\x4a\xf8\x34\x1a\xd3\xe8\xab\x20\x07\x08\x96\xeb\x2d\xda\x4d\xaa\x9f\xe6\
+        \xc2\xd3\xe8\xec\x7a\x86\xe7\x0f\xc3\x39\xce\x88\xb2\xf2\x26\x85\x04\x00\x4a\x58\xe5\x95\
+        \xb1\x00\xe0\x1a\x96\x4a\x0f\xa9\x91\x59\x25";
+
+    #[test]
+    fn compressed_frames_are_decoded() {
+        assert_eq!(super::decode(COMPRESSED, None).unwrap(), TEXT.as_bytes());
+        assert!(super::decode(&COMPRESSED[..COMPRESSED.len() - 1], None).is_err());
+        let mut corrupted = COMPRESSED.to_vec();
+        corrupted[40] ^= 1;
+        assert!(super::decode(&corrupted, None).is_err());
+        assert!(matches!(
+            super::decode(COMPRESSED, Some(100)),
+            Err(super::Error::TooLarge(100))
+        ));
+    }
+    #[test]
+    fn skippable_and_rle_frames_are_decoded() {
+        let data = b"\x50\x2a\x4d\x18\x02\x00\x00\x00hi\x28\xb5\x2f\xfd\x20\x05\x2b\x00\x00a";
+        assert_eq!(super::decode(data, None).unwrap(), b"aaaaa");
+        assert!(super::decode(b"\x28\xb5\x2f\xfd\x21\x05\x01\x00\x00\x00", None).is_err());
+    }
+}